@@ -1,12 +1,75 @@
 use axum::{
-    body::Body,
-    extract::State,
+    Json,
+    body::{Body, Bytes},
+    extract::{FromRequest, State},
     http::{Request, StatusCode, header},
     middleware::Next,
-    response::Response,
+    response::{IntoResponse, Response},
 };
+use chrono::Utc;
 
-use crate::DbPool;
+use crate::{
+    DbPool,
+    api::{github::signature_matches, ingest::failure_response},
+    models::{api_token::TokenScope, project},
+};
+
+/// `sha256=<hex>` HMAC of a signed ingest request
+pub const SIGNATURE_HEADER: &str = "x-miniapm-signature";
+/// Unix time the request was signed at
+pub const TIMESTAMP_HEADER: &str = "x-miniapm-timestamp";
+/// Slug of the project whose signing secret was used
+pub const PROJECT_HEADER: &str = "x-miniapm-project";
+/// How far a signed request's timestamp may be from now, either way, before
+/// it's treated as a replay
+pub const SIGNATURE_MAX_AGE_SECS: i64 = 300;
+
+/// Why a request was turned away, sent as `{"error": "<code>"}` so clients
+/// can tell a missing header from a wrong key
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthError {
+    /// No bearer token, DSN key or complete set of signature headers
+    MissingAuthorization,
+    /// The key or token doesn't belong to any project
+    InvalidApiKey,
+    /// Signed with an unknown project, a stale timestamp or the wrong secret
+    InvalidSignature,
+    /// A valid token whose scope doesn't cover this API
+    InsufficientScope,
+    /// Valid credentials for a project whose ingest is switched off
+    ProjectDisabled,
+}
+
+impl AuthError {
+    pub fn code(self) -> &'static str {
+        match self {
+            Self::MissingAuthorization => "missing_authorization",
+            Self::InvalidApiKey => "invalid_api_key",
+            Self::InvalidSignature => "invalid_signature",
+            Self::InsufficientScope => "insufficient_scope",
+            Self::ProjectDisabled => "project_disabled",
+        }
+    }
+
+    pub fn status(self) -> StatusCode {
+        match self {
+            Self::MissingAuthorization | Self::InvalidApiKey | Self::InvalidSignature => {
+                StatusCode::UNAUTHORIZED
+            }
+            Self::InsufficientScope | Self::ProjectDisabled => StatusCode::FORBIDDEN,
+        }
+    }
+}
+
+impl IntoResponse for AuthError {
+    fn into_response(self) -> Response {
+        (
+            self.status(),
+            Json(serde_json::json!({ "error": self.code() })),
+        )
+            .into_response()
+    }
+}
 
 /// Holds project information extracted from API key authentication
 #[derive(Clone, Debug)]
@@ -14,34 +77,188 @@ pub struct ProjectContext {
     pub project_id: Option<i64>,
 }
 
+/// Authenticate `/ingest/*` requests (project API keys, ingest-scoped tokens
+/// or a signature made with the project's signing secret)
 pub async fn auth_middleware(
     State(pool): State<DbPool>,
-    mut request: Request<Body>,
+    request: Request<Body>,
     next: Next,
-) -> Result<Response, StatusCode> {
-    // Extract Authorization header
+) -> Result<Response, AuthError> {
+    if request.headers().contains_key(SIGNATURE_HEADER) {
+        return authenticate_signed(&pool, request, next).await;
+    }
+    authenticate(&pool, TokenScope::Ingest, request, next).await
+}
+
+/// Authenticate `/api/*` read requests (read-scoped tokens only)
+pub async fn read_auth_middleware(
+    State(pool): State<DbPool>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    authenticate(&pool, TokenScope::Read, request, next).await
+}
+
+/// Authenticate Sentry SDK requests, which carry the DSN key in
+/// `X-Sentry-Auth` or a `sentry_key` query parameter instead of a bearer token
+pub async fn sentry_auth_middleware(
+    State(pool): State<DbPool>,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let api_key = sentry_key(&request).or_else(|| bearer_key(&request));
+    authenticate_key(&pool, TokenScope::Ingest, api_key, request, next).await
+}
+
+fn bearer_key(request: &Request<Body>) -> Option<String> {
     let auth_header = request
         .headers()
         .get(header::AUTHORIZATION)
-        .and_then(|h| h.to_str().ok());
+        .and_then(|h| h.to_str().ok())?;
+    auth_header.strip_prefix("Bearer ").map(str::to_string)
+}
 
-    let api_key = match auth_header {
-        Some(h) if h.starts_with("Bearer ") => &h[7..],
-        _ => return Err(StatusCode::UNAUTHORIZED),
+/// `X-Sentry-Auth: Sentry sentry_key=<key>, sentry_version=7, ...` or `?sentry_key=<key>`
+fn sentry_key(request: &Request<Body>) -> Option<String> {
+    let from_header = request
+        .headers()
+        .get("x-sentry-auth")
+        .and_then(|h| h.to_str().ok())
+        .and_then(|h| h.strip_prefix("Sentry "))
+        .and_then(|fields| {
+            fields
+                .split(',')
+                .find_map(|field| field.trim().strip_prefix("sentry_key="))
+        });
+    let from_query = || {
+        request.uri().query()?.split('&').find_map(|pair| {
+            let value = pair.strip_prefix("sentry_key=")?;
+            urlencoding::decode(value).ok().map(|v| v.into_owned())
+        })
     };
+    from_header.map(str::to_string).or_else(from_query)
+}
+
+async fn authenticate(
+    pool: &DbPool,
+    required: TokenScope,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let api_key = bearer_key(&request);
+    authenticate_key(pool, required, api_key, request, next).await
+}
 
-    // Always authenticate against project API keys
+/// Authenticate a request signed instead of carrying the API key, so the key
+/// can't leak through logs or proxies. The signature is the HMAC-SHA256 of
+/// `<timestamp>.<body>` keyed with the signing secret of the project named in
+/// `X-MiniAPM-Project`; a timestamp outside `SIGNATURE_MAX_AGE_SECS` is
+/// rejected so captured requests can't be replayed later.
+async fn authenticate_signed(
+    pool: &DbPool,
+    request: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let headers = {
+        let header = |name: &str| {
+            request
+                .headers()
+                .get(name)
+                .and_then(|h| h.to_str().ok())
+                .map(str::to_string)
+        };
+        (
+            header(SIGNATURE_HEADER),
+            header(TIMESTAMP_HEADER),
+            header(PROJECT_HEADER),
+        )
+    };
+    let (Some(signature), Some(timestamp), Some(slug)) = headers else {
+        return Err(AuthError::MissingAuthorization);
+    };
+    let fresh = timestamp
+        .parse::<i64>()
+        .is_ok_and(|at| (Utc::now().timestamp() - at).abs() <= SIGNATURE_MAX_AGE_SECS);
+    if !fresh {
+        return Err(AuthError::InvalidSignature);
+    }
+
+    let (project_id, secret) = match project::find_by_slug(pool, &slug) {
+        Ok(Some(project)) => match project::signing_secret(pool, project.id) {
+            Ok(Some(secret)) => (project.id, secret),
+            Ok(None) => return Err(AuthError::InvalidSignature),
+            Err(e) => return Ok(failure_response("Failed to load signing secret", e)),
+        },
+        Ok(None) => return Err(AuthError::InvalidSignature),
+        Err(e) => return Ok(failure_response("Failed to look up project", e)),
+    };
+
+    // Buffer the body to check it, still within the route's body limit
+    let (parts, body) = request.into_parts();
+    let body = match Bytes::from_request(Request::from_parts(parts.clone(), body), &()).await {
+        Ok(body) => body,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+    let mut signed = format!("{}.", timestamp).into_bytes();
+    signed.extend_from_slice(&body);
+    if !signature_matches(&secret, &signed, &signature) {
+        return Err(AuthError::InvalidSignature);
+    }
+    match project::is_disabled(pool, project_id) {
+        Ok(false) => {}
+        Ok(true) => return Err(AuthError::ProjectDisabled),
+        Err(e) => return Ok(failure_response("Failed to check project status", e)),
+    }
+
+    let ctx = ProjectContext {
+        project_id: Some(project_id),
+    };
+    let mut request = Request::from_parts(parts, Body::from(body));
+    request.extensions_mut().insert(ctx.clone());
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(ctx);
+    Ok(response)
+}
+
+async fn authenticate_key(
+    pool: &DbPool,
+    required: TokenScope,
+    api_key: Option<String>,
+    mut request: Request<Body>,
+    next: Next,
+) -> Result<Response, AuthError> {
+    let Some(api_key) = api_key else {
+        return Err(AuthError::MissingAuthorization);
+    };
+    let api_key = api_key.as_str();
+
+    // Project API keys carry ingest scope
     // A default project is always created on startup
-    match crate::models::project::find_by_api_key(&pool, api_key) {
-        Ok(Some(project)) => {
-            request.extensions_mut().insert(ProjectContext {
-                project_id: Some(project.id),
-            });
-            Ok(next.run(request).await)
-        }
-        Ok(None) => Err(StatusCode::UNAUTHORIZED),
-        Err(_) => Err(StatusCode::INTERNAL_SERVER_ERROR),
+    let (project_id, scope) = match crate::models::project::find_by_api_key(pool, api_key) {
+        Ok(Some(project)) => (Some(project.id), TokenScope::Ingest),
+        Ok(None) => match crate::models::api_token::verify(pool, api_key) {
+            Ok(Some(token)) => (token.project_id, token.scope),
+            Ok(None) => return Err(AuthError::InvalidApiKey),
+            Err(e) => return Ok(failure_response("Failed to verify token", e)),
+        },
+        Err(e) => return Ok(failure_response("Failed to look up API key", e)),
+    };
+
+    if scope != required {
+        return Err(AuthError::InsufficientScope);
     }
+    match project_id.map_or(Ok(false), |id| project::is_disabled(pool, id)) {
+        Ok(false) => {}
+        Ok(true) => return Err(AuthError::ProjectDisabled),
+        Err(e) => return Ok(failure_response("Failed to check project status", e)),
+    }
+
+    let ctx = ProjectContext { project_id };
+    request.extensions_mut().insert(ctx.clone());
+    // Also on the response, for `log_ingest` further out
+    let mut response = next.run(request).await;
+    response.extensions_mut().insert(ctx);
+    Ok(response)
 }
 
 #[cfg(test)]
@@ -69,14 +286,28 @@ mod tests {
                 id INTEGER PRIMARY KEY,
                 name TEXT NOT NULL UNIQUE,
                 slug TEXT NOT NULL UNIQUE,
-                api_key TEXT NOT NULL UNIQUE,
-                created_at TEXT NOT NULL
+                api_key_hash TEXT NOT NULL UNIQUE,
+                api_key_prefix TEXT,
+                created_at TEXT NOT NULL,
+                previous_api_key_hash TEXT,
+                previous_api_key_expires_at TEXT,
+                disabled_at TEXT,
+                last_used_at TEXT
+            );
+            CREATE TABLE api_tokens (
+                id INTEGER PRIMARY KEY,
+                project_id INTEGER,
+                name TEXT NOT NULL,
+                token_hash TEXT NOT NULL UNIQUE,
+                scope TEXT NOT NULL DEFAULT 'ingest',
+                created_at TEXT NOT NULL,
+                last_used_at TEXT
             );
             "#,
         )
         .unwrap();
 
-        pool
+        pool.into()
     }
 
     async fn handler() -> &'static str {
@@ -93,6 +324,33 @@ mod tests {
             .with_state(pool)
     }
 
+    fn create_read_app(pool: DbPool) -> Router {
+        Router::new()
+            .route("/test", get(handler))
+            .layer(middleware::from_fn_with_state(
+                pool.clone(),
+                read_auth_middleware,
+            ))
+            .with_state(pool)
+    }
+
+    async fn error_code(response: Response) -> String {
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let body: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        body["error"].as_str().unwrap_or_default().to_string()
+    }
+
+    async fn status_for(app: Router, token: &str) -> StatusCode {
+        let req = Request::builder()
+            .uri("/test")
+            .header("Authorization", format!("Bearer {}", token))
+            .body(Body::empty())
+            .unwrap();
+        app.oneshot(req).await.unwrap().status()
+    }
+
     #[tokio::test]
     async fn test_auth_requires_authorization_header() {
         let pool = create_test_pool();
@@ -102,6 +360,7 @@ mod tests {
 
         let response = app.oneshot(req).await.unwrap();
         assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(error_code(response).await, "missing_authorization");
     }
 
     #[tokio::test]
@@ -135,22 +394,194 @@ mod tests {
 
         let response = app.oneshot(req).await.unwrap();
         assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert_eq!(error_code(response).await, "invalid_api_key");
+    }
+
+    #[tokio::test]
+    async fn test_auth_forbids_disabled_project() {
+        let pool = create_test_pool();
+        let (project, api_key) = crate::models::project::create(&pool, "Test").unwrap();
+        crate::models::project::set_disabled(&pool, project.id, true).unwrap();
+
+        let req = Request::builder()
+            .uri("/test")
+            .header("Authorization", format!("Bearer {}", api_key))
+            .body(Body::empty())
+            .unwrap();
+        let response = create_app(pool.clone()).oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert_eq!(error_code(response).await, "project_disabled");
+
+        crate::models::project::set_disabled(&pool, project.id, false).unwrap();
+        assert_eq!(status_for(create_app(pool), &api_key).await, StatusCode::OK);
     }
 
     #[tokio::test]
     async fn test_auth_accepts_valid_project_key() {
         let pool = create_test_pool();
-        let project = crate::models::project::ensure_default_project(&pool).unwrap();
+        let (_, api_key) = crate::models::project::create(&pool, "Test").unwrap();
 
         let app = create_app(pool);
 
         let req = Request::builder()
             .uri("/test")
-            .header("Authorization", format!("Bearer {}", project.api_key))
+            .header("Authorization", format!("Bearer {}", api_key))
             .body(Body::empty())
             .unwrap();
 
         let response = app.oneshot(req).await.unwrap();
         assert_eq!(response.status(), StatusCode::OK);
     }
+
+    #[tokio::test]
+    async fn test_sentry_auth_accepts_dsn_key() {
+        let pool = create_test_pool();
+        let (_, api_key) = crate::models::project::create(&pool, "Test").unwrap();
+        let app = Router::new()
+            .route("/test", get(handler))
+            .layer(middleware::from_fn_with_state(
+                pool.clone(),
+                sentry_auth_middleware,
+            ))
+            .with_state(pool);
+
+        let send = |req: Request<Body>| {
+            let app = app.clone();
+            async move { app.oneshot(req).await.unwrap().status() }
+        };
+        let header = Request::builder()
+            .uri("/test")
+            .header(
+                "X-Sentry-Auth",
+                format!("Sentry sentry_version=7, sentry_key={api_key}, sentry_client=sentry.ruby/5.17.3"),
+            )
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(header).await, StatusCode::OK);
+
+        let query = Request::builder()
+            .uri(format!("/test?sentry_version=7&sentry_key={api_key}"))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(query).await, StatusCode::OK);
+
+        let wrong = Request::builder()
+            .uri("/test?sentry_key=wrong_key")
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(send(wrong).await, StatusCode::UNAUTHORIZED);
+    }
+
+    #[tokio::test]
+    async fn test_read_token_forbidden_on_ingest() {
+        let pool = create_test_pool();
+        let token =
+            crate::models::api_token::create(&pool, None, "dashboards", TokenScope::Read).unwrap();
+
+        assert_eq!(
+            status_for(create_app(pool.clone()), &token).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status_for(create_read_app(pool), &token).await,
+            StatusCode::OK
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ingest_token_forbidden_on_read_api() {
+        let pool = create_test_pool();
+        let (_, api_key) = crate::models::project::create(&pool, "Test").unwrap();
+        let token =
+            crate::models::api_token::create(&pool, None, "ci", TokenScope::Ingest).unwrap();
+
+        assert_eq!(
+            status_for(create_read_app(pool.clone()), &token).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(
+            status_for(create_read_app(pool.clone()), &api_key).await,
+            StatusCode::FORBIDDEN
+        );
+        assert_eq!(status_for(create_app(pool), &token).await, StatusCode::OK);
+    }
+
+    fn signed_request(timestamp: i64, signed_body: &str, body: &str) -> Request<Body> {
+        use hmac::{Hmac, Mac};
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(b"s3cret").unwrap();
+        mac.update(format!("{}.{}", timestamp, signed_body).as_bytes());
+        Request::builder()
+            .method("POST")
+            .uri("/ingest")
+            .header(PROJECT_HEADER, "shop")
+            .header(TIMESTAMP_HEADER, timestamp.to_string())
+            .header(
+                SIGNATURE_HEADER,
+                format!("sha256={}", hex::encode(mac.finalize().into_bytes())),
+            )
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_signed_requests_need_a_fresh_signature_over_the_body() {
+        let pool = crate::db::test_pool();
+        let (shop, _) = project::create(&pool, "Shop").unwrap();
+        async fn echo(body: String) -> String {
+            body
+        }
+        let app = Router::new()
+            .route("/ingest", axum::routing::post(echo))
+            .layer(middleware::from_fn_with_state(
+                pool.clone(),
+                auth_middleware,
+            ))
+            .with_state(pool.clone());
+        let now = Utc::now().timestamp();
+        let body = r#"{"requests": []}"#;
+
+        // Not accepted until the project has a secret
+        let response = app
+            .clone()
+            .oneshot(signed_request(now, body, body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        project::set_signing_secret(&pool, shop.id, Some("s3cret")).unwrap();
+
+        // The handler still gets the body
+        let response = app
+            .clone()
+            .oneshot(signed_request(now, body, body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            response
+                .extensions()
+                .get::<ProjectContext>()
+                .unwrap()
+                .project_id,
+            Some(shop.id)
+        );
+        let echoed = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        assert_eq!(echoed, body.as_bytes());
+
+        let stale = now - SIGNATURE_MAX_AGE_SECS - 1;
+        let response = app
+            .clone()
+            .oneshot(signed_request(stale, body, body))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let tampered = r#"{"requests": [{"path": "/"}]}"#;
+        let response = app
+            .oneshot(signed_request(now, body, tampered))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+    }
 }
@@ -0,0 +1,363 @@
+use axum::{
+    Extension,
+    body::Bytes,
+    extract::State,
+    http::{HeaderMap, StatusCode},
+    response::{IntoResponse, Response},
+};
+use hmac::{Hmac, Mac};
+use serde::Deserialize;
+use sha2::Sha256;
+
+use crate::{
+    DbPool,
+    api::{
+        auth::ProjectContext,
+        ingest::{Accepted, failure_response},
+    },
+    config::SharedConfig,
+    models::{deploy, project},
+};
+
+/// Subset of a GitHub webhook payload shared by the events we record
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GithubEvent {
+    pub action: Option<String>,
+    pub deployment: Option<GithubDeployment>,
+    pub deployment_status: Option<GithubDeploymentStatus>,
+    pub release: Option<GithubRelease>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GithubDeployment {
+    pub sha: String,
+    #[serde(rename = "ref")]
+    pub git_ref: Option<String>,
+    pub environment: Option<String>,
+    pub description: Option<String>,
+    pub creator: Option<GithubUser>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GithubDeploymentStatus {
+    pub state: String,
+    pub created_at: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GithubRelease {
+    pub tag_name: String,
+    /// Branch or commit the tag was cut from
+    pub target_commitish: String,
+    pub name: Option<String>,
+    pub author: Option<GithubUser>,
+    pub published_at: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct GithubUser {
+    pub login: String,
+}
+
+impl GithubEvent {
+    /// Map a `deployment_status` that reached `success` or a `published`
+    /// release. `None` for every other event, including `ping`.
+    pub fn into_deploy(self, event: &str) -> Option<deploy::IncomingDeploy> {
+        match event {
+            "deployment_status" => {
+                let status = self.deployment_status?;
+                if status.state != "success" {
+                    return None;
+                }
+                let deployment = self.deployment?;
+                // `ref` is the branch or tag deployed; it adds nothing when it's the sha
+                let version = deployment.git_ref.filter(|r| *r != deployment.sha);
+                Some(deploy::IncomingDeploy {
+                    git_sha: deployment.sha,
+                    version,
+                    env: deployment.environment,
+                    description: deployment.description.filter(|d| !d.is_empty()),
+                    deployer: deployment.creator.map(|u| u.login),
+                    timestamp: status.created_at,
+                })
+            }
+            "release" if self.action.as_deref() == Some("published") => {
+                let release = self.release?;
+                // `target_commitish` is usually a branch; only a full sha is
+                // worth matching errors against
+                let is_sha = release.target_commitish.len() == 40
+                    && release
+                        .target_commitish
+                        .bytes()
+                        .all(|b| b.is_ascii_hexdigit());
+                let version = if release.tag_name.is_empty() {
+                    release.target_commitish.clone()
+                } else {
+                    release.tag_name
+                };
+                Some(deploy::IncomingDeploy {
+                    git_sha: if is_sha {
+                        release.target_commitish
+                    } else {
+                        String::new()
+                    },
+                    version: Some(version),
+                    env: None,
+                    description: release.name.filter(|n| !n.is_empty()),
+                    deployer: release.author.map(|u| u.login),
+                    timestamp: release.published_at,
+                })
+            }
+            _ => None,
+        }
+    }
+}
+
+/// Check an `X-Hub-Signature-256: sha256=<hex>` header, the HMAC-SHA256 of the
+/// raw body keyed with the webhook secret, in constant time
+pub fn signature_matches(secret: &str, body: &[u8], signature: &str) -> bool {
+    let Some(expected) = signature
+        .strip_prefix("sha256=")
+        .and_then(|hex_digest| hex::decode(hex_digest).ok())
+    else {
+        return false;
+    };
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(secret.as_bytes()).expect("HMAC accepts any key length");
+    mac.update(body);
+    mac.verify_slice(&expected).is_ok()
+}
+
+/// Project whose secret signed the body. `global_secret`
+/// (`DEPLOY_WEBHOOK_SECRET`) is tried last and records deploys without a project.
+fn signing_project(
+    pool: &DbPool,
+    body: &[u8],
+    signature: &str,
+    global_secret: Option<String>,
+) -> anyhow::Result<Option<Option<i64>>> {
+    let project_secrets = project::webhook_secrets(pool)?
+        .into_iter()
+        .map(|(id, secret)| (Some(id), secret));
+    Ok(project_secrets
+        .chain(global_secret.map(|secret| (None, secret)))
+        .find(|(_, secret)| signature_matches(secret, body, signature))
+        .map(|(project_id, _)| project_id))
+}
+
+/// `POST /ingest/deploys/github`. GitHub can't send an API key, so the request
+/// is authenticated by its signature alone.
+pub async fn ingest_deploy(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    headers: HeaderMap,
+    body: Bytes,
+) -> Response {
+    let Some(signature) = headers
+        .get("x-hub-signature-256")
+        .and_then(|h| h.to_str().ok())
+    else {
+        return StatusCode::UNAUTHORIZED.into_response();
+    };
+    let global_secret = config.load().deploy_webhook_secret.clone();
+    let project_id = match signing_project(&pool, &body, signature, global_secret) {
+        Ok(Some(project_id)) => project_id,
+        Ok(None) => return StatusCode::UNAUTHORIZED.into_response(),
+        Err(e) => return failure_response("Failed to load webhook secrets", e),
+    };
+    let ctx = Extension(ProjectContext { project_id });
+
+    let event_name = headers
+        .get("x-github-event")
+        .and_then(|h| h.to_str().ok())
+        .unwrap_or_default();
+    let event: GithubEvent = match serde_json::from_slice(&body) {
+        Ok(event) => event,
+        Err(e) => {
+            tracing::debug!("Rejected GitHub webhook: {}", e);
+            return (StatusCode::BAD_REQUEST, ctx).into_response();
+        }
+    };
+    let Some(incoming) = event.into_deploy(event_name) else {
+        return (StatusCode::NO_CONTENT, ctx).into_response();
+    };
+
+    match deploy::insert(&pool, &incoming, project_id) {
+        Ok(id) => {
+            tracing::info!(
+                "Recorded GitHub {} deploy id={} git_sha={} (project_id={:?})",
+                event_name,
+                id,
+                incoming.git_sha,
+                project_id
+            );
+            (StatusCode::ACCEPTED, ctx, Extension(Accepted(1))).into_response()
+        }
+        Err(e) => (ctx, failure_response("Failed to record deploy", e)).into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, body::Body, http::Request, routing::post};
+    use tower::util::ServiceExt;
+
+    const FIXTURE: &str = include_str!("../../tests/fixtures/github_deployment_status.json");
+
+    fn sign(secret: &str, body: &[u8]) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(body);
+        format!("sha256={}", hex::encode(mac.finalize().into_bytes()))
+    }
+
+    fn app(pool: &DbPool, config: crate::config::Config) -> Router {
+        Router::new()
+            .route("/ingest/deploys/github", post(ingest_deploy))
+            .layer(Extension(std::sync::Arc::new(
+                arc_swap::ArcSwap::from_pointee(config),
+            )))
+            .with_state(pool.clone())
+    }
+
+    fn webhook(event: &str, signature: &str, body: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/ingest/deploys/github")
+            .header("content-type", "application/json")
+            .header("x-github-event", event)
+            .header("x-hub-signature-256", signature)
+            .body(Body::from(body.to_string()))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_deployment_status_maps_to_deploy() {
+        let event: GithubEvent = serde_json::from_str(FIXTURE).unwrap();
+        let deploy = event.into_deploy("deployment_status").unwrap();
+        assert_eq!(deploy.git_sha, "9f2c1e4b7a6d3e8f1c2b5a4d7e6f9a8b7c6d5e4f");
+        assert_eq!(deploy.version.as_deref(), Some("v2.4.0"));
+        assert_eq!(deploy.env.as_deref(), Some("production"));
+        assert_eq!(deploy.deployer.as_deref(), Some("octocat"));
+        assert_eq!(deploy.timestamp.as_deref(), Some("2026-06-01T10:05:00Z"));
+
+        // Pending and failed deployments aren't deploys yet
+        let pending = FIXTURE.replace(r#""state": "success""#, r#""state": "pending""#);
+        let event: GithubEvent = serde_json::from_str(&pending).unwrap();
+        assert!(event.into_deploy("deployment_status").is_none());
+    }
+
+    #[test]
+    fn test_published_release_maps_to_deploy() {
+        let event: GithubEvent = serde_json::from_value(serde_json::json!({
+            "action": "published",
+            "release": {
+                "tag_name": "v2.5.0",
+                "target_commitish": "4e1d2c3b9a8f7e6d5c4b3a2f1e0d9c8b7a6f5e4d",
+                "name": "Spring release",
+                "author": { "login": "hubot" },
+                "published_at": "2026-06-02T09:00:00Z"
+            }
+        }))
+        .unwrap();
+        let deploy = event.into_deploy("release").unwrap();
+        assert_eq!(deploy.git_sha, "4e1d2c3b9a8f7e6d5c4b3a2f1e0d9c8b7a6f5e4d");
+        assert_eq!(deploy.version.as_deref(), Some("v2.5.0"));
+        assert_eq!(deploy.description.as_deref(), Some("Spring release"));
+        assert_eq!(deploy.deployer.as_deref(), Some("hubot"));
+
+        // Releases usually target a branch, which isn't a commit
+        let branch: GithubEvent = serde_json::from_value(serde_json::json!({
+            "action": "published",
+            "release": {
+                "tag_name": "v2.6.0",
+                "target_commitish": "main",
+                "published_at": "2026-07-01T09:00:00Z"
+            }
+        }))
+        .unwrap();
+        let deploy = branch.into_deploy("release").unwrap();
+        assert_eq!(deploy.git_sha, "");
+        assert_eq!(deploy.version.as_deref(), Some("v2.6.0"));
+
+        let draft: GithubEvent =
+            serde_json::from_value(serde_json::json!({ "action": "created" })).unwrap();
+        assert!(draft.into_deploy("release").is_none());
+    }
+
+    #[tokio::test]
+    async fn test_webhook_requires_valid_signature() {
+        let pool = crate::db::test_pool();
+        let shop = project::ensure_default_project(&pool).unwrap();
+        project::set_webhook_secret(&pool, shop.id, Some("s3cret")).unwrap();
+        let app = app(&pool, crate::config::Config::defaults());
+
+        let forged = sign("wrong", FIXTURE.as_bytes());
+        let response = app
+            .clone()
+            .oneshot(webhook("deployment_status", &forged, FIXTURE))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        // Tampering with the body invalidates a correct signature
+        let signature = sign("s3cret", FIXTURE.as_bytes());
+        let tampered = FIXTURE.replace("production", "staging");
+        let response = app
+            .clone()
+            .oneshot(webhook("deployment_status", &signature, &tampered))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        assert!(deploy::latest(&pool, None).unwrap().is_none());
+
+        let response = app
+            .clone()
+            .oneshot(webhook("deployment_status", &signature, FIXTURE))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        let recorded = deploy::latest(&pool, Some(shop.id)).unwrap().unwrap();
+        assert_eq!(recorded.short_sha(), "9f2c1e4");
+        assert_eq!(recorded.env.as_deref(), Some("production"));
+
+        // GitHub pings a new webhook once; acknowledged but not recorded
+        let ping = r#"{"zen": "Keep it logically awesome."}"#;
+        let response = app
+            .oneshot(webhook("ping", &sign("s3cret", ping.as_bytes()), ping))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NO_CONTENT);
+    }
+
+    #[tokio::test]
+    async fn test_configured_secret_records_deploys_without_a_project() {
+        let pool = crate::db::test_pool();
+        let signature = sign("global", FIXTURE.as_bytes());
+
+        let unset = app(&pool, crate::config::Config::defaults());
+        let response = unset
+            .oneshot(webhook("deployment_status", &signature, FIXTURE))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+
+        let configured = app(
+            &pool,
+            crate::config::Config {
+                deploy_webhook_secret: Some("global".into()),
+                ..crate::config::Config::defaults()
+            },
+        );
+        let response = configured
+            .oneshot(webhook("deployment_status", &signature, FIXTURE))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::ACCEPTED);
+        assert!(deploy::latest(&pool, None).unwrap().is_some());
+    }
+}
@@ -1,31 +1,74 @@
-use axum::{Extension, Json, extract::State, http::StatusCode};
-use serde::Deserialize;
+use axum::{
+    Extension, Json,
+    body::{Body, Bytes},
+    extract::{MatchedPath, State},
+    http::{HeaderValue, Request, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, Serialize};
+use std::time::{Instant, SystemTime, UNIX_EPOCH};
+use tracing::Instrument;
 
 use crate::{
     DbPool,
     api::auth::ProjectContext,
-    models::{deploy, error as app_error, span},
+    config::SharedConfig,
+    db,
+    models::{deploy, error as app_error, log_record, project, request, span},
 };
 
+/// Seconds clients are asked to wait before retrying when the database pool
+/// is exhausted
+const RETRY_AFTER_SECS: u64 = 1;
+
+/// Header clients send to say which ingest payload format they speak
+pub const SCHEMA_HEADER: &str = "x-miniapm-schema";
+
+/// Payload format versions this server parses. Requests without
+/// `X-MiniAPM-Schema` are taken to be the oldest.
+pub const SUPPORTED_SCHEMA: std::ops::RangeInclusive<u32> = 1..=1;
+
+/// Items an ingest handler stored, attached to its response for `log_ingest`
+#[derive(Debug, Clone, Copy)]
+pub struct Accepted(pub usize);
+
 #[derive(Debug, Deserialize)]
 pub struct IncomingErrorBatch {
     pub errors: Vec<app_error::IncomingError>,
 }
 
+/// Responds 200 with accepted/rejected counts even when some spans were
+/// dropped, and 400 when every span in a non-empty batch was rejected
 pub async fn ingest_spans(
     State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
     Extension(ctx): Extension<ProjectContext>,
     Json(otlp_request): Json<span::OtlpTraceRequest>,
-) -> StatusCode {
-    match span::insert_otlp_batch(&pool, &otlp_request, ctx.project_id) {
-        Ok(count) => {
-            tracing::debug!("Ingested {} spans (project_id={:?})", count, ctx.project_id);
-            StatusCode::ACCEPTED
-        }
-        Err(e) => {
-            tracing::error!("Failed to ingest spans: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+) -> Result<(StatusCode, Extension<Accepted>, Json<span::IngestOutcome>), Response> {
+    let options = span::IngestOptions::from_config(&config.load());
+    let project_id = ctx.project_id;
+    let stored = db::blocking(move || {
+        span::insert_otlp_batch_with(&pool, &otlp_request, project_id, &options)
+    })
+    .await;
+    match stored {
+        Ok(outcome) => {
+            tracing::debug!(
+                "Ingested {} spans, {} rejected, {} errors extracted (project_id={:?})",
+                outcome.accepted,
+                outcome.rejected,
+                outcome.errors_extracted,
+                ctx.project_id
+            );
+            let status = if outcome.accepted == 0 && outcome.rejected > 0 {
+                StatusCode::BAD_REQUEST
+            } else {
+                StatusCode::OK
+            };
+            Ok((status, Extension(Accepted(outcome.accepted)), Json(outcome)))
         }
+        Err(e) => Err(failure_response("Failed to ingest spans", e)),
     }
 }
 
@@ -33,63 +76,81 @@ pub async fn ingest_deploys(
     State(pool): State<DbPool>,
     Extension(ctx): Extension<ProjectContext>,
     Json(incoming): Json<deploy::IncomingDeploy>,
-) -> StatusCode {
-    match deploy::insert(&pool, &incoming, ctx.project_id) {
+) -> Response {
+    let git_sha = incoming.git_sha.clone();
+    let project_id = ctx.project_id;
+    match db::blocking(move || deploy::insert(&pool, &incoming, project_id)).await {
         Ok(id) => {
             tracing::info!(
                 "Recorded deploy id={} git_sha={} (project_id={:?})",
                 id,
-                incoming.git_sha,
+                git_sha,
                 ctx.project_id
             );
-            StatusCode::ACCEPTED
-        }
-        Err(e) => {
-            tracing::error!("Failed to record deploy: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            (StatusCode::ACCEPTED, Extension(Accepted(1))).into_response()
         }
+        Err(e) => failure_response("Failed to record deploy", e),
     }
 }
 
 pub async fn ingest_errors(
     State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
     Extension(ctx): Extension<ProjectContext>,
     Json(incoming): Json<app_error::IncomingError>,
-) -> StatusCode {
-    match app_error::insert(&pool, &incoming, ctx.project_id) {
+) -> Response {
+    let max_per_hour = config.load().max_occurrences_per_hour;
+    let exception_class = incoming.exception_class.clone();
+    let project_id = ctx.project_id;
+    let stored =
+        db::blocking(move || app_error::insert_sampled(&pool, &incoming, project_id, max_per_hour))
+            .await;
+    match stored {
         Ok(id) => {
             tracing::debug!(
                 "Recorded error id={} class={} (project_id={:?})",
                 id,
-                incoming.exception_class,
+                exception_class,
                 ctx.project_id
             );
-            StatusCode::ACCEPTED
-        }
-        Err(e) => {
-            tracing::error!("Failed to record error: {}", e);
-            StatusCode::INTERNAL_SERVER_ERROR
+            (StatusCode::ACCEPTED, Extension(Accepted(1))).into_response()
         }
+        Err(e) => failure_response("Failed to record error", e),
     }
 }
 
 pub async fn ingest_errors_batch(
     State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
     Extension(ctx): Extension<ProjectContext>,
     Json(batch): Json<IncomingErrorBatch>,
-) -> StatusCode {
-    let mut success_count = 0;
-    let mut error_count = 0;
-
-    for error in batch.errors {
-        match app_error::insert(&pool, &error, ctx.project_id) {
-            Ok(_) => success_count += 1,
-            Err(e) => {
-                tracing::warn!("Failed to record error: {}", e);
-                error_count += 1;
+) -> Response {
+    let max_per_hour = config.load().max_occurrences_per_hour;
+    let project_id = ctx.project_id;
+    let counts = db::blocking(move || {
+        let mut success_count = 0;
+        let mut error_count = 0;
+        for error in batch.errors {
+            match app_error::insert_sampled(&pool, &error, project_id, max_per_hour) {
+                Ok(_) => success_count += 1,
+                // The rest of the batch would only queue up behind it
+                Err(e) if db::is_pool_exhausted(&e) && success_count == 0 => return Err(e),
+                Err(e) => {
+                    tracing::warn!("Failed to record error: {}", e);
+                    error_count += 1;
+                    if db::is_pool_exhausted(&e) {
+                        break;
+                    }
+                }
             }
         }
-    }
+        Ok((success_count, error_count))
+    })
+    .await;
+    let (success_count, error_count) = match counts {
+        Ok(counts) => counts,
+        Err(e) => return failure_response("Failed to record errors", e),
+    };
 
     tracing::debug!(
         "Ingested {} errors, {} failed (project_id={:?})",
@@ -98,9 +159,328 @@ pub async fn ingest_errors_batch(
         ctx.project_id
     );
 
-    if error_count > 0 && success_count == 0 {
+    let status = if error_count > 0 && success_count == 0 {
         StatusCode::INTERNAL_SERVER_ERROR
     } else {
         StatusCode::ACCEPTED
+    };
+    (status, Extension(Accepted(success_count))).into_response()
+}
+
+/// Per-batch outcome of `ingest_logs`
+#[derive(Debug, Default, Serialize)]
+pub struct LogIngestOutcome {
+    pub accepted: usize,
+    /// Lines that weren't JSON or lacked method, path, status or duration
+    pub rejected: usize,
+}
+
+/// Lograge JSON lines, one per line or as a JSON array, recorded as requests.
+/// Responds like `ingest_spans`: 200 with counts, 400 when nothing was usable.
+pub async fn ingest_logs(
+    State(pool): State<DbPool>,
+    Extension(ctx): Extension<ProjectContext>,
+    body: Bytes,
+) -> Result<(StatusCode, Extension<Accepted>, Json<LogIngestOutcome>), Response> {
+    let body = String::from_utf8_lossy(&body);
+    let lines: Vec<serde_json::Value> = if body.trim_start().starts_with('[') {
+        serde_json::from_str(&body).map_err(|_| StatusCode::BAD_REQUEST.into_response())?
+    } else {
+        body.lines()
+            .filter(|line| !line.trim().is_empty())
+            .map(|line| serde_json::from_str(line).unwrap_or(serde_json::Value::Null))
+            .collect()
+    };
+
+    let batch = request::RequestBatch {
+        requests: lines
+            .iter()
+            .filter_map(request::IncomingRequest::from_lograge)
+            .collect(),
+    };
+    let outcome = LogIngestOutcome {
+        accepted: batch.requests.len(),
+        rejected: lines.len() - batch.requests.len(),
+    };
+
+    let project_id = ctx.project_id;
+    if let Err(e) = db::blocking(move || request::insert_batch(&pool, &batch, project_id)).await {
+        return Err(failure_response("Failed to ingest logs", e));
+    }
+    tracing::debug!(
+        "Ingested {} log lines, {} rejected (project_id={:?})",
+        outcome.accepted,
+        outcome.rejected,
+        ctx.project_id
+    );
+    let status = if outcome.accepted == 0 && outcome.rejected > 0 {
+        StatusCode::BAD_REQUEST
+    } else {
+        StatusCode::OK
+    };
+    Ok((status, Extension(Accepted(outcome.accepted)), Json(outcome)))
+}
+
+/// OTLP/JSON log records. Responds like `ingest_spans`: 200 with counts, 400
+/// when every record in a non-empty batch was rejected.
+pub async fn ingest_otlp_logs(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(ctx): Extension<ProjectContext>,
+    Json(logs): Json<log_record::OtlpLogsRequest>,
+) -> Result<
+    (
+        StatusCode,
+        Extension<Accepted>,
+        Json<log_record::LogIngestOutcome>,
+    ),
+    Response,
+> {
+    let options = span::IngestOptions::from_config(&config.load());
+    let project_id = ctx.project_id;
+    let stored =
+        db::blocking(move || log_record::insert_otlp_batch(&pool, &logs, project_id, &options))
+            .await;
+    match stored {
+        Ok(outcome) => {
+            tracing::debug!(
+                "Ingested {} log records, {} rejected (project_id={:?})",
+                outcome.accepted,
+                outcome.rejected,
+                ctx.project_id
+            );
+            let status = if outcome.accepted == 0 && outcome.rejected > 0 {
+                StatusCode::BAD_REQUEST
+            } else {
+                StatusCode::OK
+            };
+            Ok((status, Extension(Accepted(outcome.accepted)), Json(outcome)))
+        }
+        Err(e) => Err(failure_response("Failed to ingest log records", e)),
+    }
+}
+
+/// 503 with `Retry-After` when no database connection freed up in time, so
+/// clients back off instead of retrying straight away; 500 for anything else
+pub fn failure_response(context: &str, e: anyhow::Error) -> Response {
+    if db::is_pool_exhausted(&e) {
+        tracing::warn!("{}: database pool exhausted", context);
+        return (
+            StatusCode::SERVICE_UNAVAILABLE,
+            [(header::RETRY_AFTER, RETRY_AFTER_SECS.to_string())],
+        )
+            .into_response();
+    }
+    tracing::error!("{}: {}", context, e);
+    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+}
+
+/// Reject ingest requests in a payload format this server doesn't know with
+/// a 400 naming the supported range, before their body is parsed under the
+/// wrong assumptions
+pub async fn check_schema_version(request: Request<Body>, next: Next) -> Response {
+    let Some(value) = request.headers().get(SCHEMA_HEADER) else {
+        return next.run(request).await;
+    };
+    let sent = value.to_str().unwrap_or_default().trim();
+    match sent.parse::<u32>() {
+        Ok(version) if SUPPORTED_SCHEMA.contains(&version) => next.run(request).await,
+        _ => {
+            tracing::debug!("Rejected ingest request with schema version {:?}", sent);
+            (
+                StatusCode::BAD_REQUEST,
+                Json(serde_json::json!({
+                    "error": "unsupported schema version",
+                    "version": sent,
+                    "supported": {
+                        "min": SUPPORTED_SCHEMA.start(),
+                        "max": SUPPORTED_SCHEMA.end(),
+                    },
+                })),
+            )
+                .into_response()
+        }
+    }
+}
+
+/// Replace the plain-text 413 from the body limit with a JSON body telling
+/// clients the cap, so exporters can log something actionable
+pub async fn payload_too_large_json(
+    State(max_body_bytes): State<usize>,
+    response: Response,
+) -> Response {
+    if response.status() != StatusCode::PAYLOAD_TOO_LARGE {
+        return response;
+    }
+    (
+        StatusCode::PAYLOAD_TOO_LARGE,
+        Json(serde_json::json!({
+            "error": "request body too large",
+            "max_body_bytes": max_body_bytes,
+        })),
+    )
+        .into_response()
+}
+
+/// Run an ingest request inside an `ingest` span and emit one structured
+/// event when it completes: project, body size, items stored, status and
+/// duration. The request id comes from `X-Request-Id` when the client sent
+/// one and is echoed back either way, so both sides' logs can be matched up.
+/// The project is the `ProjectContext` on the response, which the auth
+/// middleware and signature-checked handlers attach.
+pub async fn log_ingest(
+    State(pool): State<DbPool>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let request_id = request
+        .headers()
+        .get("x-request-id")
+        .and_then(|h| h.to_str().ok())
+        .filter(|id| !id.is_empty())
+        .map(str::to_string)
+        .unwrap_or_else(|| hex::encode(rand::random::<[u8; 8]>()));
+    let path = request.uri().path().to_string();
+    let bytes: u64 = request
+        .headers()
+        .get(header::CONTENT_LENGTH)
+        .and_then(|h| h.to_str().ok()?.parse().ok())
+        .unwrap_or(0);
+
+    let span = tracing::info_span!("ingest", request_id = %request_id, path = %path);
+    let mut response = next.run(request).instrument(span.clone()).await;
+
+    let project = response
+        .extensions()
+        .get::<ProjectContext>()
+        .and_then(|ctx| ctx.project_id)
+        .and_then(|id| project::find(&pool, id).ok().flatten())
+        .map(|p| p.slug)
+        .unwrap_or_else(|| "-".to_string());
+    let accepted = response.extensions().get::<Accepted>().map_or(0, |a| a.0);
+    span.in_scope(|| {
+        tracing::info!(
+            project = %project,
+            bytes,
+            accepted,
+            status = response.status().as_u16(),
+            duration_ms = started.elapsed().as_secs_f64() * 1000.0,
+            "Handled ingest request"
+        );
+    });
+
+    if let Ok(value) = HeaderValue::from_str(&request_id) {
+        response.headers_mut().insert("x-request-id", value);
+    }
+    response
+}
+
+/// State for `trace_ingest`: where MiniAPM's own spans are stored
+#[derive(Clone)]
+pub struct SelfTrace {
+    pub pool: DbPool,
+    /// The reserved internal project
+    pub project_id: i64,
+}
+
+/// With `SELF_TRACE` on, record every ingest request as a server span in the
+/// internal project. The span is written straight to the database rather
+/// than posted to `/ingest`, and requests already aimed at the internal
+/// project are skipped, so self-tracing never traces itself. So are requests
+/// that never authenticated, which would otherwise let anyone write spans
+/// into the internal project.
+pub async fn trace_ingest(
+    State(self_trace): State<SelfTrace>,
+    request: Request<Body>,
+    next: Next,
+) -> Response {
+    let method = request.method().to_string();
+    // The route template rather than the raw path, which is caller-controlled
+    let Some(route) = request
+        .extensions()
+        .get::<MatchedPath>()
+        .map(|path| path.as_str().to_string())
+    else {
+        return next.run(request).await;
+    };
+    let started = unix_nanos();
+    let response = next.run(request).await;
+    let ended = unix_nanos();
+
+    let Some(ctx) = response.extensions().get::<ProjectContext>() else {
+        return response;
+    };
+    let project_id = ctx.project_id;
+    if project_id == Some(self_trace.project_id)
+        || matches!(
+            response.status(),
+            StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+        )
+    {
+        return response;
+    }
+    let accepted = response.extensions().get::<Accepted>().map_or(0, |a| a.0);
+
+    let request = self_trace_request(
+        &method,
+        &route,
+        response.status(),
+        project_id,
+        accepted,
+        (started, ended),
+    );
+    let stored = db::blocking(move || {
+        span::insert_otlp_batch(&self_trace.pool, &request, Some(self_trace.project_id))
+    })
+    .await;
+    if let Err(e) = stored {
+        tracing::warn!("Failed to record self-trace span: {}", e);
     }
+    response
+}
+
+fn unix_nanos() -> u128 {
+    SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_nanos())
+}
+
+/// One OTLP server span describing an ingest request MiniAPM handled
+fn self_trace_request(
+    method: &str,
+    route: &str,
+    status: StatusCode,
+    project_id: Option<i64>,
+    accepted: usize,
+    (started, ended): (u128, u128),
+) -> span::OtlpTraceRequest {
+    let payload = serde_json::json!({
+        "resourceSpans": [{
+            "resource": {
+                "attributes": [
+                    {"key": "service.name", "value": {"stringValue": "miniapm"}},
+                ],
+            },
+            "scopeSpans": [{
+                "spans": [{
+                    "traceId": hex::encode(rand::random::<[u8; 16]>()),
+                    "spanId": hex::encode(rand::random::<[u8; 8]>()),
+                    "name": format!("{} {}", method, route),
+                    "kind": 2,
+                    "startTimeUnixNano": started.to_string(),
+                    "endTimeUnixNano": ended.to_string(),
+                    "attributes": [
+                        {"key": "http.method", "value": {"stringValue": method}},
+                        {"key": "http.route", "value": {"stringValue": route}},
+                        {"key": "http.status_code", "value": {"intValue": status.as_u16().to_string()}},
+                        {"key": "miniapm.project_id", "value": {"intValue": project_id.unwrap_or_default().to_string()}},
+                        {"key": "miniapm.accepted", "value": {"intValue": accepted.to_string()}},
+                    ],
+                    "status": {"code": if status.is_server_error() { 2 } else { 1 }},
+                }],
+            }],
+        }],
+    });
+    serde_json::from_value(payload).expect("self-trace payload matches the OTLP types")
 }
@@ -1,7 +1,12 @@
 pub mod auth;
+pub mod github;
 pub mod health;
 pub mod ingest;
+pub mod read;
+pub mod sentry;
 
-pub use auth::{ProjectContext, auth_middleware};
+pub use auth::{ProjectContext, auth_middleware, read_auth_middleware, sentry_auth_middleware};
 pub use health::health_handler;
-pub use ingest::{ingest_deploys, ingest_errors, ingest_errors_batch, ingest_spans};
+pub use ingest::{
+    ingest_deploys, ingest_errors, ingest_errors_batch, ingest_logs, ingest_otlp_logs, ingest_spans,
+};
@@ -0,0 +1,197 @@
+use axum::{
+    Extension, Json,
+    extract::{Query, State},
+    http::StatusCode,
+};
+use chrono::{Duration, Utc};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    DbPool,
+    api::auth::ProjectContext,
+    config::SharedConfig,
+    models::{AppError, TraceSummary, error as app_error, project, span},
+    web::time_range::TimeRange,
+};
+
+const MAX_LIMIT: i64 = 500;
+
+#[derive(Debug, Deserialize)]
+pub struct ErrorsQuery {
+    pub status: Option<String>,
+    pub limit: Option<i64>,
+}
+
+pub async fn list_errors(
+    State(pool): State<DbPool>,
+    Extension(ctx): Extension<ProjectContext>,
+    Query(query): Query<ErrorsQuery>,
+) -> Result<Json<Vec<AppError>>, StatusCode> {
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_LIMIT);
+    app_error::list(&pool, ctx.project_id, query.status.as_deref(), limit)
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to list errors: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct TracesQuery {
+    pub limit: Option<i64>,
+}
+
+pub async fn list_traces(
+    State(pool): State<DbPool>,
+    Extension(ctx): Extension<ProjectContext>,
+    Query(query): Query<TracesQuery>,
+) -> Result<Json<Vec<TraceSummary>>, StatusCode> {
+    let limit = query.limit.unwrap_or(50).clamp(1, MAX_LIMIT);
+    span::list_traces(&pool, ctx.project_id, None, limit)
+        .map(Json)
+        .map_err(|e| {
+            tracing::error!("Failed to list traces: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// `minute`, `hour`, `day` or `auto` (the default)
+    pub resolution: Option<String>,
+    /// Only count root spans from this `deployment.environment`
+    pub environment: Option<String>,
+    /// Only count root spans from this `service.name`
+    pub service: Option<String>,
+}
+
+#[derive(Debug, Serialize)]
+pub struct StatsResponse {
+    pub resolution: span::Resolution,
+    pub points: Vec<span::ChartPoint>,
+}
+
+/// `GET /api/stats`: request volume, latency and errors over a time range,
+/// downsampled to at most `span::MAX_CHART_POINTS` buckets, optionally
+/// scoped to one environment and/or service
+pub async fn stats(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(ctx): Extension<ProjectContext>,
+    Query(query): Query<StatsQuery>,
+) -> Result<Json<StatsResponse>, StatusCode> {
+    let requested = match query.resolution.as_deref() {
+        None | Some("" | "auto") => None,
+        Some(value) => Some(span::Resolution::parse(value).ok_or(StatusCode::BAD_REQUEST)?),
+    };
+    let range = TimeRange::parse(
+        query.range.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        "24h",
+    );
+    let until = range.until.unwrap_or_else(Utc::now);
+    let since = range
+        .since
+        .unwrap_or(until - Duration::days(span::MAX_CHART_POINTS));
+    let environment = query.environment.as_deref().filter(|v| !v.is_empty());
+    let service = query.service.as_deref().filter(|v| !v.is_empty());
+    span::chart_stats(
+        &pool,
+        ctx.project_id,
+        environment,
+        service,
+        None,
+        since,
+        until,
+        requested,
+        project::error_policy(&pool, ctx.project_id, config.load().error_status_policy),
+    )
+    .map(|(resolution, points)| Json(StatsResponse { resolution, points }))
+    .map_err(|e| {
+        tracing::error!("Failed to load chart stats: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct HotspotsQuery {
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    /// How many of the most recent traces in the range to scan
+    pub traces: Option<i64>,
+    pub limit: Option<i64>,
+}
+
+/// `GET /api/n-plus-1`: the N+1 query patterns seen most across recent
+/// traces, by route, with the time spent repeating them
+pub async fn n_plus_1_hotspots(
+    State(pool): State<DbPool>,
+    Extension(ctx): Extension<ProjectContext>,
+    Query(query): Query<HotspotsQuery>,
+) -> Result<Json<Vec<span::NPlus1Hotspot>>, StatusCode> {
+    let range = TimeRange::parse(query.range.as_deref(), None, None, "24h");
+    let since = range
+        .since
+        .map(|at| at.format("%Y-%m-%dT%H:%M:%S%.3fZ").to_string())
+        .unwrap_or_default();
+    let traces = query
+        .traces
+        .unwrap_or(span::HOTSPOT_TRACE_SAMPLE)
+        .clamp(1, span::HOTSPOT_TRACE_SAMPLE * 10);
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_LIMIT) as usize;
+    span::n_plus_1_hotspots(&pool, ctx.project_id, &since, traces)
+        .map(|mut hotspots| {
+            hotspots.truncate(limit);
+            Json(hotspots)
+        })
+        .map_err(|e| {
+            tracing::error!("Failed to find N+1 hotspots: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR
+        })
+}
+
+#[derive(Debug, Deserialize)]
+pub struct SlowQueriesQuery {
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    /// Minimum span duration in ms, 0 when omitted
+    pub threshold: Option<f64>,
+    /// `total` (default) or `p95`
+    pub sort: Option<String>,
+    pub limit: Option<i64>,
+}
+
+/// `GET /api/slow-queries`: normalized SQL shapes from recent DB spans with
+/// their table, total and p95 time, ranked by `sort`
+pub async fn slow_queries(
+    State(pool): State<DbPool>,
+    Extension(ctx): Extension<ProjectContext>,
+    Query(query): Query<SlowQueriesQuery>,
+) -> Result<Json<Vec<span::SlowQuery>>, StatusCode> {
+    let range = TimeRange::parse(query.range.as_deref(), None, None, "24h");
+    let order = match query.sort.as_deref().filter(|s| !s.is_empty()) {
+        Some(sort) => span::SlowQueryOrder::parse(sort).ok_or(StatusCode::BAD_REQUEST)?,
+        None => span::SlowQueryOrder::Total,
+    };
+    let threshold_ms = query.threshold.filter(|&t| t >= 0.0).unwrap_or(0.0);
+    let limit = query.limit.unwrap_or(20).clamp(1, MAX_LIMIT) as usize;
+    span::slow_db_spans(
+        &pool,
+        ctx.project_id,
+        threshold_ms,
+        &range.since_str().unwrap_or_default(),
+        None,
+        order,
+        limit,
+    )
+    .map(Json)
+    .map_err(|e| {
+        tracing::error!("Failed to load slow queries: {}", e);
+        StatusCode::INTERNAL_SERVER_ERROR
+    })
+}
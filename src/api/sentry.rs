@@ -0,0 +1,471 @@
+use std::collections::BTreeMap;
+
+use axum::{
+    Extension, Json,
+    body::Bytes,
+    extract::State,
+    http::StatusCode,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+
+use crate::{
+    DbPool,
+    api::{
+        auth::ProjectContext,
+        ingest::{Accepted, failure_response},
+    },
+    config::SharedConfig,
+    db,
+    models::error::{self as app_error, IncomingError, IncomingSourceContext},
+};
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SentryEvent {
+    pub event_id: Option<String>,
+    /// Epoch seconds or an RFC 3339 string
+    pub timestamp: Option<serde_json::Value>,
+    pub release: Option<String>,
+    pub fingerprint: Option<Vec<String>>,
+    /// A map, or a list of `[key, value]` pairs
+    pub tags: Option<serde_json::Value>,
+    pub user: Option<SentryUser>,
+    pub contexts: Option<SentryContexts>,
+    pub exception: Option<SentryExceptions>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SentryUser {
+    pub id: Option<serde_json::Value>,
+    pub email: Option<String>,
+    pub username: Option<String>,
+    pub ip_address: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SentryContexts {
+    pub trace: Option<SentryTraceContext>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SentryTraceContext {
+    pub trace_id: Option<String>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SentryExceptions {
+    /// Chained exceptions, the one actually raised last
+    pub values: Vec<SentryException>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SentryException {
+    #[serde(rename = "type")]
+    pub exception_type: Option<String>,
+    pub value: Option<String>,
+    pub stacktrace: Option<SentryStacktrace>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SentryStacktrace {
+    /// Outermost call first, the raising frame last
+    pub frames: Vec<SentryFrame>,
+}
+
+#[derive(Debug, Default, Deserialize)]
+#[serde(default)]
+pub struct SentryFrame {
+    pub filename: Option<String>,
+    pub abs_path: Option<String>,
+    pub module: Option<String>,
+    pub function: Option<String>,
+    pub lineno: Option<i64>,
+    pub in_app: Option<bool>,
+    pub pre_context: Option<Vec<String>>,
+    pub context_line: Option<String>,
+    pub post_context: Option<Vec<String>>,
+}
+
+impl SentryFrame {
+    /// Ruby-style `file:line:in 'method'`, the shape our grouping expects.
+    /// Library frames keep their absolute path so grouping can skip them.
+    fn backtrace_line(&self) -> String {
+        let (first, second) = if self.in_app == Some(false) {
+            (&self.abs_path, &self.filename)
+        } else {
+            (&self.filename, &self.abs_path)
+        };
+        let file = first
+            .as_deref()
+            .or(second.as_deref())
+            .or(self.module.as_deref())
+            .unwrap_or("?");
+        let function = self.function.as_deref().unwrap_or("?");
+        match self.lineno {
+            Some(line) => format!("{file}:{line}:in `{function}'"),
+            None => format!("{file}:in `{function}'"),
+        }
+    }
+}
+
+/// Placeholder Sentry SDKs put in a custom fingerprint for "the default grouping"
+const DEFAULT_FINGERPRINT: &str = "{{ default }}";
+
+impl SentryEvent {
+    /// Map onto our error payload; `None` for events without an exception
+    /// (plain `capture_message` calls), which have nothing to group on
+    pub fn into_incoming(self) -> Option<IncomingError> {
+        let exception = self.exception?.values.pop()?;
+        let exception_class = exception
+            .exception_type
+            .unwrap_or_else(|| "Error".to_string());
+        let message = exception.value.unwrap_or_default();
+        let frames = exception.stacktrace.map(|s| s.frames).unwrap_or_default();
+
+        let backtrace: Vec<String> = frames
+            .iter()
+            .rev()
+            .map(SentryFrame::backtrace_line)
+            .collect();
+        let source_context = frames
+            .iter()
+            .rev()
+            .filter(|f| f.in_app != Some(false))
+            .find_map(|f| {
+                Some(IncomingSourceContext {
+                    file: f.filename.clone().or_else(|| f.abs_path.clone())?,
+                    lineno: f.lineno?,
+                    pre_context: f.pre_context.clone(),
+                    context_line: f.context_line.clone()?,
+                    post_context: f.post_context.clone(),
+                })
+            });
+
+        let location = app_error::location_fingerprint(&exception_class, &backtrace);
+        let fingerprint = match self.fingerprint {
+            Some(parts) if !parts.is_empty() => parts
+                .iter()
+                .map(|part| {
+                    if part == DEFAULT_FINGERPRINT {
+                        location.as_str()
+                    } else {
+                        part.as_str()
+                    }
+                })
+                .collect::<Vec<_>>()
+                .join(":"),
+            _ => location,
+        };
+
+        let tags = self.tags.map(tag_map).unwrap_or_default();
+        let params = (!tags.is_empty()).then(|| serde_json::json!({ "tags": tags }));
+
+        let user_id = self.user.and_then(|user| {
+            let id = user.id.map(|id| match id {
+                serde_json::Value::String(s) => s,
+                other => other.to_string(),
+            });
+            id.or(user.username).or(user.email).or(user.ip_address)
+        });
+
+        // Only a commit-looking release can be matched against deploys
+        let git_sha = self.release.filter(|release| {
+            (7..=40).contains(&release.len()) && release.chars().all(|c| c.is_ascii_hexdigit())
+        });
+
+        Some(IncomingError {
+            exception_class,
+            message,
+            backtrace,
+            fingerprint,
+            request_id: self.contexts.and_then(|c| c.trace?.trace_id),
+            user_id,
+            params,
+            timestamp: self.timestamp.and_then(timestamp),
+            source_context,
+            git_sha,
+        })
+    }
+}
+
+/// Tags arrive either as an object or as `[key, value]` pairs
+fn tag_map(tags: serde_json::Value) -> BTreeMap<String, String> {
+    let text = |value: &serde_json::Value| match value {
+        serde_json::Value::String(s) => s.clone(),
+        other => other.to_string(),
+    };
+    match tags {
+        serde_json::Value::Object(map) => map.iter().map(|(k, v)| (k.clone(), text(v))).collect(),
+        serde_json::Value::Array(pairs) => pairs
+            .iter()
+            .filter_map(|pair| match pair.as_array()?.as_slice() {
+                [key, value] => Some((key.as_str()?.to_string(), text(value))),
+                _ => None,
+            })
+            .collect(),
+        _ => BTreeMap::new(),
+    }
+}
+
+fn timestamp(value: serde_json::Value) -> Option<String> {
+    match value {
+        serde_json::Value::Number(n) => {
+            let millis = (n.as_f64()? * 1000.0) as i64;
+            Some(DateTime::<Utc>::from_timestamp_millis(millis)?.to_rfc3339())
+        }
+        serde_json::Value::String(s) => Some(s),
+        _ => None,
+    }
+}
+
+/// Pull the `event` items out of an envelope: a header line, then items that
+/// are each a header line followed by a payload of `length` bytes, or running
+/// to the next newline when the length is left out
+pub fn parse_envelope(body: &[u8]) -> anyhow::Result<Vec<SentryEvent>> {
+    #[derive(Deserialize)]
+    struct ItemHeader {
+        #[serde(rename = "type")]
+        item_type: String,
+        length: Option<usize>,
+    }
+
+    let line_end = |rest: &[u8]| rest.iter().position(|&b| b == b'\n').unwrap_or(rest.len());
+    let mut rest = body;
+    // Envelope header: only carries routing details we get from the request
+    rest = &rest[(line_end(rest) + 1).min(rest.len())..];
+
+    let mut events = Vec::new();
+    while !rest.is_empty() {
+        let end = line_end(rest);
+        let header_line = &rest[..end];
+        rest = &rest[(end + 1).min(rest.len())..];
+        if header_line.iter().all(u8::is_ascii_whitespace) {
+            continue;
+        }
+        let header: ItemHeader = serde_json::from_slice(header_line)?;
+
+        let payload_len = match header.length {
+            Some(length) => {
+                anyhow::ensure!(length <= rest.len(), "envelope item is truncated");
+                length
+            }
+            None => line_end(rest),
+        };
+        let payload = &rest[..payload_len];
+        rest = &rest[payload_len..];
+        if rest.first() == Some(&b'\n') {
+            rest = &rest[1..];
+        }
+
+        if header.item_type == "event" {
+            events.push(serde_json::from_slice(payload)?);
+        }
+    }
+    Ok(events)
+}
+
+/// Store an event as an error, returning how many were stored (0 or 1)
+fn record(
+    pool: &DbPool,
+    event: SentryEvent,
+    project_id: Option<i64>,
+    max_per_hour: i64,
+) -> anyhow::Result<usize> {
+    let Some(incoming) = event.into_incoming() else {
+        return Ok(0);
+    };
+    app_error::insert_sampled(pool, &incoming, project_id, max_per_hour).map(|id| {
+        tracing::debug!(
+            "Recorded Sentry error id={} class={} (project_id={:?})",
+            id,
+            incoming.exception_class,
+            project_id
+        );
+        1
+    })
+}
+
+/// `POST /api/:project_id/envelope/`, what current SDKs send. The project is
+/// taken from the DSN key, not the path.
+pub async fn ingest_envelope(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(ctx): Extension<ProjectContext>,
+    body: Bytes,
+) -> Result<(Extension<Accepted>, Json<serde_json::Value>), Response> {
+    let events = parse_envelope(&body).map_err(|e| {
+        tracing::debug!("Rejected Sentry envelope: {}", e);
+        StatusCode::BAD_REQUEST.into_response()
+    })?;
+    let id = events.first().and_then(|e| e.event_id.clone());
+    let max_per_hour = config.load().max_occurrences_per_hour;
+    let project_id = ctx.project_id;
+    let accepted = db::blocking(move || {
+        events.into_iter().try_fold(0, |accepted, event| {
+            Ok(accepted + record(&pool, event, project_id, max_per_hour)?)
+        })
+    })
+    .await
+    .map_err(|e| failure_response("Failed to record Sentry error", e))?;
+    Ok((
+        Extension(Accepted(accepted)),
+        Json(serde_json::json!({ "id": id })),
+    ))
+}
+
+/// `POST /api/:project_id/store/`, a single event from older SDKs
+pub async fn ingest_store(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    Extension(ctx): Extension<ProjectContext>,
+    Json(event): Json<SentryEvent>,
+) -> Result<(Extension<Accepted>, Json<serde_json::Value>), Response> {
+    let id = event.event_id.clone();
+    let max_per_hour = config.load().max_occurrences_per_hour;
+    let project_id = ctx.project_id;
+    let accepted = db::blocking(move || record(&pool, event, project_id, max_per_hour))
+        .await
+        .map_err(|e| failure_response("Failed to record Sentry error", e))?;
+    Ok((
+        Extension(Accepted(accepted)),
+        Json(serde_json::json!({ "id": id })),
+    ))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FIXTURE: &str = include_str!("../../tests/fixtures/sentry_event.json");
+
+    fn fixture() -> SentryEvent {
+        serde_json::from_str(FIXTURE).unwrap()
+    }
+
+    #[test]
+    fn test_event_maps_to_incoming_error() {
+        let error = fixture().into_incoming().unwrap();
+
+        // The last exception in the chain is the one raised
+        assert_eq!(error.exception_class, "PaymentFailed");
+        assert_eq!(error.message, "Payment for order 1234 failed");
+        assert_eq!(
+            error.backtrace,
+            vec![
+                "app/services/charge_order.rb:31:in `call'",
+                "app/controllers/checkout_controller.rb:18:in `create'",
+                "/usr/local/bundle/gems/activesupport-7.1.3/lib/active_support/notifications.rb:206:in `instrument'",
+                "/usr/local/bundle/gems/puma-6.4.2/lib/puma/thread_pool.rb:155:in `block in spawn_thread'",
+            ]
+        );
+        assert_eq!(
+            error.fingerprint,
+            "PaymentFailed:app/services/charge_order.rb:31"
+        );
+        assert_eq!(error.user_id.as_deref(), Some("42"));
+        assert_eq!(
+            error.request_id.as_deref(),
+            Some("5b8efff798038103d269b633813fc60c")
+        );
+        assert_eq!(error.git_sha.as_deref(), Some("9f2c1e4b7a"));
+        assert_eq!(
+            error.timestamp.as_deref(),
+            Some("2026-06-01T10:00:00.125+00:00")
+        );
+        assert_eq!(error.params.unwrap()["tags"]["locale"], "en");
+
+        let context = error.source_context.unwrap();
+        assert_eq!(context.file, "app/services/charge_order.rb");
+        assert_eq!(context.lineno, 31);
+        assert_eq!(context.pre_context.unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_custom_fingerprint_is_honored() {
+        let mut event = fixture();
+        event.fingerprint = Some(vec![
+            "payments".to_string(),
+            DEFAULT_FINGERPRINT.to_string(),
+        ]);
+        event.tags = Some(serde_json::json!([["locale", "en"]]));
+        let error = event.into_incoming().unwrap();
+        assert_eq!(
+            error.fingerprint,
+            "payments:PaymentFailed:app/services/charge_order.rb:31"
+        );
+        assert_eq!(error.params.unwrap()["tags"]["locale"], "en");
+
+        let message_only = SentryEvent {
+            exception: None,
+            ..fixture()
+        };
+        assert!(message_only.into_incoming().is_none());
+    }
+
+    #[test]
+    fn test_parse_envelope_reads_event_items() {
+        let event =
+            serde_json::to_string(&serde_json::from_str::<serde_json::Value>(FIXTURE).unwrap())
+                .unwrap();
+        let session = r#"{"sid":"abc","status":"ok"}"#;
+        let envelope = format!(
+            "{{\"event_id\":\"fc6d8c0c43fc4630ad850ee518f1b9d0\"}}\n\
+             {{\"type\":\"session\",\"length\":{}}}\n{}\n\
+             {{\"type\":\"event\",\"length\":{}}}\n{}\n\
+             {{\"type\":\"event\"}}\n{}",
+            session.len(),
+            session,
+            event.len(),
+            event,
+            event,
+        );
+
+        let events = parse_envelope(envelope.as_bytes()).unwrap();
+        assert_eq!(events.len(), 2);
+        assert!(events.iter().all(|e| e.exception.is_some()));
+
+        let truncated = "{}\n{\"type\":\"event\",\"length\":500}\n{}";
+        assert!(parse_envelope(truncated.as_bytes()).is_err());
+    }
+
+    #[test]
+    fn test_envelope_event_is_recorded() {
+        let pool = crate::db::test_pool();
+        let event = parse_envelope(
+            format!(
+                "{{}}\n{{\"type\":\"event\"}}\n{}",
+                FIXTURE.replace('\n', "")
+            )
+            .as_bytes(),
+        )
+        .unwrap()
+        .pop()
+        .unwrap();
+        record(
+            &pool,
+            event,
+            None,
+            app_error::DEFAULT_MAX_OCCURRENCES_PER_HOUR,
+        )
+        .unwrap();
+
+        let conn = pool.get().unwrap();
+        let (class, user): (String, String) = conn
+            .query_row(
+                "SELECT e.exception_class, o.user_id FROM errors e JOIN error_occurrences o ON o.error_id = e.id",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(class, "PaymentFailed");
+        assert_eq!(user, "42");
+    }
+}
@@ -1,4 +1,16 @@
+use crate::models::error::DEFAULT_MAX_OCCURRENCES_PER_HOUR;
+use crate::models::project::{DisplayOffset, ErrorPolicy};
+use crate::models::span::{
+    AttributeLimits, DEFAULT_MAX_SPANS_PER_TRACE, DEFAULT_SENSITIVE_ATTRIBUTE_KEYS, IdValidation,
+    PathRule, Percentile, parse_segment_patterns,
+};
+use arc_swap::ArcSwap;
+use std::collections::HashMap;
 use std::env;
+use std::sync::Arc;
+
+/// Config shared with the background jobs, swapped in place on SIGHUP
+pub type SharedConfig = Arc<ArcSwap<Config>>;
 
 #[derive(Clone, Debug)]
 pub struct Config {
@@ -7,21 +19,105 @@ pub struct Config {
     pub retention_days_errors: i64,
     pub retention_days_hourly_rollups: i64,
     pub retention_days_spans: i64,
+    /// Days to keep traces with an error or slower than
+    /// `slow_request_threshold_ms`; at least `retention_days_spans`
+    pub retention_days_interesting_traces: i64,
     pub slow_request_threshold_ms: f64,
+    /// Latency percentiles the dashboard reports, in order
+    pub latency_percentiles: Vec<Percentile>,
+    /// What ingest does with spans whose IDs don't decode to the OTLP sizes
+    pub otlp_id_validation: IdValidation,
+    /// Lowercased key fragments whose attribute values are masked
+    pub sensitive_attribute_keys: Vec<String>,
+    /// Mask emails, card numbers and bearer tokens at ingest
+    pub redact_pii: bool,
+    /// Attributes stored per span
+    pub max_span_attributes: usize,
+    /// Characters stored per span attribute value
+    pub max_attribute_value_length: usize,
+    /// Spans stored per trace; further ones are dropped and the trace marked truncated
+    pub max_spans_per_trace: usize,
+    /// Built-in rules deriving route templates from request paths
+    pub path_normalize_rules: Vec<PathRule>,
+    /// `;`-separated `<regex>=<placeholder>` rules checked before the built-in ones
+    pub path_segment_patterns: String,
+    /// Database spans at least this slow are listed on the slow queries page
+    pub slow_query_threshold_ms: f64,
+    /// Fraction of uninteresting settled traces tail sampling keeps
+    pub tail_sample_rate: f64,
+    /// Most rows `/performance/export` streams
+    pub export_max_rows: i64,
+    /// Offset charts bucket hours in for projects without their own
+    pub display_timezone: DisplayOffset,
+    /// Secret for GitHub deploy webhooks not tied to a project
+    pub deploy_webhook_secret: Option<String>,
+    /// Days without an occurrence after which open errors are resolved, for
+    /// projects without their own threshold; 0 turns it off
+    pub error_auto_resolve_days: i64,
+    /// Which spans count as failed, for projects without their own policy
+    pub error_status_policy: ErrorPolicy,
+    /// Occurrence rows stored per error per hour
+    pub max_occurrences_per_hour: i64,
+    pub maintenance_interval_hours: u64,
+    pub vacuum_free_page_ratio: f64,
+    /// Largest request body accepted on `/ingest/*`, in bytes
+    pub max_ingest_body_bytes: usize,
+    /// Database connections in the pool
+    pub db_pool_size: u32,
+    /// How long a request waits for a free connection before it's turned away
+    pub db_acquire_timeout_ms: u64,
+    /// Page cache per connection, in KiB
+    pub sqlite_cache_size_kb: i64,
+    /// Memory-mapped I/O per connection, in MiB; 0 turns it off
+    pub sqlite_mmap_size_mb: u64,
+    /// How long a connection waits on another's write lock
+    pub sqlite_busy_timeout_ms: u64,
+    /// `OFF`, `NORMAL`, `FULL` or `EXTRA`
+    pub sqlite_synchronous: String,
+    /// OTLP/HTTP traces endpoint to forward spans to; the exporter is off when unset
+    pub upstream_otlp_endpoint: Option<String>,
+    /// Extra headers sent upstream, from `key=value,key2=value2`
+    pub upstream_otlp_headers: Vec<(String, String)>,
+    /// Fraction of traces forwarded upstream
+    pub upstream_sample_rate: f64,
+    /// Origins browsers may call `/ingest` and `/api` from (`*` for any);
+    /// no CORS headers are sent when empty
+    pub cors_allowed_origins: Vec<String>,
+    /// Methods allowed in cross-origin requests
+    pub cors_allowed_methods: Vec<String>,
+    /// Request headers allowed in cross-origin requests
+    pub cors_allowed_headers: Vec<String>,
     pub mini_apm_url: String,
     pub enable_user_accounts: bool,
     pub enable_projects: bool,
+    /// Record MiniAPM's own ingest requests as spans in the internal project
+    pub self_trace: bool,
     pub session_secret: String,
+    /// Secret project API keys are hashed with; when unset one is generated
+    /// and kept in a file next to the database
+    pub api_key_secret: Option<String>,
 }
 
 impl Config {
+    /// Read config from the environment. When `CONFIG_FILE` names a file of
+    /// `KEY=VALUE` lines, its values take precedence, so settings kept there
+    /// can be changed and picked up with SIGHUP.
     pub fn from_env() -> anyhow::Result<Self> {
+        let file = match env::var("CONFIG_FILE") {
+            Ok(path) => read_config_file(&path)?,
+            Err(_) => HashMap::new(),
+        };
+        Self::from_lookup(|key| file.get(key).cloned().or_else(|| env::var(key).ok()))
+    }
+
+    /// Build config from `var`, which returns the raw value of a setting
+    pub fn from_lookup(var: impl Fn(&str) -> Option<String>) -> anyhow::Result<Self> {
         // SESSION_SECRET is required when user accounts are enabled
-        let enable_user_accounts = env::var("ENABLE_USER_ACCOUNTS")
+        let enable_user_accounts = var("ENABLE_USER_ACCOUNTS")
             .map(|v| v == "1" || v.to_lowercase() == "true")
             .unwrap_or(false);
 
-        let session_secret = env::var("SESSION_SECRET").ok();
+        let session_secret = var("SESSION_SECRET");
 
         if enable_user_accounts && session_secret.is_none() {
             anyhow::bail!(
@@ -42,41 +138,969 @@ impl Config {
             hex::encode(bytes)
         });
 
-        Ok(Self {
-            sqlite_path: env::var("SQLITE_PATH")
-                .unwrap_or_else(|_| "./data/miniapm.db".to_string()),
-            api_key: env::var("MINI_APM_API_KEY").ok(),
-            retention_days_errors: env::var("RETENTION_DAYS_ERRORS")
-                .ok()
-                .and_then(|v| v.parse().ok())
-                .filter(|&v| v > 0)
-                .unwrap_or(30),
-            retention_days_hourly_rollups: env::var("RETENTION_DAYS_HOURLY_ROLLUPS")
-                .ok()
-                .and_then(|v| v.parse().ok())
-                .filter(|&v| v > 0)
-                .unwrap_or(90),
-            retention_days_spans: env::var("RETENTION_DAYS_SPANS")
-                .ok()
-                .and_then(|v| v.parse().ok())
-                .filter(|&v| v > 0)
-                .unwrap_or(7),
-            slow_request_threshold_ms: env::var("SLOW_REQUEST_THRESHOLD_MS")
-                .ok()
-                .and_then(|v| v.parse().ok())
-                .filter(|&v| v > 0.0)
-                .unwrap_or(500.0),
-            mini_apm_url: env::var("MINI_APM_URL")
-                .unwrap_or_else(|_| "http://localhost:3000".to_string()),
+        let retention_days_spans = number(&var, "RETENTION_DAYS_SPANS", 7)?;
+        let config = Self {
+            sqlite_path: var("SQLITE_PATH").unwrap_or_else(|| "./data/miniapm.db".to_string()),
+            api_key: var("MINI_APM_API_KEY"),
+            retention_days_errors: number(&var, "RETENTION_DAYS_ERRORS", 30)?,
+            retention_days_hourly_rollups: number(&var, "RETENTION_DAYS_HOURLY_ROLLUPS", 90)?,
+            retention_days_spans,
+            retention_days_interesting_traces: number(
+                &var,
+                "RETENTION_DAYS_INTERESTING_TRACES",
+                retention_days_spans,
+            )?,
+            slow_request_threshold_ms: number(&var, "SLOW_REQUEST_THRESHOLD_MS", 500.0)?,
+            latency_percentiles: choice(
+                &var,
+                "LATENCY_PERCENTILES",
+                vec![Percentile::P95, Percentile::P99],
+                "a comma-separated list of percentiles like p50,p99.9",
+                Percentile::parse_list,
+            )?,
+            otlp_id_validation: choice(
+                &var,
+                "OTLP_ID_VALIDATION",
+                IdValidation::Strict,
+                "strict or lenient",
+                IdValidation::parse,
+            )?,
+            sensitive_attribute_keys: list(
+                &var,
+                "SENSITIVE_ATTRIBUTE_KEYS",
+                &DEFAULT_SENSITIVE_ATTRIBUTE_KEYS.join(","),
+            )
+            .into_iter()
+            .map(|key| key.to_lowercase())
+            .collect(),
+            redact_pii: choice(&var, "REDACT_PII", true, "true or false", flag)?,
+            max_span_attributes: number(
+                &var,
+                "MAX_SPAN_ATTRIBUTES",
+                AttributeLimits::default().max_attributes,
+            )?,
+            max_attribute_value_length: number(
+                &var,
+                "MAX_ATTRIBUTE_VALUE_LENGTH",
+                AttributeLimits::default().max_value_len,
+            )?,
+            max_spans_per_trace: number(&var, "MAX_SPANS_PER_TRACE", DEFAULT_MAX_SPANS_PER_TRACE)?,
+            path_normalize_rules: choice(
+                &var,
+                "PATH_NORMALIZE_RULES",
+                vec![PathRule::Numeric, PathRule::Uuid],
+                "a comma-separated list of numeric, uuid and hex, or off",
+                PathRule::parse_list,
+            )?,
+            path_segment_patterns: var("PATH_SEGMENT_PATTERNS").unwrap_or_default(),
+            slow_query_threshold_ms: number(&var, "SLOW_QUERY_THRESHOLD_MS", 100.0)?,
+            tail_sample_rate: number(&var, "TAIL_SAMPLE_RATE", 1.0)?,
+            export_max_rows: number(&var, "EXPORT_MAX_ROWS", 10_000)?,
+            display_timezone: choice(
+                &var,
+                "DISPLAY_TIMEZONE",
+                DisplayOffset::UTC,
+                "a UTC offset like +02:00",
+                DisplayOffset::parse,
+            )?,
+            deploy_webhook_secret: var("DEPLOY_WEBHOOK_SECRET").filter(|s| !s.is_empty()),
+            error_auto_resolve_days: number(&var, "ERROR_AUTO_RESOLVE_DAYS", 0)?,
+            error_status_policy: choice(
+                &var,
+                "ERROR_STATUS_POLICY",
+                ErrorPolicy::default(),
+                "5xx, 4xx or status",
+                ErrorPolicy::parse,
+            )?,
+            max_occurrences_per_hour: number(
+                &var,
+                "MAX_OCCURRENCES_PER_HOUR",
+                DEFAULT_MAX_OCCURRENCES_PER_HOUR,
+            )?,
+            maintenance_interval_hours: number(&var, "MAINTENANCE_INTERVAL_HOURS", 168)?,
+            vacuum_free_page_ratio: number(&var, "VACUUM_FREE_PAGE_RATIO", 0.2)?,
+            max_ingest_body_bytes: number::<usize>(&var, "MAX_INGEST_BODY_MB", 16)?
+                .saturating_mul(1024 * 1024),
+            db_pool_size: number(&var, "DB_POOL_SIZE", 10)?,
+            db_acquire_timeout_ms: number(&var, "DB_ACQUIRE_TIMEOUT_MS", 2000)?,
+            sqlite_cache_size_kb: number(&var, "SQLITE_CACHE_SIZE_KB", 2000)?,
+            sqlite_mmap_size_mb: number(&var, "SQLITE_MMAP_SIZE_MB", 0)?,
+            sqlite_busy_timeout_ms: number(&var, "SQLITE_BUSY_TIMEOUT_MS", 5000)?,
+            sqlite_synchronous: var("SQLITE_SYNCHRONOUS")
+                .filter(|v| !v.trim().is_empty())
+                .map(|v| v.trim().to_uppercase())
+                .unwrap_or_else(|| "NORMAL".to_string()),
+            upstream_otlp_endpoint: var("UPSTREAM_OTLP_ENDPOINT").filter(|v| !v.is_empty()),
+            upstream_otlp_headers: var("UPSTREAM_OTLP_HEADERS")
+                .map(|v| parse_headers(&v))
+                .unwrap_or_default(),
+            upstream_sample_rate: number(&var, "UPSTREAM_SAMPLE_RATE", 1.0)?,
+            cors_allowed_origins: list(&var, "CORS_ALLOWED_ORIGINS", "")
+                .into_iter()
+                .map(|origin| origin.trim_end_matches('/').to_string())
+                .collect(),
+            cors_allowed_methods: list(&var, "CORS_ALLOWED_METHODS", "GET,POST")
+                .into_iter()
+                .map(|method| method.to_uppercase())
+                .collect(),
+            cors_allowed_headers: list(
+                &var,
+                "CORS_ALLOWED_HEADERS",
+                "authorization,content-type,x-request-id",
+            ),
+            mini_apm_url: var("MINI_APM_URL")
+                .unwrap_or_else(|| "http://localhost:3000".to_string()),
             enable_user_accounts,
-            enable_projects: env::var("ENABLE_PROJECTS")
+            enable_projects: var("ENABLE_PROJECTS")
+                .map(|v| v == "1" || v.to_lowercase() == "true")
+                .unwrap_or(false),
+            self_trace: var("SELF_TRACE")
                 .map(|v| v == "1" || v.to_lowercase() == "true")
                 .unwrap_or(false),
             session_secret,
-        })
+            api_key_secret: var("API_KEY_SECRET").filter(|s| !s.is_empty()),
+        };
+        config.validate()?;
+        Ok(config)
+    }
+
+    /// Check that settings are in range, reporting every problem at once by
+    /// the environment variable that sets it
+    pub fn validate(&self) -> anyhow::Result<()> {
+        let mut problems = Vec::new();
+        for (key, days) in [
+            ("RETENTION_DAYS_ERRORS", self.retention_days_errors),
+            (
+                "RETENTION_DAYS_HOURLY_ROLLUPS",
+                self.retention_days_hourly_rollups,
+            ),
+            ("RETENTION_DAYS_SPANS", self.retention_days_spans),
+        ] {
+            if days < 1 {
+                problems.push(format!("{} must be at least 1 day, got {}", key, days));
+            }
+        }
+        if self.retention_days_interesting_traces < self.retention_days_spans {
+            problems.push(format!(
+                "RETENTION_DAYS_INTERESTING_TRACES must be at least RETENTION_DAYS_SPANS ({}), got {}",
+                self.retention_days_spans, self.retention_days_interesting_traces
+            ));
+        }
+        if !self.slow_request_threshold_ms.is_finite() || self.slow_request_threshold_ms <= 0.0 {
+            problems.push(format!(
+                "SLOW_REQUEST_THRESHOLD_MS must be greater than 0, got {}",
+                self.slow_request_threshold_ms
+            ));
+        }
+        if !self.slow_query_threshold_ms.is_finite() || self.slow_query_threshold_ms <= 0.0 {
+            problems.push(format!(
+                "SLOW_QUERY_THRESHOLD_MS must be greater than 0, got {}",
+                self.slow_query_threshold_ms
+            ));
+        }
+        if !(0.0..=1.0).contains(&self.tail_sample_rate) {
+            problems.push(format!(
+                "TAIL_SAMPLE_RATE must be between 0 and 1, got {}",
+                self.tail_sample_rate
+            ));
+        }
+        if self.export_max_rows < 1 {
+            problems.push(format!(
+                "EXPORT_MAX_ROWS must be at least 1, got {}",
+                self.export_max_rows
+            ));
+        }
+        if self.error_auto_resolve_days < 0 {
+            problems.push(format!(
+                "ERROR_AUTO_RESOLVE_DAYS must be 0 (off) or more, got {}",
+                self.error_auto_resolve_days
+            ));
+        }
+        if self.max_occurrences_per_hour < 1 {
+            problems.push(format!(
+                "MAX_OCCURRENCES_PER_HOUR must be at least 1, got {}",
+                self.max_occurrences_per_hour
+            ));
+        }
+        if self.maintenance_interval_hours == 0 {
+            problems.push("MAINTENANCE_INTERVAL_HOURS must be at least 1".to_string());
+        }
+        if !(self.vacuum_free_page_ratio > 0.0 && self.vacuum_free_page_ratio <= 1.0) {
+            problems.push(format!(
+                "VACUUM_FREE_PAGE_RATIO must be between 0 and 1, got {}",
+                self.vacuum_free_page_ratio
+            ));
+        }
+        if self.max_ingest_body_bytes == 0 {
+            problems.push("MAX_INGEST_BODY_MB must be at least 1".to_string());
+        }
+        if self.db_pool_size == 0 {
+            problems.push("DB_POOL_SIZE must be at least 1".to_string());
+        }
+        if self.max_span_attributes == 0 {
+            problems.push("MAX_SPAN_ATTRIBUTES must be at least 1".to_string());
+        }
+        if self.max_attribute_value_length == 0 {
+            problems.push("MAX_ATTRIBUTE_VALUE_LENGTH must be at least 1".to_string());
+        }
+        if self.max_spans_per_trace == 0 {
+            problems.push("MAX_SPANS_PER_TRACE must be at least 1".to_string());
+        }
+        if let Err(e) = parse_segment_patterns(&self.path_segment_patterns) {
+            problems.push(format!("PATH_SEGMENT_PATTERNS entry {}", e));
+        }
+        if self.db_acquire_timeout_ms == 0 {
+            problems.push("DB_ACQUIRE_TIMEOUT_MS must be at least 1".to_string());
+        }
+        if self.sqlite_cache_size_kb < 1 {
+            problems.push(format!(
+                "SQLITE_CACHE_SIZE_KB must be at least 1, got {}",
+                self.sqlite_cache_size_kb
+            ));
+        }
+        if !["OFF", "NORMAL", "FULL", "EXTRA"].contains(&self.sqlite_synchronous.as_str()) {
+            problems.push(format!(
+                "SQLITE_SYNCHRONOUS must be OFF, NORMAL, FULL or EXTRA, got {}",
+                self.sqlite_synchronous
+            ));
+        }
+        if !(self.upstream_sample_rate > 0.0 && self.upstream_sample_rate <= 1.0) {
+            problems.push(format!(
+                "UPSTREAM_SAMPLE_RATE must be greater than 0 and at most 1, got {}",
+                self.upstream_sample_rate
+            ));
+        }
+        if let Err(e) = check_url(&self.mini_apm_url) {
+            problems.push(format!("MINI_APM_URL {}", e));
+        }
+        if let Some(endpoint) = &self.upstream_otlp_endpoint
+            && let Err(e) = check_url(endpoint)
+        {
+            problems.push(format!("UPSTREAM_OTLP_ENDPOINT {}", e));
+        }
+        for origin in &self.cors_allowed_origins {
+            if origin != "*"
+                && let Err(e) = check_url(origin)
+            {
+                problems.push(format!("CORS_ALLOWED_ORIGINS {}", e));
+            }
+        }
+        for method in &self.cors_allowed_methods {
+            if axum::http::Method::from_bytes(method.as_bytes()).is_err() {
+                problems.push(format!(
+                    "CORS_ALLOWED_METHODS has an invalid method: {}",
+                    method
+                ));
+            }
+        }
+        for header in &self.cors_allowed_headers {
+            if axum::http::HeaderName::from_bytes(header.as_bytes()).is_err() {
+                problems.push(format!(
+                    "CORS_ALLOWED_HEADERS has an invalid header name: {}",
+                    header
+                ));
+            }
+        }
+
+        if !problems.is_empty() {
+            anyhow::bail!("Invalid configuration:\n  - {}", problems.join("\n  - "));
+        }
+        Ok(())
+    }
+
+    /// Effective settings as `(variable, value)` pairs, with secrets redacted
+    pub fn dump(&self) -> Vec<(&'static str, String)> {
+        let redacted = |set: bool| if set { "<redacted>" } else { "" }.to_string();
+        vec![
+            ("SQLITE_PATH", self.sqlite_path.clone()),
+            ("MINI_APM_API_KEY", redacted(self.api_key_configured())),
+            ("MINI_APM_URL", self.mini_apm_url.clone()),
+            (
+                "RETENTION_DAYS_ERRORS",
+                self.retention_days_errors.to_string(),
+            ),
+            (
+                "RETENTION_DAYS_HOURLY_ROLLUPS",
+                self.retention_days_hourly_rollups.to_string(),
+            ),
+            (
+                "RETENTION_DAYS_SPANS",
+                self.retention_days_spans.to_string(),
+            ),
+            (
+                "RETENTION_DAYS_INTERESTING_TRACES",
+                self.retention_days_interesting_traces.to_string(),
+            ),
+            (
+                "SLOW_REQUEST_THRESHOLD_MS",
+                self.slow_request_threshold_ms.to_string(),
+            ),
+            (
+                "LATENCY_PERCENTILES",
+                self.latency_percentiles
+                    .iter()
+                    .map(Percentile::label)
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            (
+                "OTLP_ID_VALIDATION",
+                self.otlp_id_validation.as_str().to_string(),
+            ),
+            (
+                "SENSITIVE_ATTRIBUTE_KEYS",
+                self.sensitive_attribute_keys.join(","),
+            ),
+            ("REDACT_PII", self.redact_pii.to_string()),
+            ("MAX_SPAN_ATTRIBUTES", self.max_span_attributes.to_string()),
+            (
+                "MAX_ATTRIBUTE_VALUE_LENGTH",
+                self.max_attribute_value_length.to_string(),
+            ),
+            ("MAX_SPANS_PER_TRACE", self.max_spans_per_trace.to_string()),
+            (
+                "PATH_NORMALIZE_RULES",
+                if self.path_normalize_rules.is_empty() {
+                    "off".to_string()
+                } else {
+                    self.path_normalize_rules
+                        .iter()
+                        .map(PathRule::as_str)
+                        .collect::<Vec<_>>()
+                        .join(",")
+                },
+            ),
+            ("PATH_SEGMENT_PATTERNS", self.path_segment_patterns.clone()),
+            (
+                "SLOW_QUERY_THRESHOLD_MS",
+                self.slow_query_threshold_ms.to_string(),
+            ),
+            ("TAIL_SAMPLE_RATE", self.tail_sample_rate.to_string()),
+            ("EXPORT_MAX_ROWS", self.export_max_rows.to_string()),
+            ("DISPLAY_TIMEZONE", self.display_timezone.to_string()),
+            (
+                "DEPLOY_WEBHOOK_SECRET",
+                redacted(self.deploy_webhook_secret.is_some()),
+            ),
+            (
+                "ERROR_AUTO_RESOLVE_DAYS",
+                self.error_auto_resolve_days.to_string(),
+            ),
+            (
+                "ERROR_STATUS_POLICY",
+                self.error_status_policy.as_str().to_string(),
+            ),
+            (
+                "MAX_OCCURRENCES_PER_HOUR",
+                self.max_occurrences_per_hour.to_string(),
+            ),
+            (
+                "MAINTENANCE_INTERVAL_HOURS",
+                self.maintenance_interval_hours.to_string(),
+            ),
+            (
+                "VACUUM_FREE_PAGE_RATIO",
+                self.vacuum_free_page_ratio.to_string(),
+            ),
+            (
+                "MAX_INGEST_BODY_MB",
+                (self.max_ingest_body_bytes / (1024 * 1024)).to_string(),
+            ),
+            ("DB_POOL_SIZE", self.db_pool_size.to_string()),
+            (
+                "DB_ACQUIRE_TIMEOUT_MS",
+                self.db_acquire_timeout_ms.to_string(),
+            ),
+            (
+                "SQLITE_CACHE_SIZE_KB",
+                self.sqlite_cache_size_kb.to_string(),
+            ),
+            ("SQLITE_MMAP_SIZE_MB", self.sqlite_mmap_size_mb.to_string()),
+            (
+                "SQLITE_BUSY_TIMEOUT_MS",
+                self.sqlite_busy_timeout_ms.to_string(),
+            ),
+            ("SQLITE_SYNCHRONOUS", self.sqlite_synchronous.clone()),
+            (
+                "UPSTREAM_OTLP_ENDPOINT",
+                self.upstream_otlp_endpoint.clone().unwrap_or_default(),
+            ),
+            (
+                "UPSTREAM_OTLP_HEADERS",
+                self.upstream_otlp_headers
+                    .iter()
+                    .map(|(key, _)| format!("{}=<redacted>", key))
+                    .collect::<Vec<_>>()
+                    .join(","),
+            ),
+            (
+                "UPSTREAM_SAMPLE_RATE",
+                self.upstream_sample_rate.to_string(),
+            ),
+            ("CORS_ALLOWED_ORIGINS", self.cors_allowed_origins.join(",")),
+            ("CORS_ALLOWED_METHODS", self.cors_allowed_methods.join(",")),
+            ("CORS_ALLOWED_HEADERS", self.cors_allowed_headers.join(",")),
+            (
+                "ENABLE_USER_ACCOUNTS",
+                self.enable_user_accounts.to_string(),
+            ),
+            ("ENABLE_PROJECTS", self.enable_projects.to_string()),
+            ("SELF_TRACE", self.self_trace.to_string()),
+            ("SESSION_SECRET", redacted(true)),
+            ("API_KEY_SECRET", redacted(self.api_key_secret.is_some())),
+        ]
     }
 
     pub fn api_key_configured(&self) -> bool {
         self.api_key.as_ref().is_some_and(|k| !k.is_empty())
     }
+
+    /// Settings that differ in `new`, as `(name, "old -> new")`. Secrets only
+    /// report that they changed; the session secret is never compared since
+    /// single-user mode generates a fresh one on every read.
+    fn changes(&self, new: &Config) -> Vec<(&'static str, String)> {
+        let mut changes = Vec::new();
+        macro_rules! compare {
+            ($($field:ident),* $(,)?) => {$(
+                if self.$field != new.$field {
+                    changes.push((
+                        stringify!($field),
+                        format!("{:?} -> {:?}", self.$field, new.$field),
+                    ));
+                }
+            )*};
+        }
+        compare!(
+            sqlite_path,
+            retention_days_errors,
+            retention_days_hourly_rollups,
+            retention_days_spans,
+            retention_days_interesting_traces,
+            slow_request_threshold_ms,
+            latency_percentiles,
+            otlp_id_validation,
+            sensitive_attribute_keys,
+            redact_pii,
+            max_span_attributes,
+            max_attribute_value_length,
+            max_spans_per_trace,
+            path_normalize_rules,
+            path_segment_patterns,
+            slow_query_threshold_ms,
+            tail_sample_rate,
+            export_max_rows,
+            display_timezone,
+            error_auto_resolve_days,
+            error_status_policy,
+            max_occurrences_per_hour,
+            maintenance_interval_hours,
+            vacuum_free_page_ratio,
+            max_ingest_body_bytes,
+            db_pool_size,
+            db_acquire_timeout_ms,
+            sqlite_cache_size_kb,
+            sqlite_mmap_size_mb,
+            sqlite_busy_timeout_ms,
+            sqlite_synchronous,
+            upstream_otlp_endpoint,
+            upstream_sample_rate,
+            cors_allowed_origins,
+            cors_allowed_methods,
+            cors_allowed_headers,
+            mini_apm_url,
+            enable_user_accounts,
+            enable_projects,
+            self_trace,
+        );
+        if self.api_key != new.api_key {
+            changes.push(("api_key", "(changed)".to_string()));
+        }
+        if self.upstream_otlp_headers != new.upstream_otlp_headers {
+            changes.push(("upstream_otlp_headers", "(changed)".to_string()));
+        }
+        if self.deploy_webhook_secret != new.deploy_webhook_secret {
+            changes.push(("deploy_webhook_secret", "(changed)".to_string()));
+        }
+        if self.api_key_secret != new.api_key_secret {
+            changes.push(("api_key_secret", "(changed)".to_string()));
+        }
+        changes
+    }
+
+    /// Swap `new` into `shared`. Settings only read at startup keep their
+    /// running values. Returns one line per change for logging.
+    pub fn reload(shared: &ArcSwap<Config>, new: Config) -> Vec<String> {
+        let current = shared.load_full();
+        let mut lines = Vec::new();
+        for (name, change) in current.changes(&new) {
+            if RESTART_ONLY.contains(&name) {
+                lines.push(format!("{} changed but needs a restart", name));
+            } else {
+                lines.push(format!("{}: {}", name, change));
+            }
+        }
+
+        shared.store(Arc::new(Config {
+            sqlite_path: current.sqlite_path.clone(),
+            api_key: current.api_key.clone(),
+            maintenance_interval_hours: current.maintenance_interval_hours,
+            max_ingest_body_bytes: current.max_ingest_body_bytes,
+            db_pool_size: current.db_pool_size,
+            db_acquire_timeout_ms: current.db_acquire_timeout_ms,
+            sqlite_cache_size_kb: current.sqlite_cache_size_kb,
+            sqlite_mmap_size_mb: current.sqlite_mmap_size_mb,
+            sqlite_busy_timeout_ms: current.sqlite_busy_timeout_ms,
+            sqlite_synchronous: current.sqlite_synchronous.clone(),
+            upstream_otlp_endpoint: current.upstream_otlp_endpoint.clone(),
+            upstream_otlp_headers: current.upstream_otlp_headers.clone(),
+            upstream_sample_rate: current.upstream_sample_rate,
+            cors_allowed_origins: current.cors_allowed_origins.clone(),
+            cors_allowed_methods: current.cors_allowed_methods.clone(),
+            cors_allowed_headers: current.cors_allowed_headers.clone(),
+            enable_user_accounts: current.enable_user_accounts,
+            enable_projects: current.enable_projects,
+            self_trace: current.self_trace,
+            session_secret: current.session_secret.clone(),
+            api_key_secret: current.api_key_secret.clone(),
+            ..new
+        }));
+        lines
+    }
+}
+
+/// Settings read once at startup (the database, router, exporter, auth mode
+/// and job schedules), so a reload leaves them as they were
+const RESTART_ONLY: &[&str] = &[
+    "sqlite_path",
+    "api_key",
+    "api_key_secret",
+    "maintenance_interval_hours",
+    "max_ingest_body_bytes",
+    "db_pool_size",
+    "db_acquire_timeout_ms",
+    "sqlite_cache_size_kb",
+    "sqlite_mmap_size_mb",
+    "sqlite_busy_timeout_ms",
+    "sqlite_synchronous",
+    "upstream_otlp_endpoint",
+    "upstream_otlp_headers",
+    "upstream_sample_rate",
+    "cors_allowed_origins",
+    "cors_allowed_methods",
+    "cors_allowed_headers",
+    "enable_user_accounts",
+    "enable_projects",
+    "self_trace",
+];
+
+/// Parse a numeric setting, using `default` when it is unset or empty
+fn number<T: std::str::FromStr>(
+    var: &impl Fn(&str) -> Option<String>,
+    key: &str,
+    default: T,
+) -> anyhow::Result<T> {
+    match var(key).filter(|v| !v.trim().is_empty()) {
+        Some(value) => value
+            .trim()
+            .parse()
+            .map_err(|_| anyhow::anyhow!("{} must be a number, got {:?}", key, value)),
+        None => Ok(default),
+    }
+}
+
+/// Parse a setting with `parse`, using `default` when it is unset or empty;
+/// `expected` describes valid values in the error
+fn choice<T>(
+    var: &impl Fn(&str) -> Option<String>,
+    key: &str,
+    default: T,
+    expected: &str,
+    parse: impl Fn(&str) -> Option<T>,
+) -> anyhow::Result<T> {
+    match var(key).filter(|v| !v.trim().is_empty()) {
+        Some(value) => parse(value.trim())
+            .ok_or_else(|| anyhow::anyhow!("{} must be {}, got {:?}", key, expected, value)),
+        None => Ok(default),
+    }
+}
+
+/// `true`/`false`, also written `1`/`0` or `on`/`off`
+fn flag(value: &str) -> Option<bool> {
+    match value.to_lowercase().as_str() {
+        "true" | "1" | "on" => Some(true),
+        "false" | "0" | "off" => Some(false),
+        _ => None,
+    }
+}
+
+/// Parse a comma-separated setting, using `default` when it is unset or empty
+fn list(var: &impl Fn(&str) -> Option<String>, key: &str, default: &str) -> Vec<String> {
+    var(key)
+        .filter(|v| !v.trim().is_empty())
+        .unwrap_or_else(|| default.to_string())
+        .split(',')
+        .map(str::trim)
+        .filter(|item| !item.is_empty())
+        .map(str::to_string)
+        .collect()
+}
+
+/// An absolute http(s) URL with a host
+fn check_url(value: &str) -> Result<(), String> {
+    let url =
+        reqwest::Url::parse(value).map_err(|e| format!("is not a valid URL ({}): {}", e, value))?;
+    if !matches!(url.scheme(), "http" | "https") {
+        return Err(format!(
+            "must start with http:// or https://, got {}",
+            value
+        ));
+    }
+    if url.host_str().is_none_or(str::is_empty) {
+        return Err(format!("has no host: {}", value));
+    }
+    Ok(())
+}
+
+/// Parse `key=value` pairs separated by commas, as in `OTEL_EXPORTER_OTLP_HEADERS`
+fn parse_headers(value: &str) -> Vec<(String, String)> {
+    value
+        .split(',')
+        .filter_map(|pair| {
+            let (key, value) = pair.split_once('=')?;
+            let key = key.trim();
+            (!key.is_empty()).then(|| (key.to_string(), value.trim().to_string()))
+        })
+        .collect()
+}
+
+/// Parse a dotenv-style file: `KEY=VALUE` lines, `#` comments, optional quotes
+fn read_config_file(path: &str) -> anyhow::Result<HashMap<String, String>> {
+    let contents = std::fs::read_to_string(path)
+        .map_err(|e| anyhow::anyhow!("Cannot read CONFIG_FILE {}: {}", path, e))?;
+    Ok(contents
+        .lines()
+        .map(str::trim)
+        .filter(|line| !line.is_empty() && !line.starts_with('#'))
+        .filter_map(|line| {
+            let (key, value) = line.split_once('=')?;
+            let value = value.trim();
+            let value = value
+                .strip_prefix('"')
+                .and_then(|v| v.strip_suffix('"'))
+                .unwrap_or(value);
+            Some((key.trim().to_string(), value.to_string()))
+        })
+        .collect())
+}
+
+#[cfg(test)]
+impl Config {
+    /// Every setting at its default
+    pub fn defaults() -> Self {
+        Self::from_lookup(|_| None).expect("defaults are valid")
+    }
+
+    /// `defaults()`, shared the way handlers receive it
+    pub fn shared_defaults() -> SharedConfig {
+        Arc::new(ArcSwap::from_pointee(Self::defaults()))
+    }
+
+    /// `shared_defaults()` with projects (and so memberships) enabled
+    pub fn shared_with_projects() -> SharedConfig {
+        Arc::new(ArcSwap::from_pointee(Self {
+            enable_projects: true,
+            ..Self::defaults()
+        }))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn config(vars: &[(&str, &str)]) -> Config {
+        let vars: HashMap<String, String> = vars
+            .iter()
+            .map(|(k, v)| (k.to_string(), v.to_string()))
+            .collect();
+        Config::from_lookup(|key| vars.get(key).cloned()).unwrap()
+    }
+
+    #[test]
+    fn test_reload_applies_live_settings_and_keeps_restart_only_ones() {
+        let shared = ArcSwap::from_pointee(config(&[("SQLITE_PATH", "/data/a.db")]));
+        let lines = Config::reload(
+            &shared,
+            config(&[
+                ("SQLITE_PATH", "/data/b.db"),
+                ("RETENTION_DAYS_SPANS", "3"),
+                ("MINI_APM_API_KEY", "secret"),
+            ]),
+        );
+
+        let current = shared.load();
+        assert_eq!(current.retention_days_spans, 3);
+        assert_eq!(current.sqlite_path, "/data/a.db");
+        assert_eq!(current.api_key, None);
+        assert!(lines.contains(&"retention_days_spans: 7 -> 3".to_string()));
+        assert!(lines.contains(&"sqlite_path changed but needs a restart".to_string()));
+        assert!(lines.iter().all(|l| !l.contains("secret")));
+    }
+
+    fn problems(config: Config) -> String {
+        config.validate().unwrap_err().to_string()
+    }
+
+    #[test]
+    fn test_defaults_are_valid() {
+        assert!(config(&[]).validate().is_ok());
+    }
+
+    #[test]
+    fn test_unparsable_number_is_an_error() {
+        let err =
+            Config::from_lookup(|key| (key == "RETENTION_DAYS_SPANS").then(|| "a week".into()))
+                .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("RETENTION_DAYS_SPANS must be a number")
+        );
+        // Empty values fall back to the default
+        let config = Config::from_lookup(|key| (key == "RETENTION_DAYS_SPANS").then(String::new));
+        assert_eq!(config.unwrap().retention_days_spans, 7);
+    }
+
+    #[test]
+    fn test_retention_must_be_positive() {
+        let err = problems(Config {
+            retention_days_spans: 0,
+            retention_days_errors: -1,
+            ..config(&[])
+        });
+        assert!(err.contains("RETENTION_DAYS_SPANS must be at least 1 day, got 0"));
+        assert!(err.contains("RETENTION_DAYS_ERRORS must be at least 1 day, got -1"));
+        assert!(!err.contains("RETENTION_DAYS_HOURLY_ROLLUPS"));
+        assert!(
+            problems(Config {
+                error_auto_resolve_days: -1,
+                ..config(&[])
+            })
+            .contains("ERROR_AUTO_RESOLVE_DAYS must be 0 (off) or more, got -1")
+        );
+    }
+
+    #[test]
+    fn test_interesting_trace_retention_follows_span_retention() {
+        let config = config(&[("RETENTION_DAYS_SPANS", "3")]);
+        assert_eq!(config.retention_days_interesting_traces, 3);
+
+        let err = problems(Config {
+            retention_days_interesting_traces: 2,
+            ..config
+        });
+        assert!(err.contains(
+            "RETENTION_DAYS_INTERESTING_TRACES must be at least RETENTION_DAYS_SPANS (3), got 2"
+        ));
+    }
+
+    #[test]
+    fn test_slow_threshold_must_be_positive() {
+        let err = problems(Config {
+            slow_request_threshold_ms: 0.0,
+            slow_query_threshold_ms: -1.0,
+            ..config(&[])
+        });
+        assert!(err.contains("SLOW_REQUEST_THRESHOLD_MS"));
+        assert!(err.contains("SLOW_QUERY_THRESHOLD_MS must be greater than 0, got -1"));
+    }
+
+    #[test]
+    fn test_maintenance_interval_and_body_limit_must_be_nonzero() {
+        let err = problems(Config {
+            maintenance_interval_hours: 0,
+            max_ingest_body_bytes: 0,
+            export_max_rows: 0,
+            max_occurrences_per_hour: 0,
+            max_span_attributes: 0,
+            max_attribute_value_length: 0,
+            max_spans_per_trace: 0,
+            ..config(&[])
+        });
+        assert!(err.contains("MAINTENANCE_INTERVAL_HOURS"));
+        assert!(err.contains("MAX_SPAN_ATTRIBUTES must be at least 1"));
+        assert!(err.contains("MAX_ATTRIBUTE_VALUE_LENGTH must be at least 1"));
+        assert!(err.contains("MAX_SPANS_PER_TRACE must be at least 1"));
+        assert!(err.contains("MAX_INGEST_BODY_MB"));
+        assert!(err.contains("EXPORT_MAX_ROWS must be at least 1, got 0"));
+        assert!(err.contains("MAX_OCCURRENCES_PER_HOUR must be at least 1, got 0"));
+    }
+
+    #[test]
+    fn test_path_normalization_settings_are_checked() {
+        assert!(
+            Config::from_lookup(|key| (key == "PATH_NORMALIZE_RULES").then(|| "numeric,ids".into()))
+                .is_err()
+        );
+        let off = config(&[("PATH_NORMALIZE_RULES", "off")]);
+        assert!(off.path_normalize_rules.is_empty());
+        assert!(
+            off.dump()
+                .contains(&("PATH_NORMALIZE_RULES", "off".to_string()))
+        );
+
+        let err = Config::from_lookup(|key| {
+            (key == "PATH_SEGMENT_PATTERNS").then(|| "[a-z]{2}=:locale; [bad=:x".into())
+        })
+        .unwrap_err()
+        .to_string();
+        assert!(err.contains("PATH_SEGMENT_PATTERNS entry \"[bad\" is not a valid regex"));
+    }
+
+    #[test]
+    fn test_unknown_choice_is_an_error() {
+        let err = Config::from_lookup(|key| (key == "OTLP_ID_VALIDATION").then(|| "loose".into()))
+            .unwrap_err();
+        assert!(
+            err.to_string()
+                .contains("OTLP_ID_VALIDATION must be strict or lenient, got \"loose\"")
+        );
+        let lenient = config(&[("OTLP_ID_VALIDATION", "Lenient")]);
+        assert_eq!(lenient.otlp_id_validation, IdValidation::Lenient);
+
+        assert!(
+            Config::from_lookup(|key| (key == "DISPLAY_TIMEZONE").then(|| "Europe/Berlin".into()))
+                .is_err()
+        );
+        assert_eq!(
+            config(&[("DISPLAY_TIMEZONE", "+05:30")]).display_timezone,
+            DisplayOffset { minutes: 330 }
+        );
+        assert!(
+            Config::from_lookup(|key| (key == "ERROR_STATUS_POLICY").then(|| "400".into()))
+                .is_err()
+        );
+        assert_eq!(
+            config(&[("ERROR_STATUS_POLICY", "4xx")]).error_status_policy,
+            ErrorPolicy::ClientAndServerErrors
+        );
+
+        assert!(
+            Config::from_lookup(|key| (key == "LATENCY_PERCENTILES").then(|| "p50,median".into()))
+                .is_err()
+        );
+        assert!(
+            Config::from_lookup(|key| (key == "LATENCY_PERCENTILES").then(|| "9é".into())).is_err()
+        );
+        assert!(!config(&[("REDACT_PII", "off")]).redact_pii);
+        assert!(Config::from_lookup(|key| (key == "REDACT_PII").then(|| "no".into())).is_err());
+
+        let percentiles = config(&[("LATENCY_PERCENTILES", "p50, p999")]);
+        assert_eq!(
+            percentiles.latency_percentiles,
+            vec![Percentile(50.0), Percentile(99.9)]
+        );
+        assert!(
+            percentiles
+                .dump()
+                .contains(&("LATENCY_PERCENTILES", "p50,p99.9".to_string()))
+        );
+        assert!(
+            config(&[("LATENCY_PERCENTILES", "p50,p100")])
+                .dump()
+                .contains(&("LATENCY_PERCENTILES", "p50,p100".to_string()))
+        );
+    }
+
+    #[test]
+    fn test_ratios_must_be_fractions() {
+        let err = problems(Config {
+            vacuum_free_page_ratio: 1.5,
+            upstream_sample_rate: 0.0,
+            ..config(&[])
+        });
+        assert!(err.contains("VACUUM_FREE_PAGE_RATIO must be between 0 and 1, got 1.5"));
+        assert!(
+            problems(Config {
+                tail_sample_rate: 1.5,
+                ..config(&[])
+            })
+            .contains("TAIL_SAMPLE_RATE must be between 0 and 1, got 1.5")
+        );
+        assert!(err.contains("UPSTREAM_SAMPLE_RATE must be greater than 0 and at most 1, got 0"));
+        assert!(
+            Config::from_lookup(|key| (key == "UPSTREAM_SAMPLE_RATE").then(|| "2".into())).is_err()
+        );
+    }
+
+    #[test]
+    fn test_urls_must_be_absolute_http() {
+        for (url, expected) in [
+            ("localhost:3000", "must start with http:// or https://"),
+            ("apm.example.com", "is not a valid URL"),
+            ("http://", "is not a valid URL"),
+        ] {
+            let err = problems(Config {
+                mini_apm_url: url.to_string(),
+                ..config(&[])
+            });
+            assert!(
+                err.contains(&format!("MINI_APM_URL {}", expected)),
+                "{url}: {err}"
+            );
+        }
+        let err = problems(Config {
+            upstream_otlp_endpoint: Some("ftp://collector".to_string()),
+            ..config(&[])
+        });
+        assert!(err.contains("UPSTREAM_OTLP_ENDPOINT must start with http://"));
+        assert!(
+            config(&[("MINI_APM_URL", "https://apm.example.com:8443/")])
+                .validate()
+                .is_ok()
+        );
+    }
+
+    #[test]
+    fn test_cors_settings_are_checked() {
+        let config = config(&[
+            ("CORS_ALLOWED_ORIGINS", "https://app.example.com/ , *"),
+            ("CORS_ALLOWED_METHODS", "get, post"),
+        ]);
+        assert_eq!(
+            config.cors_allowed_origins,
+            ["https://app.example.com", "*"]
+        );
+        assert_eq!(config.cors_allowed_methods, ["GET", "POST"]);
+
+        let err = problems(Config {
+            cors_allowed_origins: vec!["ftp://app.example.com".to_string()],
+            cors_allowed_methods: vec!["GET POST".to_string()],
+            cors_allowed_headers: vec!["x custom".to_string()],
+            ..config
+        });
+        assert!(err.contains("CORS_ALLOWED_ORIGINS must start with http://"));
+        assert!(err.contains("CORS_ALLOWED_METHODS has an invalid method: GET POST"));
+        assert!(err.contains("CORS_ALLOWED_HEADERS has an invalid header name: x custom"));
+    }
+
+    #[test]
+    fn test_dump_redacts_secrets() {
+        let dump = config(&[
+            ("MINI_APM_API_KEY", "key-123"),
+            ("DEPLOY_WEBHOOK_SECRET", "hook-456"),
+            ("UPSTREAM_OTLP_HEADERS", "authorization=Bearer abc"),
+            ("RETENTION_DAYS_SPANS", "3"),
+        ])
+        .dump();
+        let text = format!("{:?}", dump);
+        assert!(!text.contains("key-123"));
+        assert!(!text.contains("hook-456"));
+        assert!(!text.contains("Bearer"));
+        assert!(dump.contains(&("RETENTION_DAYS_SPANS", "3".to_string())));
+        assert!(dump.contains(&(
+            "UPSTREAM_OTLP_HEADERS",
+            "authorization=<redacted>".to_string()
+        )));
+        assert!(dump.contains(&("MINI_APM_API_KEY", "<redacted>".to_string())));
+    }
 }
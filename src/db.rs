@@ -1,15 +1,156 @@
 use crate::config::Config;
 use r2d2::Pool;
 use r2d2_sqlite::SqliteConnectionManager;
+use rusqlite::Connection;
 use std::fs;
+use std::ops::Deref;
+use std::panic::AssertUnwindSafe;
 use std::path::Path;
+use std::sync::mpsc;
+use std::time::Duration;
 
-pub type DbPool = Pool<SqliteConnectionManager>;
+/// Connection pool for reads, plus the one connection that ingest writes are
+/// queued onto so concurrent ingests never contend for SQLite's write lock.
+/// Derefs to the pool.
+///
+/// Only ingest goes through the writer. Background jobs and the web UI write
+/// on pooled connections and rely on the busy timeout to wait out the
+/// writer's transactions; they're infrequent enough not to starve it.
+#[derive(Clone)]
+pub struct DbPool {
+    pool: Pool<SqliteConnectionManager>,
+    writer: Option<Writer>,
+}
+
+impl Deref for DbPool {
+    type Target = Pool<SqliteConnectionManager>;
+
+    fn deref(&self) -> &Self::Target {
+        &self.pool
+    }
+}
+
+/// A pool without a writer; writes run on a pooled connection instead
+impl From<Pool<SqliteConnectionManager>> for DbPool {
+    fn from(pool: Pool<SqliteConnectionManager>) -> Self {
+        Self { pool, writer: None }
+    }
+}
+
+impl DbPool {
+    /// Pool plus a writer connection opened on `path`, which must be the
+    /// database the pool connects to
+    fn with_writer(
+        pool: Pool<SqliteConnectionManager>,
+        path: &str,
+        pragmas: &Pragmas,
+    ) -> anyhow::Result<Self> {
+        let conn = Connection::open(path)?;
+        pragmas.apply(&conn)?;
+        Ok(Self {
+            pool,
+            writer: Some(Writer::start(conn)?),
+        })
+    }
+
+    /// Run `write` on the writer connection once the writes queued before it
+    /// are done, blocking until it returns. `write` must not call back into
+    /// `DbPool::write`, which would wait on itself. From async code, call
+    /// this inside `blocking`.
+    pub fn write<T: Send + 'static>(
+        &self,
+        write: impl FnOnce(&mut Connection) -> anyhow::Result<T> + Send + 'static,
+    ) -> anyhow::Result<T> {
+        let Some(writer) = &self.writer else {
+            let mut conn = self.pool.get()?;
+            return write(&mut conn);
+        };
+        let (reply, result) = mpsc::sync_channel(1);
+        writer
+            .jobs
+            .send(Box::new(move |conn| {
+                let _ = reply.send(write(conn));
+            }))
+            .map_err(|_| anyhow::anyhow!("database writer has stopped"))?;
+        result
+            .recv()
+            .map_err(|_| anyhow::anyhow!("database write panicked"))?
+    }
+}
+
+/// Run `task`, which waits on the database, on the blocking thread pool. Async
+/// handlers go through this so a wait on the writer queue or for a pooled
+/// connection doesn't hold up a runtime worker.
+pub async fn blocking<T: Send + 'static>(
+    task: impl FnOnce() -> anyhow::Result<T> + Send + 'static,
+) -> anyhow::Result<T> {
+    tokio::task::spawn_blocking(task)
+        .await
+        .map_err(|e| anyhow::anyhow!("database task failed: {}", e))?
+}
+
+type WriteJob = Box<dyn FnOnce(&mut Connection) + Send>;
+
+/// Sending half of the queue drained by the writer thread, which exits once
+/// every clone of the pool is dropped
+#[derive(Clone)]
+struct Writer {
+    jobs: mpsc::Sender<WriteJob>,
+}
+
+impl Writer {
+    fn start(mut conn: Connection) -> anyhow::Result<Self> {
+        let (jobs, queue) = mpsc::channel::<WriteJob>();
+        std::thread::Builder::new()
+            .name("db-writer".to_string())
+            .spawn(move || {
+                for job in queue {
+                    // A panicking write drops its reply; keep serving the rest
+                    if std::panic::catch_unwind(AssertUnwindSafe(|| job(&mut conn))).is_err() {
+                        tracing::error!("Database write panicked");
+                    }
+                }
+            })?;
+        Ok(Self { jobs })
+    }
+}
+
+/// Per-connection settings, applied to every pooled connection as it's opened
+/// and to the writer. WAL mode is stored in the file, so the schema sets it.
+#[derive(Debug, Clone)]
+pub struct Pragmas {
+    pub cache_size_kb: i64,
+    pub mmap_size_mb: u64,
+    pub busy_timeout_ms: u64,
+    /// Validated by `Config::validate`
+    pub synchronous: String,
+}
+
+impl Pragmas {
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            cache_size_kb: config.sqlite_cache_size_kb,
+            mmap_size_mb: config.sqlite_mmap_size_mb,
+            busy_timeout_ms: config.sqlite_busy_timeout_ms,
+            synchronous: config.sqlite_synchronous.clone(),
+        }
+    }
+
+    fn apply(&self, conn: &Connection) -> rusqlite::Result<()> {
+        conn.busy_timeout(Duration::from_millis(self.busy_timeout_ms))?;
+        // Negative cache sizes are in KiB rather than pages
+        conn.pragma_update(None, "cache_size", -self.cache_size_kb)?;
+        // Reports the new size, except on in-memory databases
+        conn.execute_batch(&format!(
+            "PRAGMA mmap_size = {}",
+            self.mmap_size_mb * 1024 * 1024
+        ))?;
+        conn.pragma_update(None, "synchronous", &self.synchronous)
+    }
+}
 
 const SCHEMA: &str = r#"
 PRAGMA journal_mode = WAL;
-PRAGMA busy_timeout = 100;
-PRAGMA synchronous = NORMAL;
 PRAGMA foreign_keys = ON;
 
 CREATE TABLE IF NOT EXISTS api_keys (
@@ -20,6 +161,16 @@ CREATE TABLE IF NOT EXISTS api_keys (
     last_used_at TEXT
 );
 
+CREATE TABLE IF NOT EXISTS api_tokens (
+    id INTEGER PRIMARY KEY,
+    project_id INTEGER REFERENCES projects(id) ON DELETE CASCADE,
+    name TEXT NOT NULL,
+    token_hash TEXT NOT NULL UNIQUE,
+    scope TEXT NOT NULL DEFAULT 'ingest',
+    created_at TEXT NOT NULL,
+    last_used_at TEXT
+);
+
 CREATE TABLE IF NOT EXISTS requests (
     id INTEGER PRIMARY KEY,
     project_id INTEGER REFERENCES projects(id) ON DELETE CASCADE,
@@ -43,6 +194,7 @@ CREATE INDEX IF NOT EXISTS idx_requests_project_id ON requests(project_id);
 CREATE INDEX IF NOT EXISTS idx_requests_happened_at ON requests(happened_at);
 CREATE INDEX IF NOT EXISTS idx_requests_path_method ON requests(path, method);
 CREATE INDEX IF NOT EXISTS idx_requests_total_ms ON requests(total_ms DESC);
+CREATE INDEX IF NOT EXISTS idx_requests_request_id ON requests(request_id);
 
 CREATE TABLE IF NOT EXISTS errors (
     id INTEGER PRIMARY KEY,
@@ -54,6 +206,11 @@ CREATE TABLE IF NOT EXISTS errors (
     last_seen_at TEXT NOT NULL,
     occurrence_count INTEGER DEFAULT 1,
     status TEXT DEFAULT 'open',
+    muted_until TEXT,
+    auto_reopen_threshold INTEGER,
+    sample_hour TEXT,
+    sample_hour_count INTEGER NOT NULL DEFAULT 0,
+    auto_resolved_at TEXT,
     UNIQUE(project_id, fingerprint)
 );
 
@@ -74,6 +231,24 @@ CREATE TABLE IF NOT EXISTS error_occurrences (
 CREATE INDEX IF NOT EXISTS idx_error_occurrences_error_id ON error_occurrences(error_id);
 CREATE INDEX IF NOT EXISTS idx_error_occurrences_happened_at ON error_occurrences(happened_at);
 
+-- Exact occurrences per error and hour, of which `stored` kept a row in
+-- error_occurrences once MAX_OCCURRENCES_PER_HOUR was reached
+CREATE TABLE IF NOT EXISTS error_hourly_counts (
+    error_id INTEGER NOT NULL REFERENCES errors(id) ON DELETE CASCADE,
+    hour TEXT NOT NULL,
+    occurrences INTEGER NOT NULL,
+    stored INTEGER NOT NULL,
+    PRIMARY KEY (error_id, hour)
+);
+
+-- Every user an error hit, including on occurrences sampling didn't keep
+CREATE TABLE IF NOT EXISTS error_users (
+    error_id INTEGER NOT NULL REFERENCES errors(id) ON DELETE CASCADE,
+    user_id TEXT NOT NULL,
+    last_seen_at TEXT NOT NULL,
+    PRIMARY KEY (error_id, user_id)
+);
+
 CREATE TABLE IF NOT EXISTS rollups_hourly (
     id INTEGER PRIMARY KEY,
     hour TEXT NOT NULL,
@@ -85,6 +260,8 @@ CREATE TABLE IF NOT EXISTS rollups_hourly (
     total_ms_p50 REAL,
     total_ms_p95 REAL,
     total_ms_p99 REAL,
+    total_ms_min REAL,
+    total_ms_max REAL,
     db_ms_sum REAL DEFAULT 0,
     db_count_sum INTEGER DEFAULT 0,
     UNIQUE(hour, path, method)
@@ -109,6 +286,19 @@ CREATE TABLE IF NOT EXISTS rollups_daily (
 
 CREATE INDEX IF NOT EXISTS idx_rollups_daily_date ON rollups_daily(date);
 
+CREATE TABLE IF NOT EXISTS regressions (
+    id INTEGER PRIMARY KEY,
+    path TEXT NOT NULL,
+    method TEXT NOT NULL,
+    week_start TEXT NOT NULL,
+    baseline_p95_ms REAL NOT NULL,
+    current_p95_ms REAL NOT NULL,
+    change_percent REAL NOT NULL,
+    direction TEXT NOT NULL,
+    detected_at TEXT NOT NULL,
+    UNIQUE(week_start, path, method)
+);
+
 CREATE TABLE IF NOT EXISTS deploys (
     id INTEGER PRIMARY KEY,
     project_id INTEGER REFERENCES projects(id) ON DELETE CASCADE,
@@ -117,7 +307,9 @@ CREATE TABLE IF NOT EXISTS deploys (
     env TEXT,
     deployed_at TEXT NOT NULL,
     description TEXT,
-    deployer TEXT
+    deployer TEXT,
+    rollback INTEGER NOT NULL DEFAULT 0,
+    notes TEXT
 );
 
 CREATE INDEX IF NOT EXISTS idx_deploys_project_id ON deploys(project_id);
@@ -127,23 +319,40 @@ CREATE TABLE IF NOT EXISTS projects (
     id INTEGER PRIMARY KEY,
     name TEXT NOT NULL UNIQUE,
     slug TEXT NOT NULL UNIQUE,
-    api_key TEXT NOT NULL UNIQUE,
+    api_key_hash TEXT NOT NULL UNIQUE,
+    api_key_prefix TEXT,
     created_at TEXT NOT NULL
 );
 
 CREATE INDEX IF NOT EXISTS idx_projects_slug ON projects(slug);
-CREATE INDEX IF NOT EXISTS idx_projects_api_key ON projects(api_key);
+CREATE INDEX IF NOT EXISTS idx_projects_api_key ON projects(api_key_hash);
 
 CREATE TABLE IF NOT EXISTS users (
     id INTEGER PRIMARY KEY,
     username TEXT NOT NULL UNIQUE,
     password_hash TEXT,
-    is_admin INTEGER NOT NULL DEFAULT 0,
+    role TEXT NOT NULL DEFAULT 'viewer',
     must_change_password INTEGER NOT NULL DEFAULT 0,
     invite_token TEXT UNIQUE,
     invite_expires_at TEXT,
     created_at TEXT NOT NULL,
-    last_login_at TEXT
+    last_login_at TEXT,
+    totp_secret TEXT
+);
+
+CREATE TABLE IF NOT EXISTS recovery_codes (
+    id INTEGER PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    code_hash TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_recovery_codes_user ON recovery_codes(user_id);
+
+CREATE TABLE IF NOT EXISTS pending_logins (
+    token TEXT PRIMARY KEY,
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    expires_at TEXT NOT NULL,
+    failed_attempts INTEGER NOT NULL DEFAULT 0
 );
 
 CREATE TABLE IF NOT EXISTS sessions (
@@ -154,6 +363,25 @@ CREATE TABLE IF NOT EXISTS sessions (
     expires_at TEXT NOT NULL
 );
 
+-- Per-user UI preferences, e.g. the default time range
+CREATE TABLE IF NOT EXISTS user_prefs (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    key TEXT NOT NULL,
+    value TEXT NOT NULL,
+    updated_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, key)
+);
+
+CREATE TABLE IF NOT EXISTS project_members (
+    user_id INTEGER NOT NULL REFERENCES users(id) ON DELETE CASCADE,
+    project_id INTEGER NOT NULL REFERENCES projects(id) ON DELETE CASCADE,
+    role TEXT NOT NULL DEFAULT 'viewer',
+    created_at TEXT NOT NULL,
+    PRIMARY KEY (user_id, project_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_project_members_project ON project_members(project_id);
+
 CREATE INDEX IF NOT EXISTS idx_sessions_token ON sessions(token);
 CREATE INDEX IF NOT EXISTS idx_sessions_expires ON sessions(expires_at);
 
@@ -191,6 +419,9 @@ CREATE TABLE IF NOT EXISTS spans (
     attributes_json TEXT,
     events_json TEXT,
     resource_attributes_json TEXT,
+    environment TEXT,
+    -- Low-cardinality `http.route` template, e.g. /products/:id
+    route_template TEXT,
     happened_at TEXT NOT NULL,
     UNIQUE(trace_id, span_id)
 );
@@ -198,26 +429,176 @@ CREATE TABLE IF NOT EXISTS spans (
 CREATE INDEX IF NOT EXISTS idx_spans_project_id ON spans(project_id);
 CREATE INDEX IF NOT EXISTS idx_spans_trace_id ON spans(trace_id);
 CREATE INDEX IF NOT EXISTS idx_spans_happened_at ON spans(happened_at);
+CREATE INDEX IF NOT EXISTS idx_spans_parent_null ON spans(parent_span_id);
+-- Root span listing: WHERE parent_span_id IS NULL ORDER BY happened_at DESC
+CREATE INDEX IF NOT EXISTS idx_spans_root_listing ON spans(parent_span_id, happened_at DESC);
 CREATE INDEX IF NOT EXISTS idx_spans_root_type ON spans(root_span_type) WHERE root_span_type IS NOT NULL;
 CREATE INDEX IF NOT EXISTS idx_spans_category ON spans(span_category);
+
+-- One row per trace, maintained at ingest so listings avoid scanning spans
+CREATE TABLE IF NOT EXISTS trace_summaries (
+    trace_id TEXT PRIMARY KEY,
+    project_id INTEGER REFERENCES projects(id) ON DELETE CASCADE,
+    root_span_name TEXT NOT NULL,
+    root_span_type TEXT,
+    start_time_unix_nano INTEGER,
+    end_time_unix_nano INTEGER,
+    duration_ms REAL NOT NULL,
+    span_count INTEGER NOT NULL,
+    status_code INTEGER NOT NULL DEFAULT 0,
+    service_name TEXT,
+    environment TEXT,
+    http_method TEXT,
+    http_url TEXT,
+    http_status_code INTEGER,
+    has_error_descendant INTEGER NOT NULL DEFAULT 0,
+    -- What routes_summary groups the root span under (see span::route_key_sql)
+    route_key TEXT,
+    -- Set once tail sampling has decided to keep the trace
+    tail_sampled INTEGER NOT NULL DEFAULT 0,
+    happened_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_trace_summaries_happened_at ON trace_summaries(happened_at DESC);
+CREATE INDEX IF NOT EXISTS idx_trace_summaries_project ON trace_summaries(project_id, happened_at DESC);
+CREATE INDEX IF NOT EXISTS idx_trace_summaries_duration ON trace_summaries(duration_ms DESC);
+-- Last span id the upstream exporter has forwarded; only kept while
+-- UPSTREAM_OTLP_ENDPOINT is set
+CREATE TABLE IF NOT EXISTS upstream_export (
+    id INTEGER PRIMARY KEY CHECK (id = 1),
+    last_span_id INTEGER NOT NULL
+);
+
+-- Traces that hit MAX_SPANS_PER_TRACE, with how many spans ingest dropped
+CREATE TABLE IF NOT EXISTS trace_truncations (
+    trace_id TEXT PRIMARY KEY,
+    dropped_spans INTEGER NOT NULL DEFAULT 0
+);
+-- OTLP log records, joined to traces by trace_id
+CREATE TABLE IF NOT EXISTS log_records (
+    id INTEGER PRIMARY KEY AUTOINCREMENT,
+    project_id INTEGER REFERENCES projects(id) ON DELETE CASCADE,
+    trace_id TEXT,
+    span_id TEXT,
+    time_unix_nano INTEGER NOT NULL,
+    severity_number INTEGER,
+    severity_text TEXT,
+    body TEXT,
+    attributes_json TEXT,
+    service_name TEXT,
+    happened_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_log_records_trace_id ON log_records(trace_id) WHERE trace_id IS NOT NULL;
+CREATE INDEX IF NOT EXISTS idx_log_records_happened_at ON log_records(happened_at);
+
+-- Expression indexes for attribute search on commonly queried keys (see span::INDEXED_ATTRIBUTE_KEYS)
+CREATE INDEX IF NOT EXISTS idx_spans_attr_http_route ON spans(json_extract(attributes_json, '$."http.route"'));
+CREATE INDEX IF NOT EXISTS idx_spans_attr_user_id ON spans(json_extract(attributes_json, '$."user.id"'));
+CREATE INDEX IF NOT EXISTS idx_spans_attr_enduser_id ON spans(json_extract(attributes_json, '$."enduser.id"'));
 "#;
 
+/// `SQLITE_PATH` for a throwaway database that lives as long as the process
+pub const MEMORY_PATH: &str = ":memory:";
+
 pub fn init(config: &Config) -> anyhow::Result<DbPool> {
-    // Ensure data directory exists
-    if let Some(parent) = Path::new(&config.sqlite_path).parent() {
-        fs::create_dir_all(parent)?;
+    let path = if config.sqlite_path == MEMORY_PATH {
+        shared_memory_uri()
+    } else {
+        // Ensure data directory exists
+        if let Some(parent) = Path::new(&config.sqlite_path).parent() {
+            fs::create_dir_all(parent)?;
+        }
+        config.sqlite_path.clone()
+    };
+    if let Some(secret) = api_key_secret(config)? {
+        crate::models::project::set_api_key_secret(&secret);
     }
+    open(
+        &path,
+        Pragmas::from_config(config),
+        config.db_pool_size,
+        Duration::from_millis(config.db_acquire_timeout_ms),
+    )
+}
 
-    let manager = SqliteConnectionManager::file(&config.sqlite_path);
-    let pool = Pool::builder().max_size(10).build(manager)?;
+/// File beside the database holding the generated API key secret
+const API_KEY_SECRET_FILE: &str = "api_key.secret";
+
+/// `API_KEY_SECRET`, or else the secret kept beside the database, generated
+/// on first start. Keeping it out of the database means a copy of the
+/// database alone can't be used to check guessed keys. An in-memory
+/// database uses the process's random secret (`None`).
+fn api_key_secret(config: &Config) -> anyhow::Result<Option<Vec<u8>>> {
+    if let Some(secret) = &config.api_key_secret {
+        return Ok(Some(secret.as_bytes().to_vec()));
+    }
+    if config.sqlite_path == MEMORY_PATH {
+        return Ok(None);
+    }
+
+    let path = Path::new(&config.sqlite_path).with_file_name(API_KEY_SECRET_FILE);
+    match fs::read_to_string(&path) {
+        Ok(secret) if secret.trim().is_empty() => {
+            anyhow::bail!("{} is empty", path.display())
+        }
+        Ok(secret) => return Ok(Some(secret.trim().as_bytes().to_vec())),
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => {}
+        Err(e) => return Err(e.into()),
+    }
+
+    use rand::Rng;
+    use std::io::Write;
+    let secret = hex::encode(rand::thread_rng().r#gen::<[u8; 32]>());
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::OpenOptionsExt;
+        options.mode(0o600);
+    }
+    options.open(&path)?.write_all(secret.as_bytes())?;
+    tracing::info!("Generated an API key secret in {}", path.display());
+    Ok(Some(secret.into_bytes()))
+}
+
+/// A fresh in-memory database shared by every connection opened on the URI,
+/// where plain `:memory:` would give each pooled connection its own. It's
+/// dropped once the last connection closes; the writer holds one open.
+fn shared_memory_uri() -> String {
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+
+    format!(
+        "file:miniapm_memory_{}_{}?mode=memory&cache=shared",
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::SeqCst)
+    )
+}
+
+/// Pool and writer on `path`, a file or an SQLite URI, with the schema migrated
+fn open(
+    path: &str,
+    pragmas: Pragmas,
+    max_size: u32,
+    acquire_timeout: Duration,
+) -> anyhow::Result<DbPool> {
+    let manager = SqliteConnectionManager::file(path).with_init({
+        let pragmas = pragmas.clone();
+        move |conn| pragmas.apply(conn)
+    });
+    let pool = Pool::builder()
+        .max_size(max_size)
+        .connection_timeout(acquire_timeout)
+        .build(manager)?;
 
     // Run migrations
     migrate(&pool)?;
 
-    Ok(pool)
+    DbPool::with_writer(pool, path, &pragmas)
 }
 
-fn migrate(pool: &DbPool) -> anyhow::Result<()> {
+fn migrate(pool: &Pool<SqliteConnectionManager>) -> anyhow::Result<()> {
     let conn = pool.get()?;
 
     // Run migrations for existing tables BEFORE executing schema
@@ -239,19 +620,235 @@ fn migrate(pool: &DbPool) -> anyhow::Result<()> {
         );
     }
 
+    // Project API keys used to be stored in plaintext in `api_key`
+    let _ = conn.execute(
+        "ALTER TABLE projects RENAME COLUMN api_key TO api_key_hash",
+        [],
+    );
+
+    let trace_summaries_exists: bool = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type='table' AND name='trace_summaries'",
+            [],
+            |_| Ok(true),
+        )
+        .unwrap_or(false);
+
+    let error_users_exists: bool = conn
+        .query_row(
+            "SELECT 1 FROM sqlite_master WHERE type='table' AND name='error_users'",
+            [],
+            |_| Ok(true),
+        )
+        .unwrap_or(false);
+
     // Execute schema (creates tables if not exist, creates indexes)
     conn.execute_batch(SCHEMA)?;
 
+    // Affected users used to be counted from the stored occurrences alone
+    if !error_users_exists {
+        conn.execute(
+            r#"INSERT OR IGNORE INTO error_users (error_id, user_id, last_seen_at)
+               SELECT error_id, user_id, MAX(happened_at) FROM error_occurrences
+               WHERE user_id IS NOT NULL
+               GROUP BY error_id, user_id"#,
+            [],
+        )?;
+    }
+
+    // Trace extent (earliest start, latest end) over all spans, not just the root
+    let added_extent = conn
+        .execute(
+            "ALTER TABLE trace_summaries ADD COLUMN start_time_unix_nano INTEGER",
+            [],
+        )
+        .is_ok();
+    let _ = conn.execute(
+        "ALTER TABLE trace_summaries ADD COLUMN end_time_unix_nano INTEGER",
+        [],
+    );
+    let mut rebuild_trace_summaries = !trace_summaries_exists || added_extent;
+
     // Add invite columns if they don't exist (for existing databases)
     let _ = conn.execute("ALTER TABLE users ADD COLUMN invite_token TEXT UNIQUE", []);
     let _ = conn.execute("ALTER TABLE users ADD COLUMN invite_expires_at TEXT", []);
 
+    // Replace the is_admin flag with a role. Existing admins stay admins and
+    // other existing users keep their previous (edit) access.
+    if conn
+        .execute(
+            "ALTER TABLE users ADD COLUMN role TEXT NOT NULL DEFAULT 'viewer'",
+            [],
+        )
+        .is_ok()
+    {
+        let _ = conn.execute(
+            "UPDATE users SET role = CASE WHEN is_admin = 1 THEN 'admin' ELSE 'editor' END",
+            [],
+        );
+    }
+
+    // Add TOTP secret for two-factor auth
+    let _ = conn.execute("ALTER TABLE users ADD COLUMN totp_secret TEXT", []);
+
     // Add source_context column if it doesn't exist
     let _ = conn.execute(
         "ALTER TABLE error_occurrences ADD COLUMN source_context TEXT",
         [],
     );
 
+    // Add per-project backtrace frame rewrites (JSON array)
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN frame_rewrites TEXT", []);
+
+    // Add per-project ingest redaction rules (JSON array)
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN redaction_rules TEXT", []);
+
+    // Per-project UTC offset for dashboard hour buckets
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN display_timezone TEXT", []);
+
+    // Per-project secret that CI deploy webhooks are signed with
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN webhook_secret TEXT", []);
+
+    // Per-project tail sampling rules, overriding TAIL_SAMPLE_RATE and
+    // SLOW_REQUEST_THRESHOLD_MS
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN tail_sample_rate REAL", []);
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN tail_slow_ms REAL", []);
+    let _ = conn.execute(
+        "ALTER TABLE trace_summaries ADD COLUMN tail_sampled INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+
+    // Add git_sha column to tie occurrences to releases
+    let _ = conn.execute("ALTER TABLE error_occurrences ADD COLUMN git_sha TEXT", []);
+
+    // Add mute columns for auto-reopening ignored errors
+    let _ = conn.execute("ALTER TABLE errors ADD COLUMN muted_until TEXT", []);
+    let _ = conn.execute(
+        "ALTER TABLE errors ADD COLUMN auto_reopen_threshold INTEGER",
+        [],
+    );
+
+    // Add the hourly counter used to sample stored occurrences
+    let _ = conn.execute("ALTER TABLE errors ADD COLUMN sample_hour TEXT", []);
+    let _ = conn.execute(
+        "ALTER TABLE errors ADD COLUMN sample_hour_count INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+
+    // Let operators flag deploys as rolled back and annotate them
+    let _ = conn.execute(
+        "ALTER TABLE deploys ADD COLUMN rollback INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+    let _ = conn.execute("ALTER TABLE deploys ADD COLUMN notes TEXT", []);
+
+    // Keep the hour's extremes so route stats can be read from rollups
+    let _ = conn.execute(
+        "ALTER TABLE rollups_hourly ADD COLUMN total_ms_min REAL",
+        [],
+    );
+    let _ = conn.execute(
+        "ALTER TABLE rollups_hourly ADD COLUMN total_ms_max REAL",
+        [],
+    );
+
+    // Denormalize deployment.environment from the resource attributes
+    if conn
+        .execute("ALTER TABLE spans ADD COLUMN environment TEXT", [])
+        .is_ok()
+    {
+        let _ = conn.execute(
+            r#"UPDATE spans SET environment = COALESCE(
+                   json_extract(resource_attributes_json, '$."deployment.environment"'),
+                   json_extract(resource_attributes_json, '$."deployment.environment.name"'))
+               WHERE resource_attributes_json IS NOT NULL"#,
+            [],
+        );
+    }
+
+    // Spans used to carry an indexed `exported` flag, written and indexed
+    // even with the exporter off. Pending spans carry over to the cursor.
+    if conn.prepare("SELECT exported FROM spans LIMIT 0").is_ok() {
+        conn.execute(
+            r#"INSERT OR IGNORE INTO upstream_export (id, last_span_id)
+               SELECT 1, COALESCE((SELECT MIN(id) - 1 FROM spans WHERE exported = 0),
+                                  (SELECT MAX(id) FROM spans), 0)"#,
+            [],
+        )?;
+        conn.execute("DROP INDEX IF EXISTS idx_spans_unexported", [])?;
+        conn.execute("ALTER TABLE spans DROP COLUMN exported", [])?;
+    }
+
+    // Group routes by their `http.route` template rather than the raw path
+    if conn
+        .execute("ALTER TABLE spans ADD COLUMN route_template TEXT", [])
+        .is_ok()
+    {
+        let _ = conn.execute(
+            r#"UPDATE spans SET route_template = NULLIF(json_extract(attributes_json, '$."http.route"'), '')
+               WHERE attributes_json IS NOT NULL"#,
+            [],
+        );
+    }
+    if conn
+        .execute("ALTER TABLE trace_summaries ADD COLUMN route_key TEXT", [])
+        .is_ok()
+    {
+        rebuild_trace_summaries = true;
+    }
+
+    // Populate trace summaries from spans ingested before the table (or one
+    // of its columns) existed. This reads the migrated `spans` columns above,
+    // so it has to run after them.
+    if rebuild_trace_summaries {
+        let rebuilt = crate::models::span::rebuild_trace_summaries(&conn)?;
+        if rebuilt > 0 {
+            tracing::info!("Built {} trace summaries from existing spans", rebuilt);
+        }
+    }
+
+    // Per-project secret ingest requests can be signed with instead of
+    // sending the API key
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN signing_secret TEXT", []);
+
+    // Resolve errors that stop recurring, per project, and remember which
+    // were resolved that way so a recurrence reopens them
+    let _ = conn.execute(
+        "ALTER TABLE projects ADD COLUMN auto_resolve_days INTEGER",
+        [],
+    );
+    let _ = conn.execute("ALTER TABLE errors ADD COLUMN auto_resolved_at TEXT", []);
+
+    // Per-project choice of which HTTP statuses count as errors
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN error_policy TEXT", []);
+
+    // Projects whose ingest was switched off, and since when
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN disabled_at TEXT", []);
+
+    // When each project's API key last authenticated
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN last_used_at TEXT", []);
+
+    // The key a rotation replaced, still accepted until it expires
+    let _ = conn.execute(
+        "ALTER TABLE projects ADD COLUMN previous_api_key_hash TEXT",
+        [],
+    );
+    let _ = conn.execute(
+        "ALTER TABLE projects ADD COLUMN previous_api_key_expires_at TEXT",
+        [],
+    );
+
+    // Hash any project API keys still stored in plaintext
+    let _ = conn.execute("ALTER TABLE projects ADD COLUMN api_key_prefix TEXT", []);
+    let _ = conn.execute(
+        "ALTER TABLE pending_logins ADD COLUMN failed_attempts INTEGER NOT NULL DEFAULT 0",
+        [],
+    );
+    let hashed = crate::models::project::hash_legacy_api_keys(&conn)?;
+    if hashed > 0 {
+        tracing::info!("Hashed {} plaintext project API keys", hashed);
+    }
+
     tracing::debug!("Database schema initialized");
     Ok(())
 }
@@ -265,3 +862,269 @@ pub fn get_db_size(pool: &DbPool) -> anyhow::Result<f64> {
     )?;
     Ok(size as f64 / 1_048_576.0) // Convert to MB
 }
+
+/// Whether `e` came from waiting too long for a pooled connection, as
+/// opposed to a failing query
+pub fn is_pool_exhausted(e: &anyhow::Error) -> bool {
+    e.downcast_ref::<r2d2::Error>().is_some()
+}
+
+/// In-memory pool with the full schema, shared across pooled connections
+#[cfg(test)]
+pub fn test_pool() -> DbPool {
+    test_pool_sized(4, Duration::from_secs(30))
+}
+
+/// `test_pool` with `max_size` connections and the given acquire timeout
+#[cfg(test)]
+pub fn test_pool_sized(max_size: u32, acquire_timeout: Duration) -> DbPool {
+    let pragmas = Pragmas::from_config(&Config::from_lookup(|_| None).unwrap());
+    open(&shared_memory_uri(), pragmas, max_size, acquire_timeout).unwrap()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn query_plan(pool: &DbPool, sql: &str) -> String {
+        let conn = pool.get().unwrap();
+        let mut stmt = conn
+            .prepare(&format!("EXPLAIN QUERY PLAN {}", sql))
+            .unwrap();
+        stmt.query_map([], |row| row.get::<_, String>(3))
+            .unwrap()
+            .collect::<Result<Vec<_>, _>>()
+            .unwrap()
+            .join("\n")
+    }
+
+    #[test]
+    fn test_trace_lookup_uses_trace_id_index() {
+        let pool = test_pool();
+        let plan = query_plan(
+            &pool,
+            "SELECT id FROM spans WHERE trace_id = 'abc' ORDER BY start_time_unix_nano",
+        );
+        assert!(plan.contains("idx_spans_trace_id"), "{}", plan);
+    }
+
+    #[test]
+    fn test_root_listing_uses_index_without_sorting() {
+        let pool = test_pool();
+        let plan = query_plan(
+            &pool,
+            "SELECT trace_id FROM spans WHERE parent_span_id IS NULL ORDER BY happened_at DESC LIMIT 50",
+        );
+        assert!(plan.contains("idx_spans_root_listing"), "{}", plan);
+        assert!(!plan.contains("TEMP B-TREE"), "{}", plan);
+    }
+
+    #[test]
+    fn test_concurrent_ingests_never_hit_busy() {
+        use crate::models::{error, request, span};
+
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("stress.db");
+        let config = Config::from_lookup(|key| {
+            (key == "SQLITE_PATH").then(|| path.to_string_lossy().into_owned())
+        })
+        .unwrap();
+        let pool = init(&config).unwrap();
+
+        let workers: Vec<_> = (0..8)
+            .map(|worker| {
+                let pool = pool.clone();
+                std::thread::spawn(move || {
+                    for i in 0..10 {
+                        let trace_id = format!("{:016x}{:016x}", worker + 1, i + 1);
+                        let batch: span::OtlpTraceRequest =
+                            serde_json::from_value(serde_json::json!({
+                                "resourceSpans": [{"scopeSpans": [{"spans": [
+                                    {
+                                        "traceId": trace_id, "spanId": "00000000000000a1",
+                                        "name": "GET /", "kind": 2,
+                                        "startTimeUnixNano": "1700000000000000000",
+                                        "endTimeUnixNano": "1700000000100000000"
+                                    },
+                                    {
+                                        "traceId": trace_id, "spanId": "00000000000000a2",
+                                        "parentSpanId": "00000000000000a1",
+                                        "name": "SELECT", "kind": 3,
+                                        "startTimeUnixNano": "1700000000010000000",
+                                        "endTimeUnixNano": "1700000000020000000",
+                                        "events": [{
+                                            "name": "exception",
+                                            "timeUnixNano": "1700000000015000000",
+                                            "attributes": [{
+                                                "key": "exception.type",
+                                                "value": {"stringValue": "Timeout"}
+                                            }]
+                                        }]
+                                    }
+                                ]}]}]
+                            }))
+                            .unwrap();
+                        span::insert_otlp_batch(&pool, &batch, None).unwrap();
+
+                        let line = serde_json::json!({
+                            "method": "GET", "path": "/", "status": 200, "duration": 5
+                        });
+                        let requests = request::RequestBatch {
+                            requests: vec![request::IncomingRequest::from_lograge(&line).unwrap()],
+                        };
+                        request::insert_batch(&pool, &requests, None).unwrap();
+
+                        let incoming: error::IncomingError =
+                            serde_json::from_value(serde_json::json!({
+                                "exception_class": "RuntimeError",
+                                "message": format!("boom {worker}"),
+                                "backtrace": ["app/models/order.rb:10"],
+                                "fingerprint": format!("stress-{worker}")
+                            }))
+                            .unwrap();
+                        error::insert(&pool, &incoming, None).unwrap();
+                    }
+                })
+            })
+            .collect();
+        for worker in workers {
+            worker.join().expect("an ingest failed");
+        }
+
+        let conn = pool.get().unwrap();
+        let count = |table: &str| -> i64 {
+            conn.query_row(&format!("SELECT COUNT(*) FROM {table}"), [], |row| {
+                row.get(0)
+            })
+            .unwrap()
+        };
+        assert_eq!(count("spans"), 160);
+        assert_eq!(count("trace_summaries"), 80);
+        assert_eq!(count("requests"), 80);
+        // 80 from span events plus 80 posted directly
+        assert_eq!(
+            conn.query_row("SELECT SUM(occurrence_count) FROM errors", [], |row| row
+                .get::<_, i64>(0))
+                .unwrap(),
+            160
+        );
+    }
+
+    #[test]
+    fn test_pragmas_apply_to_every_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("pragmas.db");
+        let config = Config::from_lookup(|key| match key {
+            "SQLITE_PATH" => Some(path.to_string_lossy().into_owned()),
+            "SQLITE_BUSY_TIMEOUT_MS" => Some("1234".to_string()),
+            "SQLITE_MMAP_SIZE_MB" => Some("64".to_string()),
+            "SQLITE_SYNCHRONOUS" => Some("full".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        let pool = init(&config).unwrap();
+
+        // Not only the connection the migrations ran on
+        let connections: Vec<_> = (0..3).map(|_| pool.get().unwrap()).collect();
+        for conn in &connections {
+            let pragma = |name: &str| -> i64 {
+                conn.query_row(&format!("PRAGMA {name}"), [], |row| row.get(0))
+                    .unwrap()
+            };
+            assert_eq!(pragma("busy_timeout"), 1234);
+            assert_eq!(pragma("mmap_size"), 64 * 1024 * 1024);
+            assert_eq!(pragma("cache_size"), -2000);
+            // FULL
+            assert_eq!(pragma("synchronous"), 2);
+        }
+    }
+
+    #[test]
+    fn test_memory_path_shares_one_database_across_connections() {
+        let config =
+            Config::from_lookup(|key| (key == "SQLITE_PATH").then(|| MEMORY_PATH.into())).unwrap();
+        let pool = init(&config).unwrap();
+        let first = pool.get().unwrap();
+        let second = pool.get().unwrap();
+
+        first
+            .execute(
+                "INSERT INTO deploys (git_sha, deployed_at) VALUES ('abc', 'now')",
+                [],
+            )
+            .unwrap();
+        let seen: String = second
+            .query_row("SELECT git_sha FROM deploys", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(seen, "abc");
+
+        // Each `init` gets its own database
+        let other = init(&config).unwrap();
+        let count: i64 = other
+            .get()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM deploys", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(count, 0);
+    }
+
+    #[test]
+    fn test_api_key_secret_is_generated_once_beside_the_database() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("miniapm.db");
+        let config = Config::from_lookup(|key| {
+            (key == "SQLITE_PATH").then(|| path.to_string_lossy().into_owned())
+        })
+        .unwrap();
+
+        let first = api_key_secret(&config).unwrap().unwrap();
+        assert_eq!(first.len(), 64);
+        assert!(dir.path().join(API_KEY_SECRET_FILE).exists());
+        assert_eq!(api_key_secret(&config).unwrap().unwrap(), first);
+
+        let configured = Config::from_lookup(|key| match key {
+            "SQLITE_PATH" => Some(path.to_string_lossy().into_owned()),
+            "API_KEY_SECRET" => Some("s3cret".to_string()),
+            _ => None,
+        })
+        .unwrap();
+        assert_eq!(api_key_secret(&configured).unwrap().unwrap(), b"s3cret");
+    }
+
+    #[test]
+    fn test_migrates_a_baseline_database_with_spans() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("miniapm.db");
+        {
+            let conn = rusqlite::Connection::open(&path).unwrap();
+            conn.execute_batch(include_str!("../tests/fixtures/baseline_schema.sql"))
+                .unwrap();
+            conn.execute(
+                r#"INSERT INTO spans (trace_id, span_id, start_time_unix_nano, end_time_unix_nano,
+                                      duration_ms, name, kind, span_category, attributes_json,
+                                      resource_attributes_json, happened_at)
+                   VALUES ('t1', 's1', 0, 5000000, 5, 'GET /users/1', 2, 'http_server',
+                           '{"http.route":"/users/:id"}',
+                           '{"deployment.environment":"production"}',
+                           '2024-01-01T00:00:00Z')"#,
+                [],
+            )
+            .unwrap();
+        }
+
+        let pragmas = Pragmas::from_config(&Config::from_lookup(|_| None).unwrap());
+        let pool = open(&path.to_string_lossy(), pragmas, 2, Duration::from_secs(30)).unwrap();
+
+        let (environment, route_key): (String, String) = pool
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT environment, route_key FROM trace_summaries WHERE trace_id = 't1'",
+                [],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .unwrap();
+        assert_eq!(environment, "production");
+        assert_eq!(route_key, "/users/:id");
+    }
+}
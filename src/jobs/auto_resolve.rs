@@ -0,0 +1,88 @@
+use crate::{
+    DbPool,
+    config::Config,
+    models::{error, project},
+};
+use chrono::{Duration, Utc};
+
+/// Resolve open errors that haven't recurred within each project's
+/// auto-resolve threshold, plus errors without a project under
+/// `ERROR_AUTO_RESOLVE_DAYS`
+pub fn run(pool: &DbPool, config: &Config) -> anyhow::Result<()> {
+    let project_ids = project::list_all(pool)?
+        .into_iter()
+        .map(|p| Some(p.id))
+        .chain([None]);
+
+    let mut resolved = 0;
+    for project_id in project_ids {
+        if let Some(days) =
+            project::auto_resolve_days(pool, project_id, config.error_auto_resolve_days)
+        {
+            let before = (Utc::now() - Duration::days(days)).to_rfc3339();
+            resolved += error::auto_resolve_stale(pool, project_id, &before)?;
+        }
+    }
+    if resolved > 0 {
+        tracing::info!("Auto-resolved {} stale errors", resolved);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::error::IncomingError;
+
+    fn seen(pool: &DbPool, fingerprint: &str, days_ago: i64, project_id: Option<i64>) -> i64 {
+        let error = IncomingError {
+            exception_class: format!("Error{}", fingerprint),
+            message: "boom".to_string(),
+            backtrace: vec![],
+            fingerprint: fingerprint.to_string(),
+            request_id: None,
+            user_id: None,
+            params: None,
+            timestamp: Some((Utc::now() - Duration::days(days_ago)).to_rfc3339()),
+            source_context: None,
+            git_sha: None,
+        };
+        error::insert(pool, &error, project_id).unwrap()
+    }
+
+    #[test]
+    fn test_each_project_uses_its_own_threshold() {
+        let pool = crate::db::test_pool();
+        let (shop, _) = project::create(&pool, "Shop").unwrap();
+        let (blog, _) = project::create(&pool, "Blog").unwrap();
+        let (docs, _) = project::create(&pool, "Docs").unwrap();
+        project::set_auto_resolve_days(&pool, shop.id, Some(7)).unwrap();
+        project::set_auto_resolve_days(&pool, blog.id, Some(30)).unwrap();
+        project::set_auto_resolve_days(&pool, docs.id, Some(0)).unwrap();
+
+        let shop_recent = seen(&pool, "shop-recent", 3, Some(shop.id));
+        let shop_stale = seen(&pool, "shop-stale", 10, Some(shop.id));
+        let blog_stale = seen(&pool, "blog-stale", 10, Some(blog.id));
+        let blog_ancient = seen(&pool, "blog-ancient", 60, Some(blog.id));
+        let docs_ancient = seen(&pool, "docs-ancient", 60, Some(docs.id));
+        // Without a project the server default applies, which is off by default
+        let unowned = seen(&pool, "unowned", 60, None);
+
+        run(&pool, &Config::defaults()).unwrap();
+        let status = |id| error::find(&pool, id).unwrap().unwrap().status;
+        assert_eq!(status(shop_recent), "open");
+        assert_eq!(status(shop_stale), "resolved");
+        assert_eq!(status(blog_stale), "open");
+        assert_eq!(status(blog_ancient), "resolved");
+        assert_eq!(status(docs_ancient), "open");
+        assert_eq!(status(unowned), "open");
+
+        let config = Config {
+            error_auto_resolve_days: 30,
+            ..Config::defaults()
+        };
+        run(&pool, &config).unwrap();
+        assert_eq!(status(unowned), "resolved");
+        assert_eq!(status(docs_ancient), "open");
+    }
+}
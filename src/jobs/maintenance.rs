@@ -0,0 +1,48 @@
+use crate::{DbPool, db};
+
+/// Checkpoint the WAL and reclaim free pages once enough of the file is unused.
+/// Returns whether a vacuum ran.
+pub fn compact(pool: &DbPool, min_free_ratio: f64) -> anyhow::Result<bool> {
+    let conn = pool.get()?;
+
+    // Always fold the WAL back into the main file so it can't grow unbounded
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+
+    let page_count: i64 = conn.query_row("PRAGMA page_count", [], |row| row.get(0))?;
+    let freelist_count: i64 = conn.query_row("PRAGMA freelist_count", [], |row| row.get(0))?;
+    let free_ratio = if page_count > 0 {
+        freelist_count as f64 / page_count as f64
+    } else {
+        0.0
+    };
+
+    if free_ratio < min_free_ratio {
+        tracing::debug!(
+            "Skipping vacuum: {:.1}% free pages (threshold {:.1}%)",
+            free_ratio * 100.0,
+            min_free_ratio * 100.0
+        );
+        return Ok(false);
+    }
+
+    let before_mb = db::get_db_size(pool)?;
+
+    // auto_vacuum = 2 (INCREMENTAL) lets us release pages without rewriting the file
+    let auto_vacuum: i64 = conn.query_row("PRAGMA auto_vacuum", [], |row| row.get(0))?;
+    if auto_vacuum == 2 {
+        conn.execute_batch("PRAGMA incremental_vacuum")?;
+    } else {
+        conn.execute_batch("VACUUM")?;
+    }
+    conn.query_row("PRAGMA wal_checkpoint(TRUNCATE)", [], |_| Ok(()))?;
+
+    let after_mb = db::get_db_size(pool)?;
+    tracing::info!(
+        "Database compacted: {:.2} MB -> {:.2} MB ({:.1}% free pages)",
+        before_mb,
+        after_mb,
+        free_ratio * 100.0
+    );
+
+    Ok(true)
+}
@@ -1,11 +1,16 @@
+mod auto_resolve;
+mod maintenance;
+mod regression;
 mod retention;
 mod rollup;
+mod tail_sampling;
+mod upstream;
 
-use crate::{DbPool, config::Config, models};
+use crate::{DbPool, config::SharedConfig, models};
 use std::time::Duration;
 use tokio::time::interval;
 
-pub fn start(pool: DbPool, config: Config) {
+pub fn start(pool: DbPool, config: SharedConfig) {
     // Session cleanup job - runs hourly
     let pool_clone = pool.clone();
     tokio::spawn(async move {
@@ -26,17 +31,31 @@ pub fn start(pool: DbPool, config: Config) {
 
     // Hourly rollup job
     let pool_clone = pool.clone();
+    let config_clone = config.clone();
     tokio::spawn(async move {
         let mut interval = interval(Duration::from_secs(3600)); // Every hour
         loop {
             interval.tick().await;
-            if let Err(e) = rollup::hourly(&pool_clone) {
+            if let Err(e) = rollup::hourly(&pool_clone, &config_clone.load()) {
                 tracing::error!("Hourly rollup failed: {}", e);
             }
         }
     });
 
-    // Daily rollup job
+    // Tail sampling job - drops uninteresting traces once they're complete
+    let pool_clone = pool.clone();
+    let config_clone = config.clone();
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(3600)); // Every hour
+        loop {
+            interval.tick().await;
+            if let Err(e) = tail_sampling::run(&pool_clone, &config_clone.load()) {
+                tracing::error!("Tail sampling failed: {}", e);
+            }
+        }
+    });
+
+    // Daily rollup job, followed by week-over-week regression detection
     let pool_clone = pool.clone();
     tokio::spawn(async move {
         let mut interval = interval(Duration::from_secs(86400)); // Every 24 hours
@@ -45,16 +64,57 @@ pub fn start(pool: DbPool, config: Config) {
             if let Err(e) = rollup::daily(&pool_clone) {
                 tracing::error!("Daily rollup failed: {}", e);
             }
+            if let Err(e) = regression::detect(&pool_clone) {
+                tracing::error!("Regression detection failed: {}", e);
+            }
+        }
+    });
+
+    // Auto-resolve job - resolves open errors that stopped recurring
+    let pool_clone = pool.clone();
+    let config_clone = config.clone();
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(86400)); // Every 24 hours
+        loop {
+            interval.tick().await;
+            if let Err(e) = auto_resolve::run(&pool_clone, &config_clone.load()) {
+                tracing::error!("Error auto-resolve failed: {}", e);
+            }
+        }
+    });
+
+    // Compaction job - weekly by default
+    let pool_clone = pool.clone();
+    let config_clone = config.clone();
+    let maintenance_secs = config.load().maintenance_interval_hours * 3600;
+    tokio::spawn(async move {
+        let mut interval = interval(Duration::from_secs(maintenance_secs));
+        // Skip the immediate first tick so startup isn't slowed by a vacuum
+        interval.tick().await;
+        loop {
+            interval.tick().await;
+            let vacuum_free_ratio = config_clone.load().vacuum_free_page_ratio;
+            if let Err(e) = maintenance::compact(&pool_clone, vacuum_free_ratio) {
+                tracing::error!("Database compaction failed: {}", e);
+            }
         }
     });
 
+    // Upstream exporter - only when an endpoint is configured
+    if let Some(upstream) = upstream::Upstream::from_config(&config.load()) {
+        upstream::spawn(pool.clone(), upstream);
+    } else if let Err(e) = models::span::stop_export(&pool) {
+        tracing::error!("Failed to reset the upstream export cursor: {}", e);
+    }
+
     // Retention job
     let pool_clone = pool.clone();
     tokio::spawn(async move {
         let mut interval = interval(Duration::from_secs(86400)); // Every 24 hours
         loop {
             interval.tick().await;
-            if let Err(e) = retention::cleanup(&pool_clone, &config) {
+            // Read on every tick so a SIGHUP reload takes effect
+            if let Err(e) = retention::cleanup(&pool_clone, &config.load()) {
                 tracing::error!("Retention cleanup failed: {}", e);
             }
         }
@@ -0,0 +1,12 @@
+use crate::{DbPool, models::regression};
+use chrono::Utc;
+
+/// Flag routes whose p95 moved week over week; runs after the daily rollup so
+/// yesterday is included
+pub fn detect(pool: &DbPool) -> anyhow::Result<()> {
+    let flagged = regression::detect(pool, Utc::now().date_naive())?;
+    if flagged > 0 {
+        tracing::info!("Flagged {} routes whose weekly p95 changed", flagged);
+    }
+    Ok(())
+}
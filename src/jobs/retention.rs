@@ -6,11 +6,23 @@ use crate::{
 use chrono::{Duration, Utc};
 
 pub fn cleanup(pool: &DbPool, config: &Config) -> anyhow::Result<()> {
-    // Delete old spans
+    // Delete old spans; traces with errors or slow ones may be kept longer
     let spans_cutoff = (Utc::now() - Duration::days(config.retention_days_spans)).to_rfc3339();
-    let deleted_spans = models::span::delete_before(pool, &spans_cutoff)?;
+    let interesting_cutoff =
+        (Utc::now() - Duration::days(config.retention_days_interesting_traces)).to_rfc3339();
+    let deleted_spans = models::span::delete_traces_before(
+        pool,
+        &spans_cutoff,
+        &interesting_cutoff,
+        config.slow_request_threshold_ms,
+        config.error_status_policy,
+    )?;
     tracing::info!("Deleted {} old spans", deleted_spans);
 
+    // Logs are kept as long as clean traces
+    let deleted_logs = models::log_record::delete_before(pool, &spans_cutoff)?;
+    tracing::info!("Deleted {} old log records", deleted_logs);
+
     // Delete old error occurrences
     let errors_cutoff = (Utc::now() - Duration::days(config.retention_days_errors)).to_rfc3339();
     let deleted_occurrences = models::error::delete_occurrences_before(pool, &errors_cutoff)?;
@@ -27,12 +39,95 @@ pub fn cleanup(pool: &DbPool, config: &Config) -> anyhow::Result<()> {
     let deleted_deploys = deploy::delete_before(pool, &deploys_cutoff)?;
     tracing::info!("Deleted {} old deploys", deleted_deploys);
 
-    // Vacuum on Sundays
-    if Utc::now().format("%u").to_string() == "7" {
-        let conn = pool.get()?;
-        conn.execute_batch("VACUUM")?;
-        tracing::info!("Database vacuumed");
-    }
+    // Second login steps that were never finished
+    let deleted_pending = models::totp::delete_expired_pending_logins(pool)?;
+    tracing::info!("Deleted {} expired pending logins", deleted_pending);
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use arc_swap::ArcSwap;
+
+    fn span_count(pool: &DbPool) -> i64 {
+        pool.get()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM spans", [], |row| row.get(0))
+            .unwrap()
+    }
+
+    #[test]
+    fn test_reloaded_config_changes_span_retention() {
+        let pool = crate::db::test_pool();
+        let happened_at = (Utc::now() - Duration::days(5)).to_rfc3339();
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO spans (trace_id, span_id, start_time_unix_nano, end_time_unix_nano,
+                                    name, span_category, happened_at)
+                 VALUES ('t', 's', 0, 0, 'GET /', 'http_server', ?1)",
+                [&happened_at],
+            )
+            .unwrap();
+
+        // Default span retention is 7 days, so a 5 day old span stays
+        let shared = ArcSwap::from_pointee(Config::from_lookup(|_| None).unwrap());
+        cleanup(&pool, &shared.load()).unwrap();
+        assert_eq!(span_count(&pool), 1);
+
+        let shorter =
+            Config::from_lookup(|key| (key == "RETENTION_DAYS_SPANS").then(|| "3".to_string()))
+                .unwrap();
+        Config::reload(&shared, shorter);
+        cleanup(&pool, &shared.load()).unwrap();
+        assert_eq!(span_count(&pool), 0);
+    }
+
+    #[test]
+    fn test_errored_and_slow_traces_outlive_clean_ones() {
+        let pool = crate::db::test_pool();
+        let happened_at = (Utc::now() - Duration::days(10)).to_rfc3339();
+        {
+            let conn = pool.get().unwrap();
+            for (trace_id, status_code, duration_ms) in
+                [("clean", 0, 20), ("failed", 2, 20), ("slow", 0, 900)]
+            {
+                conn.execute(
+                    "INSERT INTO spans (trace_id, span_id, start_time_unix_nano,
+                                        end_time_unix_nano, name, span_category, status_code,
+                                        happened_at)
+                     VALUES (?1, ?1, 0, ?2, 'GET /', 'http_server', ?3, ?4)",
+                    rusqlite::params![trace_id, duration_ms * 1_000_000, status_code, &happened_at],
+                )
+                .unwrap();
+            }
+            models::span::rebuild_trace_summaries(&conn).unwrap();
+        }
+        let traces = || -> Vec<String> {
+            pool.get()
+                .unwrap()
+                .prepare("SELECT DISTINCT trace_id FROM spans ORDER BY trace_id")
+                .unwrap()
+                .query_map([], |row| row.get(0))
+                .unwrap()
+                .collect::<Result<_, _>>()
+                .unwrap()
+        };
+
+        let config = |interesting: &str| {
+            Config::from_lookup(|key| match key {
+                "RETENTION_DAYS_SPANS" => Some("7".to_string()),
+                "RETENTION_DAYS_INTERESTING_TRACES" => Some(interesting.to_string()),
+                _ => None,
+            })
+            .unwrap()
+        };
+        cleanup(&pool, &config("30")).unwrap();
+        assert_eq!(traces(), vec!["failed", "slow"]);
+
+        cleanup(&pool, &config("7")).unwrap();
+        assert!(traces().is_empty());
+    }
+}
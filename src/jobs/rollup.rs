@@ -1,52 +1,118 @@
-use crate::{DbPool, models::rollup};
-use chrono::{Duration, Utc};
+use crate::{
+    DbPool,
+    config::Config,
+    models::{project::ErrorPolicy, rollup, span},
+};
+use chrono::{DateTime, Duration, Timelike, Utc};
 
-pub fn hourly(pool: &DbPool) -> anyhow::Result<()> {
-    let conn = pool.get()?;
+fn percentile(sorted: &[f64], p: f64) -> Option<f64> {
+    let last = sorted.len().checked_sub(1)?;
+    let idx = ((p * last as f64).round() as usize).min(last);
+    Some(sorted[idx])
+}
 
-    // Get previous hour
-    let prev_hour = (Utc::now() - Duration::hours(1))
-        .format("%Y-%m-%dT%H:00:00Z")
-        .to_string();
+/// One request for the hourly rollup: `(path, method, total_ms, db_ms, db_count, failed)`
+type Sample = (String, String, f64, f64, i64, bool);
 
-    // Aggregate requests for the hour
-    let mut stmt = conn.prepare(
+/// Requests logged during the hour plus root web spans, which is all OTLP-only
+/// apps send. A span whose trace id is a logged request id describes the same
+/// request and is left out. Sorted so each route's durations are contiguous
+/// and ascending. Rollups span every project, so failures follow
+/// `ERROR_STATUS_POLICY`; requests carry no OTLP status, only an HTTP one.
+fn hourly_samples(policy: ErrorPolicy) -> String {
+    let request_failed = match policy.http_floor() {
+        Some(floor) => format!("COALESCE(status, 0) >= {floor}"),
+        None => "0".to_string(),
+    };
+    let span_failed = policy.sql("s");
+    let route_key = span::route_key_sql("s");
+    format!(
         r#"
-        SELECT path, method,
-               COUNT(*) as request_count,
-               SUM(total_ms) as total_ms_sum,
-               SUM(db_ms) as db_ms_sum,
-               SUM(db_count) as db_count_sum
-        FROM requests
-        WHERE happened_at >= ?1 AND happened_at < datetime(?1, '+1 hour')
-        GROUP BY path, method
-        "#,
-    )?;
+    SELECT path, method, total_ms, COALESCE(db_ms, 0), COALESCE(db_count, 0), {request_failed}
+    FROM requests
+    WHERE happened_at >= ?1 AND happened_at < ?2
+    UNION ALL
+    SELECT {route_key}, COALESCE(s.http_method, 'GET'),
+           COALESCE(s.duration_ms, 0),
+           (SELECT COALESCE(SUM(d.duration_ms), 0) FROM spans d
+            WHERE d.trace_id = s.trace_id AND d.span_category = 'db'),
+           (SELECT COUNT(*) FROM spans d
+            WHERE d.trace_id = s.trace_id AND d.span_category = 'db'),
+           {span_failed}
+    FROM spans s
+    WHERE s.parent_span_id IS NULL AND s.root_span_type = 'web'
+      AND s.happened_at >= ?1 AND s.happened_at < ?2
+      AND NOT EXISTS (SELECT 1 FROM requests r WHERE r.request_id = s.trace_id)
+    ORDER BY 1, 2, 3
+"#
+    )
+}
 
-    let rollups: Vec<_> = stmt
-        .query_map([&prev_hour], |row| {
-            Ok(rollup::HourlyRollup {
-                id: 0,
-                hour: prev_hour.clone(),
-                path: row.get(0)?,
-                method: row.get(1)?,
-                request_count: row.get(2)?,
-                error_count: 0,
-                total_ms_sum: row.get(3)?,
-                total_ms_p50: None,
-                total_ms_p95: None,
-                total_ms_p99: None,
-                db_ms_sum: row.get(4)?,
-                db_count_sum: row.get(5)?,
-            })
+pub fn hourly(pool: &DbPool, config: &Config) -> anyhow::Result<()> {
+    hourly_at(
+        pool,
+        Utc::now() - Duration::hours(1),
+        config.error_status_policy,
+    )
+}
+
+/// Roll up the hour containing `at`, replacing any earlier rollup of it.
+/// Failures are counted under `policy`.
+pub fn hourly_at(pool: &DbPool, at: DateTime<Utc>, policy: ErrorPolicy) -> anyhow::Result<()> {
+    let start = at
+        .with_minute(0)
+        .and_then(|t| t.with_second(0))
+        .and_then(|t| t.with_nanosecond(0))
+        .unwrap_or(at);
+    let hour = start.format("%Y-%m-%dT%H:00:00Z").to_string();
+    // Requests are stored as `+00:00` and spans as `Z`; bounds without an
+    // offset compare correctly against both
+    let from = start.format("%Y-%m-%dT%H:%M:%S").to_string();
+    let to = (start + Duration::hours(1))
+        .format("%Y-%m-%dT%H:%M:%S")
+        .to_string();
+
+    let samples: Vec<Sample> = {
+        let conn = pool.get()?;
+        let mut stmt = conn.prepare(&hourly_samples(policy))?;
+        stmt.query_map([&from, &to], |row| {
+            Ok((
+                row.get(0)?,
+                row.get(1)?,
+                row.get(2)?,
+                row.get(3)?,
+                row.get(4)?,
+                row.get(5)?,
+            ))
         })?
-        .collect::<Result<Vec<_>, _>>()?;
+        .collect::<Result<Vec<_>, _>>()?
+    };
 
-    for r in rollups {
-        rollup::insert_hourly(pool, &r)?;
+    for route in samples.chunk_by(|a, b| a.0 == b.0 && a.1 == b.1) {
+        // Percentiles per route, nearest-rank like the routes table
+        let sorted: Vec<f64> = route.iter().map(|s| s.2).collect();
+        rollup::insert_hourly(
+            pool,
+            &rollup::HourlyRollup {
+                id: 0,
+                hour: hour.clone(),
+                path: route[0].0.clone(),
+                method: route[0].1.clone(),
+                request_count: route.len() as i64,
+                error_count: route.iter().filter(|s| s.5).count() as i64,
+                total_ms_sum: sorted.iter().sum(),
+                total_ms_p50: percentile(&sorted, 0.50),
+                total_ms_p95: percentile(&sorted, 0.95),
+                total_ms_p99: percentile(&sorted, 0.99),
+                total_ms_min: sorted.first().copied(),
+                total_ms_max: sorted.last().copied(),
+                db_ms_sum: route.iter().map(|s| s.3).sum(),
+                db_count_sum: route.iter().map(|s| s.4).sum(),
+            },
+        )?;
     }
 
-    tracing::debug!("Hourly rollup completed for {}", prev_hour);
+    tracing::debug!("Hourly rollup completed for {}", hour);
     Ok(())
 }
 
@@ -100,3 +166,235 @@ pub fn daily(pool: &DbPool) -> anyhow::Result<()> {
     tracing::debug!("Daily rollup completed for {}", prev_day);
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{
+        request::{self, IncomingRequest, RequestBatch},
+        span,
+    };
+
+    fn otlp_span(
+        trace: u8,
+        span: u8,
+        parent: Option<u8>,
+        name: &str,
+        ms: i64,
+    ) -> serde_json::Value {
+        // 2023-11-14T22:13:20Z
+        let start = 1_700_000_000_000_000_000i64 + trace as i64 * 60_000_000_000;
+        let mut value = serde_json::json!({
+            "traceId": hex::encode([trace; 16]),
+            "spanId": hex::encode([span; 8]),
+            "name": name,
+            "kind": if parent.is_some() { 3 } else { 2 },
+            "startTimeUnixNano": start.to_string(),
+            "endTimeUnixNano": (start + ms * 1_000_000).to_string(),
+            "attributes": [
+                {"key": "http.request.method", "value": {"stringValue": "GET"}}
+            ]
+        });
+        if let Some(parent) = parent {
+            value["parentSpanId"] = hex::encode([parent; 8]).into();
+            value["attributes"] = serde_json::json!([
+                {"key": "db.system", "value": {"stringValue": "postgresql"}}
+            ]);
+        }
+        value
+    }
+
+    #[test]
+    fn test_hourly_rolls_up_root_web_spans() {
+        let pool = crate::db::test_pool();
+        let batch: span::OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+            "resourceSpans": [{
+                "scopeSpans": [{"spans": [
+                    otlp_span(1, 1, None, "GET /orders", 100),
+                    otlp_span(1, 2, Some(1), "SELECT orders", 10),
+                    otlp_span(1, 3, Some(1), "SELECT users", 5),
+                    otlp_span(2, 1, None, "GET /orders", 300),
+                    otlp_span(3, 1, None, "GET /orders", 200),
+                ]}]
+            }]
+        }))
+        .unwrap();
+        span::insert_otlp_batch(&pool, &batch, None).unwrap();
+
+        // A logged request in the same hour, plus one carrying trace 3's id
+        // that must not be counted twice
+        let logged = |request_id: String, path: &str| IncomingRequest {
+            request_id: Some(request_id),
+            method: "GET".to_string(),
+            path: path.to_string(),
+            status: 500,
+            total_ms: 50.0,
+            timestamp: Some("2023-11-14T22:30:00Z".to_string()),
+            ..Default::default()
+        };
+        let requests = RequestBatch {
+            requests: vec![
+                logged("req-1".to_string(), "/health"),
+                logged(hex::encode([3u8; 16]), "GET /orders"),
+            ],
+        };
+        request::insert_batch(&pool, &requests, None).unwrap();
+
+        let at = DateTime::parse_from_rfc3339("2023-11-14T22:45:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        hourly_at(&pool, at, ErrorPolicy::default()).unwrap();
+        // Re-running the hour replaces its rows
+        hourly_at(&pool, at, ErrorPolicy::default()).unwrap();
+
+        let conn = pool.get().unwrap();
+        let rows: Vec<rollup::HourlyRollup> = conn
+            .prepare(
+                "SELECT id, hour, path, method, request_count, error_count, total_ms_sum,
+                        total_ms_p50, total_ms_p95, total_ms_p99, total_ms_min, total_ms_max,
+                        db_ms_sum, db_count_sum
+                 FROM rollups_hourly ORDER BY request_count DESC",
+            )
+            .unwrap()
+            .query_map([], |row| {
+                Ok(rollup::HourlyRollup {
+                    id: row.get(0)?,
+                    hour: row.get(1)?,
+                    path: row.get(2)?,
+                    method: row.get(3)?,
+                    request_count: row.get(4)?,
+                    error_count: row.get(5)?,
+                    total_ms_sum: row.get(6)?,
+                    total_ms_p50: row.get(7)?,
+                    total_ms_p95: row.get(8)?,
+                    total_ms_p99: row.get(9)?,
+                    total_ms_min: row.get(10)?,
+                    total_ms_max: row.get(11)?,
+                    db_ms_sum: row.get(12)?,
+                    db_count_sum: row.get(13)?,
+                })
+            })
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows.len(), 2);
+
+        let orders = &rows[0];
+        assert_eq!(orders.hour, "2023-11-14T22:00:00Z");
+        assert_eq!(
+            (orders.path.as_str(), orders.method.as_str()),
+            ("GET /orders", "GET")
+        );
+        // Spans 1 and 2, plus the request standing in for trace 3
+        assert_eq!(orders.request_count, 3);
+        assert_eq!(orders.error_count, 1);
+        assert_eq!(orders.total_ms_sum, 450.0);
+        assert_eq!(orders.total_ms_p50, Some(100.0));
+        assert_eq!(orders.total_ms_p95, Some(300.0));
+        assert_eq!(
+            (orders.total_ms_min, orders.total_ms_max),
+            (Some(50.0), Some(300.0))
+        );
+        assert_eq!(orders.db_ms_sum, 15.0);
+        assert_eq!(orders.db_count_sum, 2);
+
+        assert_eq!(rows[1].path, "/health");
+        assert_eq!(rows[1].request_count, 1);
+    }
+
+    #[test]
+    fn test_rollup_routes_match_raw_routes() {
+        let pool = crate::db::test_pool();
+        let mut spans = Vec::new();
+        for (trace, ms) in [(1u8, 120), (2, 80), (3, 410), (4, 95), (5, 60)] {
+            let mut root = otlp_span(trace, 1, None, "GET /orders", ms);
+            if trace == 3 {
+                root["status"] = serde_json::json!({"code": 2});
+            }
+            spans.push(root);
+            for query in 0..trace {
+                spans.push(otlp_span(trace, 10 + query, Some(1), "SELECT", 4));
+            }
+        }
+        spans.push(otlp_span(6, 1, None, "GET /health", 2));
+        spans.push(otlp_span(6, 2, Some(1), "SELECT 1", 1));
+        let batch: span::OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+            "resourceSpans": [{"scopeSpans": [{"spans": spans}]}]
+        }))
+        .unwrap();
+        span::insert_otlp_batch(&pool, &batch, None).unwrap();
+
+        let at = DateTime::parse_from_rfc3339("2023-11-14T22:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc);
+        hourly_at(&pool, at, ErrorPolicy::default()).unwrap();
+
+        let (since, until) = ("2023-11-14T22:00:00Z", Some("2023-11-14T23:00:00Z"));
+        let raw = span::routes_summary(
+            &pool,
+            None,
+            since,
+            until,
+            None,
+            "requests",
+            100,
+            ErrorPolicy::default(),
+        )
+        .unwrap();
+        let rolled =
+            rollup::routes_summary_from_rollups(&pool, since, until, None, "requests", 100)
+                .unwrap();
+        assert_eq!(raw.len(), 2);
+        assert_eq!(rolled.len(), raw.len());
+        for (raw, rolled) in raw.iter().zip(&rolled) {
+            assert_eq!(
+                (&rolled.path, &rolled.method, rolled.request_count),
+                (&raw.path, &raw.method, raw.request_count)
+            );
+            assert_eq!(
+                (rolled.avg_ms, rolled.p95_ms, rolled.p99_ms),
+                (raw.avg_ms, raw.p95_ms, raw.p99_ms),
+                "{}",
+                raw.path
+            );
+            assert_eq!((rolled.min_ms, rolled.max_ms), (raw.min_ms, raw.max_ms));
+            assert_eq!(
+                (rolled.avg_db_ms, rolled.avg_db_count),
+                (raw.avg_db_ms, raw.avg_db_count)
+            );
+            assert_eq!(rolled.error_count, raw.error_count);
+            assert_eq!(rolled.error_rate, raw.error_rate);
+        }
+        assert_eq!(rolled[0].error_count, 1);
+        assert_eq!(
+            rollup::routes_count_from_rollups(&pool, since, until, Some("orders")).unwrap(),
+            1
+        );
+
+        // Once retention deletes the spans, only the rollups still have the hour
+        pool.get()
+            .unwrap()
+            .execute("DELETE FROM spans", [])
+            .unwrap();
+        assert!(
+            span::routes_summary(
+                &pool,
+                None,
+                since,
+                until,
+                None,
+                "requests",
+                100,
+                ErrorPolicy::default()
+            )
+            .unwrap()
+            .is_empty()
+        );
+        assert_eq!(
+            rollup::routes_summary_from_rollups(&pool, since, until, None, "p95", 100)
+                .unwrap()
+                .len(),
+            2
+        );
+    }
+}
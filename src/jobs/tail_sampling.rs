@@ -0,0 +1,78 @@
+use crate::{
+    DbPool,
+    config::Config,
+    models::{
+        project::{self, TailSampling},
+        span,
+    },
+};
+use chrono::{Duration, Utc};
+
+/// Traces younger than this may still be receiving spans, so they aren't judged yet
+const SETTLE_MINUTES: i64 = 60;
+
+/// Prune complete traces the tail sampling rules don't keep, project by
+/// project, plus traces without a project under the configured rules
+pub fn run(pool: &DbPool, config: &Config) -> anyhow::Result<()> {
+    let defaults = TailSampling::from_config(config);
+    let before = (Utc::now() - Duration::minutes(SETTLE_MINUTES)).to_rfc3339();
+    let project_ids = project::list_all(pool)?
+        .into_iter()
+        .map(|p| Some(p.id))
+        .chain([None]);
+
+    let mut deleted = 0;
+    for project_id in project_ids {
+        let rules = project::tail_sampling(pool, project_id, defaults);
+        if rules.enabled() {
+            let policy = project::error_policy(pool, project_id, config.error_status_policy);
+            deleted += span::tail_sample(pool, project_id, &rules, &before, policy)?;
+        }
+    }
+    if deleted > 0 {
+        tracing::info!("Tail sampling dropped {} traces", deleted);
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_project_rules_override_environment() {
+        let pool = crate::db::test_pool();
+        let (shop, _) = project::create(&pool, "Shop").unwrap();
+        let (blog, _) = project::create(&pool, "Blog").unwrap();
+        project::set_tail_sampling(&pool, shop.id, Some(0.0), Some(200.0)).unwrap();
+        {
+            let conn = pool.get().unwrap();
+            for (trace_id, project_id, duration_ms) in [
+                ("shop-fast", shop.id, 20.0),
+                ("shop-slow", shop.id, 300.0),
+                ("blog-fast", blog.id, 20.0),
+            ] {
+                conn.execute(
+                    r#"INSERT INTO trace_summaries (trace_id, project_id, root_span_name,
+                           duration_ms, span_count, happened_at)
+                       VALUES (?1, ?2, 'GET /', ?3, 1, '2026-01-04T10:00:00.000Z')"#,
+                    rusqlite::params![trace_id, project_id, duration_ms],
+                )
+                .unwrap();
+            }
+        }
+
+        run(&pool, &Config::defaults()).unwrap();
+        let kept: Vec<String> = pool
+            .get()
+            .unwrap()
+            .prepare("SELECT trace_id FROM trace_summaries ORDER BY trace_id")
+            .unwrap()
+            .query_map([], |row| row.get(0))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        // The blog follows the config, which keeps everything by default
+        assert_eq!(kept, ["blog-fast", "shop-slow"]);
+    }
+}
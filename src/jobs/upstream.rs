@@ -0,0 +1,287 @@
+use std::time::Duration;
+
+use reqwest::StatusCode;
+
+use crate::{DbPool, config::Config, models::span};
+
+/// Spans read per export round
+const BATCH_SIZE: i64 = 500;
+/// Pause between rounds once the backlog is drained
+const POLL_INTERVAL: Duration = Duration::from_secs(10);
+/// Longest wait between retries while upstream is failing
+const MAX_BACKOFF: Duration = Duration::from_secs(300);
+
+/// Where and how much to forward, from `UPSTREAM_OTLP_*` config
+#[derive(Clone, Debug)]
+pub struct Upstream {
+    pub endpoint: String,
+    pub headers: Vec<(String, String)>,
+    pub sample_rate: f64,
+}
+
+impl Upstream {
+    pub fn from_config(config: &Config) -> Option<Self> {
+        Some(Self {
+            endpoint: config.upstream_otlp_endpoint.clone()?,
+            headers: config.upstream_otlp_headers.clone(),
+            sample_rate: config.upstream_sample_rate,
+        })
+    }
+}
+
+pub fn spawn(pool: DbPool, upstream: Upstream) {
+    if let Err(e) = span::start_export(&pool) {
+        tracing::error!("Upstream exporter disabled: {}", e);
+        return;
+    }
+    tokio::spawn(async move {
+        let client = match reqwest::Client::builder()
+            .timeout(Duration::from_secs(30))
+            .build()
+        {
+            Ok(client) => client,
+            Err(e) => {
+                tracing::error!("Upstream exporter disabled: {}", e);
+                return;
+            }
+        };
+        tracing::info!(
+            "Forwarding {}% of traces to {}",
+            upstream.sample_rate * 100.0,
+            upstream.endpoint
+        );
+
+        let mut failures = 0;
+        loop {
+            let delay = match export_batch(&pool, &client, &upstream).await {
+                // More backlog waiting: keep going
+                Ok(read) if read as i64 == BATCH_SIZE => {
+                    failures = 0;
+                    Duration::ZERO
+                }
+                Ok(_) => {
+                    failures = 0;
+                    POLL_INTERVAL
+                }
+                Err(e) => {
+                    failures += 1;
+                    let delay = backoff(failures);
+                    tracing::warn!("Upstream export failed, retrying in {:?}: {}", delay, e);
+                    delay
+                }
+            };
+            tokio::time::sleep(delay).await;
+        }
+    });
+}
+
+/// Doubling delay after consecutive failures, capped at `MAX_BACKOFF`
+fn backoff(failures: u32) -> Duration {
+    POLL_INTERVAL
+        .saturating_mul(2u32.saturating_pow(failures.saturating_sub(1)))
+        .min(MAX_BACKOFF)
+}
+
+/// Forward one batch of unexported spans and mark them exported. Spans left
+/// out by sampling are marked too. Returns how many spans were read; on a
+/// network error or retryable status nothing is marked, so the batch is retried.
+pub async fn export_batch(
+    pool: &DbPool,
+    client: &reqwest::Client,
+    upstream: &Upstream,
+) -> anyhow::Result<usize> {
+    let spans = span::unexported_spans(pool, BATCH_SIZE)?;
+    if spans.is_empty() {
+        return Ok(0);
+    }
+    let read = spans.len();
+    let last_id = spans.iter().map(|s| s.id).max().unwrap_or(0);
+    let forwarded: Vec<_> = spans
+        .into_iter()
+        .filter(|s| span::trace_sampled(&s.trace_id, upstream.sample_rate))
+        .map(|s| (s.resource_json, s.span))
+        .collect();
+
+    if !forwarded.is_empty() {
+        let count = forwarded.len();
+        let mut request = client
+            .post(&upstream.endpoint)
+            .json(&span::otlp_request(forwarded));
+        for (key, value) in &upstream.headers {
+            request = request.header(key, value);
+        }
+        let status = request.send().await?.status();
+
+        if status.is_success() {
+            tracing::debug!("Forwarded {} spans upstream", count);
+        } else if status.is_client_error()
+            && status != StatusCode::REQUEST_TIMEOUT
+            && status != StatusCode::TOO_MANY_REQUESTS
+        {
+            // Resending won't help, and holding the batch would stall the queue
+            tracing::warn!(
+                "Upstream rejected {} spans with {}, dropping",
+                count,
+                status
+            );
+        } else {
+            anyhow::bail!("upstream responded {}", status);
+        }
+    }
+
+    span::mark_exported(pool, last_id)?;
+    Ok(read)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Json, Router, extract::State, http::HeaderMap, routing::post};
+    use std::sync::{Arc, Mutex};
+
+    #[derive(Clone, Default)]
+    struct MockUpstream {
+        status: Arc<Mutex<u16>>,
+        received: Arc<Mutex<Vec<(HeaderMap, serde_json::Value)>>>,
+    }
+
+    async fn receive(
+        State(mock): State<MockUpstream>,
+        headers: HeaderMap,
+        Json(body): Json<serde_json::Value>,
+    ) -> axum::http::StatusCode {
+        mock.received.lock().unwrap().push((headers, body));
+        axum::http::StatusCode::from_u16(*mock.status.lock().unwrap()).unwrap()
+    }
+
+    async fn start_mock(mock: MockUpstream) -> String {
+        let app = Router::new()
+            .route("/v1/traces", post(receive))
+            .with_state(mock);
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let addr = listener.local_addr().unwrap();
+        tokio::spawn(async move { axum::serve(listener, app).await.unwrap() });
+        format!("http://{}/v1/traces", addr)
+    }
+
+    fn ingest(pool: &DbPool) {
+        let batch: span::OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+            "resourceSpans": [{
+                "resource": {"attributes": [
+                    {"key": "service.name", "value": {"stringValue": "shop"}}
+                ]},
+                "scopeSpans": [{"spans": [
+                    {
+                        "traceId": "q83vEjRWeJCrze8SNFZ4kA==",
+                        "spanId": "ESIzRFVmd4g=",
+                        "name": "GET /",
+                        "kind": 2,
+                        "startTimeUnixNano": "1700000000000000000",
+                        "endTimeUnixNano": "1700000000100000000"
+                    },
+                    {
+                        "traceId": "q83vEjRWeJCrze8SNFZ4kA==",
+                        "spanId": "qrvM3e7/ABE=",
+                        "parentSpanId": "ESIzRFVmd4g=",
+                        "name": "SELECT",
+                        "kind": 3,
+                        "startTimeUnixNano": "1700000000010000000",
+                        "endTimeUnixNano": "1700000000020000000"
+                    }
+                ]}]
+            }]
+        }))
+        .unwrap();
+        span::insert_otlp_batch(pool, &batch, None).unwrap();
+    }
+
+    #[tokio::test]
+    async fn test_export_retries_until_upstream_accepts() {
+        let mock = MockUpstream::default();
+        *mock.status.lock().unwrap() = 503;
+        let upstream = Upstream {
+            endpoint: start_mock(mock.clone()).await,
+            headers: vec![("x-api-key".to_string(), "secret".to_string())],
+            sample_rate: 1.0,
+        };
+        let pool = crate::db::test_pool();
+        let client = reqwest::Client::new();
+        ingest(&pool);
+
+        // Upstream down: nothing is marked, so the same spans go again
+        assert!(export_batch(&pool, &client, &upstream).await.is_err());
+        assert_eq!(span::unexported_spans(&pool, 10).unwrap().len(), 2);
+
+        *mock.status.lock().unwrap() = 200;
+        assert_eq!(export_batch(&pool, &client, &upstream).await.unwrap(), 2);
+        assert!(span::unexported_spans(&pool, 10).unwrap().is_empty());
+        assert_eq!(export_batch(&pool, &client, &upstream).await.unwrap(), 0);
+
+        let received = mock.received.lock().unwrap();
+        assert_eq!(received.len(), 2);
+        let (headers, body) = &received[1];
+        assert_eq!(headers["x-api-key"], "secret");
+        let spans = &body["resourceSpans"][0]["scopeSpans"][0]["spans"];
+        assert_eq!(spans.as_array().unwrap().len(), 2);
+        assert_eq!(spans[0]["traceId"], "q83vEjRWeJCrze8SNFZ4kA==");
+        assert_eq!(
+            body["resourceSpans"][0]["resource"]["attributes"][0]["value"]["stringValue"],
+            "shop"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_rejected_batch_is_dropped_not_retried() {
+        let mock = MockUpstream::default();
+        *mock.status.lock().unwrap() = 400;
+        let upstream = Upstream {
+            endpoint: start_mock(mock.clone()).await,
+            headers: vec![],
+            sample_rate: 1.0,
+        };
+        let pool = crate::db::test_pool();
+        ingest(&pool);
+
+        let client = reqwest::Client::new();
+        assert_eq!(export_batch(&pool, &client, &upstream).await.unwrap(), 2);
+        assert!(span::unexported_spans(&pool, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_enabling_export_skips_spans_stored_before() {
+        let pool = crate::db::test_pool();
+        ingest(&pool);
+        span::start_export(&pool).unwrap();
+        assert!(span::unexported_spans(&pool, 10).unwrap().is_empty());
+
+        let batch: span::OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+            "resourceSpans": [{"scopeSpans": [{"spans": [{
+                "traceId": "4bf92f3577b34da6a3ce929d0e0e4736",
+                "spanId": "00f067aa0ba902b7",
+                "name": "GET /cart",
+                "kind": 2,
+                "startTimeUnixNano": "1700000001000000000",
+                "endTimeUnixNano": "1700000001100000000"
+            }]}]}]
+        }))
+        .unwrap();
+        span::insert_otlp_batch(&pool, &batch, None).unwrap();
+        let pending = span::unexported_spans(&pool, 10).unwrap();
+        assert_eq!(pending.len(), 1);
+        assert_eq!(pending[0].span.name, "GET /cart");
+
+        // Turned off and on again: what arrived in between isn't replayed
+        span::stop_export(&pool).unwrap();
+        ingest(&pool);
+        span::start_export(&pool).unwrap();
+        assert!(span::unexported_spans(&pool, 10).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_backoff_doubles_up_to_cap() {
+        assert_eq!(backoff(1), POLL_INTERVAL);
+        assert_eq!(backoff(2), POLL_INTERVAL * 2);
+        assert_eq!(backoff(3), POLL_INTERVAL * 4);
+        assert_eq!(backoff(20), MAX_BACKOFF);
+    }
+}
@@ -1,5 +1,5 @@
 use clap::{Parser, Subcommand};
-use miniapm::{config::Config, db, server};
+use miniapm::{config::Config, db, models::api_token::TokenScope, server};
 use tracing_subscriber::{layer::SubscriberExt, util::SubscriberInitExt};
 
 #[derive(Parser)]
@@ -24,6 +24,69 @@ enum Commands {
     },
     /// List all API keys
     ListKeys,
+    /// Create a scoped API token for a project
+    CreateToken {
+        /// Name for the token
+        name: String,
+        /// Token scope: "ingest" (write to /ingest) or "read" (query /api)
+        #[arg(short, long, default_value = "read")]
+        scope: String,
+        /// Project slug (defaults to the default project)
+        #[arg(short, long)]
+        project: Option<String>,
+    },
+    /// List all scoped API tokens
+    ListTokens,
+    /// Import spans from an OTLP/HTTP JSON file
+    Import {
+        /// Path to the OTLP JSON request body
+        #[arg(short, long)]
+        file: std::path::PathBuf,
+        /// Project slug (defaults to the default project)
+        #[arg(short, long)]
+        project: Option<String>,
+    },
+    /// Give a project a new API key, keeping the old one valid for a while
+    RotateKey {
+        /// Project slug
+        #[arg(short, long)]
+        project: String,
+        /// How long the old key keeps working, e.g. 30m, 24h or 7d
+        #[arg(short, long, default_value = "24h")]
+        grace: String,
+    },
+    /// Delete one project's spans, requests and errors
+    Prune {
+        /// Project slug
+        #[arg(short, long)]
+        project: String,
+        /// Only delete data older than this date (YYYY-MM-DD or RFC 3339)
+        #[arg(short, long)]
+        before: Option<String>,
+        /// Confirm the deletion
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Recompute error fingerprints from each error's first occurrence and
+    /// merge errors that now share one
+    Refingerprint {
+        /// Project slug (defaults to every project)
+        #[arg(short, long)]
+        project: Option<String>,
+        /// Also replace fingerprints that aren't location ones, such as
+        /// those a client chose
+        #[arg(long)]
+        overwrite_custom: bool,
+        /// Confirm the merge
+        #[arg(long)]
+        yes: bool,
+    },
+    /// Validate the configuration, or print it with --dump
+    Config {
+        /// Print the effective settings (secrets redacted)
+        #[arg(long)]
+        dump: bool,
+    },
 }
 
 #[tokio::main]
@@ -42,6 +105,7 @@ async fn main() -> anyhow::Result<()> {
 
     match cli.command {
         Some(Commands::Server { port }) => {
+            anyhow::ensure!(port != 0, "--port must be between 1 and 65535");
             let pool = db::init(&config)?;
             server::run(pool, config, port).await?;
         }
@@ -70,6 +134,160 @@ async fn main() -> anyhow::Result<()> {
                 }
             }
         }
+        Some(Commands::CreateToken {
+            name,
+            scope,
+            project,
+        }) => {
+            let Some(scope) = TokenScope::parse(&scope) else {
+                anyhow::bail!("Invalid scope '{}': expected 'ingest' or 'read'", scope);
+            };
+            let pool = db::init(&config)?;
+            let project = match project {
+                Some(slug) => miniapm::models::project::find_by_slug(&pool, &slug)?
+                    .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", slug))?,
+                None => miniapm::models::project::ensure_default_project(&pool)?,
+            };
+            let token = miniapm::models::api_token::create(&pool, Some(project.id), &name, scope)?;
+            println!("API token created successfully!\n");
+            println!("Name:    {}", name);
+            println!("Project: {}", project.slug);
+            println!("Scope:   {}", scope.as_str());
+            println!("Token:   {}", token);
+            println!("\nStore this token securely - it cannot be retrieved later.");
+        }
+        Some(Commands::ListTokens) => {
+            let pool = db::init(&config)?;
+            let tokens = miniapm::models::api_token::list(&pool)?;
+            if tokens.is_empty() {
+                println!("No API tokens found.");
+            } else {
+                println!("API Tokens:");
+                for t in tokens {
+                    println!(
+                        "  - {} [{}] (created: {}, last used: {})",
+                        t.name,
+                        t.scope.as_str(),
+                        t.created_at,
+                        t.last_used_at.as_deref().unwrap_or("never")
+                    );
+                }
+            }
+        }
+        Some(Commands::Import { file, project }) => {
+            let json = std::fs::read_to_string(&file)
+                .map_err(|e| anyhow::anyhow!("Cannot read {}: {}", file.display(), e))?;
+            let request: miniapm::models::span::OtlpTraceRequest = serde_json::from_str(&json)
+                .map_err(|e| {
+                    anyhow::anyhow!(
+                        "Invalid OTLP JSON in {}: {}\n{}",
+                        file.display(),
+                        e,
+                        line_context(&json, e.line(), e.column())
+                    )
+                })?;
+            let pool = db::init(&config)?;
+            let project = match project {
+                Some(slug) => miniapm::models::project::find_by_slug(&pool, &slug)?
+                    .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", slug))?,
+                None => miniapm::models::project::ensure_default_project(&pool)?,
+            };
+            let options = miniapm::models::span::IngestOptions::from_config(&config);
+            let outcome = miniapm::models::span::insert_otlp_batch_with(
+                &pool,
+                &request,
+                Some(project.id),
+                &options,
+            )?;
+            println!(
+                "Imported {} spans into '{}' ({} rejected, {} errors extracted)",
+                outcome.accepted, project.slug, outcome.rejected, outcome.errors_extracted
+            );
+        }
+        Some(Commands::RotateKey { project, grace }) => {
+            let grace = parse_grace(&grace)?;
+            let pool = db::init(&config)?;
+            let project = miniapm::models::project::find_by_slug(&pool, &project)?
+                .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project))?;
+            let api_key = miniapm::models::project::rotate_api_key(&pool, project.id, grace)?;
+            println!("New API key for project '{}': {}", project.slug, api_key);
+            println!(
+                "The previous key keeps working until {}.",
+                (chrono::Utc::now() + grace).format("%Y-%m-%d %H:%M UTC")
+            );
+        }
+        Some(Commands::Prune {
+            project,
+            before,
+            yes,
+        }) => {
+            let before = before.as_deref().map(parse_cutoff).transpose()?;
+            let pool = db::init(&config)?;
+            let project = miniapm::models::project::find_by_slug(&pool, &project)?
+                .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", project))?;
+            let scope = match &before {
+                Some(cutoff) => format!("data older than {}", cutoff),
+                None => "all data".to_string(),
+            };
+            if !yes {
+                anyhow::bail!(
+                    "This deletes {} for project '{}'. Re-run with --yes to confirm.",
+                    scope,
+                    project.slug
+                );
+            }
+
+            let pruned =
+                miniapm::models::project::prune_data(&pool, project.id, before.as_deref())?;
+            println!("Pruned {} for project '{}':", scope, project.slug);
+            for (table, count) in pruned.by_table() {
+                println!("  {:<18} {}", table, count);
+            }
+        }
+        Some(Commands::Refingerprint {
+            project,
+            overwrite_custom,
+            yes,
+        }) => {
+            let pool = db::init(&config)?;
+            let project = match project {
+                Some(slug) => Some(
+                    miniapm::models::project::find_by_slug(&pool, &slug)?
+                        .ok_or_else(|| anyhow::anyhow!("Project '{}' not found", slug))?,
+                ),
+                None => None,
+            };
+            let scope = match &project {
+                Some(project) => format!("project '{}'", project.slug),
+                None => "every project".to_string(),
+            };
+            if !yes {
+                anyhow::bail!(
+                    "This regroups the errors of {} and deletes the ones merged away. Re-run with --yes to confirm.",
+                    scope
+                );
+            }
+
+            let outcome = miniapm::models::error::refingerprint_errors(
+                &pool,
+                project.map(|p| p.id),
+                overwrite_custom,
+            )?;
+            println!(
+                "Refingerprinted errors of {}: {} updated, {} merged, {} custom fingerprints kept",
+                scope, outcome.updated, outcome.merged, outcome.skipped
+            );
+        }
+        Some(Commands::Config { dump }) => {
+            // Loading already validated it
+            if dump {
+                for (key, value) in config.dump() {
+                    println!("{}={}", key, value);
+                }
+            } else {
+                println!("Configuration OK");
+            }
+        }
         None => {
             // Default to server
             let pool = db::init(&config)?;
@@ -79,3 +297,58 @@ async fn main() -> anyhow::Result<()> {
 
     Ok(())
 }
+
+/// Normalize a `--before` cutoff to RFC 3339 so it compares with stored timestamps
+fn parse_cutoff(value: &str) -> anyhow::Result<String> {
+    if let Ok(date) = chrono::NaiveDate::parse_from_str(value, "%Y-%m-%d") {
+        return Ok(date.and_time(chrono::NaiveTime::MIN).and_utc().to_rfc3339());
+    }
+    chrono::DateTime::parse_from_rfc3339(value)
+        .map(|dt| dt.with_timezone(&chrono::Utc).to_rfc3339())
+        .map_err(|_| {
+            anyhow::anyhow!(
+                "Invalid --before '{}': expected YYYY-MM-DD or RFC 3339",
+                value
+            )
+        })
+}
+
+/// `<n>s`, `<n>m`, `<n>h` or `<n>d`
+fn parse_grace(value: &str) -> anyhow::Result<chrono::Duration> {
+    let invalid = || {
+        anyhow::anyhow!(
+            "Invalid --grace '{}': expected a number followed by s, m, h or d (e.g. 24h)",
+            value
+        )
+    };
+    let value = value.trim();
+    let unit = value.chars().last().ok_or_else(invalid)?;
+    let amount: i64 = value[..value.len() - unit.len_utf8()]
+        .parse()
+        .map_err(|_| invalid())?;
+    if amount < 0 {
+        return Err(invalid());
+    }
+    match unit {
+        's' => chrono::Duration::try_seconds(amount),
+        'm' => chrono::Duration::try_minutes(amount),
+        'h' => chrono::Duration::try_hours(amount),
+        'd' => chrono::Duration::try_days(amount),
+        _ => None,
+    }
+    .ok_or_else(invalid)
+}
+
+/// Show the offending line of a JSON parse error with a caret under the column
+fn line_context(json: &str, line: usize, column: usize) -> String {
+    let Some(text) = json.lines().nth(line.saturating_sub(1)) else {
+        return String::new();
+    };
+    let prefix = format!("{:>5} | ", line);
+    format!(
+        "{}{}\n{}^",
+        prefix,
+        text,
+        " ".repeat(prefix.len() + column.saturating_sub(1))
+    )
+}
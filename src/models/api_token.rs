@@ -0,0 +1,125 @@
+use crate::DbPool;
+use chrono::Utc;
+use rand::Rng;
+use sha2::{Digest, Sha256};
+
+const PREFIX: &str = "mini_apm_t_";
+
+/// What a token is allowed to do
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenScope {
+    /// Write access to `/ingest/*`
+    Ingest,
+    /// Read access to `/api/*`
+    Read,
+}
+
+impl TokenScope {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            TokenScope::Ingest => "ingest",
+            TokenScope::Read => "read",
+        }
+    }
+
+    pub fn parse(s: &str) -> Option<Self> {
+        match s {
+            "ingest" => Some(TokenScope::Ingest),
+            "read" => Some(TokenScope::Read),
+            _ => None,
+        }
+    }
+}
+
+#[derive(Debug, Clone)]
+pub struct ApiToken {
+    pub id: i64,
+    pub project_id: Option<i64>,
+    pub name: String,
+    pub scope: TokenScope,
+    pub created_at: String,
+    pub last_used_at: Option<String>,
+}
+
+/// Mint a scoped token; the raw value is only returned here
+pub fn create(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    name: &str,
+    scope: TokenScope,
+) -> anyhow::Result<String> {
+    let conn = pool.get()?;
+
+    let random_bytes: [u8; 24] = rand::thread_rng().r#gen();
+    let raw_token = format!("{}{}", PREFIX, hex::encode(random_bytes));
+
+    conn.execute(
+        "INSERT INTO api_tokens (project_id, name, token_hash, scope, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        rusqlite::params![
+            project_id,
+            name,
+            hash_token(&raw_token),
+            scope.as_str(),
+            Utc::now().to_rfc3339()
+        ],
+    )?;
+
+    Ok(raw_token)
+}
+
+/// Look up a raw token, recording its use
+pub fn verify(pool: &DbPool, raw_token: &str) -> anyhow::Result<Option<ApiToken>> {
+    if raw_token.is_empty() {
+        return Ok(None);
+    }
+
+    let conn = pool.get()?;
+    let token_hash = hash_token(raw_token);
+
+    let token = conn
+        .query_row(
+            "SELECT id, project_id, name, scope, created_at, last_used_at FROM api_tokens WHERE token_hash = ?1",
+            [&token_hash],
+            map_token,
+        )
+        .ok();
+
+    if token.is_some() {
+        let _ = conn.execute(
+            "UPDATE api_tokens SET last_used_at = ?1 WHERE token_hash = ?2",
+            (Utc::now().to_rfc3339(), &token_hash),
+        );
+    }
+
+    Ok(token)
+}
+
+pub fn list(pool: &DbPool) -> anyhow::Result<Vec<ApiToken>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT id, project_id, name, scope, created_at, last_used_at FROM api_tokens ORDER BY created_at",
+    )?;
+
+    let tokens = stmt
+        .query_map([], map_token)?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(tokens)
+}
+
+fn map_token(row: &rusqlite::Row) -> rusqlite::Result<ApiToken> {
+    Ok(ApiToken {
+        id: row.get(0)?,
+        project_id: row.get(1)?,
+        name: row.get(2)?,
+        scope: TokenScope::parse(&row.get::<_, String>(3)?).unwrap_or(TokenScope::Read),
+        created_at: row.get(4)?,
+        last_used_at: row.get(5)?,
+    })
+}
+
+fn hash_token(raw_token: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(raw_token.as_bytes());
+    hex::encode(hasher.finalize())
+}
@@ -0,0 +1,31 @@
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+
+/// Position in a newest-first list for keyset pagination: the timestamp and id
+/// of the last row shown. Unlike an offset it stays put when new rows arrive
+/// at the top while someone is paging.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Cursor {
+    pub happened_at: String,
+    pub id: String,
+}
+
+impl Cursor {
+    /// Opaque, URL-safe form used in `?after=` links
+    pub fn encode(&self) -> String {
+        URL_SAFE_NO_PAD.encode(format!("{}\n{}", self.happened_at, self.id))
+    }
+
+    /// `None` for anything `encode` didn't produce
+    pub fn decode(value: &str) -> Option<Self> {
+        let bytes = URL_SAFE_NO_PAD.decode(value.trim()).ok()?;
+        let decoded = String::from_utf8(bytes).ok()?;
+        let (happened_at, id) = decoded.split_once('\n')?;
+        if happened_at.is_empty() || id.is_empty() {
+            return None;
+        }
+        Some(Self {
+            happened_at: happened_at.to_string(),
+            id: id.to_string(),
+        })
+    }
+}
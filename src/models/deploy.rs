@@ -1,6 +1,9 @@
 use crate::DbPool;
-use chrono::Utc;
+use crate::models::error as app_error;
+use chrono::{DateTime, Duration, Utc};
+use rusqlite::OptionalExtension;
 use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Deploy {
@@ -12,6 +15,10 @@ pub struct Deploy {
     pub deployed_at: String,
     pub description: Option<String>,
     pub deployer: Option<String>,
+    /// Set by an operator once the deploy has been rolled back
+    pub rolled_back: bool,
+    /// Free-form operator note, e.g. why it was rolled back
+    pub notes: Option<String>,
 }
 
 impl Deploy {
@@ -68,7 +75,7 @@ pub fn list(pool: &DbPool, project_id: Option<i64>, limit: i64) -> anyhow::Resul
         r#"
         SELECT id, project_id, git_sha, version, env,
                strftime('%Y-%m-%d %H:%M', deployed_at) as deployed_at,
-               description, deployer
+               description, deployer, rollback, notes
         FROM deploys
         WHERE (?1 IS NULL OR project_id = ?1)
         ORDER BY deployed_at DESC
@@ -87,6 +94,8 @@ pub fn list(pool: &DbPool, project_id: Option<i64>, limit: i64) -> anyhow::Resul
                 deployed_at: row.get(5)?,
                 description: row.get(6)?,
                 deployer: row.get(7)?,
+                rolled_back: row.get(8)?,
+                notes: row.get(9)?,
             })
         })?
         .collect::<Result<Vec<_>, _>>()?;
@@ -94,6 +103,259 @@ pub fn list(pool: &DbPool, project_id: Option<i64>, limit: i64) -> anyhow::Resul
     Ok(deploys)
 }
 
+/// List deploys newest first, optionally restricted to one environment
+pub fn list_paginated(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    env: Option<&str>,
+    limit: i64,
+    offset: i64,
+) -> anyhow::Result<Vec<Deploy>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT id, project_id, git_sha, version, env,
+               strftime('%Y-%m-%d %H:%M', deployed_at) as deployed_at,
+               description, deployer, rollback, notes
+        FROM deploys
+        WHERE (?1 IS NULL OR project_id = ?1)
+          AND (?2 IS NULL OR env = ?2)
+        ORDER BY deployed_at DESC, id DESC
+        LIMIT ?3 OFFSET ?4
+        "#,
+    )?;
+
+    let deploys = stmt
+        .query_map(rusqlite::params![project_id, env, limit, offset], |row| {
+            Ok(Deploy {
+                id: row.get(0)?,
+                project_id: row.get(1)?,
+                git_sha: row.get(2)?,
+                version: row.get(3)?,
+                env: row.get(4)?,
+                deployed_at: row.get(5)?,
+                description: row.get(6)?,
+                deployer: row.get(7)?,
+                rolled_back: row.get(8)?,
+                notes: row.get(9)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(deploys)
+}
+
+pub fn count(pool: &DbPool, project_id: Option<i64>, env: Option<&str>) -> anyhow::Result<i64> {
+    let conn = pool.get()?;
+    let count: i64 = conn.query_row(
+        "SELECT COUNT(*) FROM deploys WHERE (?1 IS NULL OR project_id = ?1) AND (?2 IS NULL OR env = ?2)",
+        rusqlite::params![project_id, env],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+/// Environments that have at least one deploy, for the filter dropdown
+pub fn distinct_envs(pool: &DbPool, project_id: Option<i64>) -> anyhow::Result<Vec<String>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        "SELECT DISTINCT env FROM deploys WHERE env IS NOT NULL AND (?1 IS NULL OR project_id = ?1) ORDER BY env",
+    )?;
+    let envs = stmt
+        .query_map(rusqlite::params![project_id], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(envs)
+}
+
+/// Length of the windows compared before and after a deploy
+pub const IMPACT_WINDOW_MINUTES: i64 = 60;
+
+/// Error rate and latency in the window after a deploy, next to the same
+/// length of window before it
+#[derive(Debug, Clone, Serialize)]
+pub struct DeployImpact {
+    pub requests_before: i64,
+    pub requests_after: i64,
+    /// Error occurrences per 100 requests
+    pub error_rate_before: f64,
+    pub error_rate_after: f64,
+    pub p95_before_ms: i64,
+    pub p95_after_ms: i64,
+}
+
+impl DeployImpact {
+    pub fn error_rate_delta(&self) -> f64 {
+        ((self.error_rate_after - self.error_rate_before) * 10.0).round() / 10.0
+    }
+
+    pub fn p95_delta_ms(&self) -> i64 {
+        self.p95_after_ms - self.p95_before_ms
+    }
+
+    /// Whether there was traffic on both sides to compare
+    pub fn is_comparable(&self) -> bool {
+        self.requests_before > 0 && self.requests_after > 0
+    }
+
+    /// Error rate went up by more than a point, or p95 by more than 20%
+    pub fn is_regression(&self) -> bool {
+        self.is_comparable()
+            && (self.error_rate_delta() > 1.0
+                || self.p95_after_ms as f64 > self.p95_before_ms as f64 * 1.2)
+    }
+}
+
+/// Error occurrences per 100 requests
+pub fn error_rate(errors: i64, requests: i64) -> f64 {
+    if requests == 0 {
+        0.0
+    } else {
+        errors as f64 * 100.0 / requests as f64
+    }
+}
+
+/// Compare error rate and latency around a deploy. Returns None when the
+/// deploy doesn't exist or its timestamp isn't RFC 3339.
+pub fn impact(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    deploy_id: i64,
+) -> anyhow::Result<Option<DeployImpact>> {
+    Ok(impacts(pool, project_id, &[deploy_id])?.remove(&deploy_id))
+}
+
+/// `impact` for several deploys at once, keyed by deploy id. A deploy with
+/// an `env` only counts requests from spans in that environment, and errors
+/// whose occurrence belongs to one of those traces.
+pub fn impacts(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    deploy_ids: &[i64],
+) -> anyhow::Result<HashMap<i64, DeployImpact>> {
+    if deploy_ids.is_empty() {
+        return Ok(HashMap::new());
+    }
+    let conn = pool.get()?;
+
+    let placeholders = deploy_ids.iter().map(|_| "?").collect::<Vec<_>>().join(",");
+    let deploys: Vec<(i64, Option<String>, String)> = conn
+        .prepare(&format!(
+            "SELECT id, env, deployed_at FROM deploys WHERE id IN ({placeholders})"
+        ))?
+        .query_map(rusqlite::params_from_iter(deploy_ids), |row| {
+            Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+        })?
+        .collect::<Result<_, _>>()?;
+
+    // One row of (id, env, window start, deploy time, window end) per deploy
+    let window = Duration::minutes(IMPACT_WINDOW_MINUTES);
+    let mut params: Vec<rusqlite::types::Value> = vec![project_id.into()];
+    let mut rows = Vec::new();
+    for (id, env, deployed_at) in deploys {
+        let Ok(deployed_at) = DateTime::parse_from_rfc3339(&deployed_at) else {
+            continue;
+        };
+        let at = deployed_at.with_timezone(&Utc);
+        let first = params.len() + 1;
+        rows.push(format!(
+            "(?{}, ?{}, ?{}, ?{}, ?{})",
+            first,
+            first + 1,
+            first + 2,
+            first + 3,
+            first + 4
+        ));
+        params.extend([
+            id.into(),
+            env.into(),
+            (at - window).to_rfc3339().into(),
+            at.to_rfc3339().into(),
+            (at + window).to_rfc3339().into(),
+        ]);
+    }
+    if rows.is_empty() {
+        return Ok(HashMap::new());
+    }
+
+    let weight = app_error::OCCURRENCE_WEIGHT;
+    let hourly_counts = app_error::HOURLY_COUNTS_JOIN;
+    let sql = format!(
+        r#"
+        WITH windows(deploy_id, env, since, at, until) AS (VALUES {values}),
+        roots AS (
+            SELECT w.deploy_id, s.happened_at >= w.at AS after_deploy, s.duration_ms
+            FROM windows w
+            JOIN spans s ON s.parent_span_id IS NULL
+                        AND s.happened_at >= w.since AND s.happened_at < w.until
+            WHERE (?1 IS NULL OR s.project_id = ?1)
+              AND (w.env IS NULL OR s.environment = w.env)
+        ),
+        ranked AS (
+            SELECT deploy_id, after_deploy, duration_ms,
+                   ROW_NUMBER() OVER (PARTITION BY deploy_id, after_deploy
+                                      ORDER BY duration_ms) - 1 AS idx,
+                   COUNT(*) OVER (PARTITION BY deploy_id, after_deploy) AS n
+            FROM roots
+        ),
+        latency AS (
+            SELECT deploy_id, after_deploy, MAX(n) AS requests,
+                   MAX(CASE WHEN idx = CAST(ROUND(0.95 * (n - 1)) AS INTEGER)
+                            THEN duration_ms END) AS p95
+            FROM ranked
+            GROUP BY deploy_id, after_deploy
+        ),
+        failures AS (
+            SELECT w.deploy_id, eo.happened_at >= w.at AS after_deploy,
+                   SUM({weight}) AS errors
+            FROM windows w
+            JOIN error_occurrences eo ON eo.happened_at >= w.since AND eo.happened_at < w.until
+            JOIN errors e ON e.id = eo.error_id
+            {hourly_counts}
+            WHERE (?1 IS NULL OR e.project_id = ?1)
+              AND (w.env IS NULL OR EXISTS (
+                  SELECT 1 FROM spans s WHERE s.trace_id = eo.request_id AND s.environment = w.env))
+            GROUP BY w.deploy_id, after_deploy
+        )
+        SELECT w.deploy_id,
+               COALESCE((SELECT requests FROM latency l
+                         WHERE l.deploy_id = w.deploy_id AND NOT l.after_deploy), 0),
+               COALESCE((SELECT requests FROM latency l
+                         WHERE l.deploy_id = w.deploy_id AND l.after_deploy), 0),
+               COALESCE((SELECT p95 FROM latency l
+                         WHERE l.deploy_id = w.deploy_id AND NOT l.after_deploy), 0),
+               COALESCE((SELECT p95 FROM latency l
+                         WHERE l.deploy_id = w.deploy_id AND l.after_deploy), 0),
+               COALESCE((SELECT CAST(ROUND(errors) AS INTEGER) FROM failures f
+                         WHERE f.deploy_id = w.deploy_id AND NOT f.after_deploy), 0),
+               COALESCE((SELECT CAST(ROUND(errors) AS INTEGER) FROM failures f
+                         WHERE f.deploy_id = w.deploy_id AND f.after_deploy), 0)
+        FROM windows w
+        "#,
+        values = rows.join(", "),
+    );
+
+    let mut stmt = conn.prepare(&sql)?;
+    let impacts = stmt
+        .query_map(rusqlite::params_from_iter(params), |row| {
+            let requests_before: i64 = row.get(1)?;
+            let requests_after: i64 = row.get(2)?;
+            Ok((
+                row.get::<_, i64>(0)?,
+                DeployImpact {
+                    requests_before,
+                    requests_after,
+                    error_rate_before: error_rate(row.get(5)?, requests_before),
+                    error_rate_after: error_rate(row.get(6)?, requests_after),
+                    p95_before_ms: row.get::<_, f64>(3)?.round() as i64,
+                    p95_after_ms: row.get::<_, f64>(4)?.round() as i64,
+                },
+            ))
+        })?
+        .collect::<Result<HashMap<_, _>, _>>()?;
+
+    Ok(impacts)
+}
+
 /// Get deploys within a time range for chart markers
 pub fn list_since(
     pool: &DbPool,
@@ -105,7 +367,7 @@ pub fn list_since(
         r#"
         SELECT id, project_id, git_sha, version, env,
                deployed_at,
-               description, deployer
+               description, deployer, rollback, notes
         FROM deploys
         WHERE deployed_at >= ?1 AND (?2 IS NULL OR project_id = ?2)
         ORDER BY deployed_at ASC
@@ -123,6 +385,8 @@ pub fn list_since(
                 deployed_at: row.get(5)?,
                 description: row.get(6)?,
                 deployer: row.get(7)?,
+                rolled_back: row.get(8)?,
+                notes: row.get(9)?,
             })
         })?
         .collect::<Result<Vec<_>, _>>()?;
@@ -138,7 +402,7 @@ pub fn latest(pool: &DbPool, project_id: Option<i64>) -> anyhow::Result<Option<D
             r#"
             SELECT id, project_id, git_sha, version, env,
                    strftime('%Y-%m-%d %H:%M', deployed_at) as deployed_at,
-                   description, deployer
+                   description, deployer, rollback, notes
             FROM deploys
             WHERE (?1 IS NULL OR project_id = ?1)
             ORDER BY deployed_at DESC
@@ -155,6 +419,8 @@ pub fn latest(pool: &DbPool, project_id: Option<i64>) -> anyhow::Result<Option<D
                     deployed_at: row.get(5)?,
                     description: row.get(6)?,
                     deployer: row.get(7)?,
+                    rolled_back: row.get(8)?,
+                    notes: row.get(9)?,
                 })
             },
         )
@@ -163,8 +429,239 @@ pub fn latest(pool: &DbPool, project_id: Option<i64>) -> anyhow::Result<Option<D
     Ok(deploy)
 }
 
+/// Find the deploy for a git_sha (either side may be abbreviated)
+pub fn find_by_sha(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    git_sha: &str,
+) -> anyhow::Result<Option<Deploy>> {
+    let conn = pool.get()?;
+    let deploy = conn
+        .query_row(
+            r#"
+            SELECT id, project_id, git_sha, version, env,
+                   strftime('%Y-%m-%d %H:%M', deployed_at) as deployed_at,
+                   description, deployer, rollback, notes
+            FROM deploys
+            WHERE (git_sha LIKE ?1 || '%' OR ?1 LIKE git_sha || '%')
+              AND git_sha != '' AND ?1 != ''
+              AND (?2 IS NULL OR project_id = ?2)
+            ORDER BY deployed_at ASC
+            LIMIT 1
+            "#,
+            rusqlite::params![git_sha, project_id],
+            |row| {
+                Ok(Deploy {
+                    id: row.get(0)?,
+                    project_id: row.get(1)?,
+                    git_sha: row.get(2)?,
+                    version: row.get(3)?,
+                    env: row.get(4)?,
+                    deployed_at: row.get(5)?,
+                    description: row.get(6)?,
+                    deployer: row.get(7)?,
+                    rolled_back: row.get(8)?,
+                    notes: row.get(9)?,
+                })
+            },
+        )
+        .ok();
+
+    Ok(deploy)
+}
+
+/// Project a deploy belongs to: `None` if there is no such deploy,
+/// `Some(None)` for deploys recorded without a project
+pub fn project_of(pool: &DbPool, id: i64) -> anyhow::Result<Option<Option<i64>>> {
+    let conn = pool.get()?;
+    let project_id = conn
+        .query_row(
+            "SELECT project_id FROM deploys WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(project_id)
+}
+
+/// Mark a deploy rolled back (or not) and replace its note; blank notes are
+/// cleared. Returns false when no such deploy exists.
+pub fn update_deploy(
+    pool: &DbPool,
+    id: i64,
+    rollback: bool,
+    notes: Option<String>,
+) -> anyhow::Result<bool> {
+    let conn = pool.get()?;
+    let notes = notes
+        .map(|n| n.trim().to_string())
+        .filter(|n| !n.is_empty());
+    let updated = conn.execute(
+        "UPDATE deploys SET rollback = ?2, notes = ?3 WHERE id = ?1",
+        rusqlite::params![id, rollback, notes],
+    )?;
+    Ok(updated > 0)
+}
+
 pub fn delete_before(pool: &DbPool, before: &str) -> anyhow::Result<usize> {
     let conn = pool.get()?;
     let deleted = conn.execute("DELETE FROM deploys WHERE deployed_at < ?1", [before])?;
     Ok(deleted)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn record(pool: &DbPool, sha: &str, env: Option<&str>, timestamp: &str) -> i64 {
+        insert(
+            pool,
+            &IncomingDeploy {
+                git_sha: sha.to_string(),
+                version: None,
+                env: env.map(str::to_string),
+                description: None,
+                deployer: None,
+                timestamp: Some(timestamp.to_string()),
+            },
+            None,
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_list_paginated_filters_by_env_and_pages() {
+        let pool = crate::db::test_pool();
+        for i in 0..5 {
+            let env = if i % 2 == 0 { "production" } else { "staging" };
+            record(
+                &pool,
+                &format!("sha{i}"),
+                Some(env),
+                &format!("2026-01-0{}T12:00:00+00:00", i + 1),
+            );
+        }
+        record(&pool, "untagged", None, "2026-01-09T12:00:00+00:00");
+
+        assert_eq!(count(&pool, None, None).unwrap(), 6);
+        assert_eq!(count(&pool, None, Some("production")).unwrap(), 3);
+        assert_eq!(count(&pool, None, Some("qa")).unwrap(), 0);
+        assert_eq!(
+            distinct_envs(&pool, None).unwrap(),
+            vec!["production", "staging"]
+        );
+
+        let shas = |env: Option<&str>, limit: i64, offset: i64| -> Vec<String> {
+            list_paginated(&pool, None, env, limit, offset)
+                .unwrap()
+                .into_iter()
+                .map(|d| d.git_sha)
+                .collect()
+        };
+        assert_eq!(
+            shas(Some("production"), 10, 0),
+            vec!["sha4", "sha2", "sha0"]
+        );
+        // Page boundaries: a full page, the remainder, then nothing
+        assert_eq!(shas(None, 4, 0), vec!["untagged", "sha4", "sha3", "sha2"]);
+        assert_eq!(shas(None, 4, 4), vec!["sha1", "sha0"]);
+        assert!(shas(None, 4, 8).is_empty());
+        assert_eq!(shas(Some("staging"), 1, 1), vec!["sha1"]);
+    }
+
+    #[test]
+    fn test_update_deploy_sets_rollback_and_notes() {
+        let pool = crate::db::test_pool();
+        let id = record(&pool, "abc1234", None, "2026-01-01T12:00:00+00:00");
+        let stored = || latest(&pool, None).unwrap().unwrap();
+        assert!(!stored().rolled_back);
+        assert_eq!(stored().notes, None);
+
+        assert!(update_deploy(&pool, id, true, Some(" Checkout 500s ".to_string())).unwrap());
+        assert!(stored().rolled_back);
+        assert_eq!(stored().notes.as_deref(), Some("Checkout 500s"));
+
+        // Blank notes clear the annotation
+        assert!(update_deploy(&pool, id, false, Some("  ".to_string())).unwrap());
+        assert!(!stored().rolled_back);
+        assert_eq!(stored().notes, None);
+
+        assert!(!update_deploy(&pool, id + 1, true, None).unwrap());
+    }
+
+    #[test]
+    fn test_impact_compares_windows_around_deploy() {
+        let pool = crate::db::test_pool();
+        let at = Utc::now() - Duration::hours(2);
+        let id = record(&pool, "abc1234", None, &at.to_rfc3339());
+
+        {
+            let conn = pool.get().unwrap();
+            let root = |span_id: &str, minutes: i64, duration_ms: f64| {
+                let happened_at = (at + Duration::minutes(minutes))
+                    .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                    .to_string();
+                conn.execute(
+                    "INSERT INTO spans (trace_id, span_id, start_time_unix_nano, end_time_unix_nano,
+                                        duration_ms, name, kind, span_category, happened_at)
+                     VALUES (?1, ?1, 0, 0, ?2, 'GET /', 2, 'http_server', ?3)",
+                    rusqlite::params![span_id, duration_ms, happened_at],
+                )
+                .unwrap();
+            };
+            root("b1", -30, 100.0);
+            root("b2", -10, 100.0);
+            root("a1", 5, 400.0);
+            root("a2", 20, 400.0);
+            // Outside both windows
+            root("old", -90, 5000.0);
+        }
+
+        let result = impact(&pool, None, id).unwrap().unwrap();
+        assert_eq!(result.requests_before, 2);
+        assert_eq!(result.requests_after, 2);
+        assert_eq!(result.p95_before_ms, 100);
+        assert_eq!(result.p95_after_ms, 400);
+        assert_eq!(result.p95_delta_ms(), 300);
+        assert!(result.is_regression());
+
+        assert!(impact(&pool, None, id + 1).unwrap().is_none());
+    }
+
+    #[test]
+    fn test_impacts_only_count_the_deploy_env() {
+        let pool = crate::db::test_pool();
+        let at = Utc::now() - Duration::hours(2);
+        let prod = record(&pool, "abc1234", Some("production"), &at.to_rfc3339());
+        let any = record(&pool, "def5678", None, &at.to_rfc3339());
+
+        {
+            let conn = pool.get().unwrap();
+            let root = |span_id: &str, minutes: i64, environment: &str| {
+                let happened_at = (at + Duration::minutes(minutes))
+                    .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                    .to_string();
+                conn.execute(
+                    "INSERT INTO spans (trace_id, span_id, start_time_unix_nano, end_time_unix_nano,
+                                        duration_ms, name, kind, span_category, happened_at,
+                                        environment)
+                     VALUES (?1, ?1, 0, 0, 100, 'GET /', 2, 'http_server', ?2, ?3)",
+                    rusqlite::params![span_id, happened_at, environment],
+                )
+                .unwrap();
+            };
+            root("p1", -10, "production");
+            root("p2", 10, "production");
+            root("s1", -5, "staging");
+            root("s2", 5, "staging");
+            root("s3", 15, "staging");
+        }
+
+        let impacts = impacts(&pool, None, &[prod, any]).unwrap();
+        assert_eq!(impacts.len(), 2);
+        assert_eq!(impacts[&prod].requests_before, 1);
+        assert_eq!(impacts[&prod].requests_after, 1);
+        assert_eq!(impacts[&any].requests_before, 2);
+        assert_eq!(impacts[&any].requests_after, 3);
+    }
+}
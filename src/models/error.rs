@@ -1,7 +1,13 @@
 use crate::DbPool;
+use crate::models::cursor::Cursor;
+use crate::models::deploy::Deploy;
+use crate::models::project::DisplayOffset;
+use crate::models::span;
 use chrono::Utc;
+use rand::Rng;
+use rusqlite::OptionalExtension;
 use serde::{Deserialize, Serialize};
-use std::collections::HashSet;
+use std::collections::{HashMap, HashSet};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct AppError {
@@ -13,6 +19,12 @@ pub struct AppError {
     pub last_seen_at: String,
     pub occurrence_count: i64,
     pub status: String,
+    pub muted_until: Option<String>,
+    pub auto_reopen_threshold: Option<i64>,
+    /// git_sha of the earliest occurrence that reported one
+    pub first_seen_release: Option<String>,
+    /// git_sha of the latest occurrence that reported one
+    pub last_seen_release: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -24,7 +36,10 @@ pub struct ErrorOccurrence {
     pub backtrace: Vec<String>,
     pub params: Option<serde_json::Value>,
     pub happened_at: String,
+    /// `happened_at` as reported, for matching the occurrence to a span
+    pub occurred_at: String,
     pub source_context: Option<SourceContext>,
+    pub git_sha: Option<String>,
 }
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -57,7 +72,7 @@ impl SourceContext {
     }
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct IncomingError {
     pub exception_class: String,
     pub message: String,
@@ -68,9 +83,10 @@ pub struct IncomingError {
     pub params: Option<serde_json::Value>,
     pub timestamp: Option<String>,
     pub source_context: Option<IncomingSourceContext>,
+    pub git_sha: Option<String>,
 }
 
-#[derive(Debug, Deserialize)]
+#[derive(Debug, Clone, Deserialize)]
 pub struct IncomingSourceContext {
     pub file: String,
     pub lineno: i64,
@@ -82,18 +98,84 @@ pub struct IncomingSourceContext {
 /// Minimum similarity threshold for grouping errors (50%)
 const SIMILARITY_THRESHOLD: f64 = 0.5;
 
+/// Occurrence rows kept per error per hour unless `MAX_OCCURRENCES_PER_HOUR`
+/// says otherwise
+pub const DEFAULT_MAX_OCCURRENCES_PER_HOUR: i64 = 100;
+
+/// Joins each occurrence row `eo` to its hour's exact counts as `hc`
+pub(crate) const HOURLY_COUNTS_JOIN: &str = "LEFT JOIN error_hourly_counts hc
+    ON hc.error_id = eo.error_id AND hc.hour = strftime('%Y-%m-%d %H', eo.happened_at)";
+
+/// Occurrences one stored row of `eo` stands for: past the hourly sample size
+/// each kept row represents `occurrences / stored` of its hour. Rows stored
+/// before hourly counts were kept count once.
+pub(crate) const OCCURRENCE_WEIGHT: &str =
+    "COALESCE(hc.occurrences * 1.0 / NULLIF(hc.stored, 0), 1)";
+
+/// Record an occurrence under the default per-hour sample size
 pub fn insert(
     pool: &DbPool,
     error: &IncomingError,
     project_id: Option<i64>,
 ) -> anyhow::Result<i64> {
-    let conn = pool.get()?;
+    insert_sampled(pool, error, project_id, DEFAULT_MAX_OCCURRENCES_PER_HOUR)
+}
+
+/// Record an occurrence, storing at most `max_per_hour` occurrence rows per
+/// error per hour. `occurrence_count` still counts every occurrence.
+pub fn insert_sampled(
+    pool: &DbPool,
+    error: &IncomingError,
+    project_id: Option<i64>,
+    max_per_hour: i64,
+) -> anyhow::Result<i64> {
+    let rewrites = frame_rewrites_for(pool, project_id);
+    let error = error.clone();
+    pool.write(move |conn| insert_on(conn, &error, project_id, &rewrites, max_per_hour))
+}
+
+/// Frame rewrites configured for a project, none without one
+pub(crate) fn frame_rewrites_for(pool: &DbPool, project_id: Option<i64>) -> Vec<FrameRewrite> {
+    match project_id {
+        Some(id) => crate::models::project::frame_rewrites(pool, id).unwrap_or_default(),
+        None => vec![],
+    }
+}
+
+/// `insert_sampled` on a connection the caller holds, such as the writer's
+/// while it stores a span batch
+pub(crate) fn insert_on(
+    conn: &rusqlite::Connection,
+    error: &IncomingError,
+    project_id: Option<i64>,
+    rewrites: &[FrameRewrite],
+    max_per_hour: i64,
+) -> anyhow::Result<i64> {
+    // Counting, evicting and storing an occurrence happen together or not at
+    // all, inside or outside the caller's transaction
+    conn.execute_batch("SAVEPOINT insert_error")?;
+    let result = record_occurrence(conn, error, project_id, rewrites, max_per_hour);
+    match &result {
+        Ok(_) => conn.execute_batch("RELEASE insert_error")?,
+        Err(_) => conn.execute_batch("ROLLBACK TO insert_error; RELEASE insert_error")?,
+    }
+    result
+}
+
+fn record_occurrence(
+    conn: &rusqlite::Connection,
+    error: &IncomingError,
+    project_id: Option<i64>,
+    rewrites: &[FrameRewrite],
+    max_per_hour: i64,
+) -> anyhow::Result<i64> {
     let now = Utc::now().to_rfc3339();
     let timestamp = error.timestamp.as_ref().unwrap_or(&now);
 
-    // Generate location-based fingerprint for smart grouping
+    // Generate location-based fingerprint for smart grouping, after applying
+    // any project-configured frame rewrites
     let location_fingerprint =
-        generate_location_fingerprint(&error.exception_class, &error.backtrace);
+        generate_location_fingerprint(&error.exception_class, &error.backtrace, rewrites);
 
     // Try to find existing error by:
     // 1. First check exact fingerprint match (backward compatibility)
@@ -106,31 +188,28 @@ pub fn insert(
         )
         .ok();
 
+    let mut existing_error = true;
     let error_id = if let Some(id) = existing {
         // Exact fingerprint match - update existing error
-        conn.execute(
-            "UPDATE errors SET last_seen_at = ?1, occurrence_count = occurrence_count + 1 WHERE id = ?2",
-            (timestamp, id),
-        )?;
+        conn.execute(BUMP_ERROR, (timestamp, id))?;
         id
     } else {
         // Try to find similar error by location + message similarity
         let similar_error =
-            find_similar_error(&conn, project_id, &location_fingerprint, &error.message)?;
+            find_similar_error(conn, project_id, &location_fingerprint, &error.message)?;
 
         if let Some(id) = similar_error {
             // Found similar error - group with it
-            conn.execute(
-                "UPDATE errors SET last_seen_at = ?1, occurrence_count = occurrence_count + 1 WHERE id = ?2",
-                (timestamp, id),
-            )?;
+            conn.execute(BUMP_ERROR, (timestamp, id))?;
             id
         } else {
             // No similar error found - create new one with location fingerprint
+            existing_error = false;
             conn.execute(
                 r#"
-                INSERT INTO errors (project_id, fingerprint, exception_class, message, first_seen_at, last_seen_at, occurrence_count, status)
-                VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1, 'open')
+                INSERT INTO errors (project_id, fingerprint, exception_class, message, first_seen_at, last_seen_at, occurrence_count, status,
+                                    sample_hour, sample_hour_count)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, 1, 'open', strftime('%Y-%m-%d %H', ?6), 1)
                 "#,
                 (
                     project_id,
@@ -157,29 +236,147 @@ pub fn insert(
         serde_json::to_string(&ctx).ok()
     });
 
-    // Insert occurrence
+    // Past the hourly cap, keep this occurrence with probability cap/seen,
+    // evicting a random stored one from the same hour (reservoir sampling)
+    let seen_this_hour: i64 = conn.query_row(
+        "SELECT sample_hour_count FROM errors WHERE id = ?1",
+        [error_id],
+        |row| row.get(0),
+    )?;
+    let keep = seen_this_hour <= max_per_hour
+        || rand::thread_rng().gen_range(0..seen_this_hour) < max_per_hour;
+    // Change in the hour's stored rows
+    let mut stored: i64 = 0;
+    if keep && seen_this_hour > max_per_hour {
+        stored -= conn.execute(
+            r#"
+            DELETE FROM error_occurrences WHERE id = (
+                SELECT id FROM error_occurrences
+                WHERE error_id = ?1 AND strftime('%Y-%m-%d %H', happened_at) = strftime('%Y-%m-%d %H', ?2)
+                ORDER BY RANDOM() LIMIT 1
+            )
+            "#,
+            rusqlite::params![error_id, timestamp],
+        )? as i64;
+    }
+
+    if keep {
+        stored += 1;
+        // Insert occurrence
+        conn.execute(
+            r#"
+            INSERT INTO error_occurrences (error_id, request_id, user_id, backtrace, params, happened_at, source_context, git_sha)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8)
+            "#,
+            (
+                error_id,
+                &error.request_id,
+                &error.user_id,
+                serde_json::to_string(&error.backtrace)?,
+                error.params.as_ref().and_then(|p| serde_json::to_string(p).ok()),
+                timestamp,
+                source_context_json,
+                &error.git_sha,
+            ),
+        )?;
+    }
+
+    // Exact counts for charts, which weight the sampled rows by them
     conn.execute(
         r#"
-        INSERT INTO error_occurrences (error_id, request_id, user_id, backtrace, params, happened_at, source_context)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7)
+        INSERT INTO error_hourly_counts (error_id, hour, occurrences, stored)
+        VALUES (?1, strftime('%Y-%m-%d %H', ?2), 1, ?3)
+        ON CONFLICT(error_id, hour) DO UPDATE
+        SET occurrences = occurrences + 1, stored = stored + excluded.stored
         "#,
-        (
-            error_id,
-            &error.request_id,
-            &error.user_id,
-            serde_json::to_string(&error.backtrace)?,
-            error.params.as_ref().and_then(|p| serde_json::to_string(p).ok()),
-            timestamp,
-            source_context_json,
-        ),
+        rusqlite::params![error_id, timestamp, stored],
     )?;
+    if let Some(user_id) = &error.user_id {
+        conn.execute(
+            r#"
+            INSERT INTO error_users (error_id, user_id, last_seen_at) VALUES (?1, ?2, ?3)
+            ON CONFLICT(error_id, user_id) DO UPDATE
+            SET last_seen_at = MAX(last_seen_at, excluded.last_seen_at)
+            "#,
+            rusqlite::params![error_id, user_id, timestamp],
+        )?;
+    }
+
+    if existing_error {
+        reopen_if_resurgent(conn, error_id)?;
+    }
 
     Ok(error_id)
 }
 
+/// Count an occurrence at `?1` against error `?2`, restarting the hourly
+/// sampling counter when the occurrence falls in a different hour
+const BUMP_ERROR: &str = r#"
+    UPDATE errors SET
+        last_seen_at = ?1,
+        occurrence_count = occurrence_count + 1,
+        sample_hour_count = CASE WHEN sample_hour = strftime('%Y-%m-%d %H', ?1)
+                                 THEN sample_hour_count + 1 ELSE 1 END,
+        sample_hour = strftime('%Y-%m-%d %H', ?1)
+    WHERE id = ?2
+"#;
+
+/// Flip a muted error back to open when its mute has expired or the
+/// occurrences in the current hour exceed its auto-reopen threshold, and an
+/// auto-resolved error on any recurrence
+fn reopen_if_resurgent(conn: &rusqlite::Connection, error_id: i64) -> anyhow::Result<()> {
+    // It was only resolved for going quiet, so it isn't fixed after all
+    let reopened = conn.execute(
+        r#"UPDATE errors SET status = 'open', auto_resolved_at = NULL
+           WHERE id = ?1 AND status = 'resolved' AND auto_resolved_at IS NOT NULL"#,
+        [error_id],
+    )?;
+    if reopened > 0 {
+        tracing::info!("Reopened auto-resolved error id={}", error_id);
+        return Ok(());
+    }
+
+    // The hourly sampling counter is exact, unlike the sampled occurrence rows
+    let (status, muted_until, threshold, this_hour): (String, Option<String>, Option<i64>, i64) =
+        conn.query_row(
+            r#"SELECT status, muted_until, auto_reopen_threshold,
+                      CASE WHEN sample_hour = strftime('%Y-%m-%d %H', 'now') THEN sample_hour_count ELSE 0 END
+               FROM errors WHERE id = ?1"#,
+            [error_id],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?;
+
+    if status != "ignored" {
+        return Ok(());
+    }
+
+    let mute_expired = muted_until
+        .as_deref()
+        .is_some_and(|until| until <= Utc::now().to_rfc3339().as_str());
+
+    let burst = match threshold {
+        Some(threshold) => this_hour > threshold,
+        None => false,
+    };
+
+    if mute_expired || burst {
+        conn.execute(
+            "UPDATE errors SET status = 'open', muted_until = NULL, auto_reopen_threshold = NULL WHERE id = ?1",
+            [error_id],
+        )?;
+        tracing::info!(
+            "Reopened muted error id={} ({})",
+            error_id,
+            if burst { "burst" } else { "mute expired" }
+        );
+    }
+
+    Ok(())
+}
+
 /// Find an existing error with the same location fingerprint and similar message
 fn find_similar_error(
-    conn: &r2d2::PooledConnection<r2d2_sqlite::SqliteConnectionManager>,
+    conn: &rusqlite::Connection,
     project_id: Option<i64>,
     location_fingerprint: &str,
     message: &str,
@@ -229,9 +426,13 @@ pub fn list_filtered(
     sort_by: &str,
     limit: i64,
 ) -> anyhow::Result<Vec<AppError>> {
-    list_paginated(pool, project_id, status, search, since, sort_by, limit, 0)
+    list_paginated(
+        pool, project_id, status, search, since, None, sort_by, limit, 0,
+    )
 }
 
+/// Errors whose lifetime overlaps the window: seen at or after `since` and
+/// first seen before `until`
 #[allow(clippy::too_many_arguments)]
 pub fn list_paginated(
     pool: &DbPool,
@@ -239,6 +440,7 @@ pub fn list_paginated(
     status: Option<&str>,
     search: Option<&str>,
     since: Option<&str>,
+    until: Option<&str>,
     sort_by: &str,
     limit: i64,
     offset: i64,
@@ -256,12 +458,15 @@ pub fn list_paginated(
         SELECT id, fingerprint, exception_class, message,
                strftime('%Y-%m-%d %H:%M', first_seen_at),
                strftime('%Y-%m-%d %H:%M', last_seen_at),
-               occurrence_count, status
+               occurrence_count, status, strftime('%Y-%m-%d %H:%M', muted_until), auto_reopen_threshold,
+               (SELECT git_sha FROM error_occurrences WHERE error_id = errors.id AND git_sha IS NOT NULL ORDER BY happened_at ASC LIMIT 1),
+               (SELECT git_sha FROM error_occurrences WHERE error_id = errors.id AND git_sha IS NOT NULL ORDER BY happened_at DESC LIMIT 1)
         FROM errors
         WHERE (?1 IS NULL OR project_id = ?1)
           AND (?2 IS NULL OR status = ?2)
           AND (?3 IS NULL OR exception_class LIKE '%' || ?3 || '%' OR message LIKE '%' || ?3 || '%')
           AND (?4 IS NULL OR last_seen_at >= ?4)
+          AND (?7 IS NULL OR first_seen_at < ?7)
         ORDER BY {}
         LIMIT ?5 OFFSET ?6
         "#,
@@ -271,7 +476,7 @@ pub fn list_paginated(
     let mut stmt = conn.prepare(&sql)?;
     let errors = stmt
         .query_map(
-            rusqlite::params![project_id, status, search, since, limit, offset],
+            rusqlite::params![project_id, status, search, since, limit, offset, until],
             map_error,
         )?
         .collect::<Result<Vec<_>, _>>()?;
@@ -279,12 +484,89 @@ pub fn list_paginated(
     Ok(errors)
 }
 
+/// Errors by most recent occurrence, starting after `after`, with the cursor
+/// for the next page if there is one. An error that recurs while someone is
+/// paging moves above the cursor instead of showing up twice, so the later
+/// pages skip it until the list is loaded again from the top. Fails on a
+/// cursor whose id isn't an error id.
+#[allow(clippy::too_many_arguments)]
+pub fn list_after(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    status: Option<&str>,
+    search: Option<&str>,
+    since: Option<&str>,
+    until: Option<&str>,
+    after: Option<&Cursor>,
+    limit: i64,
+) -> anyhow::Result<(Vec<AppError>, Option<Cursor>)> {
+    let conn = pool.get()?;
+    let after_id = match after {
+        Some(cursor) => Some(
+            cursor
+                .id
+                .parse::<i64>()
+                .map_err(|_| anyhow::anyhow!("invalid cursor id {:?}", cursor.id))?,
+        ),
+        None => None,
+    };
+
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT id, fingerprint, exception_class, message,
+               strftime('%Y-%m-%d %H:%M', first_seen_at),
+               strftime('%Y-%m-%d %H:%M', last_seen_at),
+               occurrence_count, status, strftime('%Y-%m-%d %H:%M', muted_until), auto_reopen_threshold,
+               (SELECT git_sha FROM error_occurrences WHERE error_id = errors.id AND git_sha IS NOT NULL ORDER BY happened_at ASC LIMIT 1),
+               (SELECT git_sha FROM error_occurrences WHERE error_id = errors.id AND git_sha IS NOT NULL ORDER BY happened_at DESC LIMIT 1),
+               last_seen_at
+        FROM errors
+        WHERE (?1 IS NULL OR project_id = ?1)
+          AND (?2 IS NULL OR status = ?2)
+          AND (?3 IS NULL OR exception_class LIKE '%' || ?3 || '%' OR message LIKE '%' || ?3 || '%')
+          AND (?4 IS NULL OR last_seen_at >= ?4)
+          AND (?5 IS NULL OR first_seen_at < ?5)
+          AND (?6 IS NULL OR last_seen_at < ?6 OR (last_seen_at = ?6 AND id < ?7))
+        ORDER BY last_seen_at DESC, id DESC
+        LIMIT ?8
+        "#,
+    )?;
+    let mut rows = stmt
+        .query_map(
+            rusqlite::params![
+                project_id,
+                status,
+                search,
+                since,
+                until,
+                after.map(|c| c.happened_at.as_str()),
+                after_id,
+                limit + 1
+            ],
+            |row| Ok((map_error(row)?, row.get::<_, String>(12)?)),
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    // The extra row only says whether there is another page
+    let next = if rows.len() as i64 > limit {
+        rows.truncate(limit as usize);
+        rows.last().map(|(error, last_seen_at)| Cursor {
+            happened_at: last_seen_at.clone(),
+            id: error.id.to_string(),
+        })
+    } else {
+        None
+    };
+    Ok((rows.into_iter().map(|(error, _)| error).collect(), next))
+}
+
 pub fn count_filtered(
     pool: &DbPool,
     project_id: Option<i64>,
     status: Option<&str>,
     search: Option<&str>,
     since: Option<&str>,
+    until: Option<&str>,
 ) -> anyhow::Result<i64> {
     let conn = pool.get()?;
 
@@ -296,8 +578,9 @@ pub fn count_filtered(
           AND (?2 IS NULL OR status = ?2)
           AND (?3 IS NULL OR exception_class LIKE '%' || ?3 || '%' OR message LIKE '%' || ?3 || '%')
           AND (?4 IS NULL OR last_seen_at >= ?4)
+          AND (?5 IS NULL OR first_seen_at < ?5)
         "#,
-        rusqlite::params![project_id, status, search, since],
+        rusqlite::params![project_id, status, search, since, until],
         |row| row.get(0),
     )?;
 
@@ -311,7 +594,9 @@ pub fn find(pool: &DbPool, id: i64) -> anyhow::Result<Option<AppError>> {
             "SELECT id, fingerprint, exception_class, message,
                     strftime('%Y-%m-%d %H:%M', first_seen_at),
                     strftime('%Y-%m-%d %H:%M', last_seen_at),
-                    occurrence_count, status
+                    occurrence_count, status, strftime('%Y-%m-%d %H:%M', muted_until), auto_reopen_threshold,
+                    (SELECT git_sha FROM error_occurrences WHERE error_id = errors.id AND git_sha IS NOT NULL ORDER BY happened_at ASC LIMIT 1),
+                    (SELECT git_sha FROM error_occurrences WHERE error_id = errors.id AND git_sha IS NOT NULL ORDER BY happened_at DESC LIMIT 1)
              FROM errors WHERE id = ?1",
             [id],
             map_error,
@@ -320,6 +605,18 @@ pub fn find(pool: &DbPool, id: i64) -> anyhow::Result<Option<AppError>> {
     Ok(error)
 }
 
+/// Project an error belongs to: `None` if there is no such error,
+/// `Some(None)` for errors recorded without a project
+pub fn project_of(pool: &DbPool, id: i64) -> anyhow::Result<Option<Option<i64>>> {
+    let conn = pool.get()?;
+    let project_id = conn
+        .query_row("SELECT project_id FROM errors WHERE id = ?1", [id], |row| {
+            row.get(0)
+        })
+        .optional()?;
+    Ok(project_id)
+}
+
 pub fn occurrences(
     pool: &DbPool,
     error_id: i64,
@@ -328,7 +625,7 @@ pub fn occurrences(
     let conn = pool.get()?;
     let mut stmt = conn.prepare(
         "SELECT id, error_id, request_id, user_id, backtrace, params,
-                strftime('%Y-%m-%d %H:%M', happened_at), source_context
+                strftime('%Y-%m-%d %H:%M', happened_at), source_context, git_sha, happened_at
          FROM error_occurrences WHERE error_id = ?1 ORDER BY happened_at DESC LIMIT ?2",
     )?;
 
@@ -345,7 +642,9 @@ pub fn occurrences(
                 backtrace: serde_json::from_str(&backtrace_str).unwrap_or_default(),
                 params: params_str.and_then(|s| serde_json::from_str(&s).ok()),
                 happened_at: row.get(6)?,
+                occurred_at: row.get(9)?,
                 source_context: source_context_str.and_then(|s| serde_json::from_str(&s).ok()),
+                git_sha: row.get(8)?,
             })
         })?
         .collect::<Result<Vec<_>, _>>()?;
@@ -353,30 +652,454 @@ pub fn occurrences(
     Ok(occs)
 }
 
+/// Occurrences of an error grouped by the release (git_sha) they were reported from
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorRelease {
+    pub git_sha: String,
+    pub occurrence_count: i64,
+    pub first_seen_at: String,
+    pub last_seen_at: String,
+}
+
+impl ErrorRelease {
+    /// First 7 characters, or the whole value when that would split a
+    /// character (client-sent, so not necessarily hex)
+    pub fn short_sha(&self) -> &str {
+        self.git_sha.get(..7).unwrap_or(&self.git_sha)
+    }
+}
+
+/// Distinct users that hit an error, from occurrences carrying a `user_id`
+/// (sampled out or not)
+pub fn affected_users(pool: &DbPool, error_id: i64) -> anyhow::Result<i64> {
+    let conn = pool.get()?;
+    let count = conn.query_row(
+        "SELECT COUNT(*) FROM error_users WHERE error_id = ?1",
+        [error_id],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
+/// List the releases an error has occurred in, oldest first
+pub fn error_releases(pool: &DbPool, error_id: i64) -> anyhow::Result<Vec<ErrorRelease>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        r#"
+        SELECT git_sha, CAST(ROUND(SUM({OCCURRENCE_WEIGHT})) AS INTEGER),
+               strftime('%Y-%m-%d %H:%M', MIN(happened_at)),
+               strftime('%Y-%m-%d %H:%M', MAX(happened_at))
+        FROM error_occurrences eo
+        {HOURLY_COUNTS_JOIN}
+        WHERE eo.error_id = ?1 AND git_sha IS NOT NULL
+        GROUP BY git_sha
+        ORDER BY MIN(happened_at) ASC
+        "#
+    ))?;
+
+    let releases = stmt
+        .query_map([error_id], |row| {
+            Ok(ErrorRelease {
+                git_sha: row.get(0)?,
+                occurrence_count: row.get(1)?,
+                first_seen_at: row.get(2)?,
+                last_seen_at: row.get(3)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(releases)
+}
+
+/// One value of a param key and how many occurrences carried it
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ParamValueCount {
+    pub value: String,
+    pub count: i64,
+}
+
+/// Most common values of one top-level param key across an error's occurrences
+#[derive(Debug, Clone, Serialize)]
+pub struct ParamBreakdown {
+    pub key: String,
+    /// Occurrences whose params had a non-null value for the key
+    pub occurrence_count: i64,
+    pub values: Vec<ParamValueCount>,
+}
+
+impl ParamBreakdown {
+    pub fn percent(&self, value: &ParamValueCount) -> i64 {
+        value.count * 100 / self.occurrence_count.max(1)
+    }
+}
+
+/// Top-level param keys seen on an error's occurrences, most common first
+pub fn param_keys(pool: &DbPool, error_id: i64, limit: i64) -> anyhow::Result<Vec<String>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT p.key
+        FROM error_occurrences eo, json_each(eo.params) p
+        WHERE eo.error_id = ?1
+          AND eo.params IS NOT NULL
+          AND json_type(eo.params) = 'object'
+        GROUP BY p.key
+        ORDER BY COUNT(*) DESC, p.key
+        LIMIT ?2
+        "#,
+    )?;
+    let keys = stmt
+        .query_map(rusqlite::params![error_id, limit], |row| row.get(0))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(keys)
+}
+
+/// Most common values of each of `keys` across an error's occurrences, read
+/// from the stored params with `json_extract`. Keys nobody sent are left out.
+pub fn param_breakdown(
+    pool: &DbPool,
+    error_id: i64,
+    keys: &[String],
+    limit: i64,
+) -> anyhow::Result<Vec<ParamBreakdown>> {
+    let conn = pool.get()?;
+    let mut count_stmt = conn.prepare(
+        r#"
+        SELECT COUNT(*)
+        FROM error_occurrences
+        WHERE error_id = ?1 AND params IS NOT NULL
+          AND json_type(params, ?2) != 'null'
+        "#,
+    )?;
+    // JSON booleans come back from json_extract as 1/0, so name them
+    let mut values_stmt = conn.prepare(
+        r#"
+        SELECT CASE json_type(params, ?2)
+                   WHEN 'true' THEN 'true'
+                   WHEN 'false' THEN 'false'
+                   ELSE CAST(json_extract(params, ?2) AS TEXT)
+               END AS value,
+               COUNT(*) AS n
+        FROM error_occurrences
+        WHERE error_id = ?1 AND params IS NOT NULL
+          AND json_type(params, ?2) != 'null'
+        GROUP BY value
+        ORDER BY n DESC, value
+        LIMIT ?3
+        "#,
+    )?;
+
+    let mut breakdowns = Vec::new();
+    // A quote can't be escaped inside a JSON path, so such keys can't be read
+    for key in keys.iter().filter(|k| !k.contains('"')) {
+        let path = format!("$.\"{}\"", key);
+        let occurrence_count: i64 =
+            count_stmt.query_row(rusqlite::params![error_id, path], |row| row.get(0))?;
+        if occurrence_count == 0 {
+            continue;
+        }
+        let values = values_stmt
+            .query_map(rusqlite::params![error_id, path, limit], |row| {
+                Ok(ParamValueCount {
+                    value: row.get(0)?,
+                    count: row.get(1)?,
+                })
+            })?
+            .collect::<Result<Vec<_>, _>>()?;
+        breakdowns.push(ParamBreakdown {
+            key: key.clone(),
+            occurrence_count,
+            values,
+        });
+    }
+    Ok(breakdowns)
+}
+
 pub fn count_since(pool: &DbPool, project_id: Option<i64>, since: &str) -> anyhow::Result<i64> {
+    count_between(pool, project_id, since, None)
+}
+
+/// Count error occurrences in `[since, until)`; an open `until` counts up to now
+pub fn count_between(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    since: &str,
+    until: Option<&str>,
+) -> anyhow::Result<i64> {
     let conn = pool.get()?;
     let count: i64 = conn.query_row(
-        "SELECT COUNT(*) FROM error_occurrences eo
-         JOIN errors e ON e.id = eo.error_id
-         WHERE eo.happened_at >= ?1 AND (?2 IS NULL OR e.project_id = ?2)",
-        rusqlite::params![since, project_id],
+        &format!(
+            "SELECT CAST(ROUND(COALESCE(SUM({OCCURRENCE_WEIGHT}), 0)) AS INTEGER)
+             FROM error_occurrences eo
+             JOIN errors e ON e.id = eo.error_id
+             {HOURLY_COUNTS_JOIN}
+             WHERE eo.happened_at >= ?1 AND (?3 IS NULL OR eo.happened_at < ?3)
+               AND (?2 IS NULL OR e.project_id = ?2)"
+        ),
+        rusqlite::params![since, project_id, until],
         |row| row.get(0),
     )?;
     Ok(count)
 }
 
+/// Error occurrences since the most recent deploy, split by whether their
+/// error group first appeared after that deploy
+#[derive(Debug, Clone, Serialize)]
+pub struct ErrorsSinceDeploy {
+    pub deploy: Deploy,
+    /// Error groups first seen after the deploy, and their occurrences
+    pub new_errors: i64,
+    pub new_occurrences: i64,
+    /// Error groups that already existed before the deploy, and their occurrences
+    pub recurring_errors: i64,
+    pub recurring_occurrences: i64,
+}
+
+impl ErrorsSinceDeploy {
+    pub fn total_occurrences(&self) -> i64 {
+        self.new_occurrences + self.recurring_occurrences
+    }
+}
+
+/// Count errors since the latest deploy; None until a deploy is recorded.
+/// Timestamps are compared with `julianday` since deploys and errors may
+/// carry client-supplied RFC 3339 strings in different offsets.
+pub fn errors_since_deploy(
+    pool: &DbPool,
+    project_id: Option<i64>,
+) -> anyhow::Result<Option<ErrorsSinceDeploy>> {
+    let Some(deploy) = crate::models::deploy::latest(pool, project_id)? else {
+        return Ok(None);
+    };
+
+    let conn = pool.get()?;
+    let (new_errors, new_occurrences, recurring_errors, recurring_occurrences) = conn.query_row(
+        &format!(
+            r#"
+            WITH d AS (SELECT julianday(deployed_at) AS at FROM deploys WHERE id = ?1)
+            SELECT COUNT(DISTINCT CASE WHEN julianday(e.first_seen_at) >= d.at THEN e.id END),
+                   CAST(ROUND(COALESCE(SUM(CASE WHEN julianday(e.first_seen_at) >= d.at
+                                                THEN {OCCURRENCE_WEIGHT} END), 0)) AS INTEGER),
+                   COUNT(DISTINCT CASE WHEN julianday(e.first_seen_at) < d.at THEN e.id END),
+                   CAST(ROUND(COALESCE(SUM(CASE WHEN julianday(e.first_seen_at) < d.at
+                                                THEN {OCCURRENCE_WEIGHT} END), 0)) AS INTEGER)
+            FROM error_occurrences eo
+            JOIN errors e ON e.id = eo.error_id
+            JOIN d
+            {HOURLY_COUNTS_JOIN}
+            WHERE julianday(eo.happened_at) >= d.at
+              AND (?2 IS NULL OR e.project_id = ?2)
+            "#
+        ),
+        rusqlite::params![deploy.id, project_id],
+        |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+    )?;
+
+    Ok(Some(ErrorsSinceDeploy {
+        deploy,
+        new_errors,
+        new_occurrences,
+        recurring_errors,
+        recurring_occurrences,
+    }))
+}
+
+/// Error occurrences attributed to one controller action or route
+#[derive(Debug, Clone, Serialize)]
+pub struct EndpointErrors {
+    /// `Controller#action` from the request log, else the trace's root span name
+    pub endpoint: String,
+    pub occurrence_count: i64,
+    pub error_count: i64,
+}
+
+/// Occurrences grouped by the endpoint that raised them, found by matching the
+/// occurrence's `request_id` to a logged request or to a trace id. Occurrences
+/// matching neither are left out.
+pub fn errors_by_endpoint(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    since: Option<&str>,
+    until: Option<&str>,
+    limit: i64,
+) -> anyhow::Result<Vec<EndpointErrors>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        r#"
+        SELECT endpoint, CAST(ROUND(SUM(weight)) AS INTEGER) AS occurrence_count,
+               COUNT(DISTINCT error_id)
+        FROM (
+            SELECT eo.error_id, {OCCURRENCE_WEIGHT} AS weight,
+                   COALESCE(
+                       (SELECT CASE WHEN r.controller IS NOT NULL
+                                    THEN r.controller || COALESCE('#' || r.action, '')
+                                    ELSE r.method || ' ' || r.path END
+                        FROM requests r WHERE r.request_id = eo.request_id LIMIT 1),
+                       (SELECT s.name FROM spans s
+                        WHERE s.trace_id = eo.request_id AND s.parent_span_id IS NULL LIMIT 1)
+                   ) AS endpoint
+            FROM error_occurrences eo
+            JOIN errors e ON e.id = eo.error_id
+            {HOURLY_COUNTS_JOIN}
+            WHERE eo.request_id IS NOT NULL
+              AND (?1 IS NULL OR e.project_id = ?1)
+              AND (?2 IS NULL OR eo.happened_at >= ?2)
+              AND (?4 IS NULL OR eo.happened_at < ?4)
+        )
+        WHERE endpoint IS NOT NULL
+        GROUP BY endpoint
+        ORDER BY occurrence_count DESC, endpoint
+        LIMIT ?3
+        "#
+    ))?;
+
+    let rows = stmt
+        .query_map(rusqlite::params![project_id, since, limit, until], |row| {
+            Ok(EndpointErrors {
+                endpoint: row.get(0)?,
+                occurrence_count: row.get(1)?,
+                error_count: row.get(2)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
+/// An error group raised while serving one route
+#[derive(Debug, Clone, Serialize)]
+pub struct RouteError {
+    pub error_id: i64,
+    pub exception_class: String,
+    pub message: String,
+    pub occurrence_count: i64,
+}
+
+/// Error groups whose occurrences belong to traces of one route (matched by
+/// `request_id` to the trace's root span, grouped as in `routes_summary`),
+/// most frequent first
+pub fn errors_for_route(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    path: &str,
+    method: &str,
+    since: &str,
+    limit: i64,
+) -> anyhow::Result<Vec<RouteError>> {
+    let route_key = span::route_key_sql("s");
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(&format!(
+        r#"
+        SELECT e.id, e.exception_class, e.message,
+               CAST(ROUND(SUM({OCCURRENCE_WEIGHT})) AS INTEGER) AS occurrence_count
+        FROM error_occurrences eo
+        JOIN errors e ON e.id = eo.error_id
+        {HOURLY_COUNTS_JOIN}
+        WHERE (?3 IS NULL OR e.project_id = ?3)
+          AND eo.happened_at >= ?4
+          AND EXISTS (
+              SELECT 1 FROM spans s
+              WHERE s.trace_id = eo.request_id
+                AND s.parent_span_id IS NULL
+                AND s.root_span_type = 'web'
+                AND {route_key} = ?1
+                AND COALESCE(s.http_method, 'GET') = ?2
+          )
+        GROUP BY e.id
+        ORDER BY occurrence_count DESC, e.id
+        LIMIT ?5
+        "#
+    ))?;
+
+    let rows = stmt
+        .query_map(
+            rusqlite::params![path, method, project_id, since, limit],
+            |row| {
+                Ok(RouteError {
+                    error_id: row.get(0)?,
+                    exception_class: row.get(1)?,
+                    message: row.get(2)?,
+                    occurrence_count: row.get(3)?,
+                })
+            },
+        )?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(rows)
+}
+
 pub fn update_status(pool: &DbPool, id: i64, status: &str) -> anyhow::Result<()> {
     let conn = pool.get()?;
-    conn.execute("UPDATE errors SET status = ?1 WHERE id = ?2", (status, id))?;
+    conn.execute(
+        "UPDATE errors SET status = ?1, muted_until = NULL, auto_reopen_threshold = NULL, auto_resolved_at = NULL WHERE id = ?2",
+        (status, id),
+    )?;
+    Ok(())
+}
+
+/// Set the status of several errors in one transaction, clearing any mute like
+/// `update_status`. Returns how many errors were updated.
+pub fn update_status_bulk(pool: &DbPool, ids: &[i64], status: &str) -> anyhow::Result<usize> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let mut updated = 0;
+    {
+        let mut stmt = tx.prepare(
+            "UPDATE errors SET status = ?1, muted_until = NULL, auto_reopen_threshold = NULL, auto_resolved_at = NULL WHERE id = ?2",
+        )?;
+        for id in ids {
+            updated += stmt.execute(rusqlite::params![status, id])?;
+        }
+    }
+    tx.commit()?;
+    Ok(updated)
+}
+
+/// Ignore an error until `muted_until` (forever when None), reopening it early
+/// if more than `auto_reopen_threshold` occurrences arrive within an hour
+pub fn mute(
+    pool: &DbPool,
+    id: i64,
+    muted_until: Option<&str>,
+    auto_reopen_threshold: Option<i64>,
+) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE errors SET status = 'ignored', muted_until = ?1, auto_reopen_threshold = ?2, auto_resolved_at = NULL WHERE id = ?3",
+        rusqlite::params![muted_until, auto_reopen_threshold, id],
+    )?;
     Ok(())
 }
 
+/// Resolve open errors of a project (`None` for errors without one) last seen
+/// before `before`, marking them auto-resolved. Returns how many were resolved.
+pub fn auto_resolve_stale(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    before: &str,
+) -> anyhow::Result<usize> {
+    let conn = pool.get()?;
+    let resolved = conn.execute(
+        r#"
+        UPDATE errors SET status = 'resolved', auto_resolved_at = ?1
+        WHERE status = 'open' AND last_seen_at < ?2
+          AND ((?3 IS NULL AND project_id IS NULL) OR project_id = ?3)
+        "#,
+        rusqlite::params![Utc::now().to_rfc3339(), before, project_id],
+    )?;
+    Ok(resolved)
+}
+
 pub fn delete_occurrences_before(pool: &DbPool, before: &str) -> anyhow::Result<usize> {
     let conn = pool.get()?;
     let deleted = conn.execute(
         "DELETE FROM error_occurrences WHERE happened_at < ?1",
         [before],
     )?;
+    // The cutoff's own hour keeps its counts for the rows still in it
+    conn.execute(
+        "DELETE FROM error_hourly_counts WHERE hour < strftime('%Y-%m-%d %H', ?1)",
+        [before],
+    )?;
+    conn.execute("DELETE FROM error_users WHERE last_seen_at < ?1", [before])?;
     Ok(deleted)
 }
 
@@ -387,21 +1110,24 @@ pub struct ErrorTrendPoint {
     pub count: i64,
 }
 
-/// Get hourly error occurrence counts for a specific error (for trend sparklines)
+/// Get hourly error occurrence counts for a specific error (for trend sparklines),
+/// bucketed in `offset`
 pub fn error_trend(
     pool: &DbPool,
     error_id: i64,
     hours: i64,
+    offset: DisplayOffset,
 ) -> anyhow::Result<Vec<ErrorTrendPoint>> {
     let conn = pool.get()?;
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare(&format!(
         r#"
         WITH hours AS (
             SELECT datetime('now', '-' || (value - 1) || ' hours') as hour
             FROM generate_series(1, ?2)
         )
-        SELECT strftime('%Y-%m-%d %H:00', h.hour) as hour,
-               COALESCE(SUM(CASE WHEN eo.happened_at IS NOT NULL THEN 1 ELSE 0 END), 0) as cnt
+        SELECT strftime('%Y-%m-%d %H:00', h.hour, ?3) as hour,
+               CAST(ROUND(COALESCE(SUM(CASE WHEN eo.happened_at IS NOT NULL
+                                            THEN {OCCURRENCE_WEIGHT} ELSE 0 END), 0)) AS INTEGER) as cnt
         FROM (
             SELECT datetime('now', '-' || (value - 1) || ' hours') as hour
             FROM (
@@ -415,20 +1141,24 @@ pub fn error_trend(
             WHERE value <= ?2
         ) h
         LEFT JOIN error_occurrences eo
-            ON strftime('%Y-%m-%d %H', eo.happened_at) = strftime('%Y-%m-%d %H', h.hour)
+            ON strftime('%Y-%m-%d %H', eo.happened_at, ?3) = strftime('%Y-%m-%d %H', h.hour, ?3)
             AND eo.error_id = ?1
-        GROUP BY strftime('%Y-%m-%d %H:00', h.hour)
+        {HOURLY_COUNTS_JOIN}
+        GROUP BY strftime('%Y-%m-%d %H:00', h.hour, ?3)
         ORDER BY hour ASC
-        "#,
-    )?;
+        "#
+    ))?;
 
     let points = stmt
-        .query_map(rusqlite::params![error_id, hours], |row| {
-            Ok(ErrorTrendPoint {
-                hour: row.get(0)?,
-                count: row.get(1)?,
-            })
-        })?
+        .query_map(
+            rusqlite::params![error_id, hours, offset.sqlite_modifier()],
+            |row| {
+                Ok(ErrorTrendPoint {
+                    hour: row.get(0)?,
+                    count: row.get(1)?,
+                })
+            },
+        )?
         .collect::<Result<Vec<_>, _>>()?;
 
     Ok(points)
@@ -439,15 +1169,17 @@ pub fn error_trend_24h(pool: &DbPool, error_id: i64) -> anyhow::Result<Vec<i64>>
     let conn = pool.get()?;
 
     // Get occurrence counts per hour for the last 24 hours
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare(&format!(
         r#"
-        SELECT strftime('%Y-%m-%d %H', happened_at) as hour, COUNT(*) as cnt
-        FROM error_occurrences
-        WHERE error_id = ?1 AND happened_at >= datetime('now', '-24 hours')
+        SELECT strftime('%Y-%m-%d %H', happened_at) as hour,
+               CAST(ROUND(SUM({OCCURRENCE_WEIGHT})) AS INTEGER) as cnt
+        FROM error_occurrences eo
+        {HOURLY_COUNTS_JOIN}
+        WHERE eo.error_id = ?1 AND happened_at >= datetime('now', '-24 hours')
         GROUP BY hour
         ORDER BY hour ASC
-        "#,
-    )?;
+        "#
+    ))?;
 
     let hour_counts: std::collections::HashMap<String, i64> = stmt
         .query_map([error_id], |row| {
@@ -467,38 +1199,42 @@ pub fn error_trend_24h(pool: &DbPool, error_id: i64) -> anyhow::Result<Vec<i64>>
     Ok(counts)
 }
 
-/// Get overall hourly error counts (for error index chart)
+/// Get overall hourly error counts (for error index chart), bucketed in `offset`
 pub fn hourly_error_stats(
     pool: &DbPool,
     project_id: Option<i64>,
     hours: i64,
+    offset: DisplayOffset,
 ) -> anyhow::Result<Vec<ErrorTrendPoint>> {
     let conn = pool.get()?;
 
-    let mut stmt = conn.prepare(
+    let mut stmt = conn.prepare(&format!(
         r#"
-        SELECT strftime('%Y-%m-%d %H:00', eo.happened_at) as hour_label, COUNT(*) as cnt
+        SELECT strftime('%Y-%m-%d %H:00', eo.happened_at, ?3) as hour_label,
+               CAST(ROUND(SUM({OCCURRENCE_WEIGHT})) AS INTEGER) as cnt
         FROM error_occurrences eo
         JOIN errors e ON e.id = eo.error_id
+        {HOURLY_COUNTS_JOIN}
         WHERE eo.happened_at >= datetime('now', '-' || ?2 || ' hours')
           AND (?1 IS NULL OR e.project_id = ?1)
-        GROUP BY strftime('%Y-%m-%d %H', eo.happened_at)
+        GROUP BY hour_label
         ORDER BY eo.happened_at ASC
-        "#,
-    )?;
+        "#
+    ))?;
 
     // Collect data into a HashMap for lookup
     let data_points: std::collections::HashMap<String, i64> = stmt
-        .query_map(rusqlite::params![project_id, hours], |row| {
-            Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?))
-        })?
+        .query_map(
+            rusqlite::params![project_id, hours, offset.sqlite_modifier()],
+            |row| Ok((row.get::<_, String>(0)?, row.get::<_, i64>(1)?)),
+        )?
         .filter_map(|r| r.ok())
         .collect();
 
     // Fill in all hours with zeros for missing data
     let mut points = Vec::with_capacity(hours as usize);
     for i in (0..hours).rev() {
-        let hour = chrono::Utc::now() - chrono::Duration::hours(i);
+        let hour = offset.now() - chrono::Duration::hours(i);
         let hour_key = hour.format("%Y-%m-%d %H:00").to_string();
         points.push(ErrorTrendPoint {
             hour: hour_key.clone(),
@@ -519,6 +1255,10 @@ fn map_error(row: &rusqlite::Row) -> rusqlite::Result<AppError> {
         last_seen_at: row.get(5)?,
         occurrence_count: row.get(6)?,
         status: row.get(7)?,
+        muted_until: row.get(8)?,
+        auto_reopen_threshold: row.get(9)?,
+        first_seen_release: row.get(10)?,
+        last_seen_release: row.get(11)?,
     })
 }
 
@@ -552,8 +1292,63 @@ fn text_similarity(a: &str, b: &str) -> f64 {
     }
 }
 
+/// Path rewrite applied to backtrace frames before fingerprinting
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct FrameRewrite {
+    pub from: String,
+    pub to: String,
+}
+
+impl FrameRewrite {
+    /// Parse rules written one per line as `from => to`
+    pub fn parse_rules(text: &str) -> Vec<FrameRewrite> {
+        text.lines()
+            .filter_map(|line| {
+                let (from, to) = line.split_once("=>")?;
+                let (from, to) = (from.trim(), to.trim());
+                if from.is_empty() || to.is_empty() {
+                    return None;
+                }
+                Some(FrameRewrite {
+                    from: from.to_string(),
+                    to: to.to_string(),
+                })
+            })
+            .collect()
+    }
+}
+
+/// Map minified or instrumented frames back to logical sources: the first
+/// `from` in a frame, with any `:line:col` right after it, becomes `to`.
+/// Positions inside a rewritten file are dropped since they change with every build.
+pub fn resolve_frames(backtrace: &[String], rewrites: &[FrameRewrite]) -> Vec<String> {
+    backtrace
+        .iter()
+        .map(|frame| {
+            for rewrite in rewrites {
+                if let Some(start) = frame.find(&rewrite.from) {
+                    let rest = &frame[start + rewrite.from.len()..];
+                    let position_len = rest
+                        .find(|c: char| c != ':' && !c.is_ascii_digit())
+                        .unwrap_or(rest.len());
+                    return format!("{}{}{}", &frame[..start], rewrite.to, &rest[position_len..]);
+                }
+            }
+            frame.clone()
+        })
+        .collect()
+}
+
 /// Extract first app frame from backtrace (skip library/framework frames)
-fn extract_error_location(backtrace: &[String]) -> Option<String> {
+fn extract_error_location(backtrace: &[String], rewrites: &[FrameRewrite]) -> Option<String> {
+    let resolved;
+    let backtrace = if rewrites.is_empty() {
+        backtrace
+    } else {
+        resolved = resolve_frames(backtrace, rewrites);
+        &resolved
+    };
+
     // Common patterns for library/framework code to skip
     let skip_patterns = [
         "/gems/",
@@ -597,9 +1392,195 @@ fn extract_error_location(backtrace: &[String]) -> Option<String> {
     })
 }
 
+/// What `refingerprint_errors` changed
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Refingerprinted {
+    /// Remaining errors whose fingerprint changed
+    pub updated: usize,
+    /// Errors folded into an older one that now has the same fingerprint
+    pub merged: usize,
+    /// Errors left alone because their fingerprint isn't a location one
+    pub skipped: usize,
+}
+
+/// Recompute every error's location fingerprint from its first occurrence's
+/// backtrace, with the project's current frame rewrites, and merge errors
+/// that end up sharing one into the earliest seen: occurrences move over,
+/// counts add up and an open duplicate reopens it. Errors without stored
+/// occurrences keep their fingerprint, as do ones whose fingerprint doesn't
+/// look like `Class:location` (set by a client or by hand) unless
+/// `overwrite_custom`. All projects when `project_id` is `None`.
+/// Destructive: merged errors are deleted.
+pub fn refingerprint_errors(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    overwrite_custom: bool,
+) -> anyhow::Result<Refingerprinted> {
+    let project_ids: Vec<Option<i64>> = {
+        let conn = pool.get()?;
+        let mut stmt = conn.prepare(
+            "SELECT DISTINCT project_id FROM errors WHERE (?1 IS NULL OR project_id = ?1)",
+        )?;
+        stmt.query_map([project_id], |row| row.get(0))?
+            .collect::<Result<_, _>>()?
+    };
+    let rewrites: HashMap<Option<i64>, Vec<FrameRewrite>> = project_ids
+        .into_iter()
+        .map(|id| (id, frame_rewrites_for(pool, id)))
+        .collect();
+
+    pool.write(move |conn| {
+        let tx = conn.transaction()?;
+        type Row = (i64, Option<i64>, String, String, Option<String>);
+        let errors: Vec<Row> = {
+            let mut stmt = tx.prepare(
+                r#"
+                SELECT e.id, e.project_id, e.fingerprint, e.exception_class,
+                       (SELECT o.backtrace FROM error_occurrences o WHERE o.error_id = e.id
+                        ORDER BY o.happened_at, o.id LIMIT 1)
+                FROM errors e
+                WHERE (?1 IS NULL OR e.project_id = ?1)
+                ORDER BY e.first_seen_at, e.id
+                "#,
+            )?;
+            stmt.query_map([project_id], |row| {
+                Ok((
+                    row.get(0)?,
+                    row.get(1)?,
+                    row.get(2)?,
+                    row.get(3)?,
+                    row.get(4)?,
+                ))
+            })?
+            .collect::<Result<_, _>>()?
+        };
+
+        // Keyed by project and new fingerprint, holding ids and old
+        // fingerprints earliest seen first, so the first member survives
+        type Groups = HashMap<(Option<i64>, String), Vec<(i64, String)>>;
+        let mut groups: Groups = HashMap::new();
+        let mut outcome = Refingerprinted::default();
+        for (id, project, old, exception_class, backtrace) in errors {
+            let custom = !old.starts_with(&format!("{}:", exception_class));
+            if custom && !overwrite_custom {
+                outcome.skipped += 1;
+            }
+            let new = match backtrace {
+                Some(json) if !custom || overwrite_custom => {
+                    let backtrace: Vec<String> = serde_json::from_str(&json).unwrap_or_default();
+                    let rewrites = rewrites.get(&project).map(Vec::as_slice).unwrap_or(&[]);
+                    generate_location_fingerprint(&exception_class, &backtrace, rewrites)
+                }
+                _ => old.clone(),
+            };
+            groups.entry((project, new)).or_default().push((id, old));
+        }
+
+        let mut renamed = Vec::new();
+        for ((_, fingerprint), members) in groups {
+            let (survivor, old) = &members[0];
+            for (duplicate, _) in &members[1..] {
+                merge_error_into(&tx, *duplicate, *survivor)?;
+                outcome.merged += 1;
+            }
+            if *old != fingerprint {
+                renamed.push((*survivor, fingerprint));
+            }
+        }
+        // Park the old fingerprints first, so a rename can't collide with one
+        // that is about to move on under UNIQUE(project_id, fingerprint)
+        for (id, _) in &renamed {
+            tx.execute(
+                "UPDATE errors SET fingerprint = 'refingerprint:' || id WHERE id = ?1",
+                [id],
+            )?;
+        }
+        for (id, fingerprint) in &renamed {
+            tx.execute(
+                "UPDATE errors SET fingerprint = ?1 WHERE id = ?2",
+                rusqlite::params![fingerprint, id],
+            )?;
+        }
+        outcome.updated = renamed.len();
+        tx.commit()?;
+        Ok(outcome)
+    })
+}
+
+/// Move `duplicate`'s occurrences and counts onto `survivor` and delete it
+fn merge_error_into(
+    conn: &rusqlite::Connection,
+    duplicate: i64,
+    survivor: i64,
+) -> anyhow::Result<()> {
+    conn.execute(
+        "UPDATE error_occurrences SET error_id = ?1 WHERE error_id = ?2",
+        [survivor, duplicate],
+    )?;
+    conn.execute(
+        r#"
+        INSERT INTO error_hourly_counts (error_id, hour, occurrences, stored)
+        SELECT ?1, hour, occurrences, stored FROM error_hourly_counts WHERE error_id = ?2
+        ON CONFLICT(error_id, hour) DO UPDATE
+        SET occurrences = occurrences + excluded.occurrences, stored = stored + excluded.stored
+        "#,
+        [survivor, duplicate],
+    )?;
+    conn.execute(
+        r#"
+        INSERT INTO error_users (error_id, user_id, last_seen_at)
+        SELECT ?1, user_id, last_seen_at FROM error_users WHERE error_id = ?2
+        ON CONFLICT(error_id, user_id) DO UPDATE
+        SET last_seen_at = MAX(last_seen_at, excluded.last_seen_at)
+        "#,
+        [survivor, duplicate],
+    )?;
+    conn.execute(
+        r#"
+        UPDATE errors SET
+            occurrence_count = errors.occurrence_count + d.occurrence_count,
+            first_seen_at = MIN(errors.first_seen_at, d.first_seen_at),
+            last_seen_at = MAX(errors.last_seen_at, d.last_seen_at),
+            status = CASE WHEN d.status = 'open' THEN 'open' ELSE errors.status END,
+            auto_resolved_at = CASE WHEN d.status = 'open' THEN NULL
+                                    ELSE errors.auto_resolved_at END,
+            -- The hourly sampling cap counts both errors' occurrences this hour
+            sample_hour_count = CASE
+                WHEN d.sample_hour IS NULL THEN errors.sample_hour_count
+                WHEN errors.sample_hour IS NULL OR d.sample_hour > errors.sample_hour
+                    THEN d.sample_hour_count
+                WHEN d.sample_hour = errors.sample_hour
+                    THEN errors.sample_hour_count + d.sample_hour_count
+                ELSE errors.sample_hour_count END,
+            sample_hour = CASE
+                WHEN errors.sample_hour IS NULL OR d.sample_hour > errors.sample_hour
+                    THEN d.sample_hour
+                ELSE errors.sample_hour END
+        FROM (SELECT occurrence_count, first_seen_at, last_seen_at, status,
+                     sample_hour, sample_hour_count
+              FROM errors WHERE id = ?2) AS d
+        WHERE errors.id = ?1
+        "#,
+        [survivor, duplicate],
+    )?;
+    conn.execute("DELETE FROM errors WHERE id = ?1", [duplicate])?;
+    tracing::info!("Merged error id={} into id={}", duplicate, survivor);
+    Ok(())
+}
+
+/// Location fingerprint without any project frame rewrites, for clients that
+/// need a fingerprint to send but don't compute one themselves
+pub fn location_fingerprint(exception_class: &str, backtrace: &[String]) -> String {
+    generate_location_fingerprint(exception_class, backtrace, &[])
+}
+
 /// Generate a location-based fingerprint from exception class and backtrace
-fn generate_location_fingerprint(exception_class: &str, backtrace: &[String]) -> String {
-    let location = extract_error_location(backtrace).unwrap_or_default();
+fn generate_location_fingerprint(
+    exception_class: &str,
+    backtrace: &[String],
+    rewrites: &[FrameRewrite],
+) -> String {
+    let location = extract_error_location(backtrace, rewrites).unwrap_or_default();
     format!("{}:{}", exception_class, location)
 }
 
@@ -671,7 +1652,7 @@ mod tests {
             "/app/models/user.rb:42:in `authenticate'".to_string(),
             "/app/controllers/sessions_controller.rb:15:in `create'".to_string(),
         ];
-        let location = extract_error_location(&backtrace);
+        let location = extract_error_location(&backtrace, &[]);
         assert_eq!(location, Some("/app/models/user.rb:42".to_string()));
     }
 
@@ -682,7 +1663,7 @@ mod tests {
             "/vendor/bundle/gems/rails-7.0.0/lib/rails.rb:5:in `run'".to_string(),
             "app/services/payment.rb:88:in `process'".to_string(),
         ];
-        let location = extract_error_location(&backtrace);
+        let location = extract_error_location(&backtrace, &[]);
         assert_eq!(location, Some("app/services/payment.rb:88".to_string()));
     }
 
@@ -692,7 +1673,7 @@ mod tests {
             "/gems/activerecord-7.0.0/lib/active_record/base.rb:123:in `find'".to_string(),
             "/vendor/bundle/gems/rails-7.0.0/lib/rails.rb:5:in `run'".to_string(),
         ];
-        let location = extract_error_location(&backtrace);
+        let location = extract_error_location(&backtrace, &[]);
         // Falls back to first frame with method stripped
         assert_eq!(
             location,
@@ -703,14 +1684,150 @@ mod tests {
     #[test]
     fn test_extract_error_location_empty_backtrace() {
         let backtrace: Vec<String> = vec![];
-        let location = extract_error_location(&backtrace);
+        let location = extract_error_location(&backtrace, &[]);
         assert_eq!(location, None);
     }
 
+    #[test]
+    fn test_refingerprint_merges_errors_that_now_share_a_fingerprint() {
+        let pool = crate::db::test_pool();
+        {
+            let conn = pool.get().unwrap();
+            // Grouped apart under an older fingerprinting scheme, though both
+            // were raised at the same line
+            for (id, fingerprint, status, first_seen, frame) in [
+                (
+                    1,
+                    "RuntimeError:app/models/user.rb:10:in `save'",
+                    "resolved",
+                    "2026-01-01T00:00:00Z",
+                    "app/models/user.rb:10:in `save'",
+                ),
+                (
+                    2,
+                    "RuntimeError:app/models/user.rb:10:in `validate'",
+                    "open",
+                    "2026-01-02T00:00:00Z",
+                    "app/models/user.rb:10:in `validate'",
+                ),
+                (
+                    3,
+                    "RuntimeError:app/models/order.rb:5:in `total'",
+                    "open",
+                    "2026-01-03T00:00:00Z",
+                    "app/models/order.rb:5:in `total'",
+                ),
+            ] {
+                conn.execute(
+                    r#"INSERT INTO errors (id, fingerprint, exception_class, message, first_seen_at,
+                           last_seen_at, occurrence_count, status, sample_hour, sample_hour_count)
+                       VALUES (?1, ?2, 'RuntimeError', 'boom', ?3, ?3, 2, ?4, '2026-01-02 00', 2)"#,
+                    rusqlite::params![id, fingerprint, first_seen, status],
+                )
+                .unwrap();
+                for _ in 0..2 {
+                    conn.execute(
+                        r#"INSERT INTO error_occurrences (error_id, backtrace, happened_at)
+                           VALUES (?1, ?2, ?3)"#,
+                        rusqlite::params![id, serde_json::json!([frame]).to_string(), first_seen],
+                    )
+                    .unwrap();
+                }
+            }
+        }
+
+        let outcome = refingerprint_errors(&pool, None, false).unwrap();
+        assert_eq!(
+            outcome,
+            Refingerprinted {
+                updated: 2,
+                merged: 1,
+                skipped: 0
+            }
+        );
+
+        let errors = list(&pool, None, None, 10).unwrap();
+        assert_eq!(errors.len(), 2);
+        let user = find(&pool, 1).unwrap().unwrap();
+        assert_eq!(user.fingerprint, "RuntimeError:app/models/user.rb:10");
+        assert_eq!(user.occurrence_count, 4);
+        assert_eq!(user.status, "open");
+        assert_eq!(user.last_seen_at, "2026-01-02 00:00");
+        assert!(find(&pool, 2).unwrap().is_none());
+        let moved: i64 = pool
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM error_occurrences WHERE error_id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(moved, 4);
+        let sample_hour_count: i64 = pool
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT sample_hour_count FROM errors WHERE id = 1",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(sample_hour_count, 4);
+
+        // Running it again changes nothing
+        assert_eq!(
+            refingerprint_errors(&pool, None, false).unwrap(),
+            Refingerprinted::default()
+        );
+    }
+
+    #[test]
+    fn test_refingerprint_keeps_custom_fingerprints_unless_asked() {
+        let pool = crate::db::test_pool();
+        {
+            let conn = pool.get().unwrap();
+            conn.execute(
+                r#"INSERT INTO errors (id, fingerprint, exception_class, message, first_seen_at,
+                       last_seen_at, occurrence_count, status)
+                   VALUES (1, 'checkout-timeouts', 'RuntimeError', 'boom',
+                           '2026-01-01T00:00:00Z', '2026-01-01T00:00:00Z', 1, 'open')"#,
+                [],
+            )
+            .unwrap();
+            conn.execute(
+                r#"INSERT INTO error_occurrences (error_id, backtrace, happened_at)
+                   VALUES (1, ?1, '2026-01-01T00:00:00Z')"#,
+                [serde_json::json!(["app/models/order.rb:5:in `total'"]).to_string()],
+            )
+            .unwrap();
+        }
+
+        assert_eq!(
+            refingerprint_errors(&pool, None, false).unwrap(),
+            Refingerprinted {
+                updated: 0,
+                merged: 0,
+                skipped: 1
+            }
+        );
+        assert_eq!(
+            find(&pool, 1).unwrap().unwrap().fingerprint,
+            "checkout-timeouts"
+        );
+
+        assert_eq!(refingerprint_errors(&pool, None, true).unwrap().updated, 1);
+        assert_eq!(
+            find(&pool, 1).unwrap().unwrap().fingerprint,
+            "RuntimeError:app/models/order.rb:5"
+        );
+    }
+
     #[test]
     fn test_generate_location_fingerprint() {
         let backtrace = vec!["app/models/user.rb:42:in `save'".to_string()];
-        let fingerprint = generate_location_fingerprint("ActiveRecord::RecordInvalid", &backtrace);
+        let fingerprint =
+            generate_location_fingerprint("ActiveRecord::RecordInvalid", &backtrace, &[]);
         assert_eq!(
             fingerprint,
             "ActiveRecord::RecordInvalid:app/models/user.rb:42"
@@ -720,7 +1837,7 @@ mod tests {
     #[test]
     fn test_generate_location_fingerprint_empty_backtrace() {
         let backtrace: Vec<String> = vec![];
-        let fingerprint = generate_location_fingerprint("RuntimeError", &backtrace);
+        let fingerprint = generate_location_fingerprint("RuntimeError", &backtrace, &[]);
         assert_eq!(fingerprint, "RuntimeError:");
     }
 
@@ -749,4 +1866,581 @@ mod tests {
             sim
         );
     }
+
+    fn incoming(message: &str) -> IncomingError {
+        IncomingError {
+            exception_class: "RuntimeError".to_string(),
+            message: message.to_string(),
+            backtrace: vec!["app/models/user.rb:42:in `save'".to_string()],
+            fingerprint: "fp".to_string(),
+            request_id: None,
+            user_id: None,
+            params: None,
+            timestamp: None,
+            source_context: None,
+            git_sha: None,
+        }
+    }
+
+    #[test]
+    fn test_muted_error_reopens_on_burst() {
+        let pool = crate::db::test_pool();
+        let id = insert(&pool, &incoming("boom"), None).unwrap();
+        mute(&pool, id, None, Some(5)).unwrap();
+
+        for _ in 0..6 {
+            insert(&pool, &incoming("boom"), None).unwrap();
+        }
+
+        let error = find(&pool, id).unwrap().unwrap();
+        assert_eq!(error.status, "open");
+        assert_eq!(error.auto_reopen_threshold, None);
+    }
+
+    #[test]
+    fn test_muted_error_stays_muted_on_trickle() {
+        let pool = crate::db::test_pool();
+        let id = insert(&pool, &incoming("boom"), None).unwrap();
+        mute(&pool, id, None, Some(5)).unwrap();
+
+        insert(&pool, &incoming("boom"), None).unwrap();
+        insert(&pool, &incoming("boom"), None).unwrap();
+
+        let error = find(&pool, id).unwrap().unwrap();
+        assert_eq!(error.status, "ignored");
+        assert_eq!(error.auto_reopen_threshold, Some(5));
+    }
+
+    #[test]
+    fn test_expired_mute_reopens() {
+        let pool = crate::db::test_pool();
+        let id = insert(&pool, &incoming("boom"), None).unwrap();
+        let past = (Utc::now() - chrono::Duration::hours(1)).to_rfc3339();
+        mute(&pool, id, Some(&past), None).unwrap();
+
+        insert(&pool, &incoming("boom"), None).unwrap();
+
+        let error = find(&pool, id).unwrap().unwrap();
+        assert_eq!(error.status, "open");
+    }
+
+    #[test]
+    fn test_update_status_bulk_updates_every_id() {
+        let pool = crate::db::test_pool();
+        let ids: Vec<i64> = ["a", "b", "c", "d"]
+            .iter()
+            .map(|fp| {
+                let error = IncomingError {
+                    fingerprint: fp.to_string(),
+                    exception_class: format!("Error{}", fp),
+                    ..incoming("boom")
+                };
+                insert(&pool, &error, None).unwrap()
+            })
+            .collect();
+        mute(&pool, ids[0], None, Some(5)).unwrap();
+
+        // Unknown ids are skipped
+        assert_eq!(
+            update_status_bulk(&pool, &[ids[0], ids[1], ids[2], 9999], "resolved").unwrap(),
+            3
+        );
+        let count = |status| count_filtered(&pool, None, Some(status), None, None, None).unwrap();
+        assert_eq!(count("resolved"), 3);
+        assert_eq!(count("open"), 1);
+        // Like a single update, the mute is cleared
+        let error = find(&pool, ids[0]).unwrap().unwrap();
+        assert_eq!(error.auto_reopen_threshold, None);
+
+        assert_eq!(update_status_bulk(&pool, &[], "ignored").unwrap(), 0);
+    }
+
+    #[test]
+    fn test_auto_resolve_stale_resolves_only_quiet_open_errors() {
+        let pool = crate::db::test_pool();
+        let (shop, _) = crate::models::project::create(&pool, "Shop").unwrap();
+        let seen = |fp: &str, days_ago: i64, project_id: Option<i64>| {
+            let error = IncomingError {
+                fingerprint: fp.to_string(),
+                exception_class: format!("Error{}", fp),
+                timestamp: Some((Utc::now() - chrono::Duration::days(days_ago)).to_rfc3339()),
+                ..incoming("boom")
+            };
+            insert(&pool, &error, project_id).unwrap()
+        };
+        let fresh = seen("fresh", 1, None);
+        let quiet = seen("quiet", 10, None);
+        let ancient = seen("ancient", 40, None);
+        let ignored = seen("ignored", 40, None);
+        mute(&pool, ignored, None, None).unwrap();
+        let other_project = seen("other", 40, Some(shop.id));
+
+        let before = (Utc::now() - chrono::Duration::days(7)).to_rfc3339();
+        assert_eq!(auto_resolve_stale(&pool, None, &before).unwrap(), 2);
+        let status = |id| find(&pool, id).unwrap().unwrap().status;
+        assert_eq!(status(fresh), "open");
+        assert_eq!(status(quiet), "resolved");
+        assert_eq!(status(ancient), "resolved");
+        assert_eq!(status(ignored), "ignored");
+        assert_eq!(status(other_project), "open");
+
+        // A recurrence reopens an auto-resolved error, not a hand-resolved one
+        update_status(&pool, quiet, "resolved").unwrap();
+        seen("quiet", 0, None);
+        seen("ancient", 0, None);
+        assert_eq!(status(quiet), "resolved");
+        assert_eq!(status(ancient), "open");
+    }
+
+    #[test]
+    fn test_error_releases_groups_by_git_sha() {
+        let pool = crate::db::test_pool();
+        let mut error = incoming("boom");
+        error.git_sha = Some("aaaaaaa1111".to_string());
+        error.timestamp = Some("2026-01-01T10:00:00Z".to_string());
+        let id = insert(&pool, &error, None).unwrap();
+        error.timestamp = Some("2026-01-01T11:00:00Z".to_string());
+        insert(&pool, &error, None).unwrap();
+
+        error.git_sha = Some("bbbbbbb2222".to_string());
+        error.timestamp = Some("2026-01-02T10:00:00Z".to_string());
+        insert(&pool, &error, None).unwrap();
+
+        error.git_sha = None;
+        insert(&pool, &error, None).unwrap();
+
+        let releases = error_releases(&pool, id).unwrap();
+        assert_eq!(releases.len(), 2);
+        assert_eq!(releases[0].git_sha, "aaaaaaa1111");
+        assert_eq!(releases[0].occurrence_count, 2);
+        assert_eq!(releases[1].short_sha(), "bbbbbbb");
+        let unicode = ErrorRelease {
+            git_sha: "résumé-1".to_string(),
+            ..releases[1].clone()
+        };
+        assert_eq!(unicode.short_sha(), "résumé-1");
+
+        let error = find(&pool, id).unwrap().unwrap();
+        assert_eq!(error.first_seen_release.as_deref(), Some("aaaaaaa1111"));
+        assert_eq!(error.last_seen_release.as_deref(), Some("bbbbbbb2222"));
+    }
+
+    #[test]
+    fn test_param_breakdown_counts_common_values() {
+        let pool = crate::db::test_pool();
+        let mut error = incoming("boom");
+        let mut id = 0;
+        for params in [
+            serde_json::json!({"plan": "pro", "endpoint": "/checkout", "trial": true}),
+            serde_json::json!({"plan": "pro", "endpoint": "/cart", "trial": false}),
+            serde_json::json!({"plan": "pro", "endpoint": "/checkout"}),
+            serde_json::json!({"plan": "free", "trial": true, "page": 2}),
+            serde_json::json!({"plan": null}),
+        ] {
+            error.params = Some(params);
+            id = insert(&pool, &error, None).unwrap();
+        }
+        error.params = None;
+        insert(&pool, &error, None).unwrap();
+
+        let keys = param_keys(&pool, id, 10).unwrap();
+        assert_eq!(keys, vec!["plan", "endpoint", "trial", "page"]);
+
+        let keys: Vec<String> = ["plan", "trial", "page", "missing"]
+            .iter()
+            .map(|k| k.to_string())
+            .collect();
+        let breakdowns = param_breakdown(&pool, id, &keys, 10).unwrap();
+        let values = |b: &ParamBreakdown| -> Vec<(String, i64)> {
+            b.values
+                .iter()
+                .map(|v| (v.value.clone(), v.count))
+                .collect()
+        };
+        // A null plan doesn't count, and a key nobody sent is left out
+        let keys: Vec<(&str, i64)> = breakdowns
+            .iter()
+            .map(|b| (b.key.as_str(), b.occurrence_count))
+            .collect();
+        assert_eq!(keys, vec![("plan", 4), ("trial", 3), ("page", 1)]);
+        assert_eq!(
+            values(&breakdowns[0]),
+            vec![("pro".to_string(), 3), ("free".to_string(), 1)]
+        );
+        assert_eq!(
+            values(&breakdowns[1]),
+            vec![("true".to_string(), 2), ("false".to_string(), 1)]
+        );
+        assert_eq!(values(&breakdowns[2]), vec![("2".to_string(), 1)]);
+        assert_eq!(breakdowns[0].percent(&breakdowns[0].values[0]), 75);
+    }
+
+    #[test]
+    fn test_errors_since_deploy_splits_new_and_recurring() {
+        let pool = crate::db::test_pool();
+        assert!(errors_since_deploy(&pool, None).unwrap().is_none());
+
+        let at = |ts: &str, message: &str, fingerprint: &str| {
+            let mut error = incoming(message);
+            error.fingerprint = fingerprint.to_string();
+            error.backtrace = vec![format!("app/{fingerprint}.rb:1:in `run'")];
+            error.timestamp = Some(ts.to_string());
+            insert(&pool, &error, None).unwrap();
+        };
+        // An older error that keeps happening after the deploy
+        at("2026-01-01T09:00:00Z", "stale cache", "old");
+        at("2026-01-01T11:00:00Z", "stale cache", "old");
+
+        crate::models::deploy::insert(
+            &pool,
+            &crate::models::deploy::IncomingDeploy {
+                git_sha: "abc1234".to_string(),
+                version: None,
+                env: None,
+                description: None,
+                deployer: None,
+                timestamp: Some("2026-01-01T10:00:00+00:00".to_string()),
+            },
+            None,
+        )
+        .unwrap();
+
+        at("2026-01-01T10:30:00Z", "nil user", "new");
+        at("2026-01-01T10:45:00Z", "nil user", "new");
+        at("2026-01-01T10:50:00Z", "nil user", "new");
+
+        let since = errors_since_deploy(&pool, None).unwrap().unwrap();
+        assert_eq!(since.deploy.git_sha, "abc1234");
+        assert_eq!(since.new_errors, 1);
+        assert_eq!(since.new_occurrences, 3);
+        assert_eq!(since.recurring_errors, 1);
+        // The 09:00 occurrence predates the deploy
+        assert_eq!(since.recurring_occurrences, 1);
+        assert_eq!(since.total_occurrences(), 4);
+    }
+
+    #[test]
+    fn test_errors_by_endpoint_groups_linked_occurrences() {
+        let pool = crate::db::test_pool();
+        {
+            let conn = pool.get().unwrap();
+            for (request_id, controller, action, method, path) in [
+                (
+                    "req-1",
+                    Some("OrdersController"),
+                    Some("create"),
+                    "POST",
+                    "/orders",
+                ),
+                (
+                    "req-2",
+                    Some("OrdersController"),
+                    Some("create"),
+                    "POST",
+                    "/orders",
+                ),
+                ("req-3", None, None, "GET", "/health"),
+            ] {
+                conn.execute(
+                    "INSERT INTO requests (request_id, method, path, controller, action, status, total_ms, happened_at)
+                     VALUES (?1, ?2, ?3, ?4, ?5, 500, 10, '2026-01-01T10:00:00Z')",
+                    rusqlite::params![request_id, method, path, controller, action],
+                )
+                .unwrap();
+            }
+            // A trace without a request log entry
+            conn.execute(
+                "INSERT INTO spans (trace_id, span_id, start_time_unix_nano, end_time_unix_nano,
+                     duration_ms, name, span_category, happened_at)
+                 VALUES ('trace-1', 'root', 0, 0, 5, 'ProfilesController#show', 'http_server',
+                         '2026-01-01T10:00:00Z')",
+                [],
+            )
+            .unwrap();
+        }
+
+        let occur = |request_id: Option<&str>, fingerprint: &str, ts: &str| {
+            let mut error = incoming("boom");
+            error.fingerprint = fingerprint.to_string();
+            error.backtrace = vec![format!("app/{fingerprint}.rb:1:in `run'")];
+            error.request_id = request_id.map(str::to_string);
+            error.timestamp = Some(ts.to_string());
+            insert(&pool, &error, None).unwrap();
+        };
+        occur(Some("req-1"), "a", "2026-01-01T10:00:00Z");
+        occur(Some("req-2"), "a", "2026-01-01T10:01:00Z");
+        occur(Some("req-2"), "b", "2026-01-01T10:02:00Z");
+        occur(Some("req-3"), "a", "2026-01-01T10:03:00Z");
+        occur(Some("trace-1"), "c", "2026-01-01T10:04:00Z");
+        // Unlinked occurrences are left out
+        occur(Some("unknown"), "a", "2026-01-01T10:05:00Z");
+        occur(None, "a", "2026-01-01T10:06:00Z");
+        // Outside the window
+        occur(Some("req-1"), "a", "2025-12-01T10:00:00Z");
+
+        let endpoints =
+            errors_by_endpoint(&pool, None, Some("2026-01-01T00:00:00Z"), None, 10).unwrap();
+        let rows: Vec<(&str, i64, i64)> = endpoints
+            .iter()
+            .map(|e| (e.endpoint.as_str(), e.occurrence_count, e.error_count))
+            .collect();
+        assert_eq!(
+            rows,
+            vec![
+                ("OrdersController#create", 3, 2),
+                ("GET /health", 1, 1),
+                ("ProfilesController#show", 1, 1),
+            ]
+        );
+
+        let all_time = errors_by_endpoint(&pool, None, None, None, 1).unwrap();
+        assert_eq!(all_time.len(), 1);
+        assert_eq!(all_time[0].occurrence_count, 4);
+
+        // The end bound is exclusive
+        let before =
+            errors_by_endpoint(&pool, None, None, Some("2026-01-01T00:00:00Z"), 10).unwrap();
+        assert_eq!(before.len(), 1);
+        assert_eq!(before[0].occurrence_count, 1);
+    }
+
+    #[test]
+    fn test_affected_users_counts_distinct_non_null_users() {
+        let pool = crate::db::test_pool();
+        let mut error_id = 0;
+        for user_id in [
+            Some("1"),
+            Some("2"),
+            Some("1"),
+            None,
+            Some("3"),
+            None,
+            Some("2"),
+        ] {
+            let mut error = incoming("boom");
+            error.user_id = user_id.map(str::to_string);
+            error_id = insert(&pool, &error, None).unwrap();
+        }
+        assert_eq!(affected_users(&pool, error_id).unwrap(), 3);
+        assert_eq!(affected_users(&pool, error_id + 1).unwrap(), 0);
+    }
+
+    fn bundle_rewrite() -> Vec<FrameRewrite> {
+        FrameRewrite::parse_rules("/app/bundle.js => app/javascript/checkout.js\n# ignored")
+    }
+
+    #[test]
+    fn test_resolve_frames_rewrites_minified_path() {
+        let backtrace = vec![
+            "at pay (/app/bundle.js:1:99999)".to_string(),
+            "at node:internal/process:12:3".to_string(),
+        ];
+        let resolved = resolve_frames(&backtrace, &bundle_rewrite());
+        assert_eq!(resolved[0], "at pay (app/javascript/checkout.js)");
+        assert_eq!(resolved[1], backtrace[1]);
+    }
+
+    #[test]
+    fn test_rewritten_frames_give_stable_fingerprint() {
+        let build_a = vec!["/app/bundle.js:1:99999".to_string()];
+        let build_b = vec!["/app/bundle.js:1:10452".to_string()];
+        let rewrites = bundle_rewrite();
+
+        assert_ne!(
+            generate_location_fingerprint("TypeError", &build_a, &[]),
+            generate_location_fingerprint("TypeError", &build_b, &[])
+        );
+        assert_eq!(
+            generate_location_fingerprint("TypeError", &build_a, &rewrites),
+            generate_location_fingerprint("TypeError", &build_b, &rewrites)
+        );
+        assert_eq!(
+            generate_location_fingerprint("TypeError", &build_a, &rewrites),
+            "TypeError:app/javascript/checkout.js"
+        );
+    }
+
+    #[test]
+    fn test_occurrences_sampled_past_hourly_cap() {
+        let pool = crate::db::test_pool();
+        let at = |hour: &str| IncomingError {
+            timestamp: Some(format!("2026-06-01T{hour}:30:00Z")),
+            ..incoming("boom")
+        };
+        let mut id = 0;
+        for _ in 0..50 {
+            id = insert_sampled(&pool, &at("10"), None, 5).unwrap();
+        }
+        insert_sampled(&pool, &at("11"), None, 5).unwrap();
+
+        let error = find(&pool, id).unwrap().unwrap();
+        assert_eq!(error.occurrence_count, 51);
+        let stored_in = |hour: &str| -> i64 {
+            pool.get()
+                .unwrap()
+                .query_row(
+                    "SELECT COUNT(*) FROM error_occurrences WHERE error_id = ?1 AND strftime('%H', happened_at) = ?2",
+                    rusqlite::params![id, hour],
+                    |row| row.get(0),
+                )
+                .unwrap()
+        };
+        assert_eq!(stored_in("10"), 5);
+        assert_eq!(stored_in("11"), 1);
+    }
+
+    #[test]
+    fn test_sampled_error_still_charts_its_true_hourly_count() {
+        let pool = crate::db::test_pool();
+        let hour = (Utc::now() - chrono::Duration::hours(2))
+            .format("%Y-%m-%dT%H")
+            .to_string();
+        let mut id = 0;
+        for i in 0..50 {
+            let error = IncomingError {
+                user_id: Some(format!("user-{}", i % 20)),
+                timestamp: Some(format!("{hour}:{:02}:00Z", i)),
+                ..incoming("boom")
+            };
+            id = insert_sampled(&pool, &error, None, 5).unwrap();
+        }
+        let stored: i64 = pool
+            .get()
+            .unwrap()
+            .query_row("SELECT COUNT(*) FROM error_occurrences", [], |row| {
+                row.get(0)
+            })
+            .unwrap();
+        assert_eq!(stored, 5);
+
+        let label = hour.replace('T', " ") + ":00";
+        let chart = hourly_error_stats(&pool, None, 24, DisplayOffset::default()).unwrap();
+        let point = chart.iter().find(|p| p.hour == label).unwrap();
+        assert_eq!(point.count, 50);
+        assert_eq!(error_trend_24h(&pool, id).unwrap().iter().sum::<i64>(), 50);
+        let trend = error_trend(&pool, id, 24, DisplayOffset::default()).unwrap();
+        assert_eq!(trend.iter().map(|p| p.count).sum::<i64>(), 50);
+        let since = (Utc::now() - chrono::Duration::hours(6)).to_rfc3339();
+        assert_eq!(count_since(&pool, None, &since).unwrap(), 50);
+        // Users whose occurrences were all sampled out still count
+        assert_eq!(affected_users(&pool, id).unwrap(), 20);
+    }
+
+    #[test]
+    fn test_keyset_pages_stay_stable_as_errors_arrive() {
+        let pool = crate::db::test_pool();
+        let record = |fingerprint: &str, minute: u32| {
+            let error = IncomingError {
+                fingerprint: fingerprint.to_string(),
+                timestamp: Some(format!("2026-01-01T10:{minute:02}:00Z")),
+                ..incoming(fingerprint)
+            };
+            insert(&pool, &error, None).unwrap()
+        };
+        for minute in 0..7 {
+            record(&format!("fp{minute}"), minute);
+        }
+        let all = list_paginated(&pool, None, None, None, None, None, "last_seen", 100, 0)
+            .unwrap()
+            .into_iter()
+            .map(|e| e.id)
+            .collect::<Vec<_>>();
+
+        let (first, cursor) = list_after(&pool, None, None, None, None, None, None, 3).unwrap();
+        // A new error arrives while the first page is on screen
+        record("fresh", 30);
+
+        let mut seen: Vec<i64> = first.iter().map(|e| e.id).collect();
+        let mut cursor = cursor;
+        while let Some(after) = cursor {
+            let (page, next) =
+                list_after(&pool, None, None, None, None, None, Some(&after), 3).unwrap();
+            seen.extend(page.iter().map(|e| e.id));
+            cursor = next;
+        }
+        assert_eq!(seen, all);
+
+        // Offset paging shows the last row of page one again
+        let second =
+            list_paginated(&pool, None, None, None, None, None, "last_seen", 3, 3).unwrap();
+        assert_eq!(second[0].id, all[2]);
+    }
+
+    #[test]
+    fn test_keyset_paging_skips_an_error_that_recurs_mid_way() {
+        let pool = crate::db::test_pool();
+        let record = |fingerprint: &str, minute: u32| {
+            let error = IncomingError {
+                fingerprint: fingerprint.to_string(),
+                timestamp: Some(format!("2026-01-01T10:{minute:02}:00Z")),
+                ..incoming(fingerprint)
+            };
+            insert(&pool, &error, None).unwrap()
+        };
+        let ids: Vec<i64> = (0..6)
+            .map(|minute| record(&format!("fp{minute}"), minute))
+            .collect();
+
+        let (first, cursor) = list_after(&pool, None, None, None, None, None, None, 3).unwrap();
+        assert_eq!(
+            first.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![ids[5], ids[4], ids[3]]
+        );
+        // fp1, still on a later page, recurs and moves to the top
+        record("fp1", 30);
+
+        let (second, next) =
+            list_after(&pool, None, None, None, None, None, cursor.as_ref(), 3).unwrap();
+        assert_eq!(
+            second.iter().map(|e| e.id).collect::<Vec<_>>(),
+            vec![ids[2], ids[0]]
+        );
+        assert!(next.is_none());
+    }
+
+    #[test]
+    fn test_list_after_rejects_a_non_numeric_cursor_id() {
+        let pool = crate::db::test_pool();
+        let cursor = Cursor {
+            happened_at: "2026-01-01T10:00:00Z".to_string(),
+            id: "abc".to_string(),
+        };
+        assert!(list_after(&pool, None, None, None, None, None, Some(&cursor), 3).is_err());
+    }
+
+    #[test]
+    fn test_hourly_error_stats_buckets_shift_with_offset() {
+        let pool = crate::db::test_pool();
+        // Two occurrences in one UTC hour, at :15 and :45
+        let hour = (Utc::now() - chrono::Duration::hours(3))
+            .format("%Y-%m-%dT%H:00:00Z")
+            .to_string();
+        let base = chrono::DateTime::parse_from_rfc3339(&hour).unwrap();
+        for minute in [15, 45] {
+            let at = base + chrono::Duration::minutes(minute);
+            let error = IncomingError {
+                timestamp: Some(at.to_rfc3339()),
+                ..incoming("boom")
+            };
+            insert(&pool, &error, None).unwrap();
+        }
+
+        let buckets = |minutes| {
+            hourly_error_stats(&pool, None, 24, DisplayOffset { minutes })
+                .unwrap()
+                .into_iter()
+                .filter(|p| p.count > 0)
+                .map(|p| (p.hour, p.count))
+                .collect::<Vec<_>>()
+        };
+        let label = |hours| {
+            (base + chrono::Duration::hours(hours))
+                .format("%Y-%m-%d %H:00")
+                .to_string()
+        };
+        assert_eq!(buckets(0), vec![(label(0), 2)]);
+        assert_eq!(buckets(-120), vec![(label(-2), 2)]);
+        // A half-hour offset moves the boundary between the two
+        assert_eq!(buckets(330), vec![(label(5), 1), (label(6), 1)]);
+    }
 }
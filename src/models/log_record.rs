@@ -0,0 +1,370 @@
+use crate::DbPool;
+use crate::models::span::{self, AttributeValue, KeyValue, Redactor, Resource};
+use chrono::DateTime;
+use serde::{Deserialize, Serialize};
+use std::collections::HashMap;
+
+// ============================================================================
+// OTLP/HTTP JSON Log Types (matching the OTLP logs protobuf JSON mapping)
+// ============================================================================
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OtlpLogsRequest {
+    pub resource_logs: Vec<ResourceLogs>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ResourceLogs {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub resource: Option<Resource>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub scope_logs: Option<Vec<ScopeLogs>>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct ScopeLogs {
+    #[serde(default)]
+    pub log_records: Vec<OtlpLogRecord>,
+}
+
+#[derive(Debug, Clone, Deserialize, Serialize)]
+#[serde(rename_all = "camelCase")]
+pub struct OtlpLogRecord {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub time_unix_nano: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub observed_time_unix_nano: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity_number: Option<i32>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub severity_text: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub body: Option<AttributeValue>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub attributes: Option<Vec<KeyValue>>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub trace_id: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub span_id: Option<String>,
+}
+
+/// Per-batch outcome of `insert_otlp_batch`, returned as the ingest response
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct LogIngestOutcome {
+    pub accepted: usize,
+    /// Records dropped for malformed IDs or timestamps
+    pub rejected: usize,
+}
+
+// ============================================================================
+// Display Types
+// ============================================================================
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LogDisplay {
+    pub id: i64,
+    pub trace_id: Option<String>,
+    pub span_id: Option<String>,
+    pub time_unix_nano: i64,
+    pub happened_at: String,
+    pub severity_number: Option<i32>,
+    pub severity_text: Option<String>,
+    pub body: Option<String>,
+    pub attributes: HashMap<String, String>,
+    pub service_name: Option<String>,
+    /// Milliseconds after the start of the trace
+    pub offset_ms: f64,
+    /// Name of the span `span_id` refers to, when it's part of the trace
+    pub span_name: Option<String>,
+}
+
+impl LogDisplay {
+    /// The sender's severity text, or the OTLP level its number falls in
+    pub fn severity_label(&self) -> String {
+        if let Some(text) = self.severity_text.as_deref().filter(|t| !t.is_empty()) {
+            return text.to_string();
+        }
+        match self.severity_number {
+            Some(1..=4) => "TRACE",
+            Some(5..=8) => "DEBUG",
+            Some(9..=12) => "INFO",
+            Some(13..=16) => "WARN",
+            Some(17..=20) => "ERROR",
+            Some(21..=24) => "FATAL",
+            _ => "LOG",
+        }
+        .to_string()
+    }
+
+    pub fn severity_class(&self) -> &'static str {
+        match self.severity_number {
+            Some(17..) => "error",
+            Some(13..=16) => "warning",
+            _ => "info",
+        }
+    }
+
+    pub fn sorted_attributes(&self) -> Vec<(&String, &String)> {
+        let mut attrs: Vec<_> = self.attributes.iter().collect();
+        attrs.sort();
+        attrs
+    }
+}
+
+// ============================================================================
+// Ingestion
+// ============================================================================
+
+/// Decode an optional OTLP ID; empty means absent, anything else must be valid
+fn optional_id(id: &Option<String>, len: usize) -> Result<Option<String>, ()> {
+    match id.as_deref().filter(|s| !s.is_empty()) {
+        Some(id) => span::parse_id(id, len).map(Some).ok_or(()),
+        None => Ok(None),
+    }
+}
+
+pub fn insert_otlp_batch(
+    pool: &DbPool,
+    request: &OtlpLogsRequest,
+    project_id: Option<i64>,
+    options: &span::IngestOptions,
+) -> anyhow::Result<LogIngestOutcome> {
+    let redactor = Redactor::for_project(pool, project_id, options);
+    let request = request.clone();
+    pool.write(move |conn| {
+        let tx = conn.transaction()?;
+        let mut outcome = LogIngestOutcome::default();
+        {
+            let mut insert = tx.prepare(
+                r#"
+                INSERT INTO log_records (project_id, trace_id, span_id, time_unix_nano,
+                    severity_number, severity_text, body, attributes_json, service_name,
+                    happened_at)
+                VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10)
+                "#,
+            )?;
+            for resource_logs in &request.resource_logs {
+                let resource_attrs = span::parse_attributes(
+                    &resource_logs
+                        .resource
+                        .as_ref()
+                        .and_then(|r| r.attributes.clone()),
+                );
+                let service_name = resource_attrs.get("service.name");
+
+                for record in resource_logs
+                    .scope_logs
+                    .iter()
+                    .flatten()
+                    .flat_map(|s| &s.log_records)
+                {
+                    // Receivers fall back to the observed time when the source had none
+                    let time = [&record.time_unix_nano, &record.observed_time_unix_nano]
+                        .into_iter()
+                        .flatten()
+                        .map(|t| t.parse::<i64>())
+                        .find(|t| t != &Ok(0));
+                    let ids = (
+                        optional_id(&record.trace_id, span::TRACE_ID_BYTES),
+                        optional_id(&record.span_id, span::SPAN_ID_BYTES),
+                    );
+                    let (Some(Ok(time_unix_nano)), (Ok(trace_id), Ok(span_id))) = (time, ids)
+                    else {
+                        tracing::warn!("Rejected log record: invalid timestamp or ids");
+                        outcome.rejected += 1;
+                        continue;
+                    };
+
+                    let mut attrs = span::parse_attributes(&record.attributes);
+                    redactor.redact(&mut attrs);
+                    let body = record
+                        .body
+                        .as_ref()
+                        .and_then(span::attribute_value)
+                        .map(|body| redactor.redact_value(&body));
+                    let happened_at = DateTime::from_timestamp_nanos(time_unix_nano)
+                        .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+                        .to_string();
+
+                    insert.execute(rusqlite::params![
+                        project_id,
+                        trace_id,
+                        span_id,
+                        time_unix_nano,
+                        record.severity_number,
+                        record.severity_text,
+                        body,
+                        serde_json::to_string(&attrs)?,
+                        service_name,
+                        happened_at,
+                    ])?;
+                    outcome.accepted += 1;
+                }
+            }
+        }
+        tx.commit()?;
+        Ok(outcome)
+    })
+}
+
+// ============================================================================
+// Queries
+// ============================================================================
+
+/// Log records emitted within a trace, oldest first, with offsets from
+/// `start_unix_nano`. `span::get_trace` loads them into `TraceDetail::logs`.
+pub(crate) fn for_trace(
+    conn: &rusqlite::Connection,
+    trace_id: &str,
+    start_unix_nano: i64,
+) -> anyhow::Result<Vec<LogDisplay>> {
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT id, trace_id, span_id, time_unix_nano, happened_at, severity_number,
+               severity_text, body, attributes_json, service_name
+        FROM log_records
+        WHERE trace_id = ?1
+        ORDER BY time_unix_nano ASC, id ASC
+        "#,
+    )?;
+    let records = stmt
+        .query_map([trace_id], |row| {
+            let attributes_json: Option<String> = row.get(8)?;
+            let time_unix_nano: i64 = row.get(3)?;
+            Ok(LogDisplay {
+                id: row.get(0)?,
+                trace_id: row.get(1)?,
+                span_id: row.get(2)?,
+                time_unix_nano,
+                happened_at: row.get(4)?,
+                severity_number: row.get(5)?,
+                severity_text: row.get(6)?,
+                body: row.get(7)?,
+                attributes: attributes_json
+                    .and_then(|json| serde_json::from_str(&json).ok())
+                    .unwrap_or_default(),
+                service_name: row.get(9)?,
+                offset_ms: (time_unix_nano - start_unix_nano) as f64 / 1_000_000.0,
+                span_name: None,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(records)
+}
+
+pub fn delete_before(pool: &DbPool, before: &str) -> anyhow::Result<usize> {
+    let conn = pool.get()?;
+    let deleted = conn.execute("DELETE FROM log_records WHERE happened_at < ?1", [before])?;
+    Ok(deleted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::span::OtlpTraceRequest;
+
+    const TRACE_ID: &str = "4bf92f3577b34da6a3ce929d0e0e4736";
+
+    fn logs(records: serde_json::Value) -> OtlpLogsRequest {
+        serde_json::from_value(serde_json::json!({
+            "resourceLogs": [{
+                "resource": {"attributes": [
+                    {"key": "service.name", "value": {"stringValue": "checkout"}}
+                ]},
+                "scopeLogs": [{"logRecords": records}]
+            }]
+        }))
+        .unwrap()
+    }
+
+    #[test]
+    fn test_log_records_join_their_trace() {
+        let pool = crate::db::test_pool();
+        let trace: OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+            "resourceSpans": [{"scopeSpans": [{"spans": [{
+                "traceId": TRACE_ID,
+                "spanId": "00f067aa0ba902b7",
+                "name": "POST /checkout",
+                "kind": 2,
+                "startTimeUnixNano": "1700000000000000000",
+                "endTimeUnixNano": "1700000000100000000"
+            }]}]}]
+        }))
+        .unwrap();
+        span::insert_otlp_batch(&pool, &trace, None).unwrap();
+
+        let outcome = insert_otlp_batch(
+            &pool,
+            &logs(serde_json::json!([
+                {
+                    "timeUnixNano": "1700000000090000000",
+                    "severityNumber": 17,
+                    "body": {"stringValue": "payment declined for bob@example.com"},
+                    "traceId": TRACE_ID,
+                    "spanId": "00f067aa0ba902b7"
+                },
+                {
+                    "timeUnixNano": "1700000000020000000",
+                    "severityNumber": 9,
+                    "severityText": "Information",
+                    "body": {"stringValue": "cart loaded"},
+                    "attributes": [{"key": "cart.items", "value": {"intValue": "3"}}],
+                    "traceId": TRACE_ID
+                },
+                {"timeUnixNano": "1700000000030000000", "body": {"stringValue": "no trace"}},
+                {"timeUnixNano": "1700000000040000000", "traceId": "not-an-id"},
+                {"body": {"stringValue": "no time"}}
+            ])),
+            None,
+            &span::IngestOptions::default(),
+        )
+        .unwrap();
+        assert_eq!(
+            outcome,
+            LogIngestOutcome {
+                accepted: 3,
+                rejected: 2
+            }
+        );
+
+        let records = span::get_trace(&pool, TRACE_ID, &[]).unwrap().unwrap().logs;
+        assert_eq!(records.len(), 2);
+        assert_eq!(records[0].body.as_deref(), Some("cart loaded"));
+        assert_eq!(records[0].severity_label(), "Information");
+        assert_eq!(records[0].attributes["cart.items"], "3");
+        assert_eq!(records[0].span_id, None);
+        assert_eq!(records[0].offset_ms, 20.0);
+        assert_eq!(records[0].service_name.as_deref(), Some("checkout"));
+        assert_eq!(records[1].severity_label(), "ERROR");
+        assert_eq!(records[1].severity_class(), "error");
+        assert_eq!(
+            records[1].body.as_deref(),
+            Some("payment declined for [REDACTED]")
+        );
+        assert_eq!(records[1].span_id.as_deref(), Some("00f067aa0ba902b7"));
+        assert_eq!(records[1].span_name.as_deref(), Some("POST /checkout"));
+        assert_eq!(records[1].happened_at, "2023-11-14T22:13:20.090Z");
+    }
+
+    #[test]
+    fn test_observed_time_stands_in_for_a_missing_timestamp() {
+        let pool = crate::db::test_pool();
+        insert_otlp_batch(
+            &pool,
+            &logs(serde_json::json!([{
+                "timeUnixNano": "0",
+                "observedTimeUnixNano": "1700000000000000000",
+                "traceId": TRACE_ID
+            }])),
+            None,
+            &span::IngestOptions::default(),
+        )
+        .unwrap();
+        let records = for_trace(&pool.get().unwrap(), TRACE_ID, 0).unwrap();
+        assert_eq!(records[0].time_unix_nano, 1_700_000_000_000_000_000);
+        assert_eq!(records[0].severity_label(), "LOG");
+    }
+}
@@ -1,15 +1,26 @@
 pub mod api_key;
+pub mod api_token;
+pub mod cursor;
 pub mod deploy;
 pub mod error;
+pub mod log_record;
+pub mod page;
 pub mod project;
+pub mod project_member;
+pub mod regression;
+pub mod request;
 pub mod rollup;
 pub mod span;
+pub mod totp;
 pub mod user;
+pub mod user_pref;
 
 pub use api_key::ApiKey;
+pub use cursor::Cursor;
 pub use deploy::Deploy;
 pub use error::{AppError, ErrorOccurrence, SourceContext};
+pub use page::Page;
 pub use project::Project;
 pub use rollup::{DailyRollup, HourlyRollup};
 pub use span::{RootSpanType, SpanCategory, SpanDisplay, TraceDetail, TraceSummary};
-pub use user::User;
+pub use user::{Role, User};
@@ -0,0 +1,97 @@
+use serde::Serialize;
+
+/// One offset page of a list, with what a caller needs to ask for the next
+#[derive(Debug, Clone, Serialize)]
+pub struct Page<T> {
+    pub items: Vec<T>,
+    /// Rows matching the filters across all pages
+    pub total: i64,
+    pub limit: i64,
+    pub offset: i64,
+    /// Whether rows remain past this page
+    pub has_more: bool,
+}
+
+impl<T> Page<T> {
+    pub fn new(items: Vec<T>, total: i64, limit: i64, offset: i64) -> Self {
+        let has_more = offset + (items.len() as i64) < total;
+        Self {
+            items,
+            total,
+            limit,
+            offset,
+            has_more,
+        }
+    }
+
+    /// Run a `count_*`/`*_paginated` pair for the page at `offset`
+    pub fn fetch(
+        limit: i64,
+        offset: i64,
+        count: impl FnOnce() -> anyhow::Result<i64>,
+        list: impl FnOnce(i64, i64) -> anyhow::Result<Vec<T>>,
+    ) -> anyhow::Result<Self> {
+        let total = count()?;
+        let items = if offset < total {
+            list(limit, offset)?
+        } else {
+            Vec::new()
+        };
+        Ok(Self::new(items, total, limit, offset))
+    }
+
+    pub fn empty(limit: i64, offset: i64) -> Self {
+        Self::new(Vec::new(), 0, limit, offset)
+    }
+
+    pub fn total_pages(&self) -> i64 {
+        if self.limit <= 0 {
+            return 0;
+        }
+        (self.total + self.limit - 1) / self.limit
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn page(total: i64, limit: i64, offset: i64) -> Page<i64> {
+        Page::fetch(
+            limit,
+            offset,
+            || Ok(total),
+            |limit, offset| Ok((offset..(offset + limit).min(total)).collect()),
+        )
+        .unwrap()
+    }
+
+    #[test]
+    fn test_has_more_until_the_last_row_is_shown() {
+        assert!(page(101, 50, 0).has_more);
+        assert!(page(101, 50, 50).has_more);
+        assert!(!page(101, 50, 100).has_more);
+        assert_eq!(page(101, 50, 100).items, vec![100]);
+    }
+
+    #[test]
+    fn test_exactly_full_last_page_has_no_more() {
+        let last = page(100, 50, 50);
+        assert_eq!(last.items.len(), 50);
+        assert!(!last.has_more);
+        assert_eq!(last.total_pages(), 2);
+    }
+
+    #[test]
+    fn test_empty_and_past_the_end_pages_have_no_more() {
+        let empty = page(0, 50, 0);
+        assert!(empty.items.is_empty());
+        assert!(!empty.has_more);
+        assert_eq!(empty.total_pages(), 0);
+
+        let past = page(10, 50, 60);
+        assert!(past.items.is_empty());
+        assert!(!past.has_more);
+        assert_eq!(past.total, 10);
+    }
+}
@@ -1,14 +1,23 @@
 use crate::DbPool;
-use chrono::Utc;
+use crate::config::Config;
+use crate::models::error::FrameRewrite;
+use crate::models::span::RedactionRule;
+use chrono::{DateTime, Duration, SecondsFormat, Utc};
+use hmac::{Hmac, Mac};
 use rand::Rng;
+use rusqlite::OptionalExtension;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::collections::HashMap;
+use std::sync::OnceLock;
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct Project {
     pub id: i64,
     pub name: String,
     pub slug: String,
-    pub api_key: String,
+    /// Leading characters of the API key, for display (the key itself is only stored hashed)
+    pub api_key_prefix: String,
     pub created_at: String,
 }
 
@@ -19,6 +28,41 @@ fn generate_api_key() -> String {
     format!("proj_{}", hex::encode(bytes))
 }
 
+static API_KEY_SECRET: OnceLock<Vec<u8>> = OnceLock::new();
+
+/// Set the server secret API keys are hashed with. Only the first call takes
+/// effect; until then a random per-process secret is used.
+pub fn set_api_key_secret(secret: &[u8]) {
+    if API_KEY_SECRET.set(secret.to_vec()).is_err() {
+        tracing::debug!("API key secret already set");
+    }
+}
+
+fn api_key_secret() -> &'static [u8] {
+    API_KEY_SECRET.get_or_init(|| rand::thread_rng().r#gen::<[u8; 32]>().to_vec())
+}
+
+/// HMAC-SHA256 of a key under the server secret, so the stored hashes can't
+/// be checked against guesses without the secret
+pub fn hash_api_key(api_key: &str) -> String {
+    let mut mac =
+        Hmac::<Sha256>::new_from_slice(api_key_secret()).expect("HMAC accepts any key length");
+    mac.update(api_key.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+/// Unkeyed SHA-256 that keys were stored as before; matches are rehashed
+fn legacy_hash_api_key(api_key: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(api_key.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+/// Displayable prefix of a key ("proj_" plus 8 characters)
+pub fn api_key_prefix(api_key: &str) -> String {
+    api_key.chars().take(13).collect()
+}
+
 /// Generate a slug from project name
 fn slugify(name: &str) -> String {
     name.to_lowercase()
@@ -43,8 +87,14 @@ pub fn ensure_default_project(pool: &DbPool) -> anyhow::Result<Project> {
         let api_key = generate_api_key();
 
         conn.execute(
-            "INSERT INTO projects (name, slug, api_key, created_at) VALUES (?1, ?2, ?3, ?4)",
-            ("Default", "default", &api_key, &now),
+            "INSERT INTO projects (name, slug, api_key_hash, api_key_prefix, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+            (
+                "Default",
+                "default",
+                hash_api_key(&api_key),
+                api_key_prefix(&api_key),
+                &now,
+            ),
         )?;
 
         tracing::info!("Created default project with API key: {}", api_key);
@@ -53,21 +103,21 @@ pub fn ensure_default_project(pool: &DbPool) -> anyhow::Result<Project> {
             id: conn.last_insert_rowid(),
             name: "Default".to_string(),
             slug: "default".to_string(),
-            api_key,
+            api_key_prefix: api_key_prefix(&api_key),
             created_at: now,
         });
     }
 
     // Return first project
     let project = conn.query_row(
-        "SELECT id, name, slug, api_key, created_at FROM projects ORDER BY id LIMIT 1",
+        "SELECT id, name, slug, COALESCE(api_key_prefix, ''), created_at FROM projects ORDER BY id LIMIT 1",
         [],
         |row| {
             Ok(Project {
                 id: row.get(0)?,
                 name: row.get(1)?,
                 slug: row.get(2)?,
-                api_key: row.get(3)?,
+                api_key_prefix: row.get(3)?,
                 created_at: row.get(4)?,
             })
         },
@@ -80,7 +130,7 @@ pub fn ensure_default_project(pool: &DbPool) -> anyhow::Result<Project> {
 pub fn list_all(pool: &DbPool) -> anyhow::Result<Vec<Project>> {
     let conn = pool.get()?;
     let mut stmt = conn.prepare(
-        "SELECT id, name, slug, api_key, strftime('%Y-%m-%d %H:%M', created_at) FROM projects ORDER BY name",
+        "SELECT id, name, slug, COALESCE(api_key_prefix, ''), strftime('%Y-%m-%d %H:%M', created_at) FROM projects ORDER BY name",
     )?;
 
     let projects = stmt
@@ -89,7 +139,7 @@ pub fn list_all(pool: &DbPool) -> anyhow::Result<Vec<Project>> {
                 id: row.get(0)?,
                 name: row.get(1)?,
                 slug: row.get(2)?,
-                api_key: row.get(3)?,
+                api_key_prefix: row.get(3)?,
                 created_at: row.get(4)?,
             })
         })?
@@ -104,14 +154,14 @@ pub fn find(pool: &DbPool, id: i64) -> anyhow::Result<Option<Project>> {
 
     let project = conn
         .query_row(
-            "SELECT id, name, slug, api_key, created_at FROM projects WHERE id = ?1",
+            "SELECT id, name, slug, COALESCE(api_key_prefix, ''), created_at FROM projects WHERE id = ?1",
             [id],
             |row| {
                 Ok(Project {
                     id: row.get(0)?,
                     name: row.get(1)?,
                     slug: row.get(2)?,
-                    api_key: row.get(3)?,
+                    api_key_prefix: row.get(3)?,
                     created_at: row.get(4)?,
                 })
             },
@@ -121,20 +171,34 @@ pub fn find(pool: &DbPool, id: i64) -> anyhow::Result<Option<Project>> {
     Ok(project)
 }
 
+/// Slug of the reserved project MiniAPM records its own ingest spans in
+pub const INTERNAL_PROJECT_SLUG: &str = "miniapm-internal";
+
+/// Find or create the reserved project self-tracing writes to. Nobody gets
+/// its API key; the spans are stored directly, never sent to `/ingest`.
+pub fn ensure_internal_project(pool: &DbPool) -> anyhow::Result<Project> {
+    if let Some(project) = find_by_slug(pool, INTERNAL_PROJECT_SLUG)? {
+        return Ok(project);
+    }
+    let (project, _) = create(pool, "MiniAPM Internal")?;
+    tracing::info!("Created internal project for self-tracing");
+    Ok(project)
+}
+
 /// Find project by slug
 pub fn find_by_slug(pool: &DbPool, slug: &str) -> anyhow::Result<Option<Project>> {
     let conn = pool.get()?;
 
     let project = conn
         .query_row(
-            "SELECT id, name, slug, api_key, created_at FROM projects WHERE slug = ?1",
+            "SELECT id, name, slug, COALESCE(api_key_prefix, ''), created_at FROM projects WHERE slug = ?1",
             [slug],
             |row| {
                 Ok(Project {
                     id: row.get(0)?,
                     name: row.get(1)?,
                     slug: row.get(2)?,
-                    api_key: row.get(3)?,
+                    api_key_prefix: row.get(3)?,
                     created_at: row.get(4)?,
                 })
             },
@@ -144,31 +208,95 @@ pub fn find_by_slug(pool: &DbPool, slug: &str) -> anyhow::Result<Option<Project>
     Ok(project)
 }
 
-/// Find project by API key
+/// How often a project's `last_used_at` is rewritten while its key is in
+/// use, so busy keys don't turn every ingest request into a write
+pub const LAST_USED_THROTTLE_SECS: i64 = 60;
+
+/// Find project by API key (compared by hash), recording that the key was used
 pub fn find_by_api_key(pool: &DbPool, api_key: &str) -> anyhow::Result<Option<Project>> {
     let conn = pool.get()?;
+    let api_key_hash = hash_api_key(api_key);
+    let legacy_hash = legacy_hash_api_key(api_key);
 
-    let project = conn
+    let found = conn
         .query_row(
-            "SELECT id, name, slug, api_key, created_at FROM projects WHERE api_key = ?1",
-            [api_key],
+            "SELECT id, name, slug, COALESCE(api_key_prefix, ''), created_at,
+                    api_key_hash, previous_api_key_hash, last_used_at
+             FROM projects
+             WHERE api_key_hash IN (?1, ?3)
+                OR (previous_api_key_hash IN (?1, ?3) AND previous_api_key_expires_at > ?2)",
+            (
+                &api_key_hash,
+                Utc::now().to_rfc3339_opts(SecondsFormat::Secs, true),
+                &legacy_hash,
+            ),
             |row| {
-                Ok(Project {
+                let project = Project {
                     id: row.get(0)?,
                     name: row.get(1)?,
                     slug: row.get(2)?,
-                    api_key: row.get(3)?,
+                    api_key_prefix: row.get(3)?,
                     created_at: row.get(4)?,
-                })
+                };
+                Ok((
+                    project,
+                    row.get::<_, String>(5)?,
+                    row.get::<_, Option<String>>(6)?,
+                    row.get::<_, Option<String>>(7)?,
+                ))
             },
         )
         .ok();
+    let Some((project, stored_hash, previous_hash, last_used)) = found else {
+        return Ok(None);
+    };
 
-    Ok(project)
+    // Upgrade a hash stored before keys were hashed with the server secret.
+    // Only when one is, so authenticating doesn't otherwise write.
+    if stored_hash == legacy_hash {
+        conn.execute(
+            "UPDATE projects SET api_key_hash = ?1 WHERE id = ?2",
+            (&api_key_hash, project.id),
+        )?;
+    }
+    if previous_hash.as_deref() == Some(legacy_hash.as_str()) {
+        conn.execute(
+            "UPDATE projects SET previous_api_key_hash = ?1 WHERE id = ?2",
+            (&api_key_hash, project.id),
+        )?;
+    }
+
+    let now = Utc::now();
+    let recently_used = last_used
+        .and_then(|at| DateTime::parse_from_rfc3339(&at).ok())
+        .is_some_and(|at| {
+            now - at.with_timezone(&Utc) < Duration::seconds(LAST_USED_THROTTLE_SECS)
+        });
+    if !recently_used {
+        conn.execute(
+            "UPDATE projects SET last_used_at = ?1 WHERE id = ?2",
+            (now.to_rfc3339_opts(SecondsFormat::Secs, true), project.id),
+        )?;
+    }
+
+    Ok(Some(project))
 }
 
-/// Create a new project
-pub fn create(pool: &DbPool, name: &str) -> anyhow::Result<Project> {
+/// When each project's API key last authenticated, by project id; projects
+/// whose key was never used are absent
+pub fn last_used_at(pool: &DbPool) -> anyhow::Result<HashMap<i64, String>> {
+    let conn = pool.get()?;
+    let mut stmt =
+        conn.prepare("SELECT id, last_used_at FROM projects WHERE last_used_at IS NOT NULL")?;
+    let used = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<HashMap<_, _>, _>>()?;
+    Ok(used)
+}
+
+/// Create a new project, returning it with its API key.
+/// The key is only stored hashed, so this is the one chance to show it.
+pub fn create(pool: &DbPool, name: &str) -> anyhow::Result<(Project, String)> {
     let conn = pool.get()?;
 
     let now = Utc::now().to_rfc3339();
@@ -176,19 +304,28 @@ pub fn create(pool: &DbPool, name: &str) -> anyhow::Result<Project> {
     let api_key = generate_api_key();
 
     conn.execute(
-        "INSERT INTO projects (name, slug, api_key, created_at) VALUES (?1, ?2, ?3, ?4)",
-        (name, &slug, &api_key, &now),
+        "INSERT INTO projects (name, slug, api_key_hash, api_key_prefix, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        (
+            name,
+            &slug,
+            hash_api_key(&api_key),
+            api_key_prefix(&api_key),
+            &now,
+        ),
     )?;
 
     let project_id = conn.last_insert_rowid();
 
-    Ok(Project {
-        id: project_id,
-        name: name.to_string(),
-        slug,
+    Ok((
+        Project {
+            id: project_id,
+            name: name.to_string(),
+            slug,
+            api_key_prefix: api_key_prefix(&api_key),
+            created_at: now,
+        },
         api_key,
-        created_at: now,
-    })
+    ))
 }
 
 /// Delete a project
@@ -198,22 +335,931 @@ pub fn delete(pool: &DbPool, id: i64) -> anyhow::Result<()> {
     Ok(())
 }
 
-/// Regenerate API key for a project
+/// Rows removed by `prune_data`, per table
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct PrunedRows {
+    pub spans: usize,
+    pub trace_summaries: usize,
+    pub log_records: usize,
+    pub requests: usize,
+    pub error_occurrences: usize,
+    pub errors: usize,
+}
+
+impl PrunedRows {
+    pub fn by_table(&self) -> [(&'static str, usize); 6] {
+        [
+            ("spans", self.spans),
+            ("trace_summaries", self.trace_summaries),
+            ("log_records", self.log_records),
+            ("requests", self.requests),
+            ("error_occurrences", self.error_occurrences),
+            ("errors", self.errors),
+        ]
+    }
+}
+
+/// Delete a project's telemetry, keeping the project itself. With `before`,
+/// only rows older than the cutoff go; errors are dropped once they have no
+/// occurrences left and otherwise get their occurrence counts recomputed.
+pub fn prune_data(pool: &DbPool, id: i64, before: Option<&str>) -> anyhow::Result<PrunedRows> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let params = rusqlite::params![id, before];
+
+    let pruned = PrunedRows {
+        spans: tx.execute(
+            "DELETE FROM spans WHERE project_id = ?1 AND (?2 IS NULL OR happened_at < ?2)",
+            params,
+        )?,
+        trace_summaries: tx.execute(
+            "DELETE FROM trace_summaries WHERE project_id = ?1 AND (?2 IS NULL OR happened_at < ?2)",
+            params,
+        )?,
+        log_records: tx.execute(
+            "DELETE FROM log_records WHERE project_id = ?1 AND (?2 IS NULL OR happened_at < ?2)",
+            params,
+        )?,
+        requests: tx.execute(
+            "DELETE FROM requests WHERE project_id = ?1 AND (?2 IS NULL OR happened_at < ?2)",
+            params,
+        )?,
+        error_occurrences: tx.execute(
+            "DELETE FROM error_occurrences
+             WHERE error_id IN (SELECT id FROM errors WHERE project_id = ?1)
+               AND (?2 IS NULL OR happened_at < ?2)",
+            params,
+        )?,
+        errors: tx.execute(
+            "DELETE FROM errors WHERE project_id = ?1
+               AND NOT EXISTS (SELECT 1 FROM error_occurrences WHERE error_id = errors.id)",
+            [id],
+        )?,
+    };
+    tx.execute(
+        "DELETE FROM error_hourly_counts
+         WHERE error_id IN (SELECT id FROM errors WHERE project_id = ?1)
+           AND hour < strftime('%Y-%m-%d %H', ?2)",
+        params,
+    )?;
+    tx.execute(
+        "DELETE FROM error_users
+         WHERE error_id IN (SELECT id FROM errors WHERE project_id = ?1)
+           AND last_seen_at < ?2",
+        params,
+    )?;
+    // Weighted like the charts, since past the hourly sample size not every
+    // occurrence has a row
+    tx.execute(
+        &format!(
+            "UPDATE errors SET occurrence_count = (
+                SELECT CAST(ROUND(COALESCE(SUM({weight}), 0)) AS INTEGER)
+                FROM error_occurrences eo
+                {join}
+                WHERE eo.error_id = errors.id
+             )
+             WHERE project_id = ?1",
+            weight = crate::models::error::OCCURRENCE_WEIGHT,
+            join = crate::models::error::HOURLY_COUNTS_JOIN,
+        ),
+        [id],
+    )?;
+    tx.commit()?;
+
+    Ok(pruned)
+}
+
+/// Regenerate API key for a project, returning the new key (shown once)
 pub fn regenerate_api_key(pool: &DbPool, id: i64) -> anyhow::Result<String> {
     let conn = pool.get()?;
     let new_key = generate_api_key();
 
     conn.execute(
-        "UPDATE projects SET api_key = ?1 WHERE id = ?2",
-        (&new_key, id),
+        "UPDATE projects SET api_key_hash = ?1, api_key_prefix = ?2, last_used_at = NULL,
+             previous_api_key_hash = NULL, previous_api_key_expires_at = NULL
+         WHERE id = ?3",
+        (hash_api_key(&new_key), api_key_prefix(&new_key), id),
+    )?;
+
+    Ok(new_key)
+}
+
+/// Give a project a new API key while the current one keeps authenticating
+/// for `grace`, so agents can be moved over without dropping data. Rotating
+/// again before the window closes retires the older key at once.
+pub fn rotate_api_key(pool: &DbPool, id: i64, grace: Duration) -> anyhow::Result<String> {
+    let conn = pool.get()?;
+    let new_key = generate_api_key();
+    let expires_at = (Utc::now() + grace).to_rfc3339_opts(SecondsFormat::Secs, true);
+
+    let updated = conn.execute(
+        "UPDATE projects SET previous_api_key_hash = api_key_hash,
+             previous_api_key_expires_at = ?1, api_key_hash = ?2, api_key_prefix = ?3
+         WHERE id = ?4",
+        (
+            &expires_at,
+            hash_api_key(&new_key),
+            api_key_prefix(&new_key),
+            id,
+        ),
     )?;
+    if updated == 0 {
+        anyhow::bail!("Project {} not found", id);
+    }
 
     Ok(new_key)
 }
 
+/// Hash any API keys still stored in plaintext (databases created before keys were hashed)
+pub fn hash_legacy_api_keys(conn: &rusqlite::Connection) -> anyhow::Result<usize> {
+    let mut stmt = conn.prepare(
+        "SELECT id, api_key_hash FROM projects WHERE substr(api_key_hash, 1, 5) = 'proj_'",
+    )?;
+    let legacy = stmt
+        .query_map([], |row| {
+            Ok((row.get::<_, i64>(0)?, row.get::<_, String>(1)?))
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    for (id, api_key) in &legacy {
+        conn.execute(
+            "UPDATE projects SET api_key_hash = ?1, api_key_prefix = ?2 WHERE id = ?3",
+            (hash_api_key(api_key), api_key_prefix(api_key), id),
+        )?;
+    }
+
+    Ok(legacy.len())
+}
+
+/// Backtrace frame rewrites configured for a project (empty when unset)
+pub fn frame_rewrites(pool: &DbPool, id: i64) -> anyhow::Result<Vec<FrameRewrite>> {
+    let conn = pool.get()?;
+    let json: Option<String> = conn
+        .query_row(
+            "SELECT frame_rewrites FROM projects WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .ok()
+        .flatten();
+
+    Ok(json
+        .and_then(|j| serde_json::from_str(&j).ok())
+        .unwrap_or_default())
+}
+
+/// Replace a project's backtrace frame rewrites
+pub fn set_frame_rewrites(pool: &DbPool, id: i64, rewrites: &[FrameRewrite]) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    let json = if rewrites.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(rewrites)?)
+    };
+    conn.execute(
+        "UPDATE projects SET frame_rewrites = ?1 WHERE id = ?2",
+        rusqlite::params![json, id],
+    )?;
+    Ok(())
+}
+
+/// Ingest redaction rules configured for a project (empty when unset)
+pub fn redaction_rules(pool: &DbPool, id: i64) -> anyhow::Result<Vec<RedactionRule>> {
+    let conn = pool.get()?;
+    let json: Option<String> = conn
+        .query_row(
+            "SELECT redaction_rules FROM projects WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .ok()
+        .flatten();
+
+    Ok(json
+        .and_then(|j| serde_json::from_str(&j).ok())
+        .unwrap_or_default())
+}
+
+/// Replace a project's ingest redaction rules
+pub fn set_redaction_rules(pool: &DbPool, id: i64, rules: &[RedactionRule]) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    let json = if rules.is_empty() {
+        None
+    } else {
+        Some(serde_json::to_string(rules)?)
+    };
+    conn.execute(
+        "UPDATE projects SET redaction_rules = ?1 WHERE id = ?2",
+        rusqlite::params![json, id],
+    )?;
+    Ok(())
+}
+
+/// Fixed UTC offset dashboard charts bucket hours in, written `+02:00`.
+/// Storage stays UTC; only the grouping and labels shift.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct DisplayOffset {
+    pub minutes: i32,
+}
+
+impl DisplayOffset {
+    pub const UTC: Self = Self { minutes: 0 };
+
+    /// `UTC`, `Z`, `+05:30`, `-0800` or `+2`
+    pub fn parse(value: &str) -> Option<Self> {
+        let value = value.trim();
+        if value.is_empty() || value.eq_ignore_ascii_case("utc") || value == "Z" {
+            return Some(Self::UTC);
+        }
+        let sign = match value.as_bytes()[0] {
+            b'+' => 1,
+            b'-' => -1,
+            _ => return None,
+        };
+        let digits = value[1..].replace(':', "");
+        if digits.is_empty() || digits.len() > 4 || !digits.bytes().all(|b| b.is_ascii_digit()) {
+            return None;
+        }
+        let (hours, minutes) = if digits.len() <= 2 {
+            (digits.parse::<i32>().ok()?, 0)
+        } else {
+            let split = digits.len() - 2;
+            (digits[..split].parse().ok()?, digits[split..].parse().ok()?)
+        };
+        if hours > 14 || minutes >= 60 {
+            return None;
+        }
+        Some(Self {
+            minutes: sign * (hours * 60 + minutes),
+        })
+    }
+
+    /// SQLite date modifier moving a UTC timestamp into this offset
+    pub fn sqlite_modifier(&self) -> String {
+        format!("{:+} minutes", self.minutes)
+    }
+
+    /// Current time shifted into this offset, for formatting bucket labels
+    pub fn now(&self) -> chrono::DateTime<Utc> {
+        Utc::now() + chrono::Duration::minutes(self.minutes as i64)
+    }
+}
+
+impl std::fmt::Display for DisplayOffset {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        if self.minutes == 0 {
+            return write!(f, "UTC");
+        }
+        let sign = if self.minutes < 0 { '-' } else { '+' };
+        let minutes = self.minutes.abs();
+        write!(f, "{}{:02}:{:02}", sign, minutes / 60, minutes % 60)
+    }
+}
+
+/// A project's own display offset, if it has one
+pub fn display_timezone(pool: &DbPool, id: i64) -> Option<DisplayOffset> {
+    let conn = pool.get().ok()?;
+    let stored: Option<String> = conn
+        .query_row(
+            "SELECT display_timezone FROM projects WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .ok()
+        .flatten();
+    stored.as_deref().and_then(DisplayOffset::parse)
+}
+
+/// Offset to bucket a project's charts in: its own setting, else `default`
+/// (`DISPLAY_TIMEZONE`)
+pub fn display_offset(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    default: DisplayOffset,
+) -> DisplayOffset {
+    project_id
+        .and_then(|id| display_timezone(pool, id))
+        .unwrap_or(default)
+}
+
+/// Set or clear (back to `DISPLAY_TIMEZONE`) a project's display offset
+pub fn set_display_offset(
+    pool: &DbPool,
+    id: i64,
+    offset: Option<DisplayOffset>,
+) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE projects SET display_timezone = ?1 WHERE id = ?2",
+        rusqlite::params![offset.map(|o| o.to_string()), id],
+    )?;
+    Ok(())
+}
+
+/// Whether a project has a deploy webhook secret set
+pub fn has_webhook_secret(pool: &DbPool, id: i64) -> bool {
+    pool.get()
+        .ok()
+        .and_then(|conn| {
+            conn.query_row(
+                "SELECT webhook_secret IS NOT NULL FROM projects WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .ok()
+        })
+        .unwrap_or(false)
+}
+
+/// Projects with a deploy webhook secret, as (project id, secret)
+pub fn webhook_secrets(pool: &DbPool) -> anyhow::Result<Vec<(i64, String)>> {
+    let conn = pool.get()?;
+    let mut stmt =
+        conn.prepare("SELECT id, webhook_secret FROM projects WHERE webhook_secret IS NOT NULL")?;
+    let secrets = stmt
+        .query_map([], |row| Ok((row.get(0)?, row.get(1)?)))?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(secrets)
+}
+
+/// Set or clear (disabling webhooks) a project's deploy webhook secret
+pub fn set_webhook_secret(pool: &DbPool, id: i64, secret: Option<&str>) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE projects SET webhook_secret = ?1 WHERE id = ?2",
+        rusqlite::params![secret, id],
+    )?;
+    Ok(())
+}
+
+/// Whether a project accepts HMAC-signed ingest requests
+pub fn has_signing_secret(pool: &DbPool, id: i64) -> bool {
+    signing_secret(pool, id).ok().flatten().is_some()
+}
+
+/// The secret a project's signed ingest requests are keyed with
+pub fn signing_secret(pool: &DbPool, id: i64) -> anyhow::Result<Option<String>> {
+    let conn = pool.get()?;
+    let secret = conn
+        .query_row(
+            "SELECT signing_secret FROM projects WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .flatten();
+    Ok(secret)
+}
+
+/// Set or clear (disabling signed requests) a project's ingest signing secret
+pub fn set_signing_secret(pool: &DbPool, id: i64, secret: Option<&str>) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE projects SET signing_secret = ?1 WHERE id = ?2",
+        rusqlite::params![secret, id],
+    )?;
+    Ok(())
+}
+
+/// Whether a project's ingest has been switched off
+pub fn is_disabled(pool: &DbPool, id: i64) -> anyhow::Result<bool> {
+    let conn = pool.get()?;
+    let disabled = conn
+        .query_row(
+            "SELECT disabled_at IS NOT NULL FROM projects WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .optional()?
+        .unwrap_or(false);
+    Ok(disabled)
+}
+
+/// Stop or resume accepting ingest for a project. Its keys and data are
+/// kept, so re-enabling picks up where it left off.
+pub fn set_disabled(pool: &DbPool, id: i64, disabled: bool) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE projects SET disabled_at = CASE WHEN ?1 THEN COALESCE(disabled_at, ?2) END
+         WHERE id = ?3",
+        rusqlite::params![disabled, Utc::now().to_rfc3339(), id],
+    )?;
+    Ok(())
+}
+
+/// Which complete traces to keep: every trace with an error, one slower than
+/// `slow_ms` or one containing an N+1, plus `sample_rate` of the rest
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TailSampling {
+    pub sample_rate: f64,
+    pub slow_ms: f64,
+}
+
+impl TailSampling {
+    /// `TAIL_SAMPLE_RATE` and `SLOW_REQUEST_THRESHOLD_MS`
+    pub fn from_config(config: &Config) -> Self {
+        Self {
+            sample_rate: config.tail_sample_rate,
+            slow_ms: config.slow_request_threshold_ms,
+        }
+    }
+
+    /// Whether any trace can be dropped at all
+    pub fn enabled(&self) -> bool {
+        self.sample_rate < 1.0
+    }
+}
+
+/// A project's own tail sampling overrides, as (sample rate, slow threshold)
+pub fn tail_sampling_overrides(pool: &DbPool, id: i64) -> (Option<f64>, Option<f64>) {
+    pool.get()
+        .ok()
+        .and_then(|conn| {
+            conn.query_row(
+                "SELECT tail_sample_rate, tail_slow_ms FROM projects WHERE id = ?1",
+                [id],
+                |row| Ok((row.get(0)?, row.get(1)?)),
+            )
+            .ok()
+        })
+        .unwrap_or((None, None))
+}
+
+/// Rules for a project's traces: its overrides, each unset one falling back
+/// to `defaults`. Traces without a project use `defaults` alone.
+pub fn tail_sampling(
+    pool: &DbPool,
+    project_id: Option<i64>,
+    defaults: TailSampling,
+) -> TailSampling {
+    let (sample_rate, slow_ms) = project_id
+        .map(|id| tail_sampling_overrides(pool, id))
+        .unwrap_or((None, None));
+    TailSampling {
+        sample_rate: sample_rate.unwrap_or(defaults.sample_rate),
+        slow_ms: slow_ms.unwrap_or(defaults.slow_ms),
+    }
+}
+
+/// Set or clear (back to the environment) a project's tail sampling overrides
+pub fn set_tail_sampling(
+    pool: &DbPool,
+    id: i64,
+    sample_rate: Option<f64>,
+    slow_ms: Option<f64>,
+) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE projects SET tail_sample_rate = ?1, tail_slow_ms = ?2 WHERE id = ?3",
+        rusqlite::params![sample_rate, slow_ms, id],
+    )?;
+    Ok(())
+}
+
+/// A project's own auto-resolve threshold; 0 turns it off for the project
+pub fn auto_resolve_override(pool: &DbPool, id: i64) -> Option<i64> {
+    pool.get()
+        .ok()
+        .and_then(|conn| {
+            conn.query_row(
+                "SELECT auto_resolve_days FROM projects WHERE id = ?1",
+                [id],
+                |row| row.get(0),
+            )
+            .ok()
+        })
+        .flatten()
+}
+
+/// Days after which a project's stale errors are resolved: its override, else
+/// `default` (`ERROR_AUTO_RESOLVE_DAYS`), which errors without a project use
+/// alone. `None` when the threshold is 0, which disables it.
+pub fn auto_resolve_days(pool: &DbPool, project_id: Option<i64>, default: i64) -> Option<i64> {
+    let days = project_id
+        .and_then(|id| auto_resolve_override(pool, id))
+        .unwrap_or(default);
+    Some(days).filter(|days| *days > 0)
+}
+
+/// Set or clear (back to the server default) a project's auto-resolve threshold
+pub fn set_auto_resolve_days(pool: &DbPool, id: i64, days: Option<i64>) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE projects SET auto_resolve_days = ?1 WHERE id = ?2",
+        rusqlite::params![days, id],
+    )?;
+    Ok(())
+}
+
+/// Which spans count as failed in error counts, error rates and status badges
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ErrorPolicy {
+    /// OTLP error status or an HTTP 5xx
+    #[default]
+    ServerErrors,
+    /// OTLP error status or any HTTP 4xx/5xx
+    ClientAndServerErrors,
+    /// Only the OTLP error status; HTTP codes are ignored
+    StatusOnly,
+}
+
+impl ErrorPolicy {
+    pub const ALL: [Self; 3] = [
+        Self::ServerErrors,
+        Self::ClientAndServerErrors,
+        Self::StatusOnly,
+    ];
+
+    pub fn parse(value: &str) -> Option<Self> {
+        match value.trim() {
+            "5xx" => Some(Self::ServerErrors),
+            "4xx" => Some(Self::ClientAndServerErrors),
+            "status" => Some(Self::StatusOnly),
+            _ => None,
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Self::ServerErrors => "5xx",
+            Self::ClientAndServerErrors => "4xx",
+            Self::StatusOnly => "status",
+        }
+    }
+
+    pub fn label(&self) -> &'static str {
+        match self {
+            Self::ServerErrors => "Error status or HTTP 5xx",
+            Self::ClientAndServerErrors => "Error status or HTTP 4xx/5xx",
+            Self::StatusOnly => "Error status only",
+        }
+    }
+
+    /// Lowest HTTP status that counts as an error, if any does
+    pub fn http_floor(&self) -> Option<i32> {
+        match self {
+            Self::ServerErrors => Some(500),
+            Self::ClientAndServerErrors => Some(400),
+            Self::StatusOnly => None,
+        }
+    }
+
+    /// Whether a span with this OTLP status (2 is error) and HTTP status failed
+    pub fn is_error(&self, status_code: i32, http_status_code: Option<i32>) -> bool {
+        status_code == 2
+            || self
+                .http_floor()
+                .zip(http_status_code)
+                .is_some_and(|(floor, code)| code >= floor)
+    }
+
+    /// SQL condition matching `is_error` on the `status_code` and
+    /// `http_status_code` columns of `table` (an alias, or "" for none)
+    pub fn sql(&self, table: &str) -> String {
+        let prefix = if table.is_empty() {
+            String::new()
+        } else {
+            format!("{table}.")
+        };
+        match self.http_floor() {
+            Some(floor) => format!(
+                "({prefix}status_code = 2 OR COALESCE({prefix}http_status_code, 0) >= {floor})"
+            ),
+            None => format!("({prefix}status_code = 2)"),
+        }
+    }
+}
+
+/// A project's own error policy, if it has one
+pub fn error_policy_override(pool: &DbPool, id: i64) -> Option<ErrorPolicy> {
+    let conn = pool.get().ok()?;
+    let stored: Option<String> = conn
+        .query_row(
+            "SELECT error_policy FROM projects WHERE id = ?1",
+            [id],
+            |row| row.get(0),
+        )
+        .ok()
+        .flatten();
+    stored.as_deref().and_then(ErrorPolicy::parse)
+}
+
+/// Policy for a project's spans: its own setting, else `default`
+/// (`ERROR_STATUS_POLICY`), which views across all projects use alone
+pub fn error_policy(pool: &DbPool, project_id: Option<i64>, default: ErrorPolicy) -> ErrorPolicy {
+    project_id
+        .and_then(|id| error_policy_override(pool, id))
+        .unwrap_or(default)
+}
+
+/// Set or clear (back to `ERROR_STATUS_POLICY`) a project's error policy
+pub fn set_error_policy(pool: &DbPool, id: i64, policy: Option<ErrorPolicy>) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE projects SET error_policy = ?1 WHERE id = ?2",
+        rusqlite::params![policy.map(|p| p.as_str()), id],
+    )?;
+    Ok(())
+}
+
 /// Get project count
 pub fn count(pool: &DbPool) -> anyhow::Result<i64> {
     let conn = pool.get()?;
     let count: i64 = conn.query_row("SELECT COUNT(*) FROM projects", [], |row| row.get(0))?;
     Ok(count)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_find_by_api_key_matches_hashed_key() {
+        let pool = crate::db::test_pool();
+        let (project, api_key) = create(&pool, "Shop").unwrap();
+
+        let found = find_by_api_key(&pool, &api_key).unwrap().unwrap();
+        assert_eq!(found.id, project.id);
+        assert_eq!(found.api_key_prefix, api_key[..13]);
+
+        let stored: String = pool
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT api_key_hash FROM projects WHERE id = ?1",
+                [project.id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_ne!(stored, api_key);
+    }
+
+    #[test]
+    fn test_key_use_is_recorded_at_most_once_per_throttle_window() {
+        let pool = crate::db::test_pool();
+        let (project, api_key) = create(&pool, "Shop").unwrap();
+        assert!(last_used_at(&pool).unwrap().is_empty());
+
+        find_by_api_key(&pool, &api_key).unwrap().unwrap();
+        let first = last_used_at(&pool).unwrap()[&project.id].clone();
+
+        // Within the window the stored time stays put
+        let earlier = "2026-01-01T00:00:00Z";
+        let within = (Utc::now() - Duration::seconds(LAST_USED_THROTTLE_SECS / 2))
+            .to_rfc3339_opts(SecondsFormat::Secs, true);
+        let set_last_used = |at: &str| {
+            pool.get()
+                .unwrap()
+                .execute(
+                    "UPDATE projects SET last_used_at = ?1 WHERE id = ?2",
+                    (at, project.id),
+                )
+                .unwrap();
+        };
+        set_last_used(&within);
+        find_by_api_key(&pool, &api_key).unwrap().unwrap();
+        assert_eq!(last_used_at(&pool).unwrap()[&project.id], within);
+
+        // and nothing is written: the key still authenticates while another
+        // connection holds the database's write lock
+        let writer = pool.get().unwrap();
+        writer
+            .execute_batch(
+                "BEGIN; INSERT INTO deploys (git_sha, deployed_at) VALUES ('abc', 'now');",
+            )
+            .unwrap();
+        find_by_api_key(&pool, &api_key).unwrap().unwrap();
+        writer.execute_batch("ROLLBACK").unwrap();
+        drop(writer);
+
+        // Once it has passed, the next use records the current time
+        set_last_used(earlier);
+        find_by_api_key(&pool, &api_key).unwrap().unwrap();
+        let latest = last_used_at(&pool).unwrap()[&project.id].clone();
+        assert!(latest.as_str() >= first.as_str());
+        assert_ne!(latest, earlier);
+
+        // A wrong key records nothing, and a new key starts out unused
+        find_by_api_key(&pool, "proj_wrong").unwrap();
+        regenerate_api_key(&pool, project.id).unwrap();
+        assert!(last_used_at(&pool).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_rotated_key_accepts_both_keys_until_grace_ends() {
+        let pool = crate::db::test_pool();
+        let (project, old_key) = create(&pool, "Shop").unwrap();
+
+        let new_key = rotate_api_key(&pool, project.id, Duration::hours(24)).unwrap();
+        assert_ne!(new_key, old_key);
+        assert_eq!(
+            find_by_api_key(&pool, &new_key).unwrap().unwrap().id,
+            project.id
+        );
+        assert_eq!(
+            find_by_api_key(&pool, &old_key).unwrap().unwrap().id,
+            project.id
+        );
+
+        // Once the window has passed only the new key works
+        pool.get()
+            .unwrap()
+            .execute(
+                "UPDATE projects SET previous_api_key_expires_at = '2026-01-01T00:00:00Z'",
+                [],
+            )
+            .unwrap();
+        assert!(find_by_api_key(&pool, &old_key).unwrap().is_none());
+        assert_eq!(
+            find_by_api_key(&pool, &new_key).unwrap().unwrap().id,
+            project.id
+        );
+
+        // Regenerating drops the previous key straight away
+        let newest = rotate_api_key(&pool, project.id, Duration::hours(24)).unwrap();
+        assert!(find_by_api_key(&pool, &new_key).unwrap().is_some());
+        regenerate_api_key(&pool, project.id).unwrap();
+        assert!(find_by_api_key(&pool, &new_key).unwrap().is_none());
+        assert!(find_by_api_key(&pool, &newest).unwrap().is_none());
+        assert!(rotate_api_key(&pool, project.id + 1, Duration::hours(1)).is_err());
+    }
+
+    #[test]
+    fn test_find_by_api_key_rejects_wrong_key_and_hash() {
+        let pool = crate::db::test_pool();
+        let (_, api_key) = create(&pool, "Shop").unwrap();
+
+        assert!(find_by_api_key(&pool, "proj_wrong").unwrap().is_none());
+        assert!(
+            find_by_api_key(&pool, &hash_api_key(&api_key))
+                .unwrap()
+                .is_none()
+        );
+    }
+
+    #[test]
+    fn test_hash_legacy_api_keys() {
+        let pool = crate::db::test_pool();
+        let conn = pool.get().unwrap();
+        conn.execute(
+            "INSERT INTO projects (name, slug, api_key_hash, created_at) VALUES ('Old', 'old', 'proj_legacy0123', '2025-01-01')",
+            [],
+        )
+        .unwrap();
+
+        assert_eq!(hash_legacy_api_keys(&conn).unwrap(), 1);
+        assert_eq!(hash_legacy_api_keys(&conn).unwrap(), 0);
+        drop(conn);
+
+        let found = find_by_api_key(&pool, "proj_legacy0123").unwrap().unwrap();
+        assert_eq!(found.slug, "old");
+        assert_eq!(found.api_key_prefix, "proj_legacy01");
+    }
+
+    #[test]
+    fn test_unkeyed_hash_is_upgraded_on_use() {
+        let pool = crate::db::test_pool();
+        let api_key = "proj_unkeyed0123";
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO projects (name, slug, api_key_hash, created_at) VALUES ('Old', 'old', ?1, '2025-01-01')",
+                [legacy_hash_api_key(api_key)],
+            )
+            .unwrap();
+        assert_ne!(hash_api_key(api_key), legacy_hash_api_key(api_key));
+
+        let found = find_by_api_key(&pool, api_key).unwrap().unwrap();
+        assert_eq!(found.slug, "old");
+        let stored: String = pool
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT api_key_hash FROM projects WHERE id = ?1",
+                [found.id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(stored, hash_api_key(api_key));
+
+        // Once upgraded, authenticating doesn't write: it succeeds while
+        // another connection holds the database's write lock
+        let writer = pool.get().unwrap();
+        writer
+            .execute_batch(
+                "BEGIN; INSERT INTO deploys (git_sha, deployed_at) VALUES ('abc', 'now');",
+            )
+            .unwrap();
+        assert!(find_by_api_key(&pool, api_key).unwrap().is_some());
+        writer.execute_batch("ROLLBACK").unwrap();
+    }
+
+    #[test]
+    fn test_display_offset_parse_and_format() {
+        let parsed = |value| DisplayOffset::parse(value).map(|o| o.minutes);
+        assert_eq!(parsed("UTC"), Some(0));
+        assert_eq!(parsed("+02:00"), Some(120));
+        assert_eq!(parsed("-0530"), Some(-330));
+        assert_eq!(parsed("+9"), Some(540));
+        assert_eq!(parsed("02:00"), None);
+        assert_eq!(parsed("+15:00"), None);
+        assert_eq!(parsed("+02:75"), None);
+        assert_eq!(parsed("Europe/Paris"), None);
+
+        assert_eq!(DisplayOffset { minutes: -330 }.to_string(), "-05:30");
+        assert_eq!(DisplayOffset::UTC.to_string(), "UTC");
+        assert_eq!(
+            DisplayOffset { minutes: 90 }.sqlite_modifier(),
+            "+90 minutes"
+        );
+    }
+
+    #[test]
+    fn test_project_display_offset_overrides_default() {
+        let pool = crate::db::test_pool();
+        let (shop, _) = create(&pool, "Shop").unwrap();
+        assert_eq!(display_timezone(&pool, shop.id), None);
+
+        let tokyo = DisplayOffset { minutes: 540 };
+        set_display_offset(&pool, shop.id, Some(tokyo)).unwrap();
+        let berlin = DisplayOffset { minutes: 60 };
+        assert_eq!(display_offset(&pool, Some(shop.id), berlin), tokyo);
+
+        set_display_offset(&pool, shop.id, None).unwrap();
+        assert_eq!(display_timezone(&pool, shop.id), None);
+        assert_eq!(display_offset(&pool, Some(shop.id), berlin), berlin);
+        assert_eq!(display_offset(&pool, None, berlin), berlin);
+    }
+
+    /// An OTLP status and HTTP status, with whether each policy calls them an
+    /// error, in `ErrorPolicy::ALL` order
+    type ErrorCase = ((i32, Option<i32>), [bool; 3]);
+
+    const ERROR_CASES: [ErrorCase; 6] = [
+        ((0, Some(200)), [false, false, false]),
+        ((0, Some(404)), [false, true, false]),
+        ((0, Some(503)), [true, true, false]),
+        ((2, Some(200)), [true, true, true]),
+        ((2, None), [true, true, true]),
+        ((0, None), [false, false, false]),
+    ];
+
+    #[test]
+    fn test_error_policy_variants_classify_statuses() {
+        for ((status, http), expected) in ERROR_CASES {
+            for (policy, expected) in ErrorPolicy::ALL.iter().zip(expected) {
+                assert_eq!(
+                    policy.is_error(status, http),
+                    expected,
+                    "{:?} on ({status}, {http:?})",
+                    policy
+                );
+            }
+        }
+        for policy in ErrorPolicy::ALL {
+            assert_eq!(ErrorPolicy::parse(policy.as_str()), Some(policy));
+        }
+        assert_eq!(ErrorPolicy::parse("500"), None);
+    }
+
+    #[test]
+    fn test_error_policy_sql_agrees_with_is_error() {
+        let pool = crate::db::test_pool();
+        let conn = pool.get().unwrap();
+        for ((status, http), _) in ERROR_CASES {
+            for policy in ErrorPolicy::ALL {
+                let failed: bool = conn
+                    .query_row(
+                        &format!(
+                            "SELECT {} FROM (SELECT ?1 AS status_code, ?2 AS http_status_code) s",
+                            policy.sql("s")
+                        ),
+                        rusqlite::params![status, http],
+                        |row| row.get(0),
+                    )
+                    .unwrap();
+                assert_eq!(failed, policy.is_error(status, http), "{:?}", policy);
+            }
+        }
+    }
+
+    #[test]
+    fn test_project_error_policy_overrides_default() {
+        let pool = crate::db::test_pool();
+        let (shop, _) = create(&pool, "Shop").unwrap();
+        assert_eq!(error_policy_override(&pool, shop.id), None);
+
+        set_error_policy(&pool, shop.id, Some(ErrorPolicy::ClientAndServerErrors)).unwrap();
+        assert_eq!(
+            error_policy(&pool, Some(shop.id), ErrorPolicy::StatusOnly),
+            ErrorPolicy::ClientAndServerErrors
+        );
+
+        set_error_policy(&pool, shop.id, None).unwrap();
+        assert_eq!(error_policy_override(&pool, shop.id), None);
+        assert_eq!(
+            error_policy(&pool, Some(shop.id), ErrorPolicy::StatusOnly),
+            ErrorPolicy::StatusOnly
+        );
+    }
+}
@@ -0,0 +1,176 @@
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+
+use crate::DbPool;
+
+use super::{Project, Role, User};
+
+/// A user's membership in a single project
+#[derive(Debug, Clone)]
+pub struct ProjectMember {
+    pub user_id: i64,
+    pub username: String,
+    pub project_id: i64,
+    pub project_name: String,
+    pub role: Role,
+}
+
+/// Grant a user access to a project, updating the role if already a member
+pub fn add(pool: &DbPool, user_id: i64, project_id: i64, role: Role) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        r#"INSERT INTO project_members (user_id, project_id, role, created_at)
+           VALUES (?1, ?2, ?3, ?4)
+           ON CONFLICT(user_id, project_id) DO UPDATE SET role = excluded.role"#,
+        (user_id, project_id, role.as_str(), Utc::now().to_rfc3339()),
+    )?;
+    Ok(())
+}
+
+/// Revoke a user's access to a project
+pub fn remove(pool: &DbPool, user_id: i64, project_id: i64) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        "DELETE FROM project_members WHERE user_id = ?1 AND project_id = ?2",
+        (user_id, project_id),
+    )?;
+    Ok(())
+}
+
+/// All memberships, for the admin user management page
+pub fn list_all(pool: &DbPool) -> anyhow::Result<Vec<ProjectMember>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        r#"SELECT m.user_id, u.username, m.project_id, p.name, m.role
+           FROM project_members m
+           JOIN users u ON u.id = m.user_id
+           JOIN projects p ON p.id = m.project_id
+           ORDER BY u.username, p.name"#,
+    )?;
+
+    let members = stmt
+        .query_map([], |row| {
+            Ok(ProjectMember {
+                user_id: row.get(0)?,
+                username: row.get(1)?,
+                project_id: row.get(2)?,
+                project_name: row.get(3)?,
+                role: Role::parse(&row.get::<_, String>(4)?),
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(members)
+}
+
+/// The user's role in a project: admins are admins everywhere, others have
+/// their membership role or none at all
+pub fn role_in(pool: &DbPool, user: &User, project_id: i64) -> anyhow::Result<Option<Role>> {
+    if user.has_role(Role::Admin) {
+        return Ok(Some(Role::Admin));
+    }
+    let conn = pool.get()?;
+    let role = conn
+        .query_row(
+            "SELECT role FROM project_members WHERE user_id = ?1 AND project_id = ?2",
+            (user.id, project_id),
+            |row| row.get::<_, String>(0),
+        )
+        .optional()?;
+    Ok(role.as_deref().map(Role::parse))
+}
+
+/// Whether the user may see the project. Admins can see every project.
+pub fn can_access(pool: &DbPool, user: &User, project_id: i64) -> anyhow::Result<bool> {
+    if user.has_role(Role::Admin) {
+        return Ok(true);
+    }
+    let conn = pool.get()?;
+    let found = conn
+        .query_row(
+            "SELECT 1 FROM project_members WHERE user_id = ?1 AND project_id = ?2",
+            (user.id, project_id),
+            |_| Ok(()),
+        )
+        .optional()?;
+    Ok(found.is_some())
+}
+
+/// Projects visible to the user: all of them for admins (or when there is no
+/// logged-in user, i.e. single-user mode), otherwise only those they belong to
+pub fn visible_projects(pool: &DbPool, user: Option<&User>) -> anyhow::Result<Vec<Project>> {
+    let user = match user {
+        Some(user) if !user.has_role(Role::Admin) => user,
+        _ => return super::project::list_all(pool),
+    };
+
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        r#"SELECT p.id, p.name, p.slug, COALESCE(p.api_key_prefix, ''), strftime('%Y-%m-%d %H:%M', p.created_at)
+           FROM projects p
+           JOIN project_members m ON m.project_id = p.id
+           WHERE m.user_id = ?1
+           ORDER BY p.name"#,
+    )?;
+
+    let projects = stmt
+        .query_map([user.id], |row| {
+            Ok(Project {
+                id: row.get(0)?,
+                name: row.get(1)?,
+                slug: row.get(2)?,
+                api_key_prefix: row.get(3)?,
+                created_at: row.get(4)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    Ok(projects)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::{project, user};
+
+    fn find_user(pool: &DbPool, id: i64) -> User {
+        user::list_all(pool)
+            .unwrap()
+            .into_iter()
+            .find(|u| u.id == id)
+            .unwrap()
+    }
+
+    #[test]
+    fn test_members_only_see_their_projects() {
+        let pool = crate::db::test_pool();
+        let (acme, _) = project::create(&pool, "Acme").unwrap();
+        let (globex, _) = project::create(&pool, "Globex").unwrap();
+        let member = find_user(
+            &pool,
+            user::create(&pool, "client", "password123", Role::Editor).unwrap(),
+        );
+        let admin = find_user(
+            &pool,
+            user::create(&pool, "boss", "password123", Role::Admin).unwrap(),
+        );
+
+        assert!(visible_projects(&pool, Some(&member)).unwrap().is_empty());
+        assert!(!can_access(&pool, &member, acme.id).unwrap());
+
+        add(&pool, member.id, acme.id, Role::Viewer).unwrap();
+        let visible = visible_projects(&pool, Some(&member)).unwrap();
+        assert_eq!(visible.len(), 1);
+        assert_eq!(visible[0].id, acme.id);
+        assert!(can_access(&pool, &member, acme.id).unwrap());
+        assert!(!can_access(&pool, &member, globex.id).unwrap());
+
+        // Admins and single-user mode see everything without memberships
+        assert!(can_access(&pool, &admin, globex.id).unwrap());
+        assert_eq!(visible_projects(&pool, Some(&admin)).unwrap().len(), 2);
+        assert_eq!(visible_projects(&pool, None).unwrap().len(), 2);
+
+        remove(&pool, member.id, acme.id).unwrap();
+        assert!(!can_access(&pool, &member, acme.id).unwrap());
+    }
+}
@@ -0,0 +1,229 @@
+use crate::DbPool;
+use chrono::{Duration, NaiveDate};
+use serde::Serialize;
+
+/// Change in weekly p95, in percent, before a route is flagged
+pub const THRESHOLD_PERCENT: f64 = 20.0;
+/// Requests a route needs in each week to be compared at all
+pub const MIN_WEEKLY_REQUESTS: i64 = 100;
+
+/// A route whose p95 this week moved past the threshold compared with last week
+#[derive(Debug, Clone, Serialize)]
+pub struct Regression {
+    pub id: i64,
+    pub path: String,
+    pub method: String,
+    /// First day of the week compared against the one before it
+    pub week_start: String,
+    pub baseline_p95_ms: f64,
+    pub current_p95_ms: f64,
+    /// Signed change from the baseline, in percent
+    pub change_percent: f64,
+    /// `slower` or `faster`
+    pub direction: String,
+    pub detected_at: String,
+}
+
+impl Regression {
+    pub fn is_slower(&self) -> bool {
+        self.direction == "slower"
+    }
+
+    pub fn change_label(&self) -> String {
+        format!("{:+.0}%", self.change_percent)
+    }
+}
+
+/// Compare the 7 days before `today` with the 7 days before that, using the
+/// request-weighted p95 of the daily rollups, and replace that week's
+/// regressions. Returns how many routes were flagged.
+pub fn detect(pool: &DbPool, today: NaiveDate) -> anyhow::Result<usize> {
+    let week_start = (today - Duration::days(7)).format("%Y-%m-%d").to_string();
+    let baseline_start = (today - Duration::days(14)).format("%Y-%m-%d").to_string();
+    let today = today.format("%Y-%m-%d").to_string();
+
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+    let changes: Vec<(String, String, f64, f64)> = {
+        let mut stmt = tx.prepare(
+            r#"
+            WITH weekly AS (
+                SELECT path, method, date >= ?2 AS current,
+                       SUM(request_count) AS requests,
+                       SUM(total_ms_p95 * request_count) / SUM(request_count) AS p95
+                FROM rollups_daily
+                WHERE date >= ?1 AND date < ?3 AND total_ms_p95 IS NOT NULL
+                GROUP BY path, method, current
+            )
+            SELECT b.path, b.method, b.p95, c.p95
+            FROM weekly b
+            JOIN weekly c ON c.path = b.path AND c.method = b.method AND c.current = 1
+            WHERE b.current = 0
+              AND b.requests >= ?4 AND c.requests >= ?4
+              AND b.p95 > 0
+            "#,
+        )?;
+        stmt.query_map(
+            rusqlite::params![baseline_start, week_start, today, MIN_WEEKLY_REQUESTS],
+            |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?, row.get(3)?)),
+        )?
+        .collect::<Result<Vec<_>, _>>()?
+    };
+
+    tx.execute(
+        "DELETE FROM regressions WHERE week_start = ?1",
+        [&week_start],
+    )?;
+    let mut flagged = 0;
+    for (path, method, baseline, current) in changes {
+        let change_percent = (current - baseline) / baseline * 100.0;
+        if change_percent.abs() < THRESHOLD_PERCENT {
+            continue;
+        }
+        let direction = if change_percent > 0.0 {
+            "slower"
+        } else {
+            "faster"
+        };
+        tx.execute(
+            r#"
+            INSERT INTO regressions (path, method, week_start, baseline_p95_ms, current_p95_ms,
+                                     change_percent, direction, detected_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, datetime('now'))
+            "#,
+            rusqlite::params![
+                path,
+                method,
+                week_start,
+                baseline,
+                current,
+                change_percent,
+                direction
+            ],
+        )?;
+        flagged += 1;
+    }
+    tx.commit()?;
+    Ok(flagged)
+}
+
+/// Regressions from the most recent detection run, slowest first
+pub fn latest(pool: &DbPool) -> anyhow::Result<Vec<Regression>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT id, path, method, week_start, baseline_p95_ms, current_p95_ms,
+               change_percent, direction, detected_at
+        FROM regressions
+        WHERE week_start = (SELECT MAX(week_start) FROM regressions)
+        ORDER BY change_percent DESC
+        "#,
+    )?;
+    let regressions = stmt
+        .query_map([], |row| {
+            Ok(Regression {
+                id: row.get(0)?,
+                path: row.get(1)?,
+                method: row.get(2)?,
+                week_start: row.get(3)?,
+                baseline_p95_ms: row.get(4)?,
+                current_p95_ms: row.get(5)?,
+                change_percent: row.get(6)?,
+                direction: row.get(7)?,
+                detected_at: row.get(8)?,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(regressions)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::rollup::{self, DailyRollup};
+
+    fn seed(pool: &DbPool, date: NaiveDate, path: &str, requests: i64, p95: f64) {
+        rollup::insert_daily(
+            pool,
+            &DailyRollup {
+                id: 0,
+                date: date.format("%Y-%m-%d").to_string(),
+                path: path.to_string(),
+                method: "GET".to_string(),
+                request_count: requests,
+                error_count: 0,
+                total_ms_p50: None,
+                total_ms_p95: Some(p95),
+                total_ms_p99: None,
+                avg_db_ms: None,
+                avg_db_count: None,
+            },
+        )
+        .unwrap();
+    }
+
+    #[test]
+    fn test_detect_flags_weekly_p95_changes() {
+        let pool = crate::db::test_pool();
+        let today = NaiveDate::from_ymd_opt(2026, 6, 15).unwrap();
+        for day in 1..=14 {
+            let date = today - Duration::days(day);
+            let this_week = day <= 7;
+            // 200ms -> 300ms: a 50% regression
+            seed(
+                &pool,
+                date,
+                "/checkout",
+                50,
+                if this_week { 300.0 } else { 200.0 },
+            );
+            // 400ms -> 200ms: an improvement
+            seed(
+                &pool,
+                date,
+                "/search",
+                50,
+                if this_week { 200.0 } else { 400.0 },
+            );
+            // Within the threshold
+            seed(
+                &pool,
+                date,
+                "/home",
+                50,
+                if this_week { 105.0 } else { 100.0 },
+            );
+            // Too little traffic to judge
+            seed(
+                &pool,
+                date,
+                "/admin",
+                5,
+                if this_week { 900.0 } else { 100.0 },
+            );
+        }
+        // Outside both weeks
+        seed(&pool, today - Duration::days(20), "/checkout", 1000, 5000.0);
+
+        assert_eq!(detect(&pool, today).unwrap(), 2);
+        let regressions = latest(&pool).unwrap();
+        assert_eq!(regressions.len(), 2);
+
+        let checkout = &regressions[0];
+        assert_eq!(checkout.path, "/checkout");
+        assert_eq!(checkout.week_start, "2026-06-08");
+        assert_eq!(checkout.baseline_p95_ms, 200.0);
+        assert_eq!(checkout.current_p95_ms, 300.0);
+        assert_eq!(checkout.change_label(), "+50%");
+        assert!(checkout.is_slower());
+
+        let search = &regressions[1];
+        assert_eq!(search.path, "/search");
+        assert_eq!(search.direction, "faster");
+        assert_eq!(search.change_percent, -50.0);
+
+        // Re-running replaces the week's results instead of duplicating them
+        assert_eq!(detect(&pool, today).unwrap(), 2);
+        assert_eq!(latest(&pool).unwrap().len(), 2);
+    }
+}
@@ -0,0 +1,254 @@
+use crate::DbPool;
+use chrono::{DateTime, Utc};
+use serde::Deserialize;
+use serde_json::Value;
+
+#[derive(Debug, Clone, Default, PartialEq, Deserialize)]
+pub struct IncomingRequest {
+    pub request_id: Option<String>,
+    pub method: String,
+    pub path: String,
+    pub controller: Option<String>,
+    pub action: Option<String>,
+    pub status: i32,
+    pub total_ms: f64,
+    pub db_ms: Option<f64>,
+    pub db_count: Option<i64>,
+    pub view_ms: Option<f64>,
+    pub host: Option<String>,
+    pub env: Option<String>,
+    pub git_sha: Option<String>,
+    pub timestamp: Option<String>,
+}
+
+#[derive(Debug, Clone, Default, Deserialize)]
+pub struct RequestBatch {
+    pub requests: Vec<IncomingRequest>,
+}
+
+/// First of `keys` present in a log line
+fn field<'a>(line: &'a Value, keys: &[&str]) -> Option<&'a Value> {
+    keys.iter()
+        .find_map(|key| line.get(key).filter(|v| !v.is_null()))
+}
+
+fn text(line: &Value, keys: &[&str]) -> Option<String> {
+    match field(line, keys)? {
+        Value::String(s) if !s.is_empty() => Some(s.clone()),
+        Value::Number(n) => Some(n.to_string()),
+        _ => None,
+    }
+}
+
+/// Numbers may be logged as JSON numbers or strings ("12.5")
+fn number(line: &Value, keys: &[&str]) -> Option<f64> {
+    match field(line, keys)? {
+        Value::Number(n) => n.as_f64(),
+        Value::String(s) => s.trim().parse().ok(),
+        _ => None,
+    }
+}
+
+impl IncomingRequest {
+    /// Map one Lograge JSON line. Besides Lograge's own keys this accepts the
+    /// `*_runtime` names from the Rails instrumentation payload, `uuid` for the
+    /// request id and the Logstash `@timestamp`. `None` when method, path,
+    /// status or duration is missing.
+    pub fn from_lograge(line: &Value) -> Option<Self> {
+        Some(Self {
+            request_id: text(line, &["request_id", "uuid", "request_uuid"]),
+            method: text(line, &["method"])?.to_uppercase(),
+            path: text(line, &["path"])?,
+            controller: text(line, &["controller"]),
+            action: text(line, &["action"]),
+            status: number(line, &["status"])? as i32,
+            total_ms: number(line, &["duration", "duration_ms", "total_ms"])?,
+            db_ms: number(line, &["db", "db_runtime", "db_ms"]),
+            db_count: number(line, &["db_count", "queries"]).map(|n| n as i64),
+            view_ms: number(line, &["view", "view_runtime", "view_ms"]),
+            host: text(line, &["host", "hostname"]),
+            env: text(line, &["env", "environment"]),
+            git_sha: text(line, &["git_sha", "revision"]),
+            timestamp: text(line, &["@timestamp", "timestamp", "time"]),
+        })
+    }
+}
+
+/// Store a batch of requests in one transaction on the writer, returning how
+/// many were stored. Requests without an id get a random one; unparsable
+/// timestamps become now.
+pub fn insert_batch(
+    pool: &DbPool,
+    batch: &RequestBatch,
+    project_id: Option<i64>,
+) -> anyhow::Result<usize> {
+    let batch = batch.clone();
+    pool.write(move |conn| store_batch(conn, &batch, project_id))
+}
+
+fn store_batch(
+    conn: &mut rusqlite::Connection,
+    batch: &RequestBatch,
+    project_id: Option<i64>,
+) -> anyhow::Result<usize> {
+    let tx = conn.transaction()?;
+    {
+        let mut stmt = tx.prepare(
+            r#"
+            INSERT INTO requests (project_id, request_id, method, path, controller, action, status,
+                                  total_ms, db_ms, db_count, view_ms, host, env, git_sha, happened_at)
+            VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15)
+            "#,
+        )?;
+        for request in &batch.requests {
+            let request_id = request
+                .request_id
+                .clone()
+                .unwrap_or_else(|| hex::encode(rand::random::<[u8; 16]>()));
+            let happened_at = request
+                .timestamp
+                .as_deref()
+                .and_then(|t| DateTime::parse_from_rfc3339(t).ok())
+                .map(|t| t.with_timezone(&Utc))
+                .unwrap_or_else(Utc::now)
+                .to_rfc3339();
+            stmt.execute(rusqlite::params![
+                project_id,
+                request_id,
+                request.method,
+                request.path,
+                request.controller,
+                request.action,
+                request.status,
+                request.total_ms,
+                request.db_ms.unwrap_or(0.0),
+                request.db_count.unwrap_or(0),
+                request.view_ms.unwrap_or(0.0),
+                request.host,
+                request.env,
+                request.git_sha,
+                happened_at,
+            ])?;
+        }
+    }
+    tx.commit()?;
+    Ok(batch.requests.len())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_lograge_default_keys() {
+        let line = serde_json::json!({
+            "method": "GET",
+            "path": "/orders/42",
+            "format": "html",
+            "controller": "OrdersController",
+            "action": "show",
+            "status": 200,
+            "duration": 58.33,
+            "view": 40.43,
+            "db": 15.26,
+            "allocations": 12034,
+            "request_id": "b5d3-77aa"
+        });
+        assert_eq!(
+            IncomingRequest::from_lograge(&line).unwrap(),
+            IncomingRequest {
+                request_id: Some("b5d3-77aa".to_string()),
+                method: "GET".to_string(),
+                path: "/orders/42".to_string(),
+                controller: Some("OrdersController".to_string()),
+                action: Some("show".to_string()),
+                status: 200,
+                total_ms: 58.33,
+                db_ms: Some(15.26),
+                view_ms: Some(40.43),
+                ..Default::default()
+            }
+        );
+    }
+
+    #[test]
+    fn test_lograge_key_variations() {
+        // Logstash formatter plus runtime names and stringly numbers
+        let line = serde_json::json!({
+            "@timestamp": "2026-06-01T10:00:00.000+02:00",
+            "@version": "1",
+            "message": "[200] GET /checkout (CheckoutController#new)",
+            "method": "get",
+            "path": "/checkout",
+            "status": "302",
+            "duration_ms": "12.5",
+            "db_runtime": 3.1,
+            "view_runtime": null,
+            "uuid": "req-1",
+            "hostname": "web-2"
+        });
+        let request = IncomingRequest::from_lograge(&line).unwrap();
+        assert_eq!(request.method, "GET");
+        assert_eq!(request.status, 302);
+        assert_eq!(request.total_ms, 12.5);
+        assert_eq!(request.db_ms, Some(3.1));
+        assert_eq!(request.view_ms, None);
+        assert_eq!(request.request_id.as_deref(), Some("req-1"));
+        assert_eq!(request.host.as_deref(), Some("web-2"));
+        assert_eq!(
+            request.timestamp.as_deref(),
+            Some("2026-06-01T10:00:00.000+02:00")
+        );
+    }
+
+    #[test]
+    fn test_lograge_missing_fields() {
+        // Only the required fields
+        let minimal = serde_json::json!({
+            "method": "POST", "path": "/login", "status": 422, "duration": 9
+        });
+        let request = IncomingRequest::from_lograge(&minimal).unwrap();
+        assert_eq!(request.controller, None);
+        assert_eq!(request.db_ms, None);
+        assert_eq!(request.request_id, None);
+
+        for missing in ["method", "path", "status", "duration"] {
+            let mut line = minimal.clone();
+            line.as_object_mut().unwrap().remove(missing);
+            assert!(IncomingRequest::from_lograge(&line).is_none(), "{missing}");
+        }
+    }
+
+    #[test]
+    fn test_insert_batch_stores_requests() {
+        let pool = crate::db::test_pool();
+        let line = serde_json::json!({
+            "method": "GET", "path": "/", "status": 200, "duration": 5,
+            "@timestamp": "2026-06-01T10:00:00+02:00"
+        });
+        let batch = RequestBatch {
+            requests: vec![
+                IncomingRequest::from_lograge(&line).unwrap(),
+                IncomingRequest {
+                    request_id: Some("abc".to_string()),
+                    db_ms: Some(2.0),
+                    ..IncomingRequest::from_lograge(&line).unwrap()
+                },
+            ],
+        };
+        assert_eq!(insert_batch(&pool, &batch, None).unwrap(), 2);
+
+        let conn = pool.get().unwrap();
+        let rows: Vec<(String, f64, String)> = conn
+            .prepare("SELECT request_id, db_ms, happened_at FROM requests ORDER BY db_ms")
+            .unwrap()
+            .query_map([], |row| Ok((row.get(0)?, row.get(1)?, row.get(2)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(rows[0].0.len(), 32);
+        assert_eq!(rows[0].1, 0.0);
+        assert_eq!(rows[0].2, "2026-06-01T08:00:00+00:00");
+        assert_eq!(rows[1].0, "abc");
+    }
+}
@@ -1,4 +1,7 @@
-use crate::DbPool;
+use crate::{
+    DbPool,
+    models::span::{self, RouteSummary},
+};
 use serde::{Deserialize, Serialize};
 
 #[derive(Debug, Clone, Serialize, Deserialize)]
@@ -13,6 +16,8 @@ pub struct HourlyRollup {
     pub total_ms_p50: Option<f64>,
     pub total_ms_p95: Option<f64>,
     pub total_ms_p99: Option<f64>,
+    pub total_ms_min: Option<f64>,
+    pub total_ms_max: Option<f64>,
     pub db_ms_sum: f64,
     pub db_count_sum: i64,
 }
@@ -37,8 +42,9 @@ pub fn insert_hourly(pool: &DbPool, rollup: &HourlyRollup) -> anyhow::Result<()>
     conn.execute(
         r#"
         INSERT OR REPLACE INTO rollups_hourly
-        (hour, path, method, request_count, error_count, total_ms_sum, total_ms_p50, total_ms_p95, total_ms_p99, db_ms_sum, db_count_sum)
-        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11)
+        (hour, path, method, request_count, error_count, total_ms_sum, total_ms_p50, total_ms_p95, total_ms_p99,
+         total_ms_min, total_ms_max, db_ms_sum, db_count_sum)
+        VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13)
         "#,
         (
             &rollup.hour,
@@ -50,6 +56,8 @@ pub fn insert_hourly(pool: &DbPool, rollup: &HourlyRollup) -> anyhow::Result<()>
             rollup.total_ms_p50,
             rollup.total_ms_p95,
             rollup.total_ms_p99,
+            rollup.total_ms_min,
+            rollup.total_ms_max,
             rollup.db_ms_sum,
             rollup.db_count_sum,
         ),
@@ -120,6 +128,95 @@ pub fn daily_for_range(
     Ok(rollups)
 }
 
+/// Route stats for whole hours from `since` (and before `until`), read from the
+/// hourly rollups. Those outlive raw spans, so long ranges keep their history,
+/// but they aren't kept per project and trail the current hour. p95 and p99
+/// are request-weighted averages of the hourly percentiles, and DB averages are
+/// per request rather than per request that queried the database.
+pub fn routes_summary_from_rollups(
+    pool: &DbPool,
+    since: &str,
+    until: Option<&str>,
+    search: Option<&str>,
+    sort: &str,
+    limit: i64,
+) -> anyhow::Result<Vec<RouteSummary>> {
+    let conn = pool.get()?;
+    let mut stmt = conn.prepare(
+        r#"
+        SELECT path, method,
+               SUM(request_count) AS request_count,
+               SUM(total_ms_sum) / SUM(request_count) AS avg_ms,
+               SUM(COALESCE(total_ms_p95, 0) * request_count) / SUM(request_count) AS p95_ms,
+               SUM(COALESCE(total_ms_p99, 0) * request_count) / SUM(request_count) AS p99_ms,
+               -- Hours rolled up before extremes were kept fall back to p99/p50
+               MAX(COALESCE(total_ms_max, total_ms_p99, 0)) AS max_ms,
+               MIN(COALESCE(total_ms_min, total_ms_p50, 0)) AS min_ms,
+               SUM(db_ms_sum) / SUM(request_count) AS avg_db_ms,
+               CAST(SUM(db_count_sum) AS REAL) / SUM(request_count) AS avg_db_count,
+               SUM(error_count) AS error_count
+        FROM rollups_hourly
+        WHERE hour >= ?1
+          AND (?2 IS NULL OR hour < ?2)
+          AND (?3 IS NULL OR path LIKE '%' || ?3 || '%')
+          AND request_count > 0
+        GROUP BY path, method
+        ORDER BY request_count DESC
+        LIMIT ?4
+        "#,
+    )?;
+
+    let mut routes = stmt
+        .query_map(rusqlite::params![since, until, search, limit], |row| {
+            let request_count: i64 = row.get(2)?;
+            let error_count: i64 = row.get(10)?;
+            let ms = |idx: usize| row.get::<_, f64>(idx).map(|v| v.round() as i64);
+            Ok(RouteSummary {
+                path: row.get(0)?,
+                method: row.get(1)?,
+                request_count,
+                avg_ms: ms(3)?,
+                p95_ms: ms(4)?,
+                p99_ms: ms(5)?,
+                max_ms: ms(6)?,
+                min_ms: ms(7)?,
+                avg_db_ms: ms(8)?,
+                avg_db_count: ms(9)?,
+                error_count,
+                error_rate: error_count as f64 / request_count as f64 * 100.0,
+            })
+        })?
+        .collect::<Result<Vec<_>, _>>()?;
+
+    span::sort_routes(&mut routes, sort);
+    Ok(routes)
+}
+
+/// Number of routes `routes_summary_from_rollups` would list without a limit
+pub fn routes_count_from_rollups(
+    pool: &DbPool,
+    since: &str,
+    until: Option<&str>,
+    search: Option<&str>,
+) -> anyhow::Result<i64> {
+    let conn = pool.get()?;
+    let count = conn.query_row(
+        r#"
+        SELECT COUNT(*) FROM (
+            SELECT 1 FROM rollups_hourly
+            WHERE hour >= ?1
+              AND (?2 IS NULL OR hour < ?2)
+              AND (?3 IS NULL OR path LIKE '%' || ?3 || '%')
+              AND request_count > 0
+            GROUP BY path, method
+        )
+        "#,
+        rusqlite::params![since, until, search],
+        |row| row.get(0),
+    )?;
+    Ok(count)
+}
+
 pub fn delete_hourly_before(pool: &DbPool, before: &str) -> anyhow::Result<usize> {
     let conn = pool.get()?;
     let deleted = conn.execute("DELETE FROM rollups_hourly WHERE hour < ?1", [before])?;
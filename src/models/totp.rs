@@ -0,0 +1,320 @@
+use crate::DbPool;
+use chrono::{Duration, Utc};
+use hmac::{Hmac, Mac};
+use rand::Rng;
+use sha1::Sha1;
+use sha2::{Digest, Sha256};
+
+/// RFC 6238 defaults, which every authenticator app assumes
+const STEP_SECS: u64 = 30;
+const DIGITS: u32 = 6;
+const RECOVERY_CODE_COUNT: usize = 10;
+/// Wrong codes allowed per pending login before it must start over
+const MAX_FAILED_ATTEMPTS: i64 = 5;
+
+/// Generate a random 160-bit TOTP secret (hex encoded for storage)
+pub fn generate_secret() -> String {
+    let bytes: [u8; 20] = rand::thread_rng().r#gen();
+    hex::encode(bytes)
+}
+
+/// RFC 4648 base32 without padding, as expected in otpauth:// URIs
+pub fn base32_encode(bytes: &[u8]) -> String {
+    const ALPHABET: &[u8; 32] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZ234567";
+    let mut out = String::new();
+    let mut buffer: u32 = 0;
+    let mut bits = 0;
+    for &byte in bytes {
+        buffer = (buffer << 8) | byte as u32;
+        bits += 8;
+        while bits >= 5 {
+            out.push(ALPHABET[((buffer >> (bits - 5)) & 31) as usize] as char);
+            bits -= 5;
+        }
+    }
+    if bits > 0 {
+        out.push(ALPHABET[((buffer << (5 - bits)) & 31) as usize] as char);
+    }
+    out
+}
+
+/// otpauth:// URI for enrolling the secret in an authenticator app
+pub fn provisioning_uri(secret_hex: &str, username: &str) -> String {
+    let secret = base32_encode(&hex::decode(secret_hex).unwrap_or_default());
+    format!(
+        "otpauth://totp/MiniAPM:{}?secret={}&issuer=MiniAPM&digits={}&period={}",
+        urlencoding::encode(username),
+        secret,
+        DIGITS,
+        STEP_SECS
+    )
+}
+
+/// HOTP value for a secret at the time step containing `unix_time`
+pub fn code_at(secret: &[u8], unix_time: u64) -> u32 {
+    let counter = unix_time / STEP_SECS;
+    let mut mac = Hmac::<Sha1>::new_from_slice(secret).expect("HMAC accepts any key length");
+    mac.update(&counter.to_be_bytes());
+    let hash = mac.finalize().into_bytes();
+
+    let offset = (hash[hash.len() - 1] & 0x0f) as usize;
+    let binary = u32::from_be_bytes([
+        hash[offset] & 0x7f,
+        hash[offset + 1],
+        hash[offset + 2],
+        hash[offset + 3],
+    ]);
+    binary % 10u32.pow(DIGITS)
+}
+
+/// Check a 6-digit code, accepting one time step of clock drift either way
+pub fn verify_code(secret_hex: &str, code: &str, unix_time: u64) -> bool {
+    let code = code.trim();
+    if code.len() != DIGITS as usize || !code.chars().all(|c| c.is_ascii_digit()) {
+        return false;
+    }
+    let Ok(code) = code.parse::<u32>() else {
+        return false;
+    };
+    let Ok(secret) = hex::decode(secret_hex) else {
+        return false;
+    };
+
+    [
+        unix_time.saturating_sub(STEP_SECS),
+        unix_time,
+        unix_time + STEP_SECS,
+    ]
+    .iter()
+    .any(|&t| code_at(&secret, t) == code)
+}
+
+pub fn now_unix() -> u64 {
+    Utc::now().timestamp().max(0) as u64
+}
+
+// ============================================================================
+// Persistence
+// ============================================================================
+
+/// The user's TOTP secret, if two-factor auth is enabled
+pub fn secret_for(pool: &DbPool, user_id: i64) -> anyhow::Result<Option<String>> {
+    let conn = pool.get()?;
+    let secret = conn
+        .query_row(
+            "SELECT totp_secret FROM users WHERE id = ?1",
+            [user_id],
+            |row| row.get(0),
+        )
+        .ok()
+        .flatten();
+    Ok(secret)
+}
+
+/// Enable two-factor auth, replacing any recovery codes. Returns the new
+/// recovery codes in plaintext; only their hashes are stored.
+pub fn enable(pool: &DbPool, user_id: i64, secret_hex: &str) -> anyhow::Result<Vec<String>> {
+    let mut conn = pool.get()?;
+    let tx = conn.transaction()?;
+
+    tx.execute(
+        "UPDATE users SET totp_secret = ?1 WHERE id = ?2",
+        (secret_hex, user_id),
+    )?;
+    tx.execute("DELETE FROM recovery_codes WHERE user_id = ?1", [user_id])?;
+
+    let mut codes = Vec::with_capacity(RECOVERY_CODE_COUNT);
+    for _ in 0..RECOVERY_CODE_COUNT {
+        let bytes: [u8; 5] = rand::thread_rng().r#gen();
+        let code = hex::encode(bytes);
+        tx.execute(
+            "INSERT INTO recovery_codes (user_id, code_hash) VALUES (?1, ?2)",
+            (user_id, hash_code(&code)),
+        )?;
+        codes.push(code);
+    }
+
+    tx.commit()?;
+    Ok(codes)
+}
+
+/// Disable two-factor auth and drop recovery codes
+pub fn disable(pool: &DbPool, user_id: i64) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE users SET totp_secret = NULL WHERE id = ?1",
+        [user_id],
+    )?;
+    conn.execute("DELETE FROM recovery_codes WHERE user_id = ?1", [user_id])?;
+    Ok(())
+}
+
+/// Consume a recovery code; each code works once
+pub fn use_recovery_code(pool: &DbPool, user_id: i64, code: &str) -> anyhow::Result<bool> {
+    let conn = pool.get()?;
+    let used = conn.execute(
+        "DELETE FROM recovery_codes WHERE user_id = ?1 AND code_hash = ?2",
+        (user_id, hash_code(&code.trim().to_lowercase())),
+    )?;
+    Ok(used > 0)
+}
+
+/// Start the second login step after a correct password
+pub fn create_pending_login(pool: &DbPool, user_id: i64) -> anyhow::Result<String> {
+    let conn = pool.get()?;
+    let token = hex::encode(rand::thread_rng().r#gen::<[u8; 32]>());
+    let expires = Utc::now() + Duration::minutes(5);
+
+    conn.execute(
+        "INSERT INTO pending_logins (token, user_id, expires_at) VALUES (?1, ?2, ?3)",
+        (&token, user_id, expires.to_rfc3339()),
+    )?;
+
+    Ok(token)
+}
+
+/// User waiting on the second login step, if the token hasn't expired
+pub fn pending_login_user(pool: &DbPool, token: &str) -> anyhow::Result<Option<i64>> {
+    let conn = pool.get()?;
+    let now = Utc::now().to_rfc3339();
+    let user_id = conn
+        .query_row(
+            "SELECT user_id FROM pending_logins WHERE token = ?1 AND expires_at > ?2",
+            [token, &now],
+            |row| row.get(0),
+        )
+        .ok();
+    Ok(user_id)
+}
+
+pub fn delete_pending_login(pool: &DbPool, token: &str) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute("DELETE FROM pending_logins WHERE token = ?1", [token])?;
+    Ok(())
+}
+
+/// Count a wrong code against a pending login, dropping it once
+/// `MAX_FAILED_ATTEMPTS` is reached. Returns whether it can still be used.
+pub fn record_failed_attempt(pool: &DbPool, token: &str) -> anyhow::Result<bool> {
+    let conn = pool.get()?;
+    conn.execute(
+        "UPDATE pending_logins SET failed_attempts = failed_attempts + 1 WHERE token = ?1",
+        [token],
+    )?;
+    let exhausted = conn.execute(
+        "DELETE FROM pending_logins WHERE token = ?1 AND failed_attempts >= ?2",
+        (token, MAX_FAILED_ATTEMPTS),
+    )?;
+    Ok(exhausted == 0)
+}
+
+/// Delete pending logins that expired before finishing the second step
+pub fn delete_expired_pending_logins(pool: &DbPool) -> anyhow::Result<usize> {
+    let conn = pool.get()?;
+    let now = Utc::now().to_rfc3339();
+    let deleted = conn.execute("DELETE FROM pending_logins WHERE expires_at <= ?1", [&now])?;
+    Ok(deleted)
+}
+
+fn hash_code(code: &str) -> String {
+    let mut hasher = Sha256::new();
+    hasher.update(code.as_bytes());
+    hex::encode(hasher.finalize())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    // RFC 6238 appendix B secret ("12345678901234567890")
+    const RFC_SECRET: &str = "3132333435363738393031323334353637383930";
+
+    #[test]
+    fn test_code_at_matches_rfc_vectors() {
+        let secret = hex::decode(RFC_SECRET).unwrap();
+        assert_eq!(code_at(&secret, 59), 287082);
+        assert_eq!(code_at(&secret, 1111111109), 81804);
+        assert_eq!(code_at(&secret, 1234567890), 5924);
+    }
+
+    #[test]
+    fn test_verify_code_valid_within_one_step() {
+        assert!(verify_code(RFC_SECRET, "081804", 1111111109));
+        assert!(verify_code(RFC_SECRET, "081804", 1111111109 + 30));
+        assert!(verify_code(RFC_SECRET, "081804", 1111111109 - 30));
+    }
+
+    #[test]
+    fn test_verify_code_rejects_invalid() {
+        assert!(!verify_code(RFC_SECRET, "000000", 1111111109));
+        assert!(!verify_code(RFC_SECRET, "81804", 1111111109));
+        assert!(!verify_code(RFC_SECRET, "08180a", 1111111109));
+    }
+
+    #[test]
+    fn test_verify_code_rejects_expired() {
+        assert!(!verify_code(RFC_SECRET, "081804", 1111111109 + 90));
+        assert!(!verify_code(RFC_SECRET, "081804", 1111111109 - 90));
+    }
+
+    #[test]
+    fn test_base32_encode() {
+        assert_eq!(base32_encode(b"foobar"), "MZXW6YTBOI");
+        assert_eq!(
+            base32_encode(b"12345678901234567890"),
+            "GEZDGNBVGY3TQOJQGEZDGNBVGY3TQOJQ"
+        );
+    }
+
+    #[test]
+    fn test_recovery_codes_are_single_use() {
+        let pool = crate::db::test_pool();
+        let user_id =
+            crate::models::user::create(&pool, "alice", "password123", crate::models::Role::Editor)
+                .unwrap();
+        let codes = enable(&pool, user_id, RFC_SECRET).unwrap();
+
+        assert_eq!(
+            secret_for(&pool, user_id).unwrap().as_deref(),
+            Some(RFC_SECRET)
+        );
+        assert!(use_recovery_code(&pool, user_id, &codes[0]).unwrap());
+        assert!(!use_recovery_code(&pool, user_id, &codes[0]).unwrap());
+        assert!(!use_recovery_code(&pool, user_id, "not-a-code").unwrap());
+    }
+
+    #[test]
+    fn test_pending_login_dropped_after_failed_attempts() {
+        let pool = crate::db::test_pool();
+        let user_id =
+            crate::models::user::create(&pool, "alice", "password123", crate::models::Role::Editor)
+                .unwrap();
+        let token = create_pending_login(&pool, user_id).unwrap();
+
+        for _ in 1..MAX_FAILED_ATTEMPTS {
+            assert!(record_failed_attempt(&pool, &token).unwrap());
+        }
+        assert_eq!(pending_login_user(&pool, &token).unwrap(), Some(user_id));
+        assert!(!record_failed_attempt(&pool, &token).unwrap());
+        assert_eq!(pending_login_user(&pool, &token).unwrap(), None);
+    }
+
+    #[test]
+    fn test_delete_expired_pending_logins() {
+        let pool = crate::db::test_pool();
+        let user_id =
+            crate::models::user::create(&pool, "alice", "password123", crate::models::Role::Editor)
+                .unwrap();
+        let live = create_pending_login(&pool, user_id).unwrap();
+        pool.get()
+            .unwrap()
+            .execute(
+                "INSERT INTO pending_logins (token, user_id, expires_at) VALUES ('old', ?1, ?2)",
+                (user_id, (Utc::now() - Duration::minutes(1)).to_rfc3339()),
+            )
+            .unwrap();
+
+        assert_eq!(delete_expired_pending_logins(&pool).unwrap(), 1);
+        assert_eq!(pending_login_user(&pool, &live).unwrap(), Some(user_id));
+    }
+}
@@ -7,13 +7,44 @@ use chrono::{Duration, Utc};
 use rand::Rng;
 use serde::{Deserialize, Serialize};
 
+/// Access level, ordered from least to most privileged
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize, Deserialize)]
+#[serde(rename_all = "snake_case")]
+pub enum Role {
+    /// Can view dashboards
+    Viewer,
+    /// Can also triage errors and manage projects
+    Editor,
+    /// Can also manage users
+    Admin,
+}
+
+impl Role {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            Role::Viewer => "viewer",
+            Role::Editor => "editor",
+            Role::Admin => "admin",
+        }
+    }
+
+    /// Unknown values fall back to the least privileged role
+    pub fn parse(s: &str) -> Self {
+        match s {
+            "admin" => Role::Admin,
+            "editor" => Role::Editor,
+            _ => Role::Viewer,
+        }
+    }
+}
+
 #[derive(Debug, Clone, Serialize, Deserialize)]
 pub struct User {
     pub id: i64,
     pub username: String,
     #[serde(skip_serializing)]
     pub password_hash: Option<String>,
-    pub is_admin: bool,
+    pub role: Role,
     pub must_change_password: bool,
     #[serde(skip_serializing)]
     pub invite_token: Option<String>,
@@ -22,6 +53,13 @@ pub struct User {
     pub last_login_at: Option<String>,
 }
 
+impl User {
+    /// Whether the user has at least the given role
+    pub fn has_role(&self, role: Role) -> bool {
+        self.role >= role
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Session {
     pub id: i64,
@@ -70,7 +108,7 @@ pub fn ensure_default_admin(pool: &DbPool) -> anyhow::Result<()> {
         let now = Utc::now().to_rfc3339();
 
         conn.execute(
-            "INSERT INTO users (username, password_hash, is_admin, must_change_password, created_at) VALUES (?1, ?2, 1, 1, ?3)",
+            "INSERT INTO users (username, password_hash, role, must_change_password, created_at) VALUES (?1, ?2, 'admin', 1, ?3)",
             ("admin", &password_hash, &now),
         )?;
 
@@ -86,14 +124,14 @@ pub fn authenticate(pool: &DbPool, username: &str, password: &str) -> anyhow::Re
 
     let user: Option<User> = conn
         .query_row(
-            "SELECT id, username, password_hash, is_admin, must_change_password, invite_token, invite_expires_at, created_at, last_login_at FROM users WHERE username = ?1",
+            "SELECT id, username, password_hash, role, must_change_password, invite_token, invite_expires_at, created_at, last_login_at FROM users WHERE username = ?1",
             [username],
             |row| {
                 Ok(User {
                     id: row.get(0)?,
                     username: row.get(1)?,
                     password_hash: row.get(2)?,
-                    is_admin: row.get::<_, i64>(3)? == 1,
+                    role: Role::parse(&row.get::<_, String>(3)?),
                     must_change_password: row.get::<_, i64>(4)? == 1,
                     invite_token: row.get(5)?,
                     invite_expires_at: row.get(6)?,
@@ -145,7 +183,7 @@ pub fn get_user_from_session(pool: &DbPool, token: &str) -> anyhow::Result<Optio
     let user: Option<User> = conn
         .query_row(
             r#"
-            SELECT u.id, u.username, u.password_hash, u.is_admin, u.must_change_password, u.invite_token, u.invite_expires_at, u.created_at, u.last_login_at
+            SELECT u.id, u.username, u.password_hash, u.role, u.must_change_password, u.invite_token, u.invite_expires_at, u.created_at, u.last_login_at
             FROM users u
             JOIN sessions s ON s.user_id = u.id
             WHERE s.token = ?1 AND s.expires_at > ?2
@@ -156,7 +194,7 @@ pub fn get_user_from_session(pool: &DbPool, token: &str) -> anyhow::Result<Optio
                     id: row.get(0)?,
                     username: row.get(1)?,
                     password_hash: row.get(2)?,
-                    is_admin: row.get::<_, i64>(3)? == 1,
+                    role: Role::parse(&row.get::<_, String>(3)?),
                     must_change_password: row.get::<_, i64>(4)? == 1,
                     invite_token: row.get(5)?,
                     invite_expires_at: row.get(6)?,
@@ -189,7 +227,7 @@ pub fn delete_expired_sessions(pool: &DbPool) -> anyhow::Result<usize> {
 pub fn list_all(pool: &DbPool) -> anyhow::Result<Vec<User>> {
     let conn = pool.get()?;
     let mut stmt = conn.prepare(
-        r#"SELECT id, username, password_hash, is_admin, must_change_password, invite_token, invite_expires_at,
+        r#"SELECT id, username, password_hash, role, must_change_password, invite_token, invite_expires_at,
                   strftime('%Y-%m-%d %H:%M', created_at),
                   CASE WHEN last_login_at IS NOT NULL THEN strftime('%Y-%m-%d %H:%M', last_login_at) ELSE NULL END
            FROM users ORDER BY username"#,
@@ -201,7 +239,7 @@ pub fn list_all(pool: &DbPool) -> anyhow::Result<Vec<User>> {
                 id: row.get(0)?,
                 username: row.get(1)?,
                 password_hash: row.get(2)?,
-                is_admin: row.get::<_, i64>(3)? == 1,
+                role: Role::parse(&row.get::<_, String>(3)?),
                 must_change_password: row.get::<_, i64>(4)? == 1,
                 invite_token: row.get(5)?,
                 invite_expires_at: row.get(6)?,
@@ -215,19 +253,14 @@ pub fn list_all(pool: &DbPool) -> anyhow::Result<Vec<User>> {
 }
 
 /// Create a new user (admin only)
-pub fn create(
-    pool: &DbPool,
-    username: &str,
-    password: &str,
-    is_admin: bool,
-) -> anyhow::Result<i64> {
+pub fn create(pool: &DbPool, username: &str, password: &str, role: Role) -> anyhow::Result<i64> {
     let conn = pool.get()?;
     let password_hash = hash_password(password)?;
     let now = Utc::now().to_rfc3339();
 
     conn.execute(
-        "INSERT INTO users (username, password_hash, is_admin, must_change_password, created_at) VALUES (?1, ?2, ?3, 0, ?4)",
-        (username, &password_hash, if is_admin { 1 } else { 0 }, &now),
+        "INSERT INTO users (username, password_hash, role, must_change_password, created_at) VALUES (?1, ?2, ?3, 0, ?4)",
+        (username, &password_hash, role.as_str(), &now),
     )?;
 
     Ok(conn.last_insert_rowid())
@@ -259,14 +292,14 @@ pub fn find(pool: &DbPool, id: i64) -> anyhow::Result<Option<User>> {
 
     let user: Option<User> = conn
         .query_row(
-            "SELECT id, username, password_hash, is_admin, must_change_password, invite_token, invite_expires_at, created_at, last_login_at FROM users WHERE id = ?1",
+            "SELECT id, username, password_hash, role, must_change_password, invite_token, invite_expires_at, created_at, last_login_at FROM users WHERE id = ?1",
             [id],
             |row| {
                 Ok(User {
                     id: row.get(0)?,
                     username: row.get(1)?,
                     password_hash: row.get(2)?,
-                    is_admin: row.get::<_, i64>(3)? == 1,
+                    role: Role::parse(&row.get::<_, String>(3)?),
                     must_change_password: row.get::<_, i64>(4)? == 1,
                     invite_token: row.get(5)?,
                     invite_expires_at: row.get(6)?,
@@ -288,15 +321,15 @@ pub fn generate_invite_token() -> String {
 }
 
 /// Create a new user with an invite token (no password yet)
-pub fn create_with_invite(pool: &DbPool, username: &str, is_admin: bool) -> anyhow::Result<String> {
+pub fn create_with_invite(pool: &DbPool, username: &str, role: Role) -> anyhow::Result<String> {
     let conn = pool.get()?;
     let invite_token = generate_invite_token();
     let now = Utc::now();
     let expires = now + Duration::days(7);
 
     conn.execute(
-        "INSERT INTO users (username, is_admin, invite_token, invite_expires_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
-        (username, if is_admin { 1 } else { 0 }, &invite_token, expires.to_rfc3339(), now.to_rfc3339()),
+        "INSERT INTO users (username, role, invite_token, invite_expires_at, created_at) VALUES (?1, ?2, ?3, ?4, ?5)",
+        (username, role.as_str(), &invite_token, expires.to_rfc3339(), now.to_rfc3339()),
     )?;
 
     Ok(invite_token)
@@ -309,14 +342,14 @@ pub fn find_by_invite_token(pool: &DbPool, token: &str) -> anyhow::Result<Option
 
     let user: Option<User> = conn
         .query_row(
-            "SELECT id, username, password_hash, is_admin, must_change_password, invite_token, invite_expires_at, created_at, last_login_at FROM users WHERE invite_token = ?1 AND invite_expires_at > ?2",
+            "SELECT id, username, password_hash, role, must_change_password, invite_token, invite_expires_at, created_at, last_login_at FROM users WHERE invite_token = ?1 AND invite_expires_at > ?2",
             [token, &now],
             |row| {
                 Ok(User {
                     id: row.get(0)?,
                     username: row.get(1)?,
                     password_hash: row.get(2)?,
-                    is_admin: row.get::<_, i64>(3)? == 1,
+                    role: Role::parse(&row.get::<_, String>(3)?),
                     must_change_password: row.get::<_, i64>(4)? == 1,
                     invite_token: row.get(5)?,
                     invite_expires_at: row.get(6)?,
@@ -0,0 +1,69 @@
+use chrono::Utc;
+use rusqlite::OptionalExtension;
+
+use crate::DbPool;
+
+/// Time window the dashboard, errors and performance pages open with
+pub const TIME_RANGE: &str = "time_range";
+/// Sort order of the errors list
+pub const ERRORS_SORT: &str = "errors_sort";
+/// Sort order of the performance routes table
+pub const PERFORMANCE_SORT: &str = "performance_sort";
+
+/// A user's saved value for `key`, if they have one
+pub fn get(pool: &DbPool, user_id: i64, key: &str) -> anyhow::Result<Option<String>> {
+    let conn = pool.get()?;
+    let value = conn
+        .query_row(
+            "SELECT value FROM user_prefs WHERE user_id = ?1 AND key = ?2",
+            (user_id, key),
+            |row| row.get(0),
+        )
+        .optional()?;
+    Ok(value)
+}
+
+/// Save a user's value for `key`, replacing any earlier one
+pub fn set(pool: &DbPool, user_id: i64, key: &str, value: &str) -> anyhow::Result<()> {
+    let conn = pool.get()?;
+    conn.execute(
+        r#"INSERT INTO user_prefs (user_id, key, value, updated_at)
+           VALUES (?1, ?2, ?3, ?4)
+           ON CONFLICT(user_id, key) DO UPDATE SET
+               value = excluded.value,
+               updated_at = excluded.updated_at"#,
+        (user_id, key, value, Utc::now().to_rfc3339()),
+    )?;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_prefs_round_trip_per_user() {
+        let pool = crate::db::test_pool();
+        let alice =
+            crate::models::user::create(&pool, "alice", "password123", crate::models::Role::Viewer)
+                .unwrap();
+        let bob =
+            crate::models::user::create(&pool, "bob", "password123", crate::models::Role::Viewer)
+                .unwrap();
+
+        assert_eq!(get(&pool, alice, TIME_RANGE).unwrap(), None);
+        set(&pool, alice, TIME_RANGE, "7d").unwrap();
+        set(&pool, alice, TIME_RANGE, "30d").unwrap();
+        set(&pool, alice, ERRORS_SORT, "count").unwrap();
+
+        assert_eq!(
+            get(&pool, alice, TIME_RANGE).unwrap().as_deref(),
+            Some("30d")
+        );
+        assert_eq!(
+            get(&pool, alice, ERRORS_SORT).unwrap().as_deref(),
+            Some("count")
+        );
+        assert_eq!(get(&pool, bob, TIME_RANGE).unwrap(), None);
+    }
+}
@@ -1,24 +1,35 @@
+use arc_swap::ArcSwap;
 use axum::{
-    Router,
+    Extension, Router,
     extract::DefaultBodyLimit,
+    http::header,
     middleware,
     routing::{get, post},
 };
 use std::net::SocketAddr;
+use std::sync::Arc;
 use tokio::signal;
 use tower_cookies::CookieManagerLayer;
-use tower_http::trace::TraceLayer;
+use tower_http::{
+    cors::{AllowOrigin, CorsLayer},
+    decompression::RequestDecompressionLayer,
+    trace::TraceLayer,
+};
 
-use crate::{DbPool, api, config::Config, jobs, models, web};
+use crate::{
+    DbPool, api,
+    config::{Config, SharedConfig},
+    jobs, models, web,
+};
 
 /// Combined state for routes that need both pool and config
 #[derive(Clone)]
 pub struct AppState {
     pub pool: DbPool,
-    pub config: Config,
+    pub config: SharedConfig,
 }
 
-/// Maximum request body size (10 MB)
+/// Maximum request body size outside `/ingest` (10 MB)
 const MAX_BODY_SIZE: usize = 10 * 1024 * 1024;
 
 pub async fn run(pool: DbPool, config: Config, port: u16) -> anyhow::Result<()> {
@@ -31,29 +42,67 @@ pub async fn run(pool: DbPool, config: Config, port: u16) -> anyhow::Result<()>
     models::user::ensure_default_admin(&pool)?;
 
     if !config.enable_projects {
-        tracing::info!("Single-project mode - API key: {}", default_project.api_key);
+        tracing::info!(
+            "Single-project mode - API key: {}... (regenerate at /api-key if lost)",
+            default_project.api_key_prefix
+        );
     }
 
-    // Start background jobs
-    jobs::start(pool.clone(), config.clone());
+    let self_trace = if config.self_trace {
+        let internal = models::project::ensure_internal_project(&pool)?;
+        tracing::info!(
+            "Self-tracing ingest requests into project {}",
+            internal.slug
+        );
+        Some(api::ingest::SelfTrace {
+            pool: pool.clone(),
+            project_id: internal.id,
+        })
+    } else {
+        None
+    };
+
+    // Start background jobs; they and the handlers read the shared config so
+    // SIGHUP can update it
+    let shared_config: SharedConfig = Arc::new(ArcSwap::from_pointee(config.clone()));
+    reload_config_on_sighup(shared_config.clone());
+    jobs::start(pool.clone(), shared_config.clone());
 
     // Build router
+    let cors = cors_layer(&config);
     let app = Router::new()
         // Health check (no auth)
         .route("/health", get(api::health_handler))
         // Ingestion API (with API key auth)
         .nest(
             "/ingest",
-            Router::new()
-                .route("/deploys", post(api::ingest_deploys))
-                .route("/v1/traces", post(api::ingest_spans))
-                .route("/errors", post(api::ingest_errors))
-                .route("/errors/batch", post(api::ingest_errors_batch))
-                .layer(middleware::from_fn_with_state(
-                    pool.clone(),
-                    api::auth_middleware,
-                )),
+            with_cors(
+                with_self_trace(ingest_routes(pool.clone(), &shared_config), self_trace),
+                &cors,
+            ),
         )
+        // Read API (read-scoped tokens)
+        .nest(
+            "/api",
+            with_cors(
+                Router::new()
+                    .route("/errors", get(api::read::list_errors))
+                    .route("/traces", get(api::read::list_traces))
+                    .route("/stats", get(api::read::stats))
+                    .route("/n-plus-1", get(api::read::n_plus_1_hotspots))
+                    .route("/slow-queries", get(api::read::slow_queries))
+                    .layer(middleware::from_fn_with_state(
+                        pool.clone(),
+                        api::read_auth_middleware,
+                    )),
+                &cors,
+            ),
+        )
+        // Sentry SDK ingestion (DSN key auth)
+        .merge(with_cors(
+            sentry_routes(pool.clone(), &shared_config),
+            &cors,
+        ))
         // Auth routes (always available)
         .merge(web::auth_routes())
         // Web UI (protected when user accounts enabled)
@@ -62,6 +111,8 @@ pub async fn run(pool: DbPool, config: Config, port: u16) -> anyhow::Result<()>
         .nest_service("/static", tower_http::services::ServeDir::new("static"))
         // State and middleware
         .with_state(pool)
+        // Handlers read live settings from here, so SIGHUP reaches them too
+        .layer(Extension(shared_config))
         .layer(DefaultBodyLimit::max(MAX_BODY_SIZE))
         .layer(CookieManagerLayer::new())
         .layer(TraceLayer::new_for_http());
@@ -84,6 +135,155 @@ pub async fn run(pool: DbPool, config: Config, port: u16) -> anyhow::Result<()>
     Ok(())
 }
 
+/// CORS for browser exporters and other cross-origin clients, `None` unless
+/// `CORS_ALLOWED_ORIGINS` is set. Values were checked by `Config::validate`.
+fn cors_layer(config: &Config) -> Option<CorsLayer> {
+    if config.cors_allowed_origins.is_empty() {
+        return None;
+    }
+    let origins = if config.cors_allowed_origins.iter().any(|o| o == "*") {
+        AllowOrigin::any()
+    } else {
+        AllowOrigin::list(
+            config
+                .cors_allowed_origins
+                .iter()
+                .filter_map(|origin| origin.parse().ok()),
+        )
+    };
+    Some(
+        CorsLayer::new()
+            .allow_origin(origins)
+            .allow_methods(
+                config
+                    .cors_allowed_methods
+                    .iter()
+                    .filter_map(|method| method.parse().ok())
+                    .collect::<Vec<_>>(),
+            )
+            .allow_headers(
+                config
+                    .cors_allowed_headers
+                    .iter()
+                    .filter_map(|header| header.parse().ok())
+                    .collect::<Vec<_>>(),
+            )
+            // Let clients read the id to quote and the 503 backoff
+            .expose_headers([
+                header::HeaderName::from_static("x-request-id"),
+                header::RETRY_AFTER,
+            ]),
+    )
+}
+
+/// Wrap `routes` in the CORS layer when one is configured. Outermost, so
+/// preflight requests are answered before authentication.
+fn with_cors(routes: Router<DbPool>, cors: &Option<CorsLayer>) -> Router<DbPool> {
+    match cors {
+        Some(cors) => routes.layer(cors.clone()),
+        None => routes,
+    }
+}
+
+/// Record each request to `routes` in the internal project when
+/// `SELF_TRACE` is on
+fn with_self_trace(
+    routes: Router<DbPool>,
+    self_trace: Option<api::ingest::SelfTrace>,
+) -> Router<DbPool> {
+    match self_trace {
+        Some(self_trace) => routes.layer(middleware::from_fn_with_state(
+            self_trace,
+            api::ingest::trace_ingest,
+        )),
+        None => routes,
+    }
+}
+
+/// `/ingest/*` routes, with their own body cap since OTLP batches can be much
+/// larger than anything the web UI posts
+fn ingest_routes(pool: DbPool, config: &SharedConfig) -> Router<DbPool> {
+    let max_body_bytes = config.load().max_ingest_body_bytes;
+    Router::new()
+        .route("/deploys", post(api::ingest_deploys))
+        .route("/v1/traces", post(api::ingest_spans))
+        .route("/errors", post(api::ingest_errors))
+        .route("/errors/batch", post(api::ingest_errors_batch))
+        .route("/logs", post(api::ingest_logs))
+        .route("/logs/otlp", post(api::ingest_otlp_logs))
+        .layer(middleware::from_fn(api::ingest::check_schema_version))
+        .layer(middleware::from_fn_with_state(
+            pool.clone(),
+            api::auth_middleware,
+        ))
+        // Authenticated by its signature rather than an API key
+        .route("/deploys/github", post(api::github::ingest_deploy))
+        .layer(middleware::from_fn_with_state(
+            pool,
+            api::ingest::log_ingest,
+        ))
+        .layer(DefaultBodyLimit::max(max_body_bytes))
+        .layer(middleware::map_response_with_state(
+            max_body_bytes,
+            api::ingest::payload_too_large_json,
+        ))
+        .layer(Extension(config.clone()))
+}
+
+/// Sentry store/envelope endpoints, at the paths SDKs derive from a DSN like
+/// `http://<api key>@host:3000/1`. SDKs may gzip their payloads.
+fn sentry_routes(pool: DbPool, config: &SharedConfig) -> Router<DbPool> {
+    let max_body_bytes = config.load().max_ingest_body_bytes;
+    Router::new()
+        .route(
+            "/api/:project_id/envelope/",
+            post(api::sentry::ingest_envelope),
+        )
+        .route("/api/:project_id/store/", post(api::sentry::ingest_store))
+        .layer(DefaultBodyLimit::max(max_body_bytes))
+        .layer(RequestDecompressionLayer::new())
+        .layer(middleware::from_fn_with_state(
+            pool.clone(),
+            api::sentry_auth_middleware,
+        ))
+        .layer(middleware::from_fn_with_state(
+            pool,
+            api::ingest::log_ingest,
+        ))
+        .layer(Extension(config.clone()))
+}
+
+/// Re-read the config on SIGHUP and swap it in for the background jobs
+fn reload_config_on_sighup(config: SharedConfig) {
+    #[cfg(unix)]
+    tokio::spawn(async move {
+        let mut hangup = match signal::unix::signal(signal::unix::SignalKind::hangup()) {
+            Ok(hangup) => hangup,
+            Err(e) => {
+                tracing::warn!("Config reload on SIGHUP unavailable: {}", e);
+                return;
+            }
+        };
+        while hangup.recv().await.is_some() {
+            match Config::from_env() {
+                Ok(new) => {
+                    let changes = Config::reload(&config, new);
+                    if changes.is_empty() {
+                        tracing::info!("Received SIGHUP, config unchanged");
+                    }
+                    for change in changes {
+                        tracing::info!("Config reloaded: {}", change);
+                    }
+                }
+                Err(e) => tracing::error!("Config reload failed, keeping current config: {}", e),
+            }
+        }
+    });
+
+    #[cfg(not(unix))]
+    let _ = config;
+}
+
 async fn shutdown_signal() {
     let ctrl_c = async {
         signal::ctrl_c()
@@ -111,3 +311,600 @@ async fn shutdown_signal() {
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        body::Body,
+        http::{Request, StatusCode, header},
+    };
+    use std::collections::BTreeMap;
+    use tower::util::ServiceExt;
+
+    fn test_config(max_ingest_body_bytes: usize) -> SharedConfig {
+        Arc::new(ArcSwap::from_pointee(Config {
+            max_ingest_body_bytes,
+            ..Config::defaults()
+        }))
+    }
+
+    async fn post_spans(body: String, limit: usize) -> axum::response::Response {
+        post_ingest("/ingest/v1/traces", body, limit, None).await
+    }
+
+    async fn post_ingest(
+        uri: &str,
+        body: String,
+        limit: usize,
+        schema: Option<&str>,
+    ) -> axum::response::Response {
+        let pool = crate::db::test_pool();
+        let (_, api_key) = models::project::create(&pool, "Test").unwrap();
+        let app = Router::new()
+            .nest("/ingest", ingest_routes(pool.clone(), &test_config(limit)))
+            .with_state(pool);
+
+        let mut req = Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
+            .header(header::CONTENT_TYPE, "application/json");
+        if let Some(schema) = schema {
+            req = req.header(api::ingest::SCHEMA_HEADER, schema);
+        }
+        app.oneshot(req.body(Body::from(body)).unwrap())
+            .await
+            .unwrap()
+    }
+
+    /// A valid, empty OTLP request padded with whitespace up to `len`
+    fn padded_body(len: usize) -> String {
+        let mut body = r#"{"resourceSpans": []}"#.to_string();
+        body.push_str(&" ".repeat(len.saturating_sub(body.len())));
+        body
+    }
+
+    async fn json_body(response: axum::response::Response) -> serde_json::Value {
+        let bytes = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        serde_json::from_slice(&bytes).unwrap()
+    }
+
+    fn spans_body(spans: serde_json::Value) -> String {
+        serde_json::json!({"resourceSpans": [{"scopeSpans": [{"spans": spans}]}]}).to_string()
+    }
+
+    fn span(span_id: &str, events: serde_json::Value) -> serde_json::Value {
+        serde_json::json!({
+            "traceId": "q83vEjRWeJCrze8SNFZ4kA==",
+            "spanId": span_id,
+            "name": "GET /",
+            "kind": 2,
+            "startTimeUnixNano": "1700000000000000000",
+            "endTimeUnixNano": "1700000000100000000",
+            "events": events
+        })
+    }
+
+    fn cors_config(vars: &[(&str, &str)]) -> Config {
+        let vars: BTreeMap<&str, &str> = vars.iter().copied().collect();
+        Config::from_lookup(|key| vars.get(key).map(|v| v.to_string())).unwrap()
+    }
+
+    fn preflight(origin: &str) -> Request<Body> {
+        Request::builder()
+            .method("OPTIONS")
+            .uri("/ingest/v1/traces")
+            .header(header::ORIGIN, origin)
+            .header(header::ACCESS_CONTROL_REQUEST_METHOD, "POST")
+            .header(
+                header::ACCESS_CONTROL_REQUEST_HEADERS,
+                "authorization,content-type",
+            )
+            .body(Body::empty())
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_cors_preflight_uses_configured_origins_and_headers() {
+        let pool = crate::db::test_pool();
+        let config = cors_config(&[
+            (
+                "CORS_ALLOWED_ORIGINS",
+                "https://app.example.com/, https://admin.example.com",
+            ),
+            (
+                "CORS_ALLOWED_HEADERS",
+                "authorization,content-type,x-custom",
+            ),
+        ]);
+        let app = Router::new()
+            .nest(
+                "/ingest",
+                with_cors(
+                    ingest_routes(pool.clone(), &test_config(1024)),
+                    &cors_layer(&config),
+                ),
+            )
+            .with_state(pool.clone());
+
+        // Answered before the API key check
+        let response = app
+            .clone()
+            .oneshot(preflight("https://app.example.com"))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let headers = response.headers();
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_ORIGIN],
+            "https://app.example.com"
+        );
+        assert_eq!(headers[header::ACCESS_CONTROL_ALLOW_METHODS], "GET,POST");
+        assert_eq!(
+            headers[header::ACCESS_CONTROL_ALLOW_HEADERS],
+            "authorization,content-type,x-custom"
+        );
+
+        let response = app
+            .oneshot(preflight("https://evil.example.com"))
+            .await
+            .unwrap();
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+
+        // Unconfigured, nothing changes: the preflight hits the auth layer
+        assert!(cors_layer(&cors_config(&[])).is_none());
+        let app = Router::new()
+            .nest(
+                "/ingest",
+                with_cors(ingest_routes(pool.clone(), &test_config(1024)), &None),
+            )
+            .with_state(pool);
+        let response = app
+            .oneshot(preflight("https://app.example.com"))
+            .await
+            .unwrap();
+        assert!(
+            !response
+                .headers()
+                .contains_key(header::ACCESS_CONTROL_ALLOW_ORIGIN)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_oversized_ingest_body_is_413_json() {
+        let response = post_spans(padded_body(4096), 1024).await;
+        assert_eq!(response.status(), StatusCode::PAYLOAD_TOO_LARGE);
+        assert_eq!(response.headers()[header::CONTENT_TYPE], "application/json");
+        assert_eq!(json_body(response).await["max_body_bytes"], 1024);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_body_within_limit_is_accepted() {
+        let response = post_spans(padded_body(1000), 1024).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_spans_reports_counts() {
+        let exception = serde_json::json!([{
+            "name": "exception",
+            "timeUnixNano": "1700000000050000000",
+            "attributes": [
+                {"key": "exception.type", "value": {"stringValue": "RuntimeError"}},
+                {"key": "exception.message", "value": {"stringValue": "boom"}}
+            ]
+        }]);
+        let body = spans_body(serde_json::json!([
+            span("ESIzRFVmd4g=", exception),
+            span("not-a-span-id", serde_json::json!([])),
+        ]));
+
+        let response = post_spans(body, 1024 * 1024).await;
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            json_body(response).await,
+            serde_json::json!({
+                "accepted": 1,
+                "rejected": 1,
+                "errors_extracted": 1,
+                "clock_skew_rejected": 0,
+                "spans_over_cap": 0
+            })
+        );
+    }
+
+    /// Fields of every event emitted while installed, with the enclosing span's name
+    #[derive(Clone, Default)]
+    struct CapturedEvents(Arc<std::sync::Mutex<Vec<BTreeMap<String, String>>>>);
+
+    struct FieldMap(BTreeMap<String, String>);
+
+    impl tracing::field::Visit for FieldMap {
+        fn record_debug(&mut self, field: &tracing::field::Field, value: &dyn std::fmt::Debug) {
+            self.0
+                .insert(field.name().to_string(), format!("{value:?}"));
+        }
+
+        fn record_str(&mut self, field: &tracing::field::Field, value: &str) {
+            self.0.insert(field.name().to_string(), value.to_string());
+        }
+    }
+
+    impl<S> tracing_subscriber::Layer<S> for CapturedEvents
+    where
+        S: tracing::Subscriber + for<'a> tracing_subscriber::registry::LookupSpan<'a>,
+    {
+        fn on_event(
+            &self,
+            event: &tracing::Event<'_>,
+            ctx: tracing_subscriber::layer::Context<'_, S>,
+        ) {
+            let mut fields = FieldMap(BTreeMap::new());
+            event.record(&mut fields);
+            if let Some(span) = ctx.event_span(event) {
+                fields.0.insert("span".to_string(), span.name().to_string());
+            }
+            self.0.lock().unwrap().push(fields.0);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ingest_emits_one_structured_event() {
+        use tracing_subscriber::layer::SubscriberExt;
+
+        let events = CapturedEvents::default();
+        let _guard =
+            tracing::subscriber::set_default(tracing_subscriber::registry().with(events.clone()));
+        let pool = crate::db::test_pool();
+        let (_, api_key) = models::project::create(&pool, "Shop").unwrap();
+        let app = Router::new()
+            .nest(
+                "/ingest",
+                ingest_routes(pool.clone(), &test_config(1024 * 1024)),
+            )
+            .with_state(pool);
+        let body = spans_body(serde_json::json!([span(
+            "ESIzRFVmd4g=",
+            serde_json::json!([])
+        )]));
+        let ingest = |key: &str, request_id: Option<&str>| {
+            let mut request = Request::builder()
+                .method("POST")
+                .uri("/ingest/v1/traces")
+                .header(header::AUTHORIZATION, format!("Bearer {key}"))
+                .header(header::CONTENT_TYPE, "application/json")
+                .header(header::CONTENT_LENGTH, body.len());
+            if let Some(request_id) = request_id {
+                request = request.header("x-request-id", request_id);
+            }
+            request.body(Body::from(body.clone())).unwrap()
+        };
+
+        let response = app
+            .clone()
+            .oneshot(ingest(&api_key, Some("req-42")))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(response.headers()["x-request-id"], "req-42");
+        let response = app.oneshot(ingest("wrong", None)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::UNAUTHORIZED);
+        // Generated when the client didn't send one
+        assert_eq!(response.headers()["x-request-id"].len(), 16);
+
+        let handled: Vec<_> = events
+            .0
+            .lock()
+            .unwrap()
+            .iter()
+            .filter(|e| e["message"] == "Handled ingest request")
+            .cloned()
+            .collect();
+        assert_eq!(handled.len(), 2);
+        let ok = &handled[0];
+        assert_eq!(ok["span"], "ingest");
+        assert_eq!(ok["project"], "shop");
+        assert_eq!(ok["bytes"], body.len().to_string());
+        assert_eq!(ok["accepted"], "1");
+        assert_eq!(ok["status"], "200");
+        assert!(ok["duration_ms"].parse::<f64>().unwrap() >= 0.0);
+        let rejected = &handled[1];
+        assert_eq!(rejected["project"], "-");
+        assert_eq!(rejected["accepted"], "0");
+        assert_eq!(rejected["status"], "401");
+    }
+
+    #[tokio::test]
+    async fn test_exhausted_pool_is_503_with_retry_after() {
+        let pool = crate::db::test_pool_sized(2, std::time::Duration::from_millis(50));
+        let (_, api_key) = models::project::create(&pool, "Test").unwrap();
+        let app = Router::new()
+            .nest(
+                "/ingest",
+                ingest_routes(pool.clone(), &test_config(1024 * 1024)),
+            )
+            .with_state(pool.clone());
+        let ingest = || {
+            Request::builder()
+                .method("POST")
+                .uri("/ingest/v1/traces")
+                .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
+                .header(header::CONTENT_TYPE, "application/json")
+                .body(Body::from(padded_body(0)))
+                .unwrap()
+        };
+
+        let held: Vec<_> = (0..2).map(|_| pool.get().unwrap()).collect();
+        let response = app.clone().oneshot(ingest()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SERVICE_UNAVAILABLE);
+        assert_eq!(response.headers()[header::RETRY_AFTER], "1");
+
+        drop(held);
+        let response = app.oneshot(ingest()).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_accepts_supported_or_missing_schema_version() {
+        let response = post_ingest("/ingest/v1/traces", padded_body(0), 1024, Some("1")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let response = post_ingest("/ingest/v1/traces", padded_body(0), 1024, None).await;
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let line = r#"{"method":"GET","path":"/","status":200,"duration":12.5}"#.to_string();
+        let response = post_ingest("/ingest/logs", line, 1024, Some("1")).await;
+        assert_eq!(response.status(), StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_ingest_rejects_unsupported_schema_version() {
+        for (uri, version) in [
+            ("/ingest/v1/traces", "2"),
+            ("/ingest/logs", "99"),
+            ("/ingest/v1/traces", "latest"),
+        ] {
+            let response = post_ingest(uri, padded_body(0), 1024, Some(version)).await;
+            assert_eq!(
+                response.status(),
+                StatusCode::BAD_REQUEST,
+                "{uri} {version}"
+            );
+            let body = json_body(response).await;
+            assert_eq!(body["version"], version);
+            assert_eq!(body["supported"], serde_json::json!({"min": 1, "max": 1}));
+        }
+    }
+
+    #[tokio::test]
+    async fn test_ingest_spans_all_rejected_is_400() {
+        let body = spans_body(serde_json::json!([span("bad", serde_json::json!([]))]));
+
+        let response = post_spans(body, 1024 * 1024).await;
+        assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        assert_eq!(json_body(response).await["rejected"], 1);
+    }
+
+    #[tokio::test]
+    async fn test_sentry_envelope_is_recorded() {
+        let pool = crate::db::test_pool();
+        let (project, api_key) = models::project::create(&pool, "Test").unwrap();
+        let app = Router::new()
+            .nest(
+                "/api",
+                Router::new().route("/errors", get(api::read::list_errors)),
+            )
+            .merge(sentry_routes(pool.clone(), &test_config(1024 * 1024)))
+            .with_state(pool.clone());
+
+        let event = include_str!("../tests/fixtures/sentry_event.json").replace('\n', "");
+        let envelope = format!(
+            "{{\"dsn\":\"http://{api_key}@localhost/1\"}}\n{{\"type\":\"event\"}}\n{event}\n"
+        );
+        let req = Request::builder()
+            .method("POST")
+            .uri(format!("/api/1/envelope/?sentry_key={api_key}"))
+            .header(header::CONTENT_TYPE, "application/x-sentry-envelope")
+            .body(Body::from(envelope))
+            .unwrap();
+        let response = app.oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            json_body(response).await["id"],
+            "fc6d8c0c43fc4630ad850ee518f1b9d0"
+        );
+
+        let errors = models::error::list(&pool, Some(project.id), None, 10).unwrap();
+        assert_eq!(errors.len(), 1);
+        assert_eq!(errors[0].exception_class, "PaymentFailed");
+    }
+
+    #[tokio::test]
+    async fn test_ingest_logs_reports_counts() {
+        let pool = crate::db::test_pool();
+        let (_, api_key) = models::project::create(&pool, "Test").unwrap();
+        let app = Router::new()
+            .nest(
+                "/ingest",
+                ingest_routes(pool.clone(), &test_config(1024 * 1024)),
+            )
+            .with_state(pool);
+
+        let body = [
+            r#"{"method":"GET","path":"/","controller":"HomeController","action":"index","status":200,"duration":12.1,"view":8.0,"db":2.5}"#,
+            r#"{"method":"GET","path":"/health","status":200}"#,
+            "Started GET / for 127.0.0.1",
+            "",
+        ]
+        .join("\n");
+        let req = Request::builder()
+            .method("POST")
+            .uri("/ingest/logs")
+            .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
+            .body(Body::from(body))
+            .unwrap();
+        let response = app.oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            json_body(response).await,
+            serde_json::json!({"accepted": 1, "rejected": 2})
+        );
+    }
+
+    #[tokio::test]
+    async fn test_ingest_otlp_logs_stores_records_for_the_project() {
+        let pool = crate::db::test_pool();
+        let (project, api_key) = models::project::create(&pool, "Test").unwrap();
+        let app = Router::new()
+            .nest(
+                "/ingest",
+                ingest_routes(pool.clone(), &test_config(1024 * 1024)),
+            )
+            .with_state(pool.clone());
+
+        let body = serde_json::json!({
+            "resourceLogs": [{"scopeLogs": [{"logRecords": [
+                {
+                    "timeUnixNano": "1700000000000000000",
+                    "body": {"stringValue": "hello"},
+                    "traceId": "4bf92f3577b34da6a3ce929d0e0e4736"
+                },
+                {"body": {"stringValue": "no timestamp"}}
+            ]}]}]
+        });
+        let req = Request::builder()
+            .method("POST")
+            .uri("/ingest/logs/otlp")
+            .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body(Body::from(body.to_string()))
+            .unwrap();
+        let response = app.oneshot(req).await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        assert_eq!(
+            json_body(response).await,
+            serde_json::json!({"accepted": 1, "rejected": 1})
+        );
+
+        let project_id: Option<i64> = pool
+            .get()
+            .unwrap()
+            .query_row("SELECT project_id FROM log_records", [], |row| row.get(0))
+            .unwrap();
+        assert_eq!(project_id, Some(project.id));
+    }
+
+    #[tokio::test]
+    async fn test_self_trace_records_ingest_requests_in_the_internal_project() {
+        let pool = crate::db::test_pool();
+        let (shop, api_key) = models::project::create(&pool, "Shop").unwrap();
+        let internal = models::project::ensure_internal_project(&pool).unwrap();
+        let self_trace = api::ingest::SelfTrace {
+            pool: pool.clone(),
+            project_id: internal.id,
+        };
+        let app = Router::new()
+            .nest(
+                "/ingest",
+                with_self_trace(
+                    ingest_routes(pool.clone(), &test_config(1024 * 1024)),
+                    Some(self_trace),
+                ),
+            )
+            .with_state(pool.clone());
+        let body = spans_body(serde_json::json!([span(
+            "ESIzRFVmd4g=",
+            serde_json::json!([])
+        )]));
+
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/ingest/v1/traces")
+                    .header(header::AUTHORIZATION, format!("Bearer {}", api_key))
+                    .header(header::CONTENT_TYPE, "application/json")
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+
+        let conn = pool.get().unwrap();
+        let internal_spans: Vec<(String, String)> = conn
+            .prepare("SELECT name, service_name FROM spans WHERE project_id = ?1")
+            .unwrap()
+            .query_map([internal.id], |row| Ok((row.get(0)?, row.get(1)?)))
+            .unwrap()
+            .collect::<Result<_, _>>()
+            .unwrap();
+        assert_eq!(
+            internal_spans,
+            vec![("POST /ingest/v1/traces".to_string(), "miniapm".to_string())]
+        );
+        let shop_spans: i64 = conn
+            .query_row(
+                "SELECT COUNT(*) FROM spans WHERE project_id = ?1",
+                [shop.id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(shop_spans, 1);
+    }
+
+    #[tokio::test]
+    async fn test_self_trace_skips_unauthenticated_requests() {
+        let pool = crate::db::test_pool();
+        models::project::create(&pool, "Shop").unwrap();
+        let internal = models::project::ensure_internal_project(&pool).unwrap();
+        let self_trace = api::ingest::SelfTrace {
+            pool: pool.clone(),
+            project_id: internal.id,
+        };
+        let app = Router::new()
+            .nest(
+                "/ingest",
+                with_self_trace(
+                    ingest_routes(pool.clone(), &test_config(1024 * 1024)),
+                    Some(self_trace),
+                ),
+            )
+            .with_state(pool.clone());
+
+        for uri in ["/ingest/v1/traces", "/ingest/no-such-route"] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .method("POST")
+                        .uri(uri)
+                        .header(header::AUTHORIZATION, "Bearer wrong")
+                        .header(header::CONTENT_TYPE, "application/json")
+                        .body(Body::from("{}"))
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert!(response.status().is_client_error());
+        }
+
+        let internal_spans: i64 = pool
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT COUNT(*) FROM spans WHERE project_id = ?1",
+                [internal.id],
+                |row| row.get(0),
+            )
+            .unwrap();
+        assert_eq!(internal_spans, 0);
+    }
+}
@@ -1,38 +1,66 @@
 use askama::Template;
 use axum::{
     extract::State,
-    response::{IntoResponse, Redirect},
+    response::{IntoResponse, Redirect, Response},
 };
+use axum_extra::extract::cookie::CookieJar;
 use tower_cookies::Cookies;
 
-use crate::{DbPool, models::project};
+use crate::{
+    DbPool,
+    models::{Role, project},
+};
 
 use super::project_context::{WebProjectContext, get_project_context};
 
 #[derive(Template)]
 #[template(path = "api_key/index.html")]
 pub struct ApiKeyTemplate {
-    pub api_key: String,
+    pub api_key_prefix: String,
+    /// Full key, present only right after it was generated
+    pub new_key: Option<String>,
+    /// Key to put in integration snippets (the new key or a placeholder)
+    pub snippet_key: String,
     pub ctx: WebProjectContext,
 }
 
 pub async fn index(State(pool): State<DbPool>, cookies: Cookies) -> ApiKeyTemplate {
-    let ctx = get_project_context(&pool, &cookies);
+    page(&pool, &cookies, None)
+}
 
-    // Get the default project's API key
-    let api_key = project::ensure_default_project(&pool)
-        .map(|p| p.api_key)
-        .unwrap_or_else(|_| "Error loading API key".to_string());
+/// The API key page, showing `new_key` in full when it was just generated
+fn page(pool: &DbPool, cookies: &Cookies, new_key: Option<String>) -> ApiKeyTemplate {
+    let ctx = get_project_context(pool, cookies);
 
-    ApiKeyTemplate { api_key, ctx }
+    // Keys are stored hashed, so only the prefix of the default project's key is known
+    let api_key_prefix = project::ensure_default_project(pool)
+        .map(|p| p.api_key_prefix)
+        .unwrap_or_else(|_| "Error loading API key".to_string());
+    let snippet_key = new_key
+        .clone()
+        .unwrap_or_else(|| "YOUR_API_KEY".to_string());
+
+    ApiKeyTemplate {
+        api_key_prefix,
+        new_key,
+        snippet_key,
+        ctx,
+    }
 }
 
-pub async fn regenerate(State(pool): State<DbPool>) -> impl IntoResponse {
-    // Get the default project and regenerate its key
-    if let Ok(project) = project::ensure_default_project(&pool) {
-        let _ = project::regenerate_api_key(&pool, project.id);
+pub async fn regenerate(State(pool): State<DbPool>, jar: CookieJar, cookies: Cookies) -> Response {
+    if let Err(resp) = super::auth::require_role(&pool, &jar, Role::Editor) {
+        return resp;
     }
-    Redirect::to("/api-key")
+
+    // Get the default project and regenerate its key. The key is only ever
+    // shown in this response, so it never travels in a cookie or a URL
+    if let Ok(project) = project::ensure_default_project(&pool)
+        && let Ok(new_key) = project::regenerate_api_key(&pool, project.id)
+    {
+        return page(&pool, &cookies, Some(new_key)).into_response();
+    }
+    Redirect::to("/api-key").into_response()
 }
 
 #[cfg(test)]
@@ -60,14 +88,18 @@ mod tests {
                 id INTEGER PRIMARY KEY,
                 name TEXT NOT NULL UNIQUE,
                 slug TEXT NOT NULL UNIQUE,
-                api_key TEXT NOT NULL UNIQUE,
-                created_at TEXT NOT NULL
+                api_key_hash TEXT NOT NULL UNIQUE,
+                api_key_prefix TEXT,
+                created_at TEXT NOT NULL,
+                previous_api_key_hash TEXT,
+                previous_api_key_expires_at TEXT,
+                last_used_at TEXT
             );
             "#,
         )
         .unwrap();
 
-        pool
+        pool.into()
     }
 
     fn create_app(pool: DbPool) -> Router {
@@ -93,14 +125,14 @@ mod tests {
 
         // Verify default project was created
         let project = project::ensure_default_project(&pool).unwrap();
-        assert!(project.api_key.starts_with("proj_"));
+        assert!(project.api_key_prefix.starts_with("proj_"));
     }
 
     #[tokio::test]
     async fn test_api_key_regenerate() {
         let pool = create_test_pool();
         let original_project = project::ensure_default_project(&pool).unwrap();
-        let original_key = original_project.api_key;
+        let original_key = original_project.api_key_prefix;
 
         let app = create_app(pool.clone());
 
@@ -111,11 +143,24 @@ mod tests {
             .unwrap();
 
         let response = app.oneshot(req).await.unwrap();
-        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert_eq!(response.status(), StatusCode::OK);
+        assert!(response.headers().get("set-cookie").is_none());
 
-        // Verify key was regenerated
+        // Verify key was regenerated and shown in the page itself
         let new_project = project::ensure_default_project(&pool).unwrap();
-        assert_ne!(original_key, new_project.api_key);
-        assert!(new_project.api_key.starts_with("proj_"));
+        assert_ne!(original_key, new_project.api_key_prefix);
+        assert!(new_project.api_key_prefix.starts_with("proj_"));
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let html = String::from_utf8(body.to_vec()).unwrap();
+        let shown = regex::Regex::new("proj_[0-9a-f]{48}")
+            .unwrap()
+            .find(&html)
+            .expect("full key in the page")
+            .as_str()
+            .to_string();
+        let found = project::find_by_api_key(&pool, &shown).unwrap().unwrap();
+        assert_eq!(found.id, new_project.id);
     }
 }
@@ -12,7 +12,8 @@ use crate::{DbPool, config::Config, models};
 
 use super::project_context::{WebProjectContext, get_project_context};
 
-const SESSION_COOKIE: &str = "miniapm_session";
+pub const SESSION_COOKIE: &str = "miniapm_session";
+const PENDING_LOGIN_COOKIE: &str = "miniapm_2fa";
 
 // Templates
 
@@ -29,6 +30,15 @@ pub struct ChangePasswordTemplate {
     pub username: String,
 }
 
+#[derive(Template)]
+#[template(path = "auth/members.html")]
+pub struct MembersTemplate {
+    pub members: Vec<models::project_member::ProjectMember>,
+    pub users: Vec<models::User>,
+    pub projects: Vec<models::Project>,
+    pub ctx: WebProjectContext,
+}
+
 #[derive(Template)]
 #[template(path = "auth/users.html")]
 pub struct UsersTemplate {
@@ -40,6 +50,26 @@ pub struct UsersTemplate {
     pub ctx: WebProjectContext,
 }
 
+#[derive(Template)]
+#[template(path = "auth/two_factor.html")]
+pub struct TwoFactorTemplate {
+    pub error: Option<String>,
+}
+
+#[derive(Template)]
+#[template(path = "auth/two_factor_setup.html")]
+pub struct TwoFactorSetupTemplate {
+    pub username: String,
+    pub enabled: bool,
+    /// Hex secret being enrolled (carried through the confirmation form)
+    pub secret: String,
+    pub secret_base32: String,
+    pub provisioning_uri: String,
+    /// Shown once, right after enrollment
+    pub recovery_codes: Vec<String>,
+    pub error: Option<String>,
+}
+
 #[derive(Template)]
 #[template(path = "auth/invite.html")]
 pub struct InviteTemplate {
@@ -55,6 +85,26 @@ pub struct LoginForm {
     pub password: String,
 }
 
+#[derive(Deserialize)]
+pub struct TwoFactorForm {
+    pub code: String,
+}
+
+#[derive(Deserialize)]
+pub struct TwoFactorSetupForm {
+    pub secret: String,
+    pub code: String,
+}
+
+/// Disabling two-factor auth needs the password or a current code
+#[derive(Deserialize)]
+pub struct TwoFactorDisableForm {
+    #[serde(default)]
+    pub password: String,
+    #[serde(default)]
+    pub code: String,
+}
+
 #[derive(Deserialize)]
 pub struct ChangePasswordForm {
     pub current_password: String,
@@ -65,7 +115,7 @@ pub struct ChangePasswordForm {
 #[derive(Deserialize)]
 pub struct CreateUserForm {
     pub username: String,
-    pub is_admin: Option<String>,
+    pub role: Option<String>,
 }
 
 // Helper to get current user from cookies
@@ -76,6 +126,66 @@ pub fn get_current_user(pool: &DbPool, jar: &CookieJar) -> Option<models::User>
         .flatten()
 }
 
+/// Check that the session user has at least `role` before a mutating action.
+/// Requests without a session only get this far in single-user mode (the web
+/// auth middleware redirects them otherwise), so they are allowed.
+#[allow(clippy::result_large_err)]
+pub fn require_role(
+    pool: &DbPool,
+    jar: &CookieJar,
+    role: models::Role,
+) -> Result<Option<models::User>, Response> {
+    match get_current_user(pool, jar) {
+        Some(user) if user.has_role(role) => Ok(Some(user)),
+        Some(_) => Err((
+            StatusCode::FORBIDDEN,
+            format!("{} access required", role.as_str()),
+        )
+            .into_response()),
+        None => Ok(None),
+    }
+}
+
+/// Check that `user` holds at least `role` in the project a looked-up row
+/// (error, trace) belongs to. With projects enabled a non-admin needs a
+/// membership with that role; rows of projects they aren't a member of, and
+/// rows without a project, answer 404 so their existence isn't revealed.
+/// Without projects (or without a session) this is the global role check.
+#[allow(clippy::result_large_err)]
+pub fn require_project_role(
+    pool: &DbPool,
+    user: Option<&models::User>,
+    projects_enabled: bool,
+    project_id: Option<i64>,
+    role: models::Role,
+) -> Result<(), Response> {
+    let Some(user) = user else {
+        return Ok(());
+    };
+    let granted = if !projects_enabled || user.has_role(models::Role::Admin) {
+        Some(user.role)
+    } else {
+        match project_id {
+            Some(project_id) => {
+                models::project_member::role_in(pool, user, project_id).map_err(|e| {
+                    tracing::error!("Failed to look up project membership: {}", e);
+                    StatusCode::INTERNAL_SERVER_ERROR.into_response()
+                })?
+            }
+            None => None,
+        }
+    };
+    match granted {
+        Some(granted) if granted >= role => Ok(()),
+        Some(_) => Err((
+            StatusCode::FORBIDDEN,
+            format!("{} access required", role.as_str()),
+        )
+            .into_response()),
+        None => Err(StatusCode::NOT_FOUND.into_response()),
+    }
+}
+
 // Handlers
 
 pub async fn login_page(State(pool): State<DbPool>, jar: CookieJar) -> Response {
@@ -94,35 +204,31 @@ pub async fn login_submit(
 ) -> Response {
     match models::user::authenticate(&pool, &form.username, &form.password) {
         Ok(Some(user)) => {
-            // Create session
-            match models::user::create_session(&pool, user.id) {
-                Ok(token) => {
-                    let cookie = Cookie::build((SESSION_COOKIE, token))
-                        .path("/")
-                        .http_only(true)
-                        .secure(true)
-                        .same_site(axum_extra::extract::cookie::SameSite::Lax)
-                        .max_age(Duration::days(7))
-                        .build();
-
-                    let jar = jar.add(cookie);
-
-                    // Redirect to change password if required
-                    if user.must_change_password {
-                        (jar, Redirect::to("/auth/change-password")).into_response()
-                    } else {
-                        (jar, Redirect::to("/")).into_response()
-                    }
-                }
-                Err(_) => Html(
-                    LoginTemplate {
-                        error: Some("Failed to create session".to_string()),
+            // Users with TOTP enabled must pass a second step before getting a session
+            if let Ok(Some(_)) = models::totp::secret_for(&pool, user.id) {
+                return match models::totp::create_pending_login(&pool, user.id) {
+                    Ok(token) => {
+                        let cookie = Cookie::build((PENDING_LOGIN_COOKIE, token))
+                            .path("/auth")
+                            .http_only(true)
+                            .secure(true)
+                            .same_site(axum_extra::extract::cookie::SameSite::Strict)
+                            .max_age(Duration::minutes(5))
+                            .build();
+                        (jar.add(cookie), Redirect::to("/auth/2fa")).into_response()
                     }
-                    .render()
-                    .unwrap_or_default(),
-                )
-                .into_response(),
+                    Err(_) => Html(
+                        LoginTemplate {
+                            error: Some("Failed to create session".to_string()),
+                        }
+                        .render()
+                        .unwrap_or_default(),
+                    )
+                    .into_response(),
+                };
             }
+
+            start_session(&pool, jar, &user)
         }
         Ok(None) => Html(
             LoginTemplate {
@@ -143,6 +249,217 @@ pub async fn login_submit(
     }
 }
 
+/// Create a session for an authenticated user and send them on
+fn start_session(pool: &DbPool, jar: CookieJar, user: &models::User) -> Response {
+    match models::user::create_session(pool, user.id) {
+        Ok(token) => {
+            let cookie = Cookie::build((SESSION_COOKIE, token))
+                .path("/")
+                .http_only(true)
+                .secure(true)
+                .same_site(axum_extra::extract::cookie::SameSite::Lax)
+                .max_age(Duration::days(7))
+                .build();
+
+            let jar = jar.add(cookie);
+
+            // Redirect to change password if required
+            if user.must_change_password {
+                (jar, Redirect::to("/auth/change-password")).into_response()
+            } else {
+                (jar, Redirect::to("/")).into_response()
+            }
+        }
+        Err(_) => Html(
+            LoginTemplate {
+                error: Some("Failed to create session".to_string()),
+            }
+            .render()
+            .unwrap_or_default(),
+        )
+        .into_response(),
+    }
+}
+
+pub async fn two_factor_page(State(pool): State<DbPool>, jar: CookieJar) -> Response {
+    let pending = jar.get(PENDING_LOGIN_COOKIE).and_then(|c| {
+        models::totp::pending_login_user(&pool, c.value())
+            .ok()
+            .flatten()
+    });
+    if pending.is_none() {
+        return Redirect::to("/auth/login").into_response();
+    }
+
+    Html(
+        TwoFactorTemplate { error: None }
+            .render()
+            .unwrap_or_default(),
+    )
+    .into_response()
+}
+
+pub async fn two_factor_submit(
+    State(pool): State<DbPool>,
+    jar: CookieJar,
+    Form(form): Form<TwoFactorForm>,
+) -> Response {
+    let Some(token) = jar.get(PENDING_LOGIN_COOKIE).map(|c| c.value().to_string()) else {
+        return Redirect::to("/auth/login").into_response();
+    };
+    let Some(user) = models::totp::pending_login_user(&pool, &token)
+        .ok()
+        .flatten()
+        .and_then(|id| models::user::find(&pool, id).ok().flatten())
+    else {
+        return Redirect::to("/auth/login").into_response();
+    };
+
+    let secret = models::totp::secret_for(&pool, user.id).ok().flatten();
+    let valid = secret
+        .as_deref()
+        .is_some_and(|s| models::totp::verify_code(s, &form.code, models::totp::now_unix()))
+        || models::totp::use_recovery_code(&pool, user.id, &form.code).unwrap_or(false);
+
+    if !valid {
+        // Too many wrong codes sends the user back to enter their password again
+        if !models::totp::record_failed_attempt(&pool, &token).unwrap_or(false) {
+            let jar = jar.remove(Cookie::build(PENDING_LOGIN_COOKIE).path("/auth"));
+            return (
+                jar,
+                Html(
+                    LoginTemplate {
+                        error: Some("Too many invalid codes, please sign in again".to_string()),
+                    }
+                    .render()
+                    .unwrap_or_default(),
+                ),
+            )
+                .into_response();
+        }
+        return Html(
+            TwoFactorTemplate {
+                error: Some("Invalid authentication code".to_string()),
+            }
+            .render()
+            .unwrap_or_default(),
+        )
+        .into_response();
+    }
+
+    let _ = models::totp::delete_pending_login(&pool, &token);
+    let jar = jar.remove(Cookie::build(PENDING_LOGIN_COOKIE).path("/auth"));
+    start_session(&pool, jar, &user)
+}
+
+pub async fn two_factor_setup_page(State(pool): State<DbPool>, jar: CookieJar) -> Response {
+    let Some(user) = get_current_user(&pool, &jar) else {
+        return Redirect::to("/auth/login").into_response();
+    };
+
+    let enabled = models::totp::secret_for(&pool, user.id)
+        .ok()
+        .flatten()
+        .is_some();
+    let secret = models::totp::generate_secret();
+    render_two_factor_setup(&user, enabled, secret, vec![], None)
+}
+
+pub async fn two_factor_setup_submit(
+    State(pool): State<DbPool>,
+    jar: CookieJar,
+    Form(form): Form<TwoFactorSetupForm>,
+) -> Response {
+    let Some(user) = get_current_user(&pool, &jar) else {
+        return Redirect::to("/auth/login").into_response();
+    };
+
+    // The code proves the authenticator app holds this secret before we rely on it
+    if !models::totp::verify_code(&form.secret, &form.code, models::totp::now_unix()) {
+        return render_two_factor_setup(
+            &user,
+            false,
+            form.secret,
+            vec![],
+            Some("Invalid code - check your authenticator app and try again".to_string()),
+        );
+    }
+
+    match models::totp::enable(&pool, user.id, &form.secret) {
+        Ok(recovery_codes) => {
+            render_two_factor_setup(&user, true, form.secret, recovery_codes, None)
+        }
+        Err(_) => render_two_factor_setup(
+            &user,
+            false,
+            form.secret,
+            vec![],
+            Some("Failed to enable two-factor authentication".to_string()),
+        ),
+    }
+}
+
+pub async fn two_factor_disable(
+    State(pool): State<DbPool>,
+    jar: CookieJar,
+    Form(form): Form<TwoFactorDisableForm>,
+) -> Response {
+    let Some(user) = get_current_user(&pool, &jar) else {
+        return Redirect::to("/auth/login").into_response();
+    };
+
+    // A stolen session alone shouldn't be enough to drop the second factor
+    let password_ok = user
+        .password_hash
+        .as_ref()
+        .is_some_and(|h| models::user::verify_password(&form.password, h));
+    let code_ok = models::totp::secret_for(&pool, user.id)
+        .ok()
+        .flatten()
+        .is_some_and(|s| models::totp::verify_code(&s, &form.code, models::totp::now_unix()));
+    if !password_ok && !code_ok {
+        return render_two_factor_setup(
+            &user,
+            true,
+            models::totp::generate_secret(),
+            vec![],
+            Some(
+                "Enter your password or a current code to disable two-factor authentication"
+                    .to_string(),
+            ),
+        );
+    }
+
+    let _ = models::totp::disable(&pool, user.id);
+    Redirect::to("/auth/2fa/setup").into_response()
+}
+
+fn render_two_factor_setup(
+    user: &models::User,
+    enabled: bool,
+    secret: String,
+    recovery_codes: Vec<String>,
+    error: Option<String>,
+) -> Response {
+    let secret_base32 = models::totp::base32_encode(&hex::decode(&secret).unwrap_or_default());
+    let provisioning_uri = models::totp::provisioning_uri(&secret, &user.username);
+
+    Html(
+        TwoFactorSetupTemplate {
+            username: user.username.clone(),
+            enabled,
+            secret,
+            secret_base32,
+            provisioning_uri,
+            recovery_codes,
+            error,
+        }
+        .render()
+        .unwrap_or_default(),
+    )
+    .into_response()
+}
+
 pub async fn logout(State(pool): State<DbPool>, jar: CookieJar) -> Response {
     if let Some(cookie) = jar.get(SESSION_COOKIE) {
         let _ = models::user::delete_session(&pool, cookie.value());
@@ -245,7 +562,7 @@ pub async fn users_page(
         return Redirect::to("/auth/login").into_response();
     };
 
-    if !user.is_admin {
+    if !user.has_role(models::Role::Admin) {
         return (StatusCode::FORBIDDEN, "Admin access required").into_response();
     }
 
@@ -277,7 +594,7 @@ pub async fn create_user(
         return Redirect::to("/auth/login").into_response();
     };
 
-    if !user.is_admin {
+    if !user.has_role(models::Role::Admin) {
         return (StatusCode::FORBIDDEN, "Admin access required").into_response();
     }
 
@@ -300,9 +617,9 @@ pub async fn create_user(
         .into_response();
     }
 
-    let is_admin = form.is_admin.as_deref() == Some("on");
+    let role = models::Role::parse(form.role.as_deref().unwrap_or("viewer"));
 
-    match models::user::create_with_invite(&pool, &form.username, is_admin) {
+    match models::user::create_with_invite(&pool, &form.username, role) {
         Ok(invite_token) => {
             let users = models::user::list_all(&pool).unwrap_or_default();
             let base_url = std::env::var("MINI_APM_URL")
@@ -345,6 +662,74 @@ pub async fn create_user(
     }
 }
 
+#[derive(Deserialize)]
+pub struct MemberForm {
+    pub user_id: i64,
+    pub project_id: i64,
+    pub role: Option<String>,
+}
+
+pub async fn members_page(
+    State(pool): State<DbPool>,
+    jar: CookieJar,
+    cookies: tower_cookies::Cookies,
+) -> Response {
+    let Some(user) = get_current_user(&pool, &jar) else {
+        return Redirect::to("/auth/login").into_response();
+    };
+
+    if !user.has_role(models::Role::Admin) {
+        return (StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    Html(
+        MembersTemplate {
+            members: models::project_member::list_all(&pool).unwrap_or_default(),
+            users: models::user::list_all(&pool).unwrap_or_default(),
+            projects: models::project::list_all(&pool).unwrap_or_default(),
+            ctx: get_project_context(&pool, &cookies),
+        }
+        .render()
+        .unwrap_or_default(),
+    )
+    .into_response()
+}
+
+pub async fn add_member(
+    State(pool): State<DbPool>,
+    jar: CookieJar,
+    Form(form): Form<MemberForm>,
+) -> Response {
+    let Some(user) = get_current_user(&pool, &jar) else {
+        return Redirect::to("/auth/login").into_response();
+    };
+
+    if !user.has_role(models::Role::Admin) {
+        return (StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    let role = models::Role::parse(form.role.as_deref().unwrap_or("viewer"));
+    let _ = models::project_member::add(&pool, form.user_id, form.project_id, role);
+    Redirect::to("/auth/members").into_response()
+}
+
+pub async fn remove_member(
+    State(pool): State<DbPool>,
+    jar: CookieJar,
+    Form(form): Form<MemberForm>,
+) -> Response {
+    let Some(user) = get_current_user(&pool, &jar) else {
+        return Redirect::to("/auth/login").into_response();
+    };
+
+    if !user.has_role(models::Role::Admin) {
+        return (StatusCode::FORBIDDEN, "Admin access required").into_response();
+    }
+
+    let _ = models::project_member::remove(&pool, form.user_id, form.project_id);
+    Redirect::to("/auth/members").into_response()
+}
+
 #[derive(Deserialize)]
 pub struct DeleteUserForm {
     pub user_id: i64,
@@ -360,7 +745,7 @@ pub async fn delete_user(
         return Redirect::to("/auth/login").into_response();
     };
 
-    if !user.is_admin {
+    if !user.has_role(models::Role::Admin) {
         return (StatusCode::FORBIDDEN, "Admin access required").into_response();
     }
 
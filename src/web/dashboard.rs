@@ -1,58 +1,201 @@
 use askama::Template;
-use axum::extract::State;
+use axum::Extension;
+use axum::extract::{Query, State};
 use chrono::{Duration, Utc};
+use serde::Deserialize;
 use tower_cookies::Cookies;
 
 use crate::{
     DbPool,
-    models::{self, deploy::Deploy, span},
+    config::SharedConfig,
+    models::{
+        self,
+        deploy::Deploy,
+        project::{self, DisplayOffset},
+        span, user_pref,
+    },
 };
 
 use super::project_context::{WebProjectContext, get_project_context};
+use super::user_prefs::Prefs;
+
+/// Windows the dashboard offers, with their length in hours
+const RANGES: [(&str, i64); 4] = [("1h", 1), ("24h", 24), ("7d", 24 * 7), ("30d", 24 * 30)];
+const DEFAULT_RANGE: (&str, i64) = ("24h", 24);
 
 #[derive(Template)]
 #[template(path = "dashboard.html")]
 pub struct DashboardTemplate {
-    pub requests_24h: i64,
-    pub errors_24h: i64,
+    /// Window the headline figures cover, one of `RANGES`
+    pub range: String,
+    pub ranges: Vec<&'static str>,
+    pub request_count: i64,
+    pub error_count: i64,
     pub avg_ms: i64,
-    pub p95_ms: i64,
-    pub p99_ms: i64,
+    /// `LATENCY_PERCENTILES`, as (label, ms)
+    pub percentiles: Vec<(String, i64)>,
+    /// Headline figures against the window before
+    pub comparison: span::DashboardComparison,
     pub recent_errors: Vec<models::AppError>,
     pub slow_requests: Vec<span::TraceSummary>,
     pub hourly_stats: Vec<span::TimeSeriesPoint>,
+    /// Offset the hourly charts are bucketed in
+    pub display_offset: DisplayOffset,
+    /// Requests per minute over the last hour
+    pub rpm_last_hour: Vec<span::MinutePoint>,
+    pub current_rpm: i64,
     pub deploys: Vec<Deploy>,
+    pub errors_since_deploy: Option<models::error::ErrorsSinceDeploy>,
     pub ctx: WebProjectContext,
 }
 
-pub async fn index(State(pool): State<DbPool>, cookies: Cookies) -> DashboardTemplate {
+impl DashboardTemplate {
+    pub fn is_range(&self, name: &str) -> bool {
+        self.range == name
+    }
+
+    /// Window the hourly charts cover, never less than a day
+    pub fn chart_range(&self) -> &str {
+        if self.range == "1h" {
+            "24h"
+        } else {
+            &self.range
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DashboardQuery {
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+}
+
+pub async fn index(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    cookies: Cookies,
+    Query(query): Query<DashboardQuery>,
+) -> DashboardTemplate {
     let ctx = get_project_context(&pool, &cookies);
     let project_id = ctx.project_id();
-    let since = (Utc::now() - Duration::hours(24)).to_rfc3339();
+    let config = config.load();
+
+    // Other pages share the saved range; ones the dashboard lacks, like
+    // `all`, show the default here
+    let saved = Prefs::load(&pool, &cookies).resolve(
+        user_pref::TIME_RANGE,
+        query.range.as_deref(),
+        super::time_range::TimeRange::is_preset,
+    );
+    let (range, hours) = RANGES
+        .into_iter()
+        .find(|(name, _)| saved.as_deref() == Some(*name))
+        .unwrap_or(DEFAULT_RANGE);
+    let window = Duration::hours(hours);
+    let since = (Utc::now() - window).to_rfc3339();
+    // Hourly charts show at least a day so short windows still have a shape
+    let chart_hours = hours.max(24);
+    let chart_since = (Utc::now() - Duration::hours(chart_hours)).to_rfc3339();
 
-    let requests_24h = span::count_since(&pool, project_id, &since).unwrap_or(0);
-    let errors_24h = models::error::count_since(&pool, project_id, &since).unwrap_or(0);
-    let latency_stats =
-        span::latency_stats_since(&pool, project_id, &since).unwrap_or(span::LatencyStats {
+    let request_count = span::count_since(&pool, project_id, &since).unwrap_or(0);
+    let error_count = models::error::count_since(&pool, project_id, &since).unwrap_or(0);
+    let requested = config.latency_percentiles.clone();
+    let latency = span::latency_percentiles_between(&pool, project_id, &since, None, &requested)
+        .unwrap_or_else(|_| span::LatencyPercentiles {
             avg_ms: 0,
-            p95_ms: 0,
-            p99_ms: 0,
+            percentiles: requested.iter().map(|&p| (p, 0)).collect(),
         });
+    let comparison = span::compare_periods(&pool, project_id, window).unwrap_or_default();
     let recent_errors = models::error::list(&pool, project_id, Some("open"), 5).unwrap_or_default();
     let slow_requests = span::slow_traces(&pool, project_id, 500.0, 5).unwrap_or_default();
-    let hourly_stats = span::hourly_stats(&pool, project_id, 24).unwrap_or_default();
-    let deploys = models::deploy::list_since(&pool, project_id, &since).unwrap_or_default();
+    let display_offset = project::display_offset(&pool, project_id, config.display_timezone);
+    let error_policy = project::error_policy(&pool, project_id, config.error_status_policy);
+    let hourly_stats =
+        span::hourly_stats(&pool, project_id, chart_hours, display_offset, error_policy)
+            .unwrap_or_default();
+    let rpm_last_hour = span::throughput_per_minute(&pool, project_id, 60).unwrap_or_default();
+    // The current minute is still filling up, so report the last complete one
+    let current_rpm = rpm_last_hour
+        .iter()
+        .rev()
+        .nth(1)
+        .map(|p| p.count)
+        .unwrap_or(0);
+    let deploys = models::deploy::list_since(&pool, project_id, &chart_since).unwrap_or_default();
+    let errors_since_deploy = models::error::errors_since_deploy(&pool, project_id)
+        .ok()
+        .flatten();
 
     DashboardTemplate {
-        requests_24h,
-        errors_24h,
-        avg_ms: latency_stats.avg_ms,
-        p95_ms: latency_stats.p95_ms,
-        p99_ms: latency_stats.p99_ms,
+        range: range.to_string(),
+        ranges: RANGES.iter().map(|(name, _)| *name).collect(),
+        request_count,
+        error_count,
+        avg_ms: latency.avg_ms,
+        percentiles: latency
+            .percentiles
+            .iter()
+            .map(|(p, ms)| (p.label(), *ms))
+            .collect(),
+        comparison,
         recent_errors,
         slow_requests,
         hourly_stats,
+        display_offset,
+        rpm_last_hour,
+        current_rpm,
         deploys,
+        errors_since_deploy,
         ctx,
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, header},
+        routing::get,
+    };
+    use tower::util::ServiceExt;
+
+    async fn dashboard(pool: &DbPool, uri: &str, token: &str) -> String {
+        let app = Router::new()
+            .route("/", get(index))
+            .layer(Extension(crate::config::Config::shared_defaults()))
+            .layer(tower_cookies::CookieManagerLayer::new())
+            .with_state(pool.clone());
+        let request = Request::builder()
+            .uri(uri)
+            .header(header::COOKIE, format!("miniapm_session={}", token))
+            .body(Body::empty())
+            .unwrap();
+        let body = app.oneshot(request).await.unwrap().into_body();
+        let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_dashboard_opens_on_the_saved_range_or_24h() {
+        let pool = crate::db::test_pool();
+        let user =
+            models::user::create(&pool, "viewer", "password123", models::Role::Viewer).unwrap();
+        let token = models::user::create_session(&pool, user).unwrap();
+
+        let html = dashboard(&pool, "/", &token).await;
+        assert!(html.contains(r#"class="active">24h</a>"#));
+        assert!(html.contains("Requests (24h)"));
+
+        dashboard(&pool, "/?range=7d", &token).await;
+        let html = dashboard(&pool, "/", &token).await;
+        assert!(html.contains(r#"class="active">7d</a>"#));
+        assert!(html.contains("Requests (7d)"));
+
+        // `all` is saved from other pages but has no dashboard equivalent
+        user_pref::set(&pool, user, user_pref::TIME_RANGE, "all").unwrap();
+        let html = dashboard(&pool, "/", &token).await;
+        assert!(html.contains("Requests (24h)"));
+    }
+}
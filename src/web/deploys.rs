@@ -1,40 +1,78 @@
 use askama::Template;
-use axum::extract::State;
+use axum::Form;
+use axum::extract::{Extension, Path, Query, State};
 use axum::http::Request;
 use axum::http::header::HOST;
+use axum::response::{IntoResponse, Redirect, Response};
+use axum_extra::extract::cookie::CookieJar;
+use serde::Deserialize;
 use tower_cookies::Cookies;
 
 use crate::{
     DbPool,
+    config::SharedConfig,
     models::{
-        deploy::{self, Deploy},
-        project,
+        self,
+        deploy::{self, Deploy, DeployImpact},
     },
 };
 
 use super::project_context::{WebProjectContext, get_project_context};
 
+const PAGE_SIZE: i64 = 50;
+
 #[derive(Template)]
 #[template(path = "deploys/index.html")]
 pub struct DeploysTemplate {
-    pub deploys: Vec<Deploy>,
-    pub api_key: String,
+    /// Each deploy with its before/after comparison, when one could be computed
+    pub deploys: Vec<(Deploy, Option<DeployImpact>)>,
+    pub env: Option<String>,
+    pub envs: Vec<String>,
+    pub total_count: i64,
+    pub page: i64,
+    pub total_pages: i64,
+    pub impact_window_minutes: i64,
     pub base_url: String,
     pub ctx: WebProjectContext,
 }
 
+#[derive(Deserialize)]
+pub struct DeploysQuery {
+    pub env: Option<String>,
+    pub page: Option<i64>,
+}
+
 pub async fn index<B>(
     State(pool): State<DbPool>,
     cookies: Cookies,
+    Query(query): Query<DeploysQuery>,
     request: Request<B>,
 ) -> DeploysTemplate {
     let ctx = get_project_context(&pool, &cookies);
     let project_id = ctx.project_id();
-    let deploys = deploy::list(&pool, project_id, 50).unwrap_or_default();
+    let env = query.env.filter(|e| !e.is_empty());
+    let page = query.page.unwrap_or(1).max(1);
 
-    let api_key = project::ensure_default_project(&pool)
-        .map(|p| p.api_key)
-        .unwrap_or_else(|_| "YOUR_API_KEY".to_string());
+    let total_count = deploy::count(&pool, project_id, env.as_deref()).unwrap_or(0);
+    let total_pages = (total_count + PAGE_SIZE - 1) / PAGE_SIZE;
+    let deploys = deploy::list_paginated(
+        &pool,
+        project_id,
+        env.as_deref(),
+        PAGE_SIZE,
+        (page - 1) * PAGE_SIZE,
+    )
+    .unwrap_or_default();
+    let ids: Vec<i64> = deploys.iter().map(|d| d.id).collect();
+    let mut impacts = deploy::impacts(&pool, project_id, &ids).unwrap_or_default();
+    let deploys = deploys
+        .into_iter()
+        .map(|d| {
+            let impact = impacts.remove(&d.id);
+            (d, impact)
+        })
+        .collect();
+    let envs = deploy::distinct_envs(&pool, project_id).unwrap_or_default();
 
     // Extract base URL from request
     let host = request
@@ -53,8 +91,158 @@ pub async fn index<B>(
 
     DeploysTemplate {
         deploys,
-        api_key,
+        env,
+        envs,
+        total_count,
+        page,
+        total_pages,
+        impact_window_minutes: deploy::IMPACT_WINDOW_MINUTES,
         base_url,
         ctx,
     }
 }
+
+#[derive(Deserialize)]
+pub struct AnnotateForm {
+    /// Checkbox: present when the deploy was rolled back
+    pub rollback: Option<String>,
+    pub notes: Option<String>,
+}
+
+pub async fn annotate(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Path(id): Path<i64>,
+    Form(form): Form<AnnotateForm>,
+) -> Response {
+    let user = super::auth::get_current_user(&pool, &jar);
+    let project_id = deploy::project_of(&pool, id).ok().flatten().flatten();
+    if let Err(resp) = super::auth::require_project_role(
+        &pool,
+        user.as_ref(),
+        config.load().enable_projects,
+        project_id,
+        models::Role::Editor,
+    ) {
+        return resp;
+    }
+    let _ = deploy::update_deploy(&pool, id, form.rollback.is_some(), form.notes);
+    Redirect::to("/deploys").into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        body::Body,
+        http::{StatusCode, header},
+        routing::post,
+    };
+    use tower::util::ServiceExt;
+
+    #[tokio::test]
+    async fn test_annotated_deploy_renders_rollback_and_note() {
+        let pool = crate::db::test_pool();
+        let id = deploy::insert(
+            &pool,
+            &deploy::IncomingDeploy {
+                git_sha: "abc1234".to_string(),
+                version: Some("v1.2.0".to_string()),
+                env: None,
+                description: None,
+                deployer: None,
+                timestamp: None,
+            },
+            None,
+        )
+        .unwrap();
+        let editor =
+            models::user::create(&pool, "editor", "password123", models::Role::Editor).unwrap();
+        let token = models::user::create_session(&pool, editor).unwrap();
+
+        let response = Router::new()
+            .route("/deploys/:id/annotate", post(annotate))
+            .layer(Extension(crate::config::Config::shared_defaults()))
+            .with_state(pool.clone())
+            .oneshot(
+                axum::http::Request::builder()
+                    .method("POST")
+                    .uri(format!("/deploys/{}/annotate", id))
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .header(header::COOKIE, format!("miniapm_session={}", token))
+                    .body(Body::from("rollback=1&notes=Checkout+500s"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let deploys = deploy::list_paginated(&pool, None, None, 10, 0).unwrap();
+        let html = DeploysTemplate {
+            deploys: deploys.into_iter().map(|d| (d, None)).collect(),
+            env: None,
+            envs: vec![],
+            total_count: 1,
+            page: 1,
+            total_pages: 1,
+            impact_window_minutes: deploy::IMPACT_WINDOW_MINUTES,
+            base_url: "http://localhost:3000".to_string(),
+            ctx: WebProjectContext {
+                current_project: None,
+                projects: vec![],
+                projects_enabled: false,
+                restricted: false,
+            },
+        }
+        .render()
+        .unwrap();
+        assert!(html.contains(r#"<span class="badge badge-rolled-back">Rolled back</span>"#));
+        assert!(html.contains(r#"value="Checkout 500s""#));
+        assert!(html.contains("checked"));
+    }
+
+    #[tokio::test]
+    async fn test_annotate_requires_editing_the_deploys_project() {
+        let pool = crate::db::test_pool();
+        let (acme, _) = models::project::create(&pool, "Acme").unwrap();
+        let id = deploy::insert(
+            &pool,
+            &deploy::IncomingDeploy {
+                git_sha: "abc1234".to_string(),
+                version: None,
+                env: None,
+                description: None,
+                deployer: None,
+                timestamp: None,
+            },
+            Some(acme.id),
+        )
+        .unwrap();
+        let editor =
+            models::user::create(&pool, "editor", "password123", models::Role::Editor).unwrap();
+        let token = models::user::create_session(&pool, editor).unwrap();
+
+        let response = Router::new()
+            .route("/deploys/:id/annotate", post(annotate))
+            .layer(Extension(crate::config::Config::shared_with_projects()))
+            .with_state(pool.clone())
+            .oneshot(
+                axum::http::Request::builder()
+                    .method("POST")
+                    .uri(format!("/deploys/{}/annotate", id))
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .header(header::COOKIE, format!("miniapm_session={}", token))
+                    .body(Body::from("rollback=1&notes=Not+mine"))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+
+        let deploys = deploy::list_paginated(&pool, None, None, 10, 0).unwrap();
+        assert!(!deploys[0].rolled_back);
+        assert_eq!(deploys[0].notes, None);
+    }
+}
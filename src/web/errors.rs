@@ -1,17 +1,24 @@
 use askama::Template;
-use axum::Form;
 use axum::extract::{Path, Query, State};
-use axum::response::Redirect;
-use chrono::{Duration, Utc};
+use axum::response::{IntoResponse, Redirect, Response};
+use axum::{Extension, Form};
+use axum_extra::extract::cookie::CookieJar;
+use chrono::{DateTime, Duration, Utc};
 use serde::Deserialize;
+use std::collections::HashMap;
 use tower_cookies::Cookies;
 
-use crate::{DbPool, models};
+use crate::{DbPool, config::SharedConfig, models, models::user_pref};
 
-use super::project_context::{WebProjectContext, get_project_context};
+use super::project_context::{WebProjectContext, current_user, get_project_context};
+use super::time_range::TimeRange;
+use super::user_prefs::Prefs;
 
 const PAGE_SIZE: i64 = 50;
 
+/// Orders the errors list offers, the first being the default
+const SORTS: [&str; 3] = ["last_seen", "first_seen", "count"];
+
 #[derive(Template)]
 #[template(path = "errors/index.html")]
 pub struct ErrorsIndexTemplate {
@@ -19,11 +26,19 @@ pub struct ErrorsIndexTemplate {
     pub total_count: i64,
     pub status: Option<String>,
     pub search: Option<String>,
-    pub period: String,
+    pub range: TimeRange,
     pub sort: String,
     pub page: i64,
     pub total_pages: i64,
+    /// Whether this is a later keyset page of the default order
+    pub paged: bool,
+    /// `?after=` value for the next keyset page
+    pub next_cursor: Option<String>,
     pub hourly_errors: Vec<models::error::ErrorTrendPoint>,
+    /// Offset the hourly chart is bucketed in
+    pub display_offset: models::project::DisplayOffset,
+    /// Endpoints raising the most errors in the selected period
+    pub endpoints: Vec<models::error::EndpointErrors>,
     pub ctx: WebProjectContext,
 }
 
@@ -31,73 +46,137 @@ pub struct ErrorsIndexTemplate {
 pub struct ErrorsQuery {
     pub status: Option<String>,
     pub search: Option<String>,
-    pub period: Option<String>,
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
     pub sort: Option<String>,
     pub page: Option<i64>,
+    /// Keyset cursor, used instead of `page` for the default order
+    pub after: Option<String>,
 }
 
 pub async fn index(
     State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
     cookies: Cookies,
     Query(query): Query<ErrorsQuery>,
-) -> ErrorsIndexTemplate {
+) -> Response {
+    // Error cursors carry a numeric id; anything else wasn't produced here
+    let after = match query.after.as_deref().filter(|s| !s.is_empty()) {
+        Some(value) => {
+            match models::Cursor::decode(value).filter(|c| c.id.parse::<i64>().is_ok()) {
+                Some(cursor) => Some(cursor),
+                None => {
+                    return (axum::http::StatusCode::BAD_REQUEST, "Invalid cursor").into_response();
+                }
+            }
+        }
+        None => None,
+    };
     let ctx = get_project_context(&pool, &cookies);
     let project_id = ctx.project_id();
 
-    let period = query.period.unwrap_or_else(|| "all".to_string());
-    let sort = query.sort.unwrap_or_else(|| "last_seen".to_string());
+    let prefs = Prefs::load(&pool, &cookies);
+    let range = TimeRange::parse(
+        prefs
+            .resolve(
+                user_pref::TIME_RANGE,
+                query.range.as_deref(),
+                TimeRange::is_preset,
+            )
+            .as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        "all",
+    );
+    let sort = prefs
+        .resolve(user_pref::ERRORS_SORT, query.sort.as_deref(), |s| {
+            SORTS.contains(&s)
+        })
+        .unwrap_or_else(|| SORTS[0].to_string());
     let search = query.search.clone().filter(|s| !s.is_empty());
     let page = query.page.unwrap_or(1).max(1);
 
-    let since = match period.as_str() {
-        "1h" => Some(Utc::now() - Duration::hours(1)),
-        "24h" => Some(Utc::now() - Duration::hours(24)),
-        "7d" => Some(Utc::now() - Duration::days(7)),
-        "30d" => Some(Utc::now() - Duration::days(30)),
-        _ => None, // "all"
-    };
-
-    let since_str = since.map(|s| s.to_rfc3339());
+    let since_str = range.since_str();
+    let until_str = range.until_str();
 
-    let total_count = models::error::count_filtered(
-        &pool,
-        project_id,
-        query.status.as_deref(),
-        search.as_deref(),
-        since_str.as_deref(),
-    )
-    .unwrap_or(0);
+    let count = || {
+        models::error::count_filtered(
+            &pool,
+            project_id,
+            query.status.as_deref(),
+            search.as_deref(),
+            since_str.as_deref(),
+            until_str.as_deref(),
+        )
+    };
 
+    // The default order pages by cursor so recurring errors don't shift pages
+    let (errors, total_count, next_cursor) = if sort == "last_seen" {
+        let total_count = count().unwrap_or(0);
+        let (errors, next_cursor) = models::error::list_after(
+            &pool,
+            project_id,
+            query.status.as_deref(),
+            search.as_deref(),
+            since_str.as_deref(),
+            until_str.as_deref(),
+            after.as_ref(),
+            PAGE_SIZE,
+        )
+        .unwrap_or_default();
+        (errors, total_count, next_cursor)
+    } else {
+        let offset = (page - 1) * PAGE_SIZE;
+        let page = models::Page::fetch(PAGE_SIZE, offset, count, |limit, offset| {
+            models::error::list_paginated(
+                &pool,
+                project_id,
+                query.status.as_deref(),
+                search.as_deref(),
+                since_str.as_deref(),
+                until_str.as_deref(),
+                &sort,
+                limit,
+                offset,
+            )
+        })
+        .unwrap_or_else(|_| models::Page::empty(PAGE_SIZE, offset));
+        (page.items, page.total, None)
+    };
     let total_pages = (total_count + PAGE_SIZE - 1) / PAGE_SIZE;
-    let offset = (page - 1) * PAGE_SIZE;
 
-    let errors = models::error::list_paginated(
+    let display_offset =
+        models::project::display_offset(&pool, project_id, config.load().display_timezone);
+    let hourly_errors = models::error::hourly_error_stats(&pool, project_id, 24, display_offset)
+        .unwrap_or_default();
+    let endpoints = models::error::errors_by_endpoint(
         &pool,
         project_id,
-        query.status.as_deref(),
-        search.as_deref(),
         since_str.as_deref(),
-        &sort,
-        PAGE_SIZE,
-        offset,
+        until_str.as_deref(),
+        10,
     )
     .unwrap_or_default();
 
-    let hourly_errors =
-        models::error::hourly_error_stats(&pool, project_id, 24).unwrap_or_default();
-
     ErrorsIndexTemplate {
         errors,
         total_count,
         status: query.status,
         search,
-        period,
+        range,
         sort,
         page,
         total_pages,
+        paged: after.is_some(),
+        next_cursor: next_cursor.map(|c| c.encode()),
         hourly_errors,
+        display_offset,
+        endpoints,
         ctx,
     }
+    .into_response()
 }
 
 #[derive(Template)]
@@ -105,15 +184,89 @@ pub async fn index(
 pub struct ErrorShowTemplate {
     pub error: Option<models::AppError>,
     pub occurrences: Vec<models::ErrorOccurrence>,
+    /// Distinct users across all occurrences
+    pub affected_user_count: i64,
     pub trend_24h: Vec<i64>,
+    pub releases: Vec<models::error::ErrorRelease>,
+    /// Deploy matching the first release this error was seen in
+    pub regressed_in: Option<models::Deploy>,
+    /// Stored traces of the occurrences, by occurrence id
+    pub trace_links: HashMap<i64, TraceLink>,
+    /// Param keys found on the occurrences, most common first
+    pub param_keys: Vec<String>,
+    /// Top values of the selected param keys
+    pub param_breakdowns: Vec<models::error::ParamBreakdown>,
     pub ctx: WebProjectContext,
 }
 
+impl ErrorShowTemplate {
+    pub fn trace_link(&self, occurrence: &models::ErrorOccurrence) -> Option<&TraceLink> {
+        self.trace_links.get(&occurrence.id)
+    }
+}
+
+/// Trace an occurrence's `request_id` resolved to
+#[derive(Debug, Clone, PartialEq)]
+pub struct TraceLink {
+    pub trace_id: String,
+    /// Id and name of the span whose exception event matches the occurrence
+    pub span: Option<(String, String)>,
+}
+
+impl TraceLink {
+    pub fn href(&self) -> String {
+        match &self.span {
+            Some((span_id, _)) => format!("/traces/{}#span-{}", self.trace_id, span_id),
+            None => format!("/traces/{}", self.trace_id),
+        }
+    }
+}
+
+/// Look up the trace an occurrence's `request_id` names. SDKs may report a
+/// request id that isn't a trace id, or the trace may be sampled out or
+/// expired; those occurrences get no link.
+fn trace_link(pool: &DbPool, occurrence: &models::ErrorOccurrence) -> Option<TraceLink> {
+    let request_id = occurrence.request_id.as_deref()?;
+    // Only the span ids and names are used, so nothing needs masking
+    let trace = models::span::get_trace(pool, request_id, &[])
+        .ok()
+        .flatten()?;
+    let span = DateTime::parse_from_rfc3339(&occurrence.occurred_at)
+        .ok()
+        .and_then(|at| trace.exception_span(at.with_timezone(&Utc)))
+        .map(|span| (span.span_id.clone(), span.name.clone()));
+    Some(TraceLink {
+        trace_id: trace.trace_id,
+        span,
+    })
+}
+
+/// Param keys offered on the error page
+const PARAM_KEY_LIMIT: i64 = 20;
+
+/// Keys broken down when none are picked
+const DEFAULT_PARAM_KEYS: usize = 3;
+
+/// Values listed per param key
+const PARAM_VALUE_LIMIT: i64 = 5;
+
+#[derive(Deserialize)]
+pub struct ShowQuery {
+    /// Comma-separated param keys to break down
+    pub params: Option<String>,
+}
+
 pub async fn show(
     State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
     cookies: Cookies,
     Path(id): Path<i64>,
-) -> ErrorShowTemplate {
+    Query(query): Query<ShowQuery>,
+) -> Response {
+    let user = current_user(&pool, &cookies);
+    if let Err(resp) = require_error_role(&pool, &config, user.as_ref(), id, models::Role::Viewer) {
+        return resp;
+    }
     let ctx = get_project_context(&pool, &cookies);
     let error = models::error::find(&pool, id).unwrap_or(None);
     let occurrences = if error.is_some() {
@@ -121,30 +274,554 @@ pub async fn show(
     } else {
         vec![]
     };
+    let affected_user_count = models::error::affected_users(&pool, id).unwrap_or(0);
     let trend_24h = models::error::error_trend_24h(&pool, id).unwrap_or_default();
+    let releases = models::error::error_releases(&pool, id).unwrap_or_default();
+    let regressed_in = error.as_ref().and_then(|e| {
+        e.first_seen_release.as_deref().and_then(|sha| {
+            models::deploy::find_by_sha(&pool, ctx.project_id(), sha)
+                .ok()
+                .flatten()
+        })
+    });
+    let trace_links = occurrences
+        .iter()
+        .filter_map(|occ| trace_link(&pool, occ).map(|link| (occ.id, link)))
+        .collect();
+    let param_keys = models::error::param_keys(&pool, id, PARAM_KEY_LIMIT).unwrap_or_default();
+    let selected: Vec<String> = match query.params.as_deref() {
+        Some(params) => params
+            .split(',')
+            .map(str::trim)
+            .filter(|k| !k.is_empty())
+            .map(String::from)
+            .collect(),
+        None => param_keys
+            .iter()
+            .take(DEFAULT_PARAM_KEYS)
+            .cloned()
+            .collect(),
+    };
+    let param_breakdowns =
+        models::error::param_breakdown(&pool, id, &selected, PARAM_VALUE_LIMIT).unwrap_or_default();
 
     ErrorShowTemplate {
         error,
         occurrences,
+        affected_user_count,
         trend_24h,
+        releases,
+        regressed_in,
+        trace_links,
+        param_keys,
+        param_breakdowns,
         ctx,
     }
+    .into_response()
+}
+
+/// Require `role` in the project of error `id`, see
+/// [`super::auth::require_project_role`]
+#[allow(clippy::result_large_err)]
+fn require_error_role(
+    pool: &DbPool,
+    config: &SharedConfig,
+    user: Option<&models::User>,
+    id: i64,
+    role: models::Role,
+) -> Result<(), Response> {
+    let project_id = models::error::project_of(pool, id).ok().flatten().flatten();
+    super::auth::require_project_role(pool, user, config.load().enable_projects, project_id, role)
 }
 
 #[derive(Deserialize)]
 pub struct UpdateStatusForm {
     pub status: String,
+    /// Hours to keep an ignored error muted (empty = forever)
+    pub mute_hours: Option<String>,
+    /// Occurrences per hour that reopen an ignored error (empty = never)
+    pub reopen_threshold: Option<String>,
 }
 
 pub async fn update_status(
     State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
     Path(id): Path<i64>,
     Form(form): Form<UpdateStatusForm>,
-) -> Redirect {
+) -> Response {
+    let user = super::auth::get_current_user(&pool, &jar);
+    if let Err(resp) = require_error_role(&pool, &config, user.as_ref(), id, models::Role::Editor) {
+        return resp;
+    }
+
     // Validate status
     let valid_statuses = ["open", "resolved", "ignored"];
-    if valid_statuses.contains(&form.status.as_str()) {
+    if form.status == "ignored" {
+        let muted_until = form
+            .mute_hours
+            .as_deref()
+            .and_then(|h| h.parse::<i64>().ok())
+            .filter(|&h| h > 0)
+            .map(|h| (Utc::now() + Duration::hours(h)).to_rfc3339());
+        let threshold = form
+            .reopen_threshold
+            .as_deref()
+            .and_then(|t| t.parse::<i64>().ok())
+            .filter(|&t| t > 0);
+        let _ = models::error::mute(&pool, id, muted_until.as_deref(), threshold);
+    } else if valid_statuses.contains(&form.status.as_str()) {
         let _ = models::error::update_status(&pool, id, &form.status);
     }
-    Redirect::to(&format!("/errors/{}", id))
+    Redirect::to(&format!("/errors/{}", id)).into_response()
+}
+
+/// `POST /errors/bulk-status` with `status` and one `ids` field per checked
+/// error. Ignoring in bulk mutes forever; per-error options stay on the detail
+/// page.
+pub async fn update_status_bulk(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Form(fields): Form<Vec<(String, String)>>,
+) -> Response {
+    let user = super::auth::get_current_user(&pool, &jar);
+    let valid_statuses = ["open", "resolved", "ignored"];
+    let status = fields
+        .iter()
+        .find(|(key, _)| key == "status")
+        .map(|(_, value)| value.as_str());
+    let ids: Vec<i64> = fields
+        .iter()
+        .filter(|(key, _)| key == "ids")
+        .filter_map(|(_, value)| value.parse().ok())
+        .collect();
+    // One error outside the user's projects rejects the whole batch
+    for &id in &ids {
+        if let Err(resp) =
+            require_error_role(&pool, &config, user.as_ref(), id, models::Role::Editor)
+        {
+            return resp;
+        }
+    }
+    if let Some(status) = status.filter(|s| valid_statuses.contains(s))
+        && !ids.is_empty()
+    {
+        match models::error::update_status_bulk(&pool, &ids, status) {
+            Ok(updated) => tracing::info!("Marked {} errors {}", updated, status),
+            Err(e) => tracing::error!("Failed to update error statuses: {}", e),
+        }
+    }
+    Redirect::to("/errors").into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, StatusCode, header},
+        routing::post,
+    };
+    use tower::util::ServiceExt;
+
+    fn create_app(pool: DbPool) -> Router {
+        Router::new()
+            .route("/errors/:id/status", post(update_status))
+            .layer(Extension(crate::config::Config::shared_defaults()))
+            .with_state(pool)
+    }
+
+    fn status_request(token: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/errors/1/status")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(header::COOKIE, format!("miniapm_session={}", token))
+            .body(Body::from("status=resolved"))
+            .unwrap()
+    }
+
+    async fn errors_page(pool: &DbPool, uri: &str, token: Option<&str>) -> String {
+        let app = Router::new()
+            .route("/errors", axum::routing::get(index))
+            .layer(Extension(crate::config::Config::shared_defaults()))
+            .layer(tower_cookies::CookieManagerLayer::new())
+            .with_state(pool.clone());
+        let mut request = Request::builder().uri(uri);
+        if let Some(token) = token {
+            request = request.header(header::COOKIE, format!("miniapm_session={}", token));
+        }
+        let response = app
+            .oneshot(request.body(Body::empty()).unwrap())
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        String::from_utf8(body.to_vec()).unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_invalid_cursor_is_rejected() {
+        let pool = crate::db::test_pool();
+        let app = Router::new()
+            .route("/errors", axum::routing::get(index))
+            .layer(Extension(crate::config::Config::shared_defaults()))
+            .layer(tower_cookies::CookieManagerLayer::new())
+            .with_state(pool);
+        let cursor = models::Cursor {
+            happened_at: "2026-01-01T10:00:00Z".to_string(),
+            id: "abc".to_string(),
+        };
+
+        for after in [cursor.encode(), "not a cursor".to_string()] {
+            let response = app
+                .clone()
+                .oneshot(
+                    Request::builder()
+                        .uri(format!("/errors?after={}", after.replace(' ', "%20")))
+                        .body(Body::empty())
+                        .unwrap(),
+                )
+                .await
+                .unwrap();
+            assert_eq!(response.status(), StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_chosen_range_and_sort_stick_for_the_user() {
+        let pool = crate::db::test_pool();
+        let user =
+            models::user::create(&pool, "viewer", "password123", models::Role::Viewer).unwrap();
+        let token = models::user::create_session(&pool, user).unwrap();
+
+        // Nothing saved yet: the page defaults
+        // Status and period both have an "All" filter
+        let html = errors_page(&pool, "/errors", Some(&token)).await;
+        assert_eq!(html.matches(r#"class="active">All</a>"#).count(), 2);
+        assert!(html.contains(r#"class="active">Last Seen</a>"#));
+
+        errors_page(&pool, "/errors?range=7d&sort=count", Some(&token)).await;
+        let html = errors_page(&pool, "/errors", Some(&token)).await;
+        assert!(html.contains(r#"class="active">7d</a>"#));
+        assert_eq!(html.matches(r#"class="active">All</a>"#).count(), 1);
+        assert!(html.contains(r#"class="active">Count</a>"#));
+
+        // Unknown sorts and custom ranges apply once but aren't saved
+        errors_page(
+            &pool,
+            "/errors?range=custom&from=2026-01-01&sort=bogus",
+            Some(&token),
+        )
+        .await;
+        assert_eq!(
+            models::user_pref::get(&pool, user, user_pref::TIME_RANGE)
+                .unwrap()
+                .as_deref(),
+            Some("7d")
+        );
+        assert_eq!(
+            models::user_pref::get(&pool, user, user_pref::ERRORS_SORT)
+                .unwrap()
+                .as_deref(),
+            Some("count")
+        );
+
+        // Without a session there's no one to remember for
+        let html = errors_page(&pool, "/errors", None).await;
+        assert_eq!(html.matches(r#"class="active">All</a>"#).count(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_viewer_cannot_change_error_status() {
+        let pool = crate::db::test_pool();
+        let viewer =
+            models::user::create(&pool, "viewer", "password123", models::Role::Viewer).unwrap();
+        let editor =
+            models::user::create(&pool, "editor", "password123", models::Role::Editor).unwrap();
+        let viewer_token = models::user::create_session(&pool, viewer).unwrap();
+        let editor_token = models::user::create_session(&pool, editor).unwrap();
+
+        let response = create_app(pool.clone())
+            .oneshot(status_request(&viewer_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        let response = create_app(pool)
+            .oneshot(status_request(&editor_token))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+
+    #[tokio::test]
+    async fn test_bulk_status_update_resolves_checked_errors() {
+        let pool = crate::db::test_pool();
+        let editor =
+            models::user::create(&pool, "editor", "password123", models::Role::Editor).unwrap();
+        let token = models::user::create_session(&pool, editor).unwrap();
+        let ids: Vec<i64> = ["a", "b", "c"]
+            .iter()
+            .map(|fp| {
+                models::error::insert(
+                    &pool,
+                    &models::error::IncomingError {
+                        exception_class: format!("Error{}", fp),
+                        message: "boom".to_string(),
+                        backtrace: vec![],
+                        fingerprint: fp.to_string(),
+                        request_id: None,
+                        user_id: None,
+                        params: None,
+                        timestamp: None,
+                        source_context: None,
+                        git_sha: None,
+                    },
+                    None,
+                )
+                .unwrap()
+            })
+            .collect();
+
+        let app = Router::new()
+            .route("/errors/bulk-status", post(update_status_bulk))
+            .layer(Extension(crate::config::Config::shared_defaults()))
+            .with_state(pool.clone());
+        let response = app
+            .oneshot(
+                Request::builder()
+                    .method("POST")
+                    .uri("/errors/bulk-status")
+                    .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                    .header(header::COOKIE, format!("miniapm_session={}", token))
+                    .body(Body::from(format!(
+                        "ids={}&ids={}&status=resolved",
+                        ids[0], ids[2]
+                    )))
+                    .unwrap(),
+            )
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+
+        let statuses: Vec<String> = ids
+            .iter()
+            .map(|&id| models::error::find(&pool, id).unwrap().unwrap().status)
+            .collect();
+        assert_eq!(statuses, ["resolved", "open", "resolved"]);
+    }
+
+    /// An error in the "Acme" project, and a session for an editor who is
+    /// only a member of another; returns the project and error ids and token
+    fn foreign_error(pool: &DbPool) -> (i64, i64, String) {
+        let (acme, _) = models::project::create(pool, "Acme").unwrap();
+        let (globex, _) = models::project::create(pool, "Globex").unwrap();
+        let error_id = models::error::insert(
+            pool,
+            &models::error::IncomingError {
+                exception_class: "NoMethodError".to_string(),
+                message: "undefined method".to_string(),
+                backtrace: vec![],
+                fingerprint: "acme-only".to_string(),
+                request_id: None,
+                user_id: None,
+                params: None,
+                timestamp: None,
+                source_context: None,
+                git_sha: None,
+            },
+            Some(acme.id),
+        )
+        .unwrap();
+        let outsider =
+            models::user::create(pool, "outsider", "password123", models::Role::Editor).unwrap();
+        models::project_member::add(pool, outsider, globex.id, models::Role::Editor).unwrap();
+        let token = models::user::create_session(pool, outsider).unwrap();
+        (acme.id, error_id, token)
+    }
+
+    async fn send_with_projects(pool: &DbPool, request: Request<Body>) -> StatusCode {
+        let app = Router::new()
+            .route("/errors/:id", axum::routing::get(show))
+            .route("/errors/:id/status", post(update_status))
+            .route("/errors/bulk-status", post(update_status_bulk))
+            .layer(Extension(crate::config::Config::shared_with_projects()))
+            .layer(tower_cookies::CookieManagerLayer::new())
+            .with_state(pool.clone());
+        app.oneshot(request).await.unwrap().status()
+    }
+
+    fn form_request(uri: &str, token: &str, body: String) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri(uri)
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(header::COOKIE, format!("miniapm_session={}", token))
+            .body(Body::from(body))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_non_member_cannot_see_error() {
+        let pool = crate::db::test_pool();
+        let (_, error_id, token) = foreign_error(&pool);
+        let request = Request::builder()
+            .uri(format!("/errors/{}", error_id))
+            .header(header::COOKIE, format!("miniapm_session={}", token))
+            .body(Body::empty())
+            .unwrap();
+        assert_eq!(
+            send_with_projects(&pool, request).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_member_cannot_change_error_status() {
+        let pool = crate::db::test_pool();
+        let (_, error_id, token) = foreign_error(&pool);
+        let request = form_request(
+            &format!("/errors/{}/status", error_id),
+            &token,
+            "status=resolved".to_string(),
+        );
+        assert_eq!(
+            send_with_projects(&pool, request).await,
+            StatusCode::NOT_FOUND
+        );
+        let error = models::error::find(&pool, error_id).unwrap().unwrap();
+        assert_eq!(error.status, "open");
+    }
+
+    #[tokio::test]
+    async fn test_non_member_cannot_bulk_change_error_status() {
+        let pool = crate::db::test_pool();
+        let (_, error_id, token) = foreign_error(&pool);
+        let request = form_request(
+            "/errors/bulk-status",
+            &token,
+            format!("ids={}&status=resolved", error_id),
+        );
+        assert_eq!(
+            send_with_projects(&pool, request).await,
+            StatusCode::NOT_FOUND
+        );
+        let error = models::error::find(&pool, error_id).unwrap().unwrap();
+        assert_eq!(error.status, "open");
+    }
+
+    #[tokio::test]
+    async fn test_project_role_decides_who_may_change_error_status() {
+        let pool = crate::db::test_pool();
+        let (acme, error_id, _) = foreign_error(&pool);
+        // A global viewer who edits Acme, and a global editor who only views it
+        let project_editor =
+            models::user::create(&pool, "triager", "password123", models::Role::Viewer).unwrap();
+        let project_viewer =
+            models::user::create(&pool, "watcher", "password123", models::Role::Editor).unwrap();
+        models::project_member::add(&pool, project_editor, acme, models::Role::Editor).unwrap();
+        models::project_member::add(&pool, project_viewer, acme, models::Role::Viewer).unwrap();
+        let uri = format!("/errors/{}/status", error_id);
+
+        let token = models::user::create_session(&pool, project_viewer).unwrap();
+        let request = form_request(&uri, &token, "status=resolved".to_string());
+        assert_eq!(
+            send_with_projects(&pool, request).await,
+            StatusCode::FORBIDDEN
+        );
+
+        let token = models::user::create_session(&pool, project_editor).unwrap();
+        let request = form_request(&uri, &token, "status=resolved".to_string());
+        assert_eq!(
+            send_with_projects(&pool, request).await,
+            StatusCode::SEE_OTHER
+        );
+        let error = models::error::find(&pool, error_id).unwrap().unwrap();
+        assert_eq!(error.status, "resolved");
+    }
+
+    #[test]
+    fn test_occurrence_links_to_its_trace_and_span() {
+        let pool = crate::db::test_pool();
+        let batch: models::span::OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+            "resourceSpans": [{"scopeSpans": [{"spans": [
+                {
+                    "traceId": "7c1e3a2b9d8f4e6a5b4c3d2e1f0a9b8c",
+                    "spanId": "a1b2c3d4e5f60718",
+                    "name": "POST /checkout",
+                    "kind": 2,
+                    "startTimeUnixNano": "1700000000000000000",
+                    "endTimeUnixNano": "1700000000300000000"
+                },
+                {
+                    "traceId": "7c1e3a2b9d8f4e6a5b4c3d2e1f0a9b8c",
+                    "spanId": "0f1e2d3c4b5a6978",
+                    "parentSpanId": "a1b2c3d4e5f60718",
+                    "name": "PaymentGateway#charge",
+                    "startTimeUnixNano": "1700000000100000000",
+                    "endTimeUnixNano": "1700000000200000000",
+                    "events": [{
+                        "name": "exception",
+                        "timeUnixNano": "1700000000180000000",
+                        "attributes": [
+                            {"key": "exception.type", "value": {"stringValue": "CardDeclined"}},
+                            {"key": "exception.message", "value": {"stringValue": "declined"}}
+                        ]
+                    }]
+                }
+            ]}]}]
+        }))
+        .unwrap();
+        models::span::insert_otlp_batch(&pool, &batch, None).unwrap();
+        // Reported by an SDK with a request id that matches no trace
+        let untraced = models::error::insert(
+            &pool,
+            &models::error::IncomingError {
+                exception_class: "Timeout".to_string(),
+                message: "upstream timed out".to_string(),
+                backtrace: vec!["app/jobs/sync.rb:3".to_string()],
+                fingerprint: "timeout".to_string(),
+                request_id: Some("req-8f3a".to_string()),
+                user_id: None,
+                params: None,
+                timestamp: Some("2026-06-01T10:00:00Z".to_string()),
+                source_context: None,
+                git_sha: None,
+            },
+            None,
+        )
+        .unwrap();
+        let traced: i64 = pool
+            .get()
+            .unwrap()
+            .query_row(
+                "SELECT id FROM errors WHERE exception_class = 'CardDeclined'",
+                [],
+                |row| row.get(0),
+            )
+            .unwrap();
+
+        let occurrence = &models::error::occurrences(&pool, traced, 1).unwrap()[0];
+        let link = trace_link(&pool, occurrence).unwrap();
+        assert_eq!(link.trace_id, "7c1e3a2b9d8f4e6a5b4c3d2e1f0a9b8c");
+        assert_eq!(
+            link.span,
+            Some((
+                "0f1e2d3c4b5a6978".to_string(),
+                "PaymentGateway#charge".to_string()
+            ))
+        );
+        assert_eq!(
+            link.href(),
+            "/traces/7c1e3a2b9d8f4e6a5b4c3d2e1f0a9b8c#span-0f1e2d3c4b5a6978"
+        );
+
+        let occurrence = &models::error::occurrences(&pool, untraced, 1).unwrap()[0];
+        assert_eq!(trace_link(&pool, occurrence), None);
+    }
 }
@@ -7,7 +7,9 @@ mod errors;
 mod performance;
 pub mod project_context;
 mod projects;
+pub mod time_range;
 mod traces;
+mod user_prefs;
 
 use axum::{
     Router, middleware,
@@ -20,17 +22,74 @@ pub fn routes(pool: DbPool) -> Router<DbPool> {
     Router::new()
         .route("/", get(dashboard::index))
         .route("/errors", get(errors::index))
+        .route("/errors/bulk-status", post(errors::update_status_bulk))
         .route("/errors/:id", get(errors::show))
         .route("/errors/:id/status", post(errors::update_status))
         .route("/traces", get(traces::index))
+        .route("/traces/compare", get(traces::compare))
         .route("/traces/:trace_id", get(traces::show))
+        .route("/traces/:trace_id/export", get(traces::export))
+        .route("/traces/:trace_id/flamegraph", get(traces::flamegraph))
+        .route(
+            "/traces/:trace_id/spans/:span_id/attributes",
+            get(traces::span_attributes),
+        )
         .route("/performance", get(performance::index))
+        .route("/performance/export", get(performance::export))
+        .route(
+            "/performance/slow-requests",
+            get(performance::slow_requests),
+        )
+        .route("/performance/route", get(performance::route))
+        .route(
+            "/performance/route/flamegraph",
+            get(performance::route_flamegraph),
+        )
+        .route("/performance/exemplar", get(performance::exemplar))
+        .route("/performance/jobs", get(performance::jobs))
+        .route("/performance/commands", get(performance::commands))
+        .route("/performance/queries", get(performance::queries))
+        .route("/performance/services", get(performance::services))
         .route("/deploys", get(deploys::index))
+        .route("/deploys/:id/annotate", post(deploys::annotate))
         .route("/projects/switch", post(projects::switch_project))
         .route("/projects", get(projects::index))
         .route("/projects/create", post(projects::create))
         .route("/projects/delete", post(projects::delete))
         .route("/projects/regenerate-key", post(projects::regenerate_key))
+        .route(
+            "/projects/frame-rewrites",
+            post(projects::update_frame_rewrites),
+        )
+        .route(
+            "/projects/redaction-rules",
+            post(projects::update_redaction_rules),
+        )
+        .route(
+            "/projects/display-timezone",
+            post(projects::update_display_timezone),
+        )
+        .route(
+            "/projects/webhook-secret",
+            post(projects::update_webhook_secret),
+        )
+        .route(
+            "/projects/signing-secret",
+            post(projects::update_signing_secret),
+        )
+        .route("/projects/ingest", post(projects::update_ingest))
+        .route(
+            "/projects/tail-sampling",
+            post(projects::update_tail_sampling),
+        )
+        .route(
+            "/projects/auto-resolve",
+            post(projects::update_auto_resolve),
+        )
+        .route(
+            "/projects/error-policy",
+            post(projects::update_error_policy),
+        )
         .route("/api-key", get(api_key::index))
         .route("/api-key/regenerate", post(api_key::regenerate))
         .layer(middleware::from_fn_with_state(
@@ -46,6 +105,15 @@ pub fn auth_routes() -> Router<DbPool> {
             get(auth::login_page).post(auth::login_submit),
         )
         .route("/auth/logout", post(auth::logout))
+        .route(
+            "/auth/2fa",
+            get(auth::two_factor_page).post(auth::two_factor_submit),
+        )
+        .route(
+            "/auth/2fa/setup",
+            get(auth::two_factor_setup_page).post(auth::two_factor_setup_submit),
+        )
+        .route("/auth/2fa/disable", post(auth::two_factor_disable))
         .route(
             "/auth/change-password",
             get(auth::change_password_page).post(auth::change_password_submit),
@@ -53,6 +121,9 @@ pub fn auth_routes() -> Router<DbPool> {
         .route("/auth/users", get(auth::users_page))
         .route("/auth/users/create", post(auth::create_user))
         .route("/auth/users/delete", post(auth::delete_user))
+        .route("/auth/members", get(auth::members_page))
+        .route("/auth/members/add", post(auth::add_member))
+        .route("/auth/members/remove", post(auth::remove_member))
         .route(
             "/auth/invite/{token}",
             get(auth::invite_page).post(auth::invite_submit),
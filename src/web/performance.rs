@@ -1,20 +1,44 @@
 use askama::Template;
-use axum::extract::{Query, State};
+use axum::{
+    body::Body,
+    extract::{Extension, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Json, Redirect, Response},
+};
 use chrono::{Duration, Utc};
+use futures_util::{StreamExt, stream};
 use serde::Deserialize;
 use tower_cookies::Cookies;
 
-use crate::{DbPool, models::span};
+use crate::{
+    DbPool,
+    config::SharedConfig,
+    models::{Page, error, project, regression, rollup, span, user_pref},
+};
 
 use super::project_context::{WebProjectContext, get_project_context};
+use super::time_range::TimeRange;
+use super::user_prefs::Prefs;
+
+/// Orders the routes table offers, the first being the default
+const ROUTE_SORTS: [&str; 7] = ["requests", "avg", "p95", "p99", "max", "db", "errors"];
 
 #[derive(Template)]
 #[template(path = "performance/index.html")]
 pub struct RoutesTemplate {
     pub routes: Vec<span::RouteSummary>,
+    /// Routes that are unusually slow right now
+    pub anomalies: Vec<span::LatencyAnomaly>,
+    /// Week-over-week p95 changes from the last detection run
+    pub regressions: Vec<regression::Regression>,
+    /// How many of those got slower
+    pub regression_count: usize,
+    pub regression_threshold_percent: f64,
     pub total_count: i64,
     pub max_requests: i64,
-    pub period: String,
+    /// Whether the routes were read from hourly rollups instead of raw spans
+    pub from_rollups: bool,
+    pub range: TimeRange,
     pub search: Option<String>,
     pub sort: String,
     pub ctx: WebProjectContext,
@@ -22,47 +46,1041 @@ pub struct RoutesTemplate {
 
 #[derive(Deserialize)]
 pub struct RoutesQuery {
-    pub period: Option<String>,
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
     pub search: Option<String>,
     pub sort: Option<String>,
 }
 
 pub async fn index(
     State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
     cookies: Cookies,
     Query(query): Query<RoutesQuery>,
 ) -> RoutesTemplate {
     let ctx = get_project_context(&pool, &cookies);
     let project_id = ctx.project_id();
 
-    let period = query.period.unwrap_or_else(|| "24h".to_string());
-    let sort = query.sort.unwrap_or_else(|| "requests".to_string());
+    let prefs = Prefs::load(&pool, &cookies);
+    let range = TimeRange::parse(
+        prefs
+            .resolve(
+                user_pref::TIME_RANGE,
+                query.range.as_deref(),
+                TimeRange::is_preset,
+            )
+            .as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        "24h",
+    );
+    let sort = prefs
+        .resolve(user_pref::PERFORMANCE_SORT, query.sort.as_deref(), |s| {
+            ROUTE_SORTS.contains(&s)
+        })
+        .unwrap_or_else(|| ROUTE_SORTS[0].to_string());
     let search = query.search.clone().filter(|s| !s.is_empty());
 
-    let since = match period.as_str() {
-        "1h" => Utc::now() - Duration::hours(1),
-        "7d" => Utc::now() - Duration::days(7),
-        "30d" => Utc::now() - Duration::days(30),
-        _ => Utc::now() - Duration::hours(24),
-    };
-
-    let since_str = since.to_rfc3339();
+    // `range=all` leaves no lower bound, and every timestamp sorts after ""
+    let since_str = range.since_str().unwrap_or_default();
+    let until_str = range.until_str();
 
-    let routes = span::routes_summary(&pool, project_id, &since_str, search.as_deref(), &sort, 100)
-        .unwrap_or_default();
+    // Rollups aren't kept per project, so a selected project always reads spans
+    let from_rollups = project_id.is_none()
+        && reaches_past_span_retention(&range, config.load().retention_days_spans);
+    let (routes, total_count) = if from_rollups {
+        (
+            rollup::routes_summary_from_rollups(
+                &pool,
+                &since_str,
+                until_str.as_deref(),
+                search.as_deref(),
+                &sort,
+                100,
+            )
+            .unwrap_or_default(),
+            rollup::routes_count_from_rollups(
+                &pool,
+                &since_str,
+                until_str.as_deref(),
+                search.as_deref(),
+            )
+            .unwrap_or(0),
+        )
+    } else {
+        (
+            span::routes_summary(
+                &pool,
+                project_id,
+                &since_str,
+                until_str.as_deref(),
+                search.as_deref(),
+                &sort,
+                100,
+                project::error_policy(&pool, project_id, config.load().error_status_policy),
+            )
+            .unwrap_or_default(),
+            span::routes_count(
+                &pool,
+                project_id,
+                &since_str,
+                until_str.as_deref(),
+                search.as_deref(),
+            )
+            .unwrap_or(0),
+        )
+    };
 
-    let total_count =
-        span::routes_count(&pool, project_id, &since_str, search.as_deref()).unwrap_or(0);
+    let anomalies = span::detect_latency_anomalies(&pool, project_id).unwrap_or_default();
+    let regressions = regression::latest(&pool).unwrap_or_default();
+    let regression_count = regressions.iter().filter(|r| r.is_slower()).count();
 
     let max_requests = routes.iter().map(|r| r.request_count).max().unwrap_or(1);
 
     RoutesTemplate {
         routes,
+        anomalies,
+        regressions,
+        regression_count,
+        regression_threshold_percent: regression::THRESHOLD_PERCENT,
         total_count,
         max_requests,
-        period,
+        from_rollups,
+        range,
         search,
         sort,
         ctx,
     }
 }
+
+/// Raw spans are only kept for `retention_days`; a range starting before
+/// that (with an hour's slack for the retention job) would silently lose its
+/// oldest days, so it reads the hourly rollups instead
+fn reaches_past_span_retention(range: &TimeRange, retention_days: i64) -> bool {
+    range.since.is_none_or(|since| {
+        since < Utc::now() - Duration::days(retention_days) - Duration::hours(1)
+    })
+}
+
+/// Bars in the route latency histogram
+const ROUTE_HISTOGRAM_BUCKETS: usize = 12;
+
+/// Recent traces listed on the route page
+const ROUTE_TRACE_LIMIT: i64 = 25;
+
+/// Error groups listed on the route page
+const ROUTE_ERROR_LIMIT: i64 = 10;
+
+/// Traces merged into a route's flamegraph, to bound its cost
+const ROUTE_FLAMEGRAPH_SAMPLES: i64 = 50;
+
+/// One endpoint's traffic: latency trend and distribution, the errors it
+/// raised, its recent traces and a p95 trace to start digging from
+#[derive(Template)]
+#[template(path = "performance/route.html")]
+pub struct RouteTemplate {
+    pub path: String,
+    pub method: String,
+    pub trend: Vec<span::ChartPoint>,
+    /// Slowest average in `trend`, for scaling its bars
+    pub max_trend_ms: f64,
+    pub buckets: Vec<span::LatencyBucket>,
+    pub request_count: i64,
+    pub max_count: i64,
+    pub errors: Vec<error::RouteError>,
+    pub traces: Vec<span::TraceSummary>,
+    pub slow_trace: Option<span::TraceSummary>,
+    /// Which statuses the trace list marks as errors
+    pub error_policy: project::ErrorPolicy,
+    pub range: TimeRange,
+    pub ctx: WebProjectContext,
+}
+
+impl RouteTemplate {
+    /// Bar height for a trend point, as a percentage of the slowest one
+    pub fn trend_height(&self, point: &span::ChartPoint) -> i64 {
+        (point.avg_ms * 100.0 / self.max_trend_ms).round() as i64
+    }
+}
+
+#[derive(Deserialize)]
+pub struct RouteQuery {
+    pub path: String,
+    pub method: Option<String>,
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+}
+
+pub async fn route(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    cookies: Cookies,
+    Query(query): Query<RouteQuery>,
+) -> RouteTemplate {
+    let ctx = get_project_context(&pool, &cookies);
+    let project_id = ctx.project_id();
+    let error_policy = project::error_policy(&pool, project_id, config.load().error_status_policy);
+
+    let range = TimeRange::parse(query.range.as_deref(), None, None, "24h");
+    let method = query
+        .method
+        .filter(|m| !m.is_empty())
+        .unwrap_or_else(|| "GET".to_string());
+    let path = query.path;
+    let since_str = range.since_str().unwrap_or_default();
+
+    let until = Utc::now();
+    let since = range
+        .since
+        .unwrap_or(until - Duration::days(span::MAX_CHART_POINTS));
+    let trend = span::chart_stats(
+        &pool,
+        project_id,
+        None,
+        None,
+        Some((&path, &method)),
+        since,
+        until,
+        None,
+        error_policy,
+    )
+    .map(|(_, points)| points)
+    .unwrap_or_default();
+    let max_trend_ms = trend.iter().map(|p| p.avg_ms).fold(1.0, f64::max);
+
+    let buckets = span::route_latency_histogram(
+        &pool,
+        project_id,
+        &path,
+        &method,
+        &since_str,
+        ROUTE_HISTOGRAM_BUCKETS,
+    )
+    .unwrap_or_default();
+    let request_count = buckets.iter().map(|b| b.count).sum();
+    let max_count = buckets.iter().map(|b| b.count).max().unwrap_or(0).max(1);
+
+    let errors = error::errors_for_route(
+        &pool,
+        project_id,
+        &path,
+        &method,
+        &since_str,
+        ROUTE_ERROR_LIMIT,
+    )
+    .unwrap_or_default();
+    let traces = span::route_traces(
+        &pool,
+        project_id,
+        &path,
+        &method,
+        &since_str,
+        ROUTE_TRACE_LIMIT,
+    )
+    .unwrap_or_default();
+    let slow_trace = span::representative_slow_trace(&pool, project_id, &path, &method, &since_str)
+        .unwrap_or_default();
+
+    RouteTemplate {
+        path,
+        method,
+        trend,
+        max_trend_ms,
+        buckets,
+        request_count,
+        max_count,
+        errors,
+        traces,
+        slow_trace,
+        error_policy,
+        range,
+        ctx,
+    }
+}
+
+/// `GET /performance/route/flamegraph`: the route's average time per span
+/// name across a sample of its traces, as JSON for a flamegraph renderer
+pub async fn route_flamegraph(
+    State(pool): State<DbPool>,
+    cookies: Cookies,
+    Query(query): Query<RouteQuery>,
+) -> Response {
+    let project_id = get_project_context(&pool, &cookies).project_id();
+    let range = TimeRange::parse(query.range.as_deref(), None, None, "24h");
+    let method = query
+        .method
+        .filter(|m| !m.is_empty())
+        .unwrap_or_else(|| "GET".to_string());
+
+    match span::route_flamegraph(
+        &pool,
+        project_id,
+        &query.path,
+        &method,
+        &range.since_str().unwrap_or_default(),
+        ROUTE_FLAMEGRAPH_SAMPLES,
+    ) {
+        Ok(Some(graph)) => Json(graph).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!(
+                "Failed to build flamegraph for {} {}: {}",
+                method,
+                query.path,
+                e
+            );
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ExemplarQuery {
+    pub path: String,
+    pub ms: f64,
+}
+
+/// Sends a percentile link to the route's trace nearest that latency, or to
+/// the route's traces when none is left
+pub async fn exemplar(
+    State(pool): State<DbPool>,
+    cookies: Cookies,
+    Query(query): Query<ExemplarQuery>,
+) -> Redirect {
+    let project_id = get_project_context(&pool, &cookies).project_id();
+    match span::exemplar_trace_near(&pool, project_id, &query.path, query.ms) {
+        Ok(Some(trace)) => Redirect::to(&format!("/traces/{}", trace.trace_id)),
+        _ => Redirect::to(&format!(
+            "/traces?search={}",
+            urlencoding::encode(&query.path)
+        )),
+    }
+}
+
+#[derive(Template)]
+#[template(path = "performance/jobs.html")]
+pub struct JobsTemplate {
+    pub jobs: Vec<span::JobSummary>,
+    pub max_executions: i64,
+    pub range: TimeRange,
+    pub sort: String,
+    pub ctx: WebProjectContext,
+}
+
+#[derive(Deserialize)]
+pub struct JobsQuery {
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    pub sort: Option<String>,
+}
+
+pub async fn jobs(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    cookies: Cookies,
+    Query(query): Query<JobsQuery>,
+) -> JobsTemplate {
+    let ctx = get_project_context(&pool, &cookies);
+    let project_id = ctx.project_id();
+    let error_policy = project::error_policy(&pool, project_id, config.load().error_status_policy);
+
+    let range = TimeRange::parse(
+        query.range.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        "24h",
+    );
+    let sort = query.sort.unwrap_or_else(|| "count".to_string());
+
+    let jobs = span::jobs_summary(
+        &pool,
+        project_id,
+        &range.since_str().unwrap_or_default(),
+        range.until_str().as_deref(),
+        &sort,
+        100,
+        error_policy,
+    )
+    .unwrap_or_default();
+    let max_executions = jobs.iter().map(|j| j.execution_count).max().unwrap_or(1);
+
+    JobsTemplate {
+        jobs,
+        max_executions,
+        range,
+        sort,
+        ctx,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "performance/commands.html")]
+pub struct CommandsTemplate {
+    pub commands: Vec<span::CommandSummary>,
+    pub range: TimeRange,
+    pub ctx: WebProjectContext,
+}
+
+#[derive(Deserialize)]
+pub struct CommandsQuery {
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+pub async fn commands(
+    State(pool): State<DbPool>,
+    cookies: Cookies,
+    Query(query): Query<CommandsQuery>,
+) -> CommandsTemplate {
+    let ctx = get_project_context(&pool, &cookies);
+    let project_id = ctx.project_id();
+
+    // Commands run rarely, so default to a week
+    let range = TimeRange::parse(
+        query.range.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        "7d",
+    );
+
+    let commands = span::commands_summary(
+        &pool,
+        project_id,
+        &range.since_str().unwrap_or_default(),
+        range.until_str().as_deref(),
+        100,
+    )
+    .unwrap_or_default();
+
+    CommandsTemplate {
+        commands,
+        range,
+        ctx,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "performance/queries.html")]
+pub struct SlowQueriesTemplate {
+    pub queries: Vec<span::SlowQuery>,
+    pub range: TimeRange,
+    pub threshold_ms: f64,
+    pub ctx: WebProjectContext,
+}
+
+#[derive(Deserialize)]
+pub struct SlowQueriesQuery {
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// Minimum query duration in ms (defaults to SLOW_QUERY_THRESHOLD_MS)
+    pub threshold: Option<f64>,
+}
+
+pub async fn queries(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    cookies: Cookies,
+    Query(query): Query<SlowQueriesQuery>,
+) -> SlowQueriesTemplate {
+    let ctx = get_project_context(&pool, &cookies);
+    let project_id = ctx.project_id();
+
+    let range = TimeRange::parse(
+        query.range.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        "24h",
+    );
+    let threshold_ms = query
+        .threshold
+        .filter(|&t| t >= 0.0)
+        .unwrap_or(config.load().slow_query_threshold_ms);
+
+    let queries = span::slow_db_spans(
+        &pool,
+        project_id,
+        threshold_ms,
+        &range.since_str().unwrap_or_default(),
+        range.until_str().as_deref(),
+        span::SlowQueryOrder::Total,
+        100,
+    )
+    .unwrap_or_default();
+
+    SlowQueriesTemplate {
+        queries,
+        range,
+        threshold_ms,
+        ctx,
+    }
+}
+
+#[derive(Template)]
+#[template(path = "performance/services.html")]
+pub struct ServicesTemplate {
+    pub map: span::ServiceMap,
+    pub graph: ServiceGraph,
+    pub range: TimeRange,
+    pub ctx: WebProjectContext,
+}
+
+#[derive(Deserialize)]
+pub struct ServicesQuery {
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+}
+
+const GRAPH_MARGIN: usize = 20;
+const GRAPH_COLUMN_WIDTH: usize = 240;
+const GRAPH_ROW_HEIGHT: usize = 56;
+const GRAPH_NODE_WIDTH: usize = 180;
+const GRAPH_NODE_HEIGHT: usize = 36;
+/// Longer names are cut to fit the node box
+const GRAPH_LABEL_CHARS: usize = 22;
+
+/// Service map laid out for SVG, one column per call depth
+pub struct ServiceGraph {
+    pub width: usize,
+    pub height: usize,
+    pub nodes: Vec<GraphNode>,
+    pub edges: Vec<GraphEdge>,
+}
+
+pub struct GraphNode {
+    pub name: String,
+    pub label: String,
+    pub is_database: bool,
+    pub x: usize,
+    pub y: usize,
+}
+
+pub struct GraphEdge {
+    pub x1: usize,
+    pub y1: usize,
+    pub x2: usize,
+    pub y2: usize,
+    /// 1-6px, scaled to the busiest edge
+    pub stroke_width: f64,
+    pub title: String,
+}
+
+impl ServiceGraph {
+    fn layout(map: &span::ServiceMap) -> Self {
+        let mut rows_per_column: Vec<usize> = Vec::new();
+        let mut positions = std::collections::HashMap::new();
+        let nodes: Vec<GraphNode> = map
+            .nodes
+            .iter()
+            .map(|node| {
+                if rows_per_column.len() <= node.depth {
+                    rows_per_column.resize(node.depth + 1, 0);
+                }
+                let row = rows_per_column[node.depth];
+                rows_per_column[node.depth] += 1;
+                let x = GRAPH_MARGIN + node.depth * GRAPH_COLUMN_WIDTH;
+                let y = GRAPH_MARGIN + row * GRAPH_ROW_HEIGHT;
+                positions.insert(node.name.as_str(), (x, y));
+                let label = if node.name.chars().count() > GRAPH_LABEL_CHARS {
+                    let cut: String = node.name.chars().take(GRAPH_LABEL_CHARS - 1).collect();
+                    format!("{}…", cut)
+                } else {
+                    node.name.clone()
+                };
+                GraphNode {
+                    name: node.name.clone(),
+                    label,
+                    is_database: node.is_database,
+                    x,
+                    y,
+                }
+            })
+            .collect();
+
+        let busiest = map.edges.iter().map(|e| e.call_count).max().unwrap_or(1) as f64;
+        let edges = map
+            .edges
+            .iter()
+            .filter_map(|edge| {
+                let (sx, sy) = positions.get(edge.source.as_str())?;
+                let (tx, ty) = positions.get(edge.target.as_str())?;
+                Some(GraphEdge {
+                    x1: sx + GRAPH_NODE_WIDTH,
+                    y1: sy + GRAPH_NODE_HEIGHT / 2,
+                    x2: *tx,
+                    y2: ty + GRAPH_NODE_HEIGHT / 2,
+                    stroke_width: 1.0 + 5.0 * edge.call_count as f64 / busiest,
+                    title: format!(
+                        "{} → {}: {} calls, {}ms avg",
+                        edge.source, edge.target, edge.call_count, edge.avg_ms
+                    ),
+                })
+            })
+            .collect();
+
+        let columns = rows_per_column.len().max(1);
+        let rows = rows_per_column.iter().copied().max().unwrap_or(1).max(1);
+        Self {
+            width: 2 * GRAPH_MARGIN + (columns - 1) * GRAPH_COLUMN_WIDTH + GRAPH_NODE_WIDTH,
+            height: 2 * GRAPH_MARGIN + (rows - 1) * GRAPH_ROW_HEIGHT + GRAPH_NODE_HEIGHT,
+            nodes,
+            edges,
+        }
+    }
+}
+
+/// Dependencies between services, databases and external hosts
+pub async fn services(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    cookies: Cookies,
+    Query(query): Query<ServicesQuery>,
+) -> ServicesTemplate {
+    let ctx = get_project_context(&pool, &cookies);
+    let project_id = ctx.project_id();
+    let error_policy = project::error_policy(&pool, project_id, config.load().error_status_policy);
+
+    let range = TimeRange::parse(
+        query.range.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        "24h",
+    );
+
+    let map = span::service_map(
+        &pool,
+        project_id,
+        &range.since_str().unwrap_or_default(),
+        range.until_str().as_deref(),
+        error_policy,
+    )
+    .unwrap_or_default();
+    let graph = ServiceGraph::layout(&map);
+
+    ServicesTemplate {
+        map,
+        graph,
+        range,
+        ctx,
+    }
+}
+
+/// Rows fetched from SQLite per chunk while streaming an export
+const EXPORT_PAGE_SIZE: i64 = 500;
+
+/// CSV columns, in output order
+pub const EXPORT_CSV_COLUMNS: [&str; 9] = [
+    "trace_id",
+    "happened_at",
+    "name",
+    "http_method",
+    "http_url",
+    "http_status_code",
+    "duration_ms",
+    "span_count",
+    "service_name",
+];
+
+#[derive(Deserialize)]
+pub struct ExportQuery {
+    pub format: Option<String>,
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// Minimum duration in ms (defaults to SLOW_REQUEST_THRESHOLD_MS)
+    pub threshold: Option<f64>,
+    /// Substring match on route name or URL
+    pub route: Option<String>,
+}
+
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+fn csv_row(trace: &span::TraceSummary) -> String {
+    let fields = [
+        trace.trace_id.clone(),
+        trace.happened_at.clone(),
+        trace.root_span_name.clone(),
+        trace.http_method.clone().unwrap_or_default(),
+        trace.http_url.clone().unwrap_or_default(),
+        trace
+            .http_status_code
+            .map(|c| c.to_string())
+            .unwrap_or_default(),
+        format!("{:.2}", trace.duration_ms),
+        trace.span_count.to_string(),
+        trace.service_name.clone().unwrap_or_default(),
+    ];
+    let mut line = fields
+        .iter()
+        .map(|f| csv_field(f))
+        .collect::<Vec<_>>()
+        .join(",");
+    line.push('\n');
+    line
+}
+
+/// Rows per page of `slow_requests` unless `limit` asks otherwise
+const SLOW_REQUESTS_PAGE_SIZE: i64 = 50;
+const SLOW_REQUESTS_MAX_LIMIT: i64 = 500;
+
+#[derive(Deserialize)]
+pub struct SlowRequestsQuery {
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
+    /// Minimum duration in ms (defaults to SLOW_REQUEST_THRESHOLD_MS)
+    pub threshold: Option<f64>,
+    /// Substring match on route name or URL
+    pub route: Option<String>,
+    pub limit: Option<i64>,
+    pub offset: Option<i64>,
+}
+
+/// One page of slow requests, slowest first, as JSON with paging metadata
+pub async fn slow_requests(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    cookies: Cookies,
+    Query(query): Query<SlowRequestsQuery>,
+) -> Response {
+    let ctx = get_project_context(&pool, &cookies);
+    let project_id = ctx.project_id();
+
+    let range = TimeRange::parse(
+        query.range.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        "24h",
+    );
+    let since = range.since_str();
+    let until = range.until_str();
+    let threshold = query
+        .threshold
+        .filter(|&t| t >= 0.0)
+        .unwrap_or(config.load().slow_request_threshold_ms);
+    let route = query.route.filter(|s| !s.is_empty());
+    let limit = query
+        .limit
+        .unwrap_or(SLOW_REQUESTS_PAGE_SIZE)
+        .clamp(1, SLOW_REQUESTS_MAX_LIMIT);
+    let offset = query.offset.unwrap_or(0).max(0);
+
+    let page = Page::fetch(
+        limit,
+        offset,
+        || {
+            span::count_traces_filtered(
+                &pool,
+                project_id,
+                None,
+                since.as_deref(),
+                until.as_deref(),
+                route.as_deref(),
+                Some(threshold),
+                None,
+                None,
+            )
+        },
+        |limit, offset| {
+            span::list_traces_paginated(
+                &pool,
+                project_id,
+                None,
+                since.as_deref(),
+                until.as_deref(),
+                route.as_deref(),
+                Some(threshold),
+                None,
+                None,
+                "duration",
+                limit,
+                offset,
+            )
+        },
+    );
+    match page {
+        Ok(page) => Json(page).into_response(),
+        Err(e) => {
+            tracing::error!("Failed to list slow requests: {}", e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// Stream slow requests as CSV or NDJSON, paging through SQLite so large
+/// exports never sit in memory at once
+pub async fn export(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    cookies: Cookies,
+    Query(query): Query<ExportQuery>,
+) -> Response {
+    let ctx = get_project_context(&pool, &cookies);
+    let project_id = ctx.project_id();
+
+    let json = query.format.as_deref() == Some("json");
+    let range = TimeRange::parse(
+        query.range.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        "24h",
+    );
+    let since = range.since_str();
+    let until = range.until_str();
+    let threshold = query
+        .threshold
+        .filter(|&t| t >= 0.0)
+        .unwrap_or(config.load().slow_request_threshold_ms);
+    let route = query.route.filter(|s| !s.is_empty());
+
+    let max_rows = config.load().export_max_rows;
+    let total = match span::count_traces_filtered(
+        &pool,
+        project_id,
+        None,
+        since.as_deref(),
+        until.as_deref(),
+        route.as_deref(),
+        Some(threshold),
+        None,
+        None,
+    ) {
+        Ok(total) => total,
+        Err(e) => {
+            tracing::error!("Failed to count slow requests for export: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+    let truncated = total > max_rows;
+
+    let header_chunk = if json {
+        None
+    } else {
+        Some(format!("{}\n", EXPORT_CSV_COLUMNS.join(",")))
+    };
+
+    // A failed page ends the stream with an error, so the download aborts
+    // instead of looking like a complete (shorter) export
+    let pages = stream::unfold(Some(0i64), move |offset| {
+        let pool = pool.clone();
+        let since = since.clone();
+        let until = until.clone();
+        let route = route.clone();
+        async move {
+            let offset = offset.filter(|&offset| offset < max_rows)?;
+            let limit = EXPORT_PAGE_SIZE.min(max_rows - offset);
+            let traces = span::list_traces_paginated(
+                &pool,
+                project_id,
+                None,
+                since.as_deref(),
+                until.as_deref(),
+                route.as_deref(),
+                Some(threshold),
+                None,
+                None,
+                "duration",
+                limit,
+                offset,
+            );
+            let traces = match traces {
+                Ok(traces) if traces.is_empty() => return None,
+                Ok(traces) => traces,
+                Err(e) => {
+                    tracing::error!("Failed to export slow requests: {}", e);
+                    return Some((Err(e), None));
+                }
+            };
+
+            let chunk: String = traces
+                .iter()
+                .map(|t| {
+                    if json {
+                        serde_json::to_string(t).unwrap_or_default() + "\n"
+                    } else {
+                        csv_row(t)
+                    }
+                })
+                .collect();
+            Some((Ok(chunk), Some(offset + traces.len() as i64)))
+        }
+    });
+    let body = stream::iter(header_chunk.map(Ok)).chain(pages);
+
+    let (content_type, filename) = if json {
+        ("application/x-ndjson", "slow-requests.ndjson")
+    } else {
+        ("text/csv; charset=utf-8", "slow-requests.csv")
+    };
+
+    (
+        [
+            (header::CONTENT_TYPE, content_type.to_string()),
+            (
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"{}\"", filename),
+            ),
+            (
+                header::HeaderName::from_static("x-export-truncated"),
+                truncated.to_string(),
+            ),
+            (
+                header::HeaderName::from_static("x-export-total"),
+                total.to_string(),
+            ),
+        ],
+        Body::from_stream(body),
+    )
+        .into_response()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use axum::{Router, http::Request, routing::get};
+    use tower::util::ServiceExt;
+
+    #[test]
+    fn test_rollups_follow_the_configured_span_retention() {
+        let week = TimeRange::parse(Some("7d"), None, None, "24h");
+        assert!(!reaches_past_span_retention(&week, 7));
+        assert!(reaches_past_span_retention(&week, 3));
+        let all = TimeRange::parse(Some("all"), None, None, "24h");
+        assert!(reaches_past_span_retention(&all, 365));
+    }
+
+    #[test]
+    fn test_csv_column_order() {
+        assert_eq!(
+            EXPORT_CSV_COLUMNS.join(","),
+            "trace_id,happened_at,name,http_method,http_url,http_status_code,duration_ms,span_count,service_name"
+        );
+
+        let trace = span::TraceSummary {
+            trace_id: "abc".to_string(),
+            root_span_name: "UsersController#index".to_string(),
+            root_span_type: None,
+            duration_ms: 812.5,
+            span_count: 14,
+            status_code: 0,
+            service_name: Some("web".to_string()),
+            environment: None,
+            http_method: Some("GET".to_string()),
+            http_url: Some("/users?page=1,2".to_string()),
+            http_status_code: Some(200),
+            happened_at: "2026-01-01 10:00".to_string(),
+            has_error_descendant: false,
+        };
+        assert_eq!(
+            csv_row(&trace),
+            "abc,2026-01-01 10:00,UsersController#index,GET,\"/users?page=1,2\",200,812.50,14,web\n"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_route_page_only_shows_the_selected_route() {
+        let pool = crate::db::test_pool();
+        let happened_at = (Utc::now() - Duration::minutes(5))
+            .format("%Y-%m-%dT%H:%M:%S%.3fZ")
+            .to_string();
+        {
+            let conn = pool.get().unwrap();
+            for (trace_id, method, name) in [
+                ("trace-users-get", "GET", "UsersController#index"),
+                ("trace-users-post", "POST", "UsersController#index"),
+                ("trace-posts-get", "GET", "PostsController#index"),
+            ] {
+                conn.execute(
+                    r#"INSERT INTO spans (trace_id, span_id, start_time_unix_nano,
+                           end_time_unix_nano, duration_ms, name, span_category, root_span_type,
+                           http_method, happened_at)
+                       VALUES (?1, ?1, 0, 0, 40, ?3, 'http_server', 'web', ?2, ?4)"#,
+                    rusqlite::params![trace_id, method, name, happened_at],
+                )
+                .unwrap();
+            }
+            for (id, class, trace_id) in [
+                (1, "UsersGetError", "trace-users-get"),
+                (2, "UsersPostError", "trace-users-post"),
+            ] {
+                conn.execute(
+                    r#"INSERT INTO errors (id, fingerprint, exception_class, message,
+                           first_seen_at, last_seen_at)
+                       VALUES (?1, ?2, ?2, 'boom', ?3, ?3)"#,
+                    rusqlite::params![id, class, happened_at],
+                )
+                .unwrap();
+                conn.execute(
+                    r#"INSERT INTO error_occurrences (error_id, request_id, backtrace, happened_at)
+                       VALUES (?1, ?2, '[]', ?3)"#,
+                    rusqlite::params![id, trace_id, happened_at],
+                )
+                .unwrap();
+            }
+            span::rebuild_trace_summaries(&conn).unwrap();
+        }
+
+        let app = Router::new()
+            .route("/performance/route", get(route))
+            .layer(Extension(crate::config::Config::shared_defaults()))
+            .layer(tower_cookies::CookieManagerLayer::new())
+            .with_state(pool);
+        let request = Request::builder()
+            .uri("/performance/route?path=UsersController%23index&method=GET")
+            .body(Body::empty())
+            .unwrap();
+        let body = app.oneshot(request).await.unwrap().into_body();
+        let html = String::from_utf8(
+            axum::body::to_bytes(body, usize::MAX)
+                .await
+                .unwrap()
+                .to_vec(),
+        )
+        .unwrap();
+
+        assert!(html.contains("1 request &middot;"));
+        assert!(html.contains("trace-users-get"));
+        assert!(html.contains("UsersGetError"));
+        assert!(!html.contains("trace-users-post"));
+        assert!(!html.contains("UsersPostError"));
+        assert!(!html.contains("trace-posts-get"));
+    }
+
+    #[tokio::test]
+    async fn test_export_fails_instead_of_returning_a_partial_file() {
+        let pool = crate::db::test_pool();
+        pool.get()
+            .unwrap()
+            .execute("DROP TABLE trace_summaries", [])
+            .unwrap();
+
+        let app = Router::new()
+            .route("/performance/export", get(export))
+            .layer(Extension(crate::config::Config::shared_defaults()))
+            .layer(tower_cookies::CookieManagerLayer::new())
+            .with_state(pool);
+        let request = Request::builder()
+            .uri("/performance/export")
+            .body(Body::empty())
+            .unwrap();
+        let response = app.oneshot(request).await.unwrap();
+        assert_eq!(response.status(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+}
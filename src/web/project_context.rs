@@ -1,6 +1,9 @@
 use tower_cookies::Cookies;
 
-use crate::{DbPool, models::project::Project};
+use crate::{
+    DbPool,
+    models::{self, project::Project},
+};
 
 pub const PROJECT_COOKIE: &str = "miniapm_project";
 
@@ -10,11 +13,21 @@ pub struct WebProjectContext {
     pub current_project: Option<Project>,
     pub projects: Vec<Project>,
     pub projects_enabled: bool,
+    /// The user only has access to the projects they are a member of
+    pub restricted: bool,
 }
 
+/// Project ID that matches no rows, used when a restricted user has no projects
+/// (`None` would mean "all projects")
+const NO_PROJECT: i64 = 0;
+
 impl WebProjectContext {
     pub fn project_id(&self) -> Option<i64> {
-        self.current_project.as_ref().map(|p| p.id)
+        match &self.current_project {
+            Some(p) => Some(p.id),
+            None if self.restricted => Some(NO_PROJECT),
+            None => None,
+        }
     }
 
     /// Check if the given project ID is the current project (for template use)
@@ -38,10 +51,16 @@ pub fn get_project_context(pool: &DbPool, cookies: &Cookies) -> WebProjectContex
             current_project: None,
             projects: vec![],
             projects_enabled: false,
+            restricted: false,
         };
     }
 
-    let projects = crate::models::project::list_all(pool).unwrap_or_default();
+    let user = current_user(pool, cookies);
+    let restricted = user
+        .as_ref()
+        .is_some_and(|u| !u.has_role(models::Role::Admin));
+    let projects =
+        models::project_member::visible_projects(pool, user.as_ref()).unwrap_or_default();
 
     // Get project slug from cookie
     let project_slug = cookies.get(PROJECT_COOKIE).map(|c| c.value().to_string());
@@ -50,10 +69,27 @@ pub fn get_project_context(pool: &DbPool, cookies: &Cookies) -> WebProjectContex
         Some(slug) => projects.iter().find(|p| p.slug == slug).cloned(),
         None => projects.first().cloned(),
     };
+    // Never fall back to the all-projects view for a restricted user
+    let current_project = match current_project {
+        None if restricted => projects.first().cloned(),
+        other => other,
+    };
 
     WebProjectContext {
         current_project,
         projects,
         projects_enabled,
+        restricted,
     }
 }
+
+/// Logged-in user for the request, if any
+pub fn current_user(pool: &DbPool, cookies: &Cookies) -> Option<models::User> {
+    let token = cookies
+        .get(super::auth::SESSION_COOKIE)?
+        .value()
+        .to_string();
+    models::user::get_user_from_session(pool, &token)
+        .ok()
+        .flatten()
+}
@@ -1,24 +1,79 @@
 use askama::Template;
 use axum::{
     Form,
-    extract::{Query, State},
-    response::{IntoResponse, Redirect},
+    extract::{Extension, Query, State},
+    http::StatusCode,
+    response::{IntoResponse, Redirect, Response},
 };
+use axum_extra::extract::cookie::CookieJar;
 use serde::Deserialize;
+use std::collections::HashMap;
 use tower_cookies::{Cookie, Cookies};
 
-use crate::{DbPool, models::project};
+use crate::{
+    DbPool,
+    config::SharedConfig,
+    models::{Role, error::FrameRewrite, project, project_member, span::RedactionRule},
+};
+
+use super::auth::{get_current_user, require_project_role, require_role};
 
-use super::project_context::{PROJECT_COOKIE, WebProjectContext, get_project_context};
+use super::project_context::{
+    PROJECT_COOKIE, WebProjectContext, current_user, get_project_context,
+};
 
 #[derive(Template)]
 #[template(path = "projects/index.html")]
 pub struct ProjectsTemplate {
     pub projects: Vec<project::Project>,
+    /// When each project's API key last authenticated, by project id
+    pub last_used: HashMap<i64, String>,
     pub message: Option<String>,
+    /// API key generated by this request, shown once
+    pub new_key: Option<String>,
+    /// Frame rewrite rules of the current project, one `from => to` per line
+    pub frame_rewrites: String,
+    /// Ingest redaction rules of the current project, one per line
+    pub redaction_rules: String,
+    /// The current project's display offset, empty when it follows `DISPLAY_TIMEZONE`
+    pub display_timezone: String,
+    /// Whether the current project accepts signed deploy webhooks
+    pub has_webhook_secret: bool,
+    /// Whether the current project accepts signed ingest requests
+    pub has_signing_secret: bool,
+    /// Whether ingest is switched off for the current project
+    pub ingest_disabled: bool,
+    /// The current project's tail sampling overrides, as entered in the form
+    /// (percent kept, slow threshold in ms); empty when following the environment
+    pub tail_sample_percent: String,
+    pub tail_slow_ms: String,
+    /// Rules in effect for the current project
+    pub tail_sampling: project::TailSampling,
+    /// The current project's auto-resolve override in days, empty when
+    /// following the environment
+    pub auto_resolve_days: String,
+    /// Threshold in effect for the current project, `None` when disabled
+    pub auto_resolve_effective: Option<i64>,
+    /// The current project's error policy, `None` when following `ERROR_STATUS_POLICY`
+    pub error_policy: Option<project::ErrorPolicy>,
+    /// Policy in effect for the current project
+    pub error_policy_effective: project::ErrorPolicy,
+    pub error_policies: [project::ErrorPolicy; 3],
     pub ctx: WebProjectContext,
 }
 
+impl ProjectsTemplate {
+    /// When `id`'s API key last authenticated, or "never"
+    pub fn last_used(&self, id: &i64) -> &str {
+        self.last_used.get(id).map_or("never", String::as_str)
+    }
+
+    /// Whether `policy` is the current project's own setting
+    pub fn is_error_policy(&self, policy: &project::ErrorPolicy) -> bool {
+        self.error_policy.as_ref() == Some(policy)
+    }
+}
+
 #[derive(Deserialize)]
 pub struct ProjectsQuery {
     pub message: Option<String>,
@@ -26,15 +81,100 @@ pub struct ProjectsQuery {
 
 pub async fn index(
     State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
     cookies: Cookies,
     Query(query): Query<ProjectsQuery>,
 ) -> ProjectsTemplate {
-    let ctx = get_project_context(&pool, &cookies);
-    let projects = project::list_all(&pool).unwrap_or_default();
+    page(&pool, &config, &cookies, query.message, None)
+}
+
+/// The projects page, showing `new_key` in full when it was just generated
+fn page(
+    pool: &DbPool,
+    config: &SharedConfig,
+    cookies: &Cookies,
+    message: Option<String>,
+    new_key: Option<String>,
+) -> ProjectsTemplate {
+    let ctx = get_project_context(pool, cookies);
+    let user = current_user(pool, cookies);
+    let projects = project_member::visible_projects(pool, user.as_ref()).unwrap_or_default();
+    let frame_rewrites = ctx
+        .project_id()
+        .and_then(|id| project::frame_rewrites(pool, id).ok())
+        .unwrap_or_default()
+        .iter()
+        .map(|r| format!("{} => {}", r.from, r.to))
+        .collect::<Vec<_>>()
+        .join("\n");
+    let redaction_rules = ctx
+        .project_id()
+        .and_then(|id| project::redaction_rules(pool, id).ok())
+        .unwrap_or_default()
+        .iter()
+        .map(RedactionRule::to_line)
+        .collect::<Vec<_>>()
+        .join("\n");
+    let display_timezone = ctx
+        .project_id()
+        .and_then(|id| project::display_timezone(pool, id))
+        .map(|offset| offset.to_string())
+        .unwrap_or_default();
+    let has_webhook_secret = ctx
+        .project_id()
+        .is_some_and(|id| project::has_webhook_secret(pool, id));
+    let has_signing_secret = ctx
+        .project_id()
+        .is_some_and(|id| project::has_signing_secret(pool, id));
+    let ingest_disabled = ctx
+        .project_id()
+        .is_some_and(|id| project::is_disabled(pool, id).unwrap_or(false));
+    let (sample_rate, slow_ms) = ctx
+        .project_id()
+        .map(|id| project::tail_sampling_overrides(pool, id))
+        .unwrap_or((None, None));
+    let tail_sampling = project::tail_sampling(
+        pool,
+        ctx.project_id(),
+        project::TailSampling::from_config(&config.load()),
+    );
+    let auto_resolve_days = ctx
+        .project_id()
+        .and_then(|id| project::auto_resolve_override(pool, id))
+        .map(|days| days.to_string())
+        .unwrap_or_default();
+    let auto_resolve_effective = project::auto_resolve_days(
+        pool,
+        ctx.project_id(),
+        config.load().error_auto_resolve_days,
+    );
+    let error_policy = ctx
+        .project_id()
+        .and_then(|id| project::error_policy_override(pool, id));
+    let error_policy_effective =
+        project::error_policy(pool, ctx.project_id(), config.load().error_status_policy);
 
     ProjectsTemplate {
         projects,
-        message: query.message,
+        last_used: project::last_used_at(pool).unwrap_or_default(),
+        message,
+        new_key,
+        frame_rewrites,
+        redaction_rules,
+        display_timezone,
+        has_webhook_secret,
+        has_signing_secret,
+        ingest_disabled,
+        tail_sample_percent: sample_rate
+            .map(|rate| (rate * 100.0).to_string())
+            .unwrap_or_default(),
+        tail_slow_ms: slow_ms.map(|ms| ms.to_string()).unwrap_or_default(),
+        tail_sampling,
+        auto_resolve_days,
+        auto_resolve_effective,
+        error_policy,
+        error_policy_effective,
+        error_policies: project::ErrorPolicy::ALL,
         ctx,
     }
 }
@@ -44,13 +184,50 @@ pub struct SwitchForm {
     pub slug: String,
 }
 
-pub async fn switch_project(cookies: Cookies, Form(form): Form<SwitchForm>) -> impl IntoResponse {
+pub async fn switch_project(
+    State(pool): State<DbPool>,
+    cookies: Cookies,
+    Form(form): Form<SwitchForm>,
+) -> Response {
+    if let Some(user) = current_user(&pool, &cookies) {
+        let allowed = project::find_by_slug(&pool, &form.slug)
+            .ok()
+            .flatten()
+            .is_some_and(|p| project_member::can_access(&pool, &user, p.id).unwrap_or(false));
+        if !allowed {
+            return (
+                StatusCode::FORBIDDEN,
+                "You are not a member of this project",
+            )
+                .into_response();
+        }
+    }
+
     let cookie = Cookie::build((PROJECT_COOKIE, form.slug))
         .path("/")
         .http_only(true)
         .build();
     cookies.add(cookie);
-    Redirect::to("/")
+    Redirect::to("/").into_response()
+}
+
+/// Require editor access to a specific project: with projects enabled, an
+/// editor membership of it (or admin)
+#[allow(clippy::result_large_err)]
+fn require_project_editor(
+    pool: &DbPool,
+    config: &SharedConfig,
+    jar: &CookieJar,
+    project_id: i64,
+) -> Result<(), Response> {
+    let user = get_current_user(pool, jar);
+    require_project_role(
+        pool,
+        user.as_ref(),
+        config.load().enable_projects,
+        Some(project_id),
+        Role::Editor,
+    )
 }
 
 #[derive(Deserialize)]
@@ -58,13 +235,33 @@ pub struct CreateForm {
     pub name: String,
 }
 
-pub async fn create(State(pool): State<DbPool>, Form(form): Form<CreateForm>) -> impl IntoResponse {
+pub async fn create(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    cookies: Cookies,
+    Form(form): Form<CreateForm>,
+) -> Response {
+    let user = match require_role(&pool, &jar, Role::Editor) {
+        Ok(user) => user,
+        Err(resp) => return resp,
+    };
     if form.name.trim().is_empty() {
-        return Redirect::to("/projects");
+        return Redirect::to("/projects").into_response();
     }
 
-    let _ = project::create(&pool, form.name.trim());
-    Redirect::to("/projects")
+    if let Ok((project, api_key)) = project::create(&pool, form.name.trim()) {
+        // The creator needs membership to see the project they just made
+        if let Some(user) = user
+            && !user.has_role(Role::Admin)
+        {
+            let _ = project_member::add(&pool, user.id, project.id, user.role);
+        }
+        // Render the key here rather than after a redirect, so it never travels
+        // in a cookie or a URL
+        return page(&pool, &config, &cookies, None, Some(api_key)).into_response();
+    }
+    Redirect::to("/projects").into_response()
 }
 
 #[derive(Deserialize)]
@@ -72,9 +269,17 @@ pub struct DeleteForm {
     pub id: i64,
 }
 
-pub async fn delete(State(pool): State<DbPool>, Form(form): Form<DeleteForm>) -> impl IntoResponse {
+pub async fn delete(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Form(form): Form<DeleteForm>,
+) -> Response {
+    if let Err(resp) = require_project_editor(&pool, &config, &jar, form.id) {
+        return resp;
+    }
     let _ = project::delete(&pool, form.id);
-    Redirect::to("/projects")
+    Redirect::to("/projects").into_response()
 }
 
 #[derive(Deserialize)]
@@ -84,8 +289,356 @@ pub struct RegenerateKeyForm {
 
 pub async fn regenerate_key(
     State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    cookies: Cookies,
     Form(form): Form<RegenerateKeyForm>,
-) -> impl IntoResponse {
-    let _ = project::regenerate_api_key(&pool, form.id);
-    Redirect::to("/projects")
+) -> Response {
+    if let Err(resp) = require_project_editor(&pool, &config, &jar, form.id) {
+        return resp;
+    }
+    if let Ok(api_key) = project::regenerate_api_key(&pool, form.id) {
+        return page(&pool, &config, &cookies, None, Some(api_key)).into_response();
+    }
+    Redirect::to("/projects").into_response()
+}
+
+#[derive(Deserialize)]
+pub struct FrameRewritesForm {
+    pub id: i64,
+    pub rules: String,
+}
+
+pub async fn update_frame_rewrites(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Form(form): Form<FrameRewritesForm>,
+) -> Response {
+    if let Err(resp) = require_project_editor(&pool, &config, &jar, form.id) {
+        return resp;
+    }
+    let rewrites = FrameRewrite::parse_rules(&form.rules);
+    let _ = project::set_frame_rewrites(&pool, form.id, &rewrites);
+    Redirect::to("/projects").into_response()
+}
+
+#[derive(Deserialize)]
+pub struct RedactionRulesForm {
+    pub id: i64,
+    pub rules: String,
+}
+
+pub async fn update_redaction_rules(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Form(form): Form<RedactionRulesForm>,
+) -> Response {
+    if let Err(resp) = require_project_editor(&pool, &config, &jar, form.id) {
+        return resp;
+    }
+    match RedactionRule::parse_rules(&form.rules) {
+        Ok(rules) => {
+            let _ = project::set_redaction_rules(&pool, form.id, &rules);
+            Redirect::to("/projects").into_response()
+        }
+        Err(message) => Redirect::to(&format!(
+            "/projects?message={}",
+            urlencoding::encode(&message)
+        ))
+        .into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct DisplayTimezoneForm {
+    pub id: i64,
+    pub offset: String,
+}
+
+/// Blank clears the project's offset so it follows `DISPLAY_TIMEZONE` again
+pub async fn update_display_timezone(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Form(form): Form<DisplayTimezoneForm>,
+) -> Response {
+    if let Err(resp) = require_project_editor(&pool, &config, &jar, form.id) {
+        return resp;
+    }
+    let offset = form.offset.trim();
+    if offset.is_empty() {
+        let _ = project::set_display_offset(&pool, form.id, None);
+        return Redirect::to("/projects").into_response();
+    }
+    match project::DisplayOffset::parse(offset) {
+        Some(parsed) => {
+            let _ = project::set_display_offset(&pool, form.id, Some(parsed));
+            Redirect::to("/projects").into_response()
+        }
+        None => Redirect::to(&format!(
+            "/projects?message={}",
+            urlencoding::encode(&format!(
+                "Invalid UTC offset {:?}, expected something like +02:00",
+                offset
+            ))
+        ))
+        .into_response(),
+    }
+}
+
+/// Blank clears the secret, so signed ingest requests for the project are rejected
+pub async fn update_signing_secret(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Form(form): Form<WebhookSecretForm>,
+) -> Response {
+    if let Err(resp) = require_project_editor(&pool, &config, &jar, form.id) {
+        return resp;
+    }
+    let secret = Some(form.secret.trim()).filter(|s| !s.is_empty());
+    let _ = project::set_signing_secret(&pool, form.id, secret);
+    Redirect::to("/projects").into_response()
+}
+
+#[derive(Deserialize)]
+pub struct IngestForm {
+    pub id: i64,
+    /// `disable` or `enable`
+    pub action: String,
+}
+
+/// Switch ingest off or back on; while off, the project's keys get a 403
+pub async fn update_ingest(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Form(form): Form<IngestForm>,
+) -> Response {
+    if let Err(resp) = require_project_editor(&pool, &config, &jar, form.id) {
+        return resp;
+    }
+    let _ = project::set_disabled(&pool, form.id, form.action == "disable");
+    Redirect::to("/projects").into_response()
+}
+
+#[derive(Deserialize)]
+pub struct TailSamplingForm {
+    pub id: i64,
+    /// Percent of uninteresting traces kept
+    pub sample_percent: String,
+    pub slow_ms: String,
+}
+
+/// A blank field clears that override so it follows the environment again
+pub async fn update_tail_sampling(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Form(form): Form<TailSamplingForm>,
+) -> Response {
+    if let Err(resp) = require_project_editor(&pool, &config, &jar, form.id) {
+        return resp;
+    }
+    let parse = |value: &str| -> Result<Option<f64>, ()> {
+        match value.trim() {
+            "" => Ok(None),
+            value => value.parse::<f64>().map(Some).map_err(|_| ()),
+        }
+    };
+    let sample_percent = parse(&form.sample_percent)
+        .ok()
+        .filter(|p| p.is_none_or(|p| (0.0..=100.0).contains(&p)));
+    let slow_ms = parse(&form.slow_ms)
+        .ok()
+        .filter(|ms| ms.is_none_or(|ms| ms.is_finite() && ms > 0.0));
+    match (sample_percent, slow_ms) {
+        (Some(sample_percent), Some(slow_ms)) => {
+            let sample_rate = sample_percent.map(|p| p / 100.0);
+            let _ = project::set_tail_sampling(&pool, form.id, sample_rate, slow_ms);
+            Redirect::to("/projects").into_response()
+        }
+        _ => Redirect::to(&format!(
+            "/projects?message={}",
+            urlencoding::encode(
+                "Tail sampling needs a percentage from 0 to 100 and a threshold above 0 ms"
+            )
+        ))
+        .into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct AutoResolveForm {
+    pub id: i64,
+    pub days: String,
+}
+
+/// A blank field clears the override so the project follows the environment;
+/// 0 turns auto-resolve off for the project
+pub async fn update_auto_resolve(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Form(form): Form<AutoResolveForm>,
+) -> Response {
+    if let Err(resp) = require_project_editor(&pool, &config, &jar, form.id) {
+        return resp;
+    }
+    let days = match form.days.trim() {
+        "" => Ok(None),
+        days => days.parse::<i64>().map(Some).map_err(|_| ()),
+    };
+    match days {
+        Ok(days) if days.is_none_or(|d| d >= 0) => {
+            let _ = project::set_auto_resolve_days(&pool, form.id, days);
+            Redirect::to("/projects").into_response()
+        }
+        _ => Redirect::to(&format!(
+            "/projects?message={}",
+            urlencoding::encode("Auto-resolve needs a whole number of days, or 0 to turn it off")
+        ))
+        .into_response(),
+    }
+}
+
+#[derive(Deserialize)]
+pub struct ErrorPolicyForm {
+    pub id: i64,
+    /// Empty follows the environment again
+    pub policy: String,
+}
+
+pub async fn update_error_policy(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Form(form): Form<ErrorPolicyForm>,
+) -> Response {
+    if let Err(resp) = require_project_editor(&pool, &config, &jar, form.id) {
+        return resp;
+    }
+    let policy = match form.policy.trim() {
+        "" => Ok(None),
+        policy => project::ErrorPolicy::parse(policy).map(Some).ok_or(()),
+    };
+    match policy {
+        Ok(policy) => {
+            let _ = project::set_error_policy(&pool, form.id, policy);
+            Redirect::to("/projects").into_response()
+        }
+        Err(()) => Redirect::to(&format!(
+            "/projects?message={}",
+            urlencoding::encode("Unknown error policy")
+        ))
+        .into_response(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::user;
+    use axum::{
+        Router,
+        body::Body,
+        http::{Request, header},
+        routing::post,
+    };
+    use tower::util::ServiceExt;
+    use tower_cookies::CookieManagerLayer;
+
+    fn switch_request(token: &str, slug: &str) -> Request<Body> {
+        Request::builder()
+            .method("POST")
+            .uri("/projects/switch")
+            .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+            .header(header::COOKIE, format!("miniapm_session={}", token))
+            .body(Body::from(format!("slug={}", slug)))
+            .unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_switch_project_requires_membership() {
+        let pool = crate::db::test_pool();
+        let (acme, _) = project::create(&pool, "Acme").unwrap();
+        let user_id = user::create(&pool, "client", "password123", Role::Viewer).unwrap();
+        let token = user::create_session(&pool, user_id).unwrap();
+        let app = Router::new()
+            .route("/projects/switch", post(switch_project))
+            .layer(CookieManagerLayer::new())
+            .with_state(pool.clone());
+
+        let response = app
+            .clone()
+            .oneshot(switch_request(&token, &acme.slug))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+
+        project_member::add(&pool, user_id, acme.id, Role::Viewer).unwrap();
+        let response = app
+            .oneshot(switch_request(&token, &acme.slug))
+            .await
+            .unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+    }
+
+    #[tokio::test]
+    async fn test_project_settings_follow_the_membership_role() {
+        let pool = crate::db::test_pool();
+        let (acme, _) = project::create(&pool, "Acme").unwrap();
+        let app = Router::new()
+            .route("/projects/delete", post(delete))
+            .layer(Extension(crate::config::Config::shared_with_projects()))
+            .with_state(pool.clone());
+        let delete_request = |token: &str| {
+            Request::builder()
+                .method("POST")
+                .uri("/projects/delete")
+                .header(header::CONTENT_TYPE, "application/x-www-form-urlencoded")
+                .header(header::COOKIE, format!("miniapm_session={}", token))
+                .body(Body::from(format!("id={}", acme.id)))
+                .unwrap()
+        };
+
+        // A global editor who only views this project can't delete it
+        let editor = user::create(&pool, "editor", "password123", Role::Editor).unwrap();
+        project_member::add(&pool, editor, acme.id, Role::Viewer).unwrap();
+        let token = user::create_session(&pool, editor).unwrap();
+        let response = app.clone().oneshot(delete_request(&token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::FORBIDDEN);
+        assert!(project::find(&pool, acme.id).unwrap().is_some());
+
+        // A global viewer who edits this project can
+        let viewer = user::create(&pool, "viewer", "password123", Role::Viewer).unwrap();
+        project_member::add(&pool, viewer, acme.id, Role::Editor).unwrap();
+        let token = user::create_session(&pool, viewer).unwrap();
+        let response = app.oneshot(delete_request(&token)).await.unwrap();
+        assert_eq!(response.status(), StatusCode::SEE_OTHER);
+        assert!(project::find(&pool, acme.id).unwrap().is_none());
+    }
+}
+
+#[derive(Deserialize)]
+pub struct WebhookSecretForm {
+    pub id: i64,
+    pub secret: String,
+}
+
+/// Blank clears the secret, so webhooks for the project are rejected
+pub async fn update_webhook_secret(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    jar: CookieJar,
+    Form(form): Form<WebhookSecretForm>,
+) -> Response {
+    if let Err(resp) = require_project_editor(&pool, &config, &jar, form.id) {
+        return resp;
+    }
+    let secret = Some(form.secret.trim()).filter(|s| !s.is_empty());
+    let _ = project::set_webhook_secret(&pool, form.id, secret);
+    Redirect::to("/projects").into_response()
 }
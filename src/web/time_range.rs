@@ -0,0 +1,233 @@
+use chrono::{DateTime, Duration, NaiveDate, NaiveDateTime, Utc};
+
+/// Format of `<input type="datetime-local">`, which custom bounds round-trip through
+const INPUT_FORMAT: &str = "%Y-%m-%dT%H:%M";
+
+/// Window picked with `?range=1h|24h|7d|30d|all|custom&from=&to=`, shared by
+/// every page with a time filter. Custom bounds are UTC; `to` may be left out
+/// for a window that runs up to now. Query structs alias `range` to `period`,
+/// which older links use.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TimeRange {
+    /// Preset name, `all` or `custom`
+    pub range: String,
+    pub since: Option<DateTime<Utc>>,
+    pub until: Option<DateTime<Utc>>,
+}
+
+impl TimeRange {
+    /// Parse the query parameters, falling back to the page's `default` preset
+    /// for unknown presets and invalid custom bounds
+    pub fn parse(range: Option<&str>, from: Option<&str>, to: Option<&str>, default: &str) -> Self {
+        Self::parse_at(range, from, to, default, Utc::now())
+    }
+
+    fn parse_at(
+        range: Option<&str>,
+        from: Option<&str>,
+        to: Option<&str>,
+        default: &str,
+        now: DateTime<Utc>,
+    ) -> Self {
+        let parsed = match range.filter(|r| !r.is_empty()).unwrap_or(default) {
+            "custom" => Self::custom(from, to, now),
+            preset => Self::preset(preset, now),
+        };
+        parsed
+            .or_else(|| Self::preset(default, now))
+            .unwrap_or(Self {
+                range: "all".to_string(),
+                since: None,
+                until: None,
+            })
+    }
+
+    fn preset(name: &str, now: DateTime<Utc>) -> Option<Self> {
+        let window = match name {
+            "1h" => Some(Duration::hours(1)),
+            "24h" => Some(Duration::hours(24)),
+            "7d" => Some(Duration::days(7)),
+            "30d" => Some(Duration::days(30)),
+            "all" => None,
+            _ => return None,
+        };
+        Some(Self {
+            range: name.to_string(),
+            since: window.map(|w| now - w),
+            until: None,
+        })
+    }
+
+    /// `from` is required and must be in the past; `to`, when given, after it
+    fn custom(from: Option<&str>, to: Option<&str>, now: DateTime<Utc>) -> Option<Self> {
+        let since = parse_bound(from?)?;
+        let until = match to.filter(|t| !t.trim().is_empty()) {
+            Some(to) => Some(parse_bound(to)?),
+            None => None,
+        };
+        if since > now || until.is_some_and(|until| until <= since) {
+            return None;
+        }
+        Some(Self {
+            range: "custom".to_string(),
+            since: Some(since),
+            until,
+        })
+    }
+
+    /// Whether `name` is a preset window (or `all`), as opposed to `custom`
+    pub fn is_preset(name: &str) -> bool {
+        Self::preset(name, Utc::now()).is_some()
+    }
+
+    pub fn is_custom(&self) -> bool {
+        self.range == "custom"
+    }
+
+    /// Lower bound for the model queries, `None` for all time
+    pub fn since_str(&self) -> Option<String> {
+        self.since.map(|s| s.to_rfc3339())
+    }
+
+    /// Exclusive upper bound for the model queries, `None` up to now
+    pub fn until_str(&self) -> Option<String> {
+        self.until.map(|u| u.to_rfc3339())
+    }
+
+    /// Custom bounds formatted for the range form, empty for presets
+    pub fn start_input(&self) -> String {
+        self.input_value(self.since)
+    }
+
+    pub fn end_input(&self) -> String {
+        self.input_value(self.until)
+    }
+
+    fn input_value(&self, bound: Option<DateTime<Utc>>) -> String {
+        match bound {
+            Some(bound) if self.is_custom() => bound.format(INPUT_FORMAT).to_string(),
+            _ => String::new(),
+        }
+    }
+
+    /// Query string selecting this range, for links that keep it
+    pub fn query(&self) -> String {
+        if !self.is_custom() {
+            return format!("range={}", self.range);
+        }
+        let mut query = format!("range=custom&from={}", self.start_input());
+        if self.until.is_some() {
+            query.push_str(&format!("&to={}", self.end_input()));
+        }
+        query
+    }
+}
+
+/// RFC 3339, a `datetime-local` value or a bare date, the last two taken as UTC
+fn parse_bound(value: &str) -> Option<DateTime<Utc>> {
+    let value = value.trim();
+    if let Ok(at) = DateTime::parse_from_rfc3339(value) {
+        return Some(at.with_timezone(&Utc));
+    }
+    ["%Y-%m-%dT%H:%M", "%Y-%m-%dT%H:%M:%S", "%Y-%m-%d %H:%M"]
+        .iter()
+        .find_map(|format| NaiveDateTime::parse_from_str(value, format).ok())
+        .or_else(|| {
+            NaiveDate::parse_from_str(value, "%Y-%m-%d")
+                .ok()
+                .and_then(|date| date.and_hms_opt(0, 0, 0))
+        })
+        .map(|naive| naive.and_utc())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn now() -> DateTime<Utc> {
+        DateTime::parse_from_rfc3339("2026-06-10T12:00:00Z")
+            .unwrap()
+            .with_timezone(&Utc)
+    }
+
+    fn parse(range: Option<&str>, from: Option<&str>, to: Option<&str>) -> TimeRange {
+        TimeRange::parse_at(range, from, to, "24h", now())
+    }
+
+    #[test]
+    fn test_presets() {
+        for (name, window) in [
+            ("1h", Duration::hours(1)),
+            ("24h", Duration::hours(24)),
+            ("7d", Duration::days(7)),
+            ("30d", Duration::days(30)),
+        ] {
+            let range = parse(Some(name), None, None);
+            assert_eq!(range.range, name);
+            assert_eq!(range.since, Some(now() - window));
+            assert_eq!(range.until, None);
+            assert_eq!(range.query(), format!("range={}", name));
+        }
+
+        let all = parse(Some("all"), None, None);
+        assert_eq!((all.since, all.until), (None, None));
+
+        // Missing or unknown presets fall back to the page default
+        assert_eq!(parse(None, None, None).range, "24h");
+        assert_eq!(parse(Some("90d"), None, None).range, "24h");
+        assert_eq!(
+            TimeRange::parse_at(Some(""), None, None, "all", now()).since,
+            None
+        );
+    }
+
+    #[test]
+    fn test_custom_bounds() {
+        let range = parse(
+            Some("custom"),
+            Some("2026-06-01T08:30"),
+            Some("2026-06-02T00:00:00+02:00"),
+        );
+        assert!(range.is_custom());
+        assert_eq!(
+            range.since_str().as_deref(),
+            Some("2026-06-01T08:30:00+00:00")
+        );
+        assert_eq!(
+            range.until_str().as_deref(),
+            Some("2026-06-01T22:00:00+00:00")
+        );
+        assert_eq!(
+            range.query(),
+            "range=custom&from=2026-06-01T08:30&to=2026-06-01T22:00"
+        );
+
+        // Without `to` the window is open-ended
+        let open = parse(Some("custom"), Some("2026-06-01"), Some(""));
+        assert_eq!(
+            open.since_str().as_deref(),
+            Some("2026-06-01T00:00:00+00:00")
+        );
+        assert_eq!(open.until, None);
+        assert_eq!(open.query(), "range=custom&from=2026-06-01T00:00");
+    }
+
+    #[test]
+    fn test_invalid_custom_bounds_fall_back_to_default() {
+        for (from, to) in [
+            // Missing or unparsable start
+            (None, Some("2026-06-02")),
+            (Some("yesterday"), None),
+            // End not after start
+            (Some("2026-06-02"), Some("2026-06-01")),
+            (Some("2026-06-02"), Some("2026-06-02")),
+            (Some("2026-06-01"), Some("soon")),
+            // Starts in the future
+            (Some("2026-07-01"), None),
+        ] {
+            let range = parse(Some("custom"), from, to);
+            assert_eq!(range.range, "24h", "{from:?} {to:?}");
+            assert_eq!(range.start_input(), "");
+        }
+    }
+}
@@ -1,12 +1,17 @@
 use askama::Template;
-use axum::extract::{Path, Query, State};
-use chrono::{Duration, Utc};
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::{StatusCode, header},
+    response::{IntoResponse, Response},
+};
 use serde::Deserialize;
 use tower_cookies::Cookies;
 
-use crate::{DbPool, models};
+use crate::{DbPool, config::SharedConfig, models};
 
-use super::project_context::{WebProjectContext, get_project_context};
+use super::project_context::{WebProjectContext, current_user, get_project_context};
+use super::time_range::TimeRange;
 
 const PAGE_SIZE: i64 = 50;
 
@@ -17,11 +22,22 @@ pub struct TracesIndexTemplate {
     pub total_count: i64,
     pub type_filter: Option<String>,
     pub search: Option<String>,
-    pub period: String,
+    pub range: TimeRange,
     pub min_duration: Option<String>,
+    pub environment: Option<String>,
+    pub service: Option<String>,
+    /// Distinct values for the environment and service dropdowns
+    pub environments: Vec<String>,
+    pub services: Vec<String>,
     pub sort: String,
     pub page: i64,
     pub total_pages: i64,
+    /// Whether this is a later keyset page of the default order
+    pub paged: bool,
+    /// `?after=` value for the next keyset page
+    pub next_cursor: Option<String>,
+    /// Which statuses the status column marks as errors
+    pub error_policy: models::project::ErrorPolicy,
     pub ctx: WebProjectContext,
 }
 
@@ -30,14 +46,22 @@ pub struct TracesQuery {
     #[serde(rename = "type")]
     pub root_type: Option<String>,
     pub search: Option<String>,
-    pub period: Option<String>,
+    #[serde(alias = "period")]
+    pub range: Option<String>,
+    pub from: Option<String>,
+    pub to: Option<String>,
     pub min_duration: Option<String>,
+    pub environment: Option<String>,
+    pub service: Option<String>,
     pub sort: Option<String>,
     pub page: Option<i64>,
+    /// Keyset cursor, used instead of `page` for the default order
+    pub after: Option<String>,
 }
 
 pub async fn index(
     State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
     cookies: Cookies,
     Query(query): Query<TracesQuery>,
 ) -> TracesIndexTemplate {
@@ -49,59 +73,102 @@ pub async fn index(
         .as_deref()
         .and_then(models::RootSpanType::parse);
 
-    let period = query.period.unwrap_or_else(|| "all".to_string());
+    let range = TimeRange::parse(
+        query.range.as_deref(),
+        query.from.as_deref(),
+        query.to.as_deref(),
+        "all",
+    );
     let sort = query.sort.unwrap_or_else(|| "recent".to_string());
     let search = query.search.clone().filter(|s| !s.is_empty());
     let min_duration = query.min_duration.clone().filter(|s| !s.is_empty());
+    let environment = query.environment.clone().filter(|s| !s.is_empty());
+    let service = query.service.clone().filter(|s| !s.is_empty());
     let page = query.page.unwrap_or(1).max(1);
 
-    let since = match period.as_str() {
-        "1h" => Some(Utc::now() - Duration::hours(1)),
-        "24h" => Some(Utc::now() - Duration::hours(24)),
-        "7d" => Some(Utc::now() - Duration::days(7)),
-        "30d" => Some(Utc::now() - Duration::days(30)),
-        _ => None, // "all"
-    };
-
-    let since_str = since.map(|s| s.to_rfc3339());
+    let since_str = range.since_str();
+    let until_str = range.until_str();
     let min_duration_ms: Option<f64> = min_duration.as_ref().and_then(|s| s.parse().ok());
 
-    let total_count = models::span::count_traces_filtered(
-        &pool,
-        project_id,
-        root_type_filter,
-        since_str.as_deref(),
-        search.as_deref(),
-        min_duration_ms,
-    )
-    .unwrap_or(0);
+    let after = query.after.as_deref().and_then(models::Cursor::decode);
+    // `search` may be `attr:key=value`, matching span attributes instead of names and URLs
+    let count = || {
+        models::span::count_traces_filtered(
+            &pool,
+            project_id,
+            root_type_filter,
+            since_str.as_deref(),
+            until_str.as_deref(),
+            search.as_deref(),
+            min_duration_ms,
+            environment.as_deref(),
+            service.as_deref(),
+        )
+    };
 
+    // The default order pages by cursor so new traces don't shift pages
+    let (traces, total_count, next_cursor) = if sort == "recent" {
+        let total_count = count().unwrap_or(0);
+        let (traces, next_cursor) = models::span::list_traces_after(
+            &pool,
+            project_id,
+            root_type_filter,
+            since_str.as_deref(),
+            until_str.as_deref(),
+            search.as_deref(),
+            min_duration_ms,
+            environment.as_deref(),
+            service.as_deref(),
+            after.as_ref(),
+            PAGE_SIZE,
+        )
+        .unwrap_or_default();
+        (traces, total_count, next_cursor)
+    } else {
+        let offset = (page - 1) * PAGE_SIZE;
+        let page = models::Page::fetch(PAGE_SIZE, offset, count, |limit, offset| {
+            models::span::list_traces_paginated(
+                &pool,
+                project_id,
+                root_type_filter,
+                since_str.as_deref(),
+                until_str.as_deref(),
+                search.as_deref(),
+                min_duration_ms,
+                environment.as_deref(),
+                service.as_deref(),
+                &sort,
+                limit,
+                offset,
+            )
+        })
+        .unwrap_or_else(|_| models::Page::empty(PAGE_SIZE, offset));
+        (page.items, page.total, None)
+    };
     let total_pages = (total_count + PAGE_SIZE - 1) / PAGE_SIZE;
-    let offset = (page - 1) * PAGE_SIZE;
 
-    let traces = models::span::list_traces_paginated(
-        &pool,
-        project_id,
-        root_type_filter,
-        since_str.as_deref(),
-        search.as_deref(),
-        min_duration_ms,
-        &sort,
-        PAGE_SIZE,
-        offset,
-    )
-    .unwrap_or_default();
+    let environments = models::span::distinct_environments(&pool, project_id).unwrap_or_default();
+    let services = models::span::distinct_services(&pool, project_id).unwrap_or_default();
+    let error_policy =
+        models::project::error_policy(&pool, project_id, config.load().error_status_policy);
 
     TracesIndexTemplate {
         traces,
         total_count,
         type_filter: query.root_type,
         search,
-        period,
+        range,
         min_duration,
+        environment,
+        service,
+        environments,
+        services,
         sort,
         page,
         total_pages,
+        paged: after.is_some(),
+        next_cursor: next_cursor.map(|c| c.encode()),
+        error_policy,
         ctx,
     }
 }
@@ -111,16 +178,32 @@ pub async fn index(
 pub struct TraceShowTemplate {
     pub trace: Option<models::TraceDetail>,
     pub n_plus_1_issues: Vec<models::span::NPlus1Issue>,
+    /// Runs of internal spans are folded into summary rows
+    pub collapsed: bool,
     pub ctx: WebProjectContext,
 }
 
+#[derive(Deserialize)]
+pub struct ShowQuery {
+    /// `collapse=1` folds runs of sibling internal spans
+    pub collapse: Option<String>,
+}
+
 pub async fn show(
     State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
     cookies: Cookies,
     Path(trace_id): Path<String>,
-) -> TraceShowTemplate {
+    Query(query): Query<ShowQuery>,
+) -> Response {
+    let user = current_user(&pool, &cookies);
+    if let Err(resp) = require_trace_access(&pool, &config, user.as_ref(), &trace_id) {
+        return resp;
+    }
     let ctx = get_project_context(&pool, &cookies);
-    let trace = models::span::get_trace(&pool, &trace_id).unwrap_or(None);
+    let collapsed = matches!(query.collapse.as_deref(), Some("1" | "true"));
+    let sensitive = &config.load().sensitive_attribute_keys;
+    let mut trace = models::span::get_trace(&pool, &trace_id, sensitive).unwrap_or(None);
 
     // Detect N+1 issues
     let n_plus_1_issues = if let Some(ref t) = trace {
@@ -129,9 +212,477 @@ pub async fn show(
         vec![]
     };
 
+    if collapsed && let Some(t) = trace.as_mut() {
+        models::span::collapse_internal_spans(t);
+    }
+
     TraceShowTemplate {
         trace,
         n_plus_1_issues,
+        collapsed,
         ctx,
     }
+    .into_response()
+}
+
+/// Require read access to the project of `trace_id`, see
+/// [`super::auth::require_project_role`]
+#[allow(clippy::result_large_err)]
+fn require_trace_access(
+    pool: &DbPool,
+    config: &SharedConfig,
+    user: Option<&models::User>,
+    trace_id: &str,
+) -> Result<(), Response> {
+    let project_id = models::span::trace_project(pool, trace_id)
+        .ok()
+        .flatten()
+        .flatten();
+    super::auth::require_project_role(
+        pool,
+        user,
+        config.load().enable_projects,
+        project_id,
+        models::Role::Viewer,
+    )
+}
+
+#[derive(Template)]
+#[template(path = "traces/compare.html")]
+pub struct TraceCompareTemplate {
+    pub diff: models::span::TraceDiff,
+    pub ctx: WebProjectContext,
+}
+
+#[derive(Deserialize)]
+pub struct CompareQuery {
+    pub a: String,
+    pub b: String,
+    pub format: Option<String>,
+}
+
+/// Side-by-side diff of two traces, as HTML or with `format=json`
+pub async fn compare(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    cookies: Cookies,
+    Query(query): Query<CompareQuery>,
+) -> Response {
+    let user = current_user(&pool, &cookies);
+    for trace_id in [&query.a, &query.b] {
+        if let Err(resp) = require_trace_access(&pool, &config, user.as_ref(), trace_id) {
+            return resp;
+        }
+    }
+    let sensitive = &config.load().sensitive_attribute_keys;
+    let load = |trace_id: &str| models::span::get_trace(&pool, trace_id, sensitive);
+    let (a, b) = match (load(&query.a), load(&query.b)) {
+        (Ok(Some(a)), Ok(Some(b))) => (a, b),
+        (Ok(_), Ok(_)) => return StatusCode::NOT_FOUND.into_response(),
+        (Err(e), _) | (_, Err(e)) => {
+            tracing::error!("Failed to load traces for comparison: {}", e);
+            return StatusCode::INTERNAL_SERVER_ERROR.into_response();
+        }
+    };
+
+    let diff = models::span::diff_traces(&a, &b);
+    if query.format.as_deref() == Some("json") {
+        return Json(diff).into_response();
+    }
+    TraceCompareTemplate {
+        diff,
+        ctx: get_project_context(&pool, &cookies),
+    }
+    .into_response()
+}
+
+/// Download a trace as OTLP/JSON for re-import into other tooling
+pub async fn export(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    cookies: Cookies,
+    Path(trace_id): Path<String>,
+) -> Response {
+    let user = current_user(&pool, &cookies);
+    if let Err(resp) = require_trace_access(&pool, &config, user.as_ref(), &trace_id) {
+        return resp;
+    }
+    match models::span::export_trace_otlp(&pool, &trace_id) {
+        Ok(Some(request)) => (
+            [(
+                header::CONTENT_DISPOSITION,
+                format!("attachment; filename=\"trace-{}.json\"", trace_id),
+            )],
+            Json(request),
+        )
+            .into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to export trace {}: {}", trace_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// `GET /traces/:trace_id/flamegraph`: spans merged by name with self and
+/// total time, as JSON for a flamegraph renderer
+pub async fn flamegraph(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    cookies: Cookies,
+    Path(trace_id): Path<String>,
+) -> Response {
+    let user = current_user(&pool, &cookies);
+    if let Err(resp) = require_trace_access(&pool, &config, user.as_ref(), &trace_id) {
+        return resp;
+    }
+    match models::span::trace_flamegraph(&pool, &trace_id) {
+        Ok(Some(graph)) => Json(graph).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!("Failed to build flamegraph for trace {}: {}", trace_id, e);
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+/// `GET /traces/:trace_id/spans/:span_id/attributes`: one span's stored
+/// attributes, events and resource attributes as JSON, for debugging
+pub async fn span_attributes(
+    State(pool): State<DbPool>,
+    Extension(config): Extension<SharedConfig>,
+    cookies: Cookies,
+    Path((trace_id, span_id)): Path<(String, String)>,
+) -> Response {
+    let user = current_user(&pool, &cookies);
+    if let Err(resp) = require_trace_access(&pool, &config, user.as_ref(), &trace_id) {
+        return resp;
+    }
+    let sensitive = &config.load().sensitive_attribute_keys;
+    match models::span::raw_span_attributes(&pool, &trace_id, &span_id, sensitive) {
+        Ok(Some(raw)) => Json(raw).into_response(),
+        Ok(None) => StatusCode::NOT_FOUND.into_response(),
+        Err(e) => {
+            tracing::error!(
+                "Failed to load attributes of span {} in trace {}: {}",
+                span_id,
+                trace_id,
+                e
+            );
+            StatusCode::INTERNAL_SERVER_ERROR.into_response()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::models::span::{OtlpTraceRequest, insert_otlp_batch};
+    use axum::{Router, body::Body, http::Request, routing::get};
+    use tower::util::ServiceExt;
+
+    const FOREIGN_TRACE: &str = "0af7651916cd43dd8448eb211c80319c";
+
+    /// A trace in one project, and a session for an editor who is only a
+    /// member of another
+    fn foreign_trace(pool: &DbPool) -> String {
+        let (acme, _) = models::project::create(pool, "Acme").unwrap();
+        let (globex, _) = models::project::create(pool, "Globex").unwrap();
+        let batch: OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+            "resourceSpans": [{"scopeSpans": [{"spans": [{
+                "traceId": FOREIGN_TRACE,
+                "spanId": "b7ad6b7169203331",
+                "name": "GET /invoices",
+                "kind": 2,
+                "startTimeUnixNano": "1700000000000000000",
+                "endTimeUnixNano": "1700000000100000000"
+            }]}]}]
+        }))
+        .unwrap();
+        insert_otlp_batch(pool, &batch, Some(acme.id)).unwrap();
+        let outsider =
+            models::user::create(pool, "outsider", "password123", models::Role::Editor).unwrap();
+        models::project_member::add(pool, outsider, globex.id, models::Role::Editor).unwrap();
+        models::user::create_session(pool, outsider).unwrap()
+    }
+
+    fn app(pool: &DbPool, config: SharedConfig) -> Router {
+        Router::new()
+            .route("/traces/:trace_id", get(show))
+            .route("/traces/:trace_id/export", get(export))
+            .route("/traces/compare", get(compare))
+            .route("/traces/:trace_id/flamegraph", get(flamegraph))
+            .route(
+                "/traces/:trace_id/spans/:span_id/attributes",
+                get(span_attributes),
+            )
+            .layer(Extension(config))
+            .layer(tower_cookies::CookieManagerLayer::new())
+            .with_state(pool.clone())
+    }
+
+    async fn get_with_projects(pool: &DbPool, uri: &str, token: &str) -> StatusCode {
+        let request = Request::builder()
+            .uri(uri)
+            .header(header::COOKIE, format!("miniapm_session={}", token))
+            .body(Body::empty())
+            .unwrap();
+        app(pool, crate::config::Config::shared_with_projects())
+            .oneshot(request)
+            .await
+            .unwrap()
+            .status()
+    }
+
+    #[tokio::test]
+    async fn test_non_member_cannot_see_trace() {
+        let pool = crate::db::test_pool();
+        let token = foreign_trace(&pool);
+        let uri = format!("/traces/{}", FOREIGN_TRACE);
+        assert_eq!(
+            get_with_projects(&pool, &uri, &token).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_member_cannot_export_trace() {
+        let pool = crate::db::test_pool();
+        let token = foreign_trace(&pool);
+        let uri = format!("/traces/{}/export", FOREIGN_TRACE);
+        assert_eq!(
+            get_with_projects(&pool, &uri, &token).await,
+            StatusCode::NOT_FOUND
+        );
+
+        // Members get the download
+        let acme = models::project::list_all(&pool)
+            .unwrap()
+            .into_iter()
+            .find(|p| p.name == "Acme")
+            .unwrap();
+        let member =
+            models::user::create(&pool, "member", "password123", models::Role::Viewer).unwrap();
+        models::project_member::add(&pool, member, acme.id, models::Role::Viewer).unwrap();
+        let token = models::user::create_session(&pool, member).unwrap();
+        assert_eq!(get_with_projects(&pool, &uri, &token).await, StatusCode::OK);
+    }
+
+    #[tokio::test]
+    async fn test_non_member_cannot_see_flamegraph() {
+        let pool = crate::db::test_pool();
+        let token = foreign_trace(&pool);
+        let uri = format!("/traces/{}/flamegraph", FOREIGN_TRACE);
+        assert_eq!(
+            get_with_projects(&pool, &uri, &token).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_member_cannot_see_span_attributes() {
+        let pool = crate::db::test_pool();
+        let token = foreign_trace(&pool);
+        let uri = format!(
+            "/traces/{}/spans/b7ad6b7169203331/attributes",
+            FOREIGN_TRACE
+        );
+        assert_eq!(
+            get_with_projects(&pool, &uri, &token).await,
+            StatusCode::NOT_FOUND
+        );
+    }
+
+    #[tokio::test]
+    async fn test_non_member_cannot_compare_with_foreign_trace() {
+        let pool = crate::db::test_pool();
+        let token = foreign_trace(&pool);
+        // The outsider's own trace doesn't open up the other side
+        let globex = models::project::list_all(&pool)
+            .unwrap()
+            .into_iter()
+            .find(|p| p.name == "Globex")
+            .unwrap();
+        let batch: OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+            "resourceSpans": [{"scopeSpans": [{"spans": [{
+                "traceId": "4bf92f3577b34da6a3ce929d0e0e4736",
+                "spanId": "00f067aa0ba902b7",
+                "name": "GET /invoices",
+                "kind": 2,
+                "startTimeUnixNano": "1700000000000000000",
+                "endTimeUnixNano": "1700000000200000000"
+            }]}]}]
+        }))
+        .unwrap();
+        insert_otlp_batch(&pool, &batch, Some(globex.id)).unwrap();
+
+        for (a, b) in [
+            (FOREIGN_TRACE, "4bf92f3577b34da6a3ce929d0e0e4736"),
+            ("4bf92f3577b34da6a3ce929d0e0e4736", FOREIGN_TRACE),
+        ] {
+            let uri = format!("/traces/compare?a={}&b={}&format=json", a, b);
+            assert_eq!(
+                get_with_projects(&pool, &uri, &token).await,
+                StatusCode::NOT_FOUND
+            );
+        }
+    }
+
+    #[test]
+    fn test_show_renders_span_events_in_chronological_order() {
+        let pool = crate::db::test_pool();
+        let batch: OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+            "resourceSpans": [{"scopeSpans": [{"spans": [{
+                "traceId": "4bf92f3577b34da6a3ce929d0e0e4736",
+                "spanId": "00f067aa0ba902b7",
+                "name": "GET /cart",
+                "kind": 2,
+                "startTimeUnixNano": "1700000000000000000",
+                "endTimeUnixNano": "1700000000100000000",
+                "events": [
+                    {"name": "cart.loaded", "timeUnixNano": "1700000000080000000"},
+                    {
+                        "name": "cache.miss",
+                        "timeUnixNano": "1700000000010000000",
+                        "attributes": [{"key": "cache.key", "value": {"stringValue": "cart/7"}}]
+                    }
+                ]
+            }]}]}]
+        }))
+        .unwrap();
+        insert_otlp_batch(&pool, &batch, None).unwrap();
+
+        let html = TraceShowTemplate {
+            trace: models::span::get_trace(&pool, "4bf92f3577b34da6a3ce929d0e0e4736", &[]).unwrap(),
+            n_plus_1_issues: vec![],
+            collapsed: false,
+            ctx: WebProjectContext {
+                current_project: None,
+                projects: vec![],
+                projects_enabled: false,
+                restricted: false,
+            },
+        }
+        .render()
+        .unwrap();
+
+        assert_eq!(html.matches(r#"class="waterfall-event""#).count(), 2);
+        assert!(html.contains(r#"style="left: 10.00%;""#));
+        assert!(html.contains("cache.key=cart/7"));
+        let miss = html.find("cache.miss").unwrap();
+        let loaded = html.find("cart.loaded").unwrap();
+        assert!(miss < loaded);
+    }
+
+    #[test]
+    fn test_show_lists_logs_linked_to_their_span() {
+        let pool = crate::db::test_pool();
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let batch: OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+            "resourceSpans": [{"scopeSpans": [{"spans": [{
+                "traceId": trace_id,
+                "spanId": "00f067aa0ba902b7",
+                "name": "GET /cart",
+                "kind": 2,
+                "startTimeUnixNano": "1700000000000000000",
+                "endTimeUnixNano": "1700000000100000000"
+            }]}]}]
+        }))
+        .unwrap();
+        insert_otlp_batch(&pool, &batch, None).unwrap();
+        let logs: models::log_record::OtlpLogsRequest = serde_json::from_value(serde_json::json!({
+            "resourceLogs": [{"scopeLogs": [{"logRecords": [{
+                "timeUnixNano": "1700000000025000000",
+                "severityNumber": 13,
+                "body": {"stringValue": "cart is stale"},
+                "traceId": trace_id,
+                "spanId": "00f067aa0ba902b7"
+            }]}]}]
+        }))
+        .unwrap();
+        models::log_record::insert_otlp_batch(&pool, &logs, None, &Default::default()).unwrap();
+
+        let html = TraceShowTemplate {
+            trace: models::span::get_trace(&pool, trace_id, &[]).unwrap(),
+            n_plus_1_issues: vec![],
+            collapsed: false,
+            ctx: WebProjectContext {
+                current_project: None,
+                projects: vec![],
+                projects_enabled: false,
+                restricted: false,
+            },
+        }
+        .render()
+        .unwrap();
+
+        assert!(html.contains("<h2>Logs</h2>"));
+        assert!(html.contains("+25.00ms"));
+        assert!(html.contains(r#"<span class="log-severity log-warning">WARN</span>"#));
+        assert!(html.contains("cart is stale"));
+        assert!(html.contains(r##"<a href="#span-00f067aa0ba902b7">"##));
+    }
+
+    #[tokio::test]
+    async fn test_span_attributes_returns_stored_json_or_404() {
+        let pool = crate::db::test_pool();
+        let trace_id = "4bf92f3577b34da6a3ce929d0e0e4736";
+        let batch: OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+            "resourceSpans": [{
+                "resource": {"attributes": [
+                    {"key": "service.name", "value": {"stringValue": "shop"}},
+                    {"key": "deploy.api_key", "value": {"stringValue": "k-123"}}
+                ]},
+                "scopeSpans": [{"spans": [{
+                    "traceId": trace_id,
+                    "spanId": "00f067aa0ba902b7",
+                    "name": "GET /cart",
+                    "kind": 2,
+                    "startTimeUnixNano": "1700000000000000000",
+                    "endTimeUnixNano": "1700000000100000000",
+                    "attributes": [
+                        {"key": "cart.items", "value": {"intValue": "3"}},
+                        {"key": "session_token", "value": {"stringValue": "s3cr3t"}}
+                    ],
+                    "events": [{
+                        "name": "cart.loaded",
+                        "timeUnixNano": "1700000000080000000",
+                        "attributes": [
+                            {"key": "auth.authorization", "value": {"stringValue": "Basic abc"}}
+                        ]
+                    }]
+                }]}]
+            }]
+        }))
+        .unwrap();
+        insert_otlp_batch(&pool, &batch, None).unwrap();
+
+        let fetch = |span_id: &str| {
+            let request = Request::builder()
+                .uri(format!("/traces/{}/spans/{}/attributes", trace_id, span_id))
+                .body(Body::empty())
+                .unwrap();
+            app(&pool, crate::config::Config::shared_defaults()).oneshot(request)
+        };
+        let response = fetch("00f067aa0ba902b7").await.unwrap();
+        assert_eq!(response.status(), StatusCode::OK);
+        let body = axum::body::to_bytes(response.into_body(), usize::MAX)
+            .await
+            .unwrap();
+        let raw: serde_json::Value = serde_json::from_slice(&body).unwrap();
+        assert_eq!(raw["name"], "GET /cart");
+        assert_eq!(raw["attributes"]["cart.items"], "3");
+        assert_eq!(raw["events"][0]["name"], "cart.loaded");
+        assert_eq!(raw["resource_attributes"]["service.name"], "shop");
+        // Sensitive values are masked in attributes and events alike
+        assert_eq!(raw["attributes"]["session_token"], "[REDACTED]");
+        assert_eq!(raw["resource_attributes"]["deploy.api_key"], "[REDACTED]");
+        assert_eq!(
+            raw["events"][0]["attributes"][0]["value"]["stringValue"],
+            "[REDACTED]"
+        );
+
+        let response = fetch("ffffffffffffffff").await.unwrap();
+        assert_eq!(response.status(), StatusCode::NOT_FOUND);
+    }
 }
@@ -0,0 +1,46 @@
+use tower_cookies::Cookies;
+
+use crate::{DbPool, models::user_pref};
+
+use super::project_context::current_user;
+
+/// Page settings that stick for the signed-in user. Without user accounts
+/// there's no one to key them by, so nothing is saved and pages keep their
+/// defaults.
+pub struct Prefs<'a> {
+    pool: &'a DbPool,
+    user_id: Option<i64>,
+}
+
+impl<'a> Prefs<'a> {
+    pub fn load(pool: &'a DbPool, cookies: &Cookies) -> Self {
+        Self {
+            pool,
+            user_id: current_user(pool, cookies).map(|user| user.id),
+        }
+    }
+
+    /// The value a page should use for `key`: `requested` when the query has
+    /// one, saving it for next time if `remember` accepts it, and otherwise
+    /// the user's saved value. `None` leaves the page on its default.
+    pub fn resolve(
+        &self,
+        key: &str,
+        requested: Option<&str>,
+        remember: impl Fn(&str) -> bool,
+    ) -> Option<String> {
+        match (requested.filter(|value| !value.is_empty()), self.user_id) {
+            (Some(value), Some(user_id)) => {
+                if remember(value)
+                    && let Err(e) = user_pref::set(self.pool, user_id, key, value)
+                {
+                    tracing::warn!("Failed to save {} preference: {}", key, e);
+                }
+                Some(value.to_string())
+            }
+            (Some(value), None) => Some(value.to_string()),
+            (None, Some(user_id)) => user_pref::get(self.pool, user_id, key).ok().flatten(),
+            (None, None) => None,
+        }
+    }
+}
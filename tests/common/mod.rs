@@ -0,0 +1,25 @@
+#![allow(dead_code)]
+
+use std::path::Path;
+use std::process::{Command, Output};
+
+use miniapm::DbPool;
+use r2d2_sqlite::SqliteConnectionManager;
+
+/// Run the `miniapm` binary against the database at `db`
+pub fn miniapm(db: &Path, args: &[&str]) -> Output {
+    Command::new(env!("CARGO_BIN_EXE_miniapm"))
+        .args(args)
+        .env("SQLITE_PATH", db)
+        .env_remove("ENABLE_USER_ACCOUNTS")
+        .output()
+        .unwrap()
+}
+
+/// Create the schema through the binary, then open the same file
+pub fn open_db(db: &Path) -> DbPool {
+    assert!(miniapm(db, &["list-tokens"]).status.success());
+    r2d2::Pool::new(SqliteConnectionManager::file(db))
+        .unwrap()
+        .into()
+}
@@ -0,0 +1,63 @@
+mod common;
+
+use std::path::Path;
+
+use common::{miniapm, open_db};
+use miniapm::models::{project, span};
+
+const FIXTURE: &str = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/trace.json");
+
+#[test]
+fn import_loads_fixture_spans_into_project() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("miniapm.db");
+    let pool = open_db(&db);
+    let (shop, _) = project::create(&pool, "Shop").unwrap();
+
+    let output = miniapm(&db, &["import", "--file", FIXTURE, "--project", "shop"]);
+    assert!(output.status.success(), "{:?}", output);
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(
+        stdout.contains("Imported 3 spans into 'shop' (0 rejected, 1 errors extracted)"),
+        "{stdout}"
+    );
+
+    let trace = span::get_trace(&pool, "5b8efff798038103d269b633813fc60c", &[])
+        .unwrap()
+        .unwrap();
+    assert_eq!(trace.spans.len(), 3);
+    assert_eq!(trace.root_span.unwrap().name, "GET /checkout");
+
+    let traces = span::list_traces(&pool, Some(shop.id), None, 10).unwrap();
+    assert_eq!(traces.len(), 1);
+    assert_eq!(traces[0].environment.as_deref(), Some("production"));
+    let errors = miniapm::models::error::list(&pool, Some(shop.id), None, 10).unwrap();
+    assert_eq!(errors[0].exception_class, "NoMethodError");
+}
+
+#[test]
+fn import_reports_parse_errors_with_line_context() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("miniapm.db");
+    open_db(&db);
+
+    let file = dir.path().join("broken.json");
+    std::fs::write(
+        &file,
+        "{\n  \"resourceSpans\": [\n    {\"scopeSpans\": oops}\n  ]\n}\n",
+    )
+    .unwrap();
+
+    let output = miniapm(&db, &["import", "--file", path(&file)]);
+    assert!(!output.status.success());
+    let stderr = String::from_utf8_lossy(&output.stderr);
+    assert!(stderr.contains("line 3"), "{stderr}");
+    assert!(
+        stderr.contains("    3 |     {\"scopeSpans\": oops}"),
+        "{stderr}"
+    );
+}
+
+fn path(p: &Path) -> &str {
+    p.to_str().unwrap()
+}
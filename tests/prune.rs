@@ -0,0 +1,135 @@
+mod common;
+
+use common::{miniapm, open_db};
+use miniapm::{
+    DbPool,
+    models::{error, project, span},
+};
+
+fn seed(pool: &DbPool, project_id: i64, day: &str, span_id: &str) {
+    let start = chrono::DateTime::parse_from_rfc3339(&format!("{day}T12:00:00Z"))
+        .unwrap()
+        .timestamp_nanos_opt()
+        .unwrap();
+    let batch: span::OtlpTraceRequest = serde_json::from_value(serde_json::json!({
+        "resourceSpans": [{"scopeSpans": [{"spans": [{
+            "traceId": format!("{:0>32}", span_id),
+            "spanId": format!("{:0>16}", span_id),
+            "name": "GET /",
+            "kind": 2,
+            "startTimeUnixNano": start.to_string(),
+            "endTimeUnixNano": (start + 1_000_000).to_string()
+        }]}]}]
+    }))
+    .unwrap();
+    span::insert_otlp_batch(pool, &batch, Some(project_id)).unwrap();
+
+    error::insert(
+        pool,
+        &error::IncomingError {
+            exception_class: "RuntimeError".to_string(),
+            message: "boom".to_string(),
+            backtrace: vec!["app/models/user.rb:42:in `save'".to_string()],
+            fingerprint: "fp".to_string(),
+            request_id: None,
+            user_id: None,
+            params: None,
+            timestamp: Some(format!("{day}T12:00:00Z")),
+            source_context: None,
+            git_sha: None,
+        },
+        Some(project_id),
+    )
+    .unwrap();
+
+    pool.get()
+        .unwrap()
+        .execute(
+            "INSERT INTO requests (project_id, request_id, method, path, status, total_ms, happened_at)
+             VALUES (?1, ?2, 'GET', '/', 200, 10, ?3)",
+            rusqlite::params![project_id, span_id, format!("{day}T12:00:00Z")],
+        )
+        .unwrap();
+}
+
+fn count(pool: &DbPool, table: &str, project_id: i64) -> i64 {
+    let sql = if table == "error_occurrences" {
+        "SELECT COUNT(*) FROM error_occurrences eo JOIN errors e ON e.id = eo.error_id WHERE e.project_id = ?1".to_string()
+    } else {
+        format!("SELECT COUNT(*) FROM {table} WHERE project_id = ?1")
+    };
+    pool.get()
+        .unwrap()
+        .query_row(&sql, [project_id], |row| row.get(0))
+        .unwrap()
+}
+
+const TABLES: [&str; 5] = [
+    "spans",
+    "trace_summaries",
+    "requests",
+    "error_occurrences",
+    "errors",
+];
+
+#[test]
+fn prune_requires_confirmation_and_respects_cutoff() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("miniapm.db");
+    let pool = open_db(&db);
+    let (noisy, _) = project::create(&pool, "Noisy").unwrap();
+    seed(&pool, noisy.id, "2025-06-01", "1");
+    seed(&pool, noisy.id, "2026-06-01", "2");
+
+    let refused = miniapm(&db, &["prune", "--project", "noisy"]);
+    assert!(!refused.status.success());
+    assert!(String::from_utf8_lossy(&refused.stderr).contains("--yes"));
+    assert_eq!(count(&pool, "spans", noisy.id), 2);
+
+    let output = miniapm(
+        &db,
+        &[
+            "prune",
+            "--project",
+            "noisy",
+            "--before",
+            "2026-01-01",
+            "--yes",
+        ],
+    );
+    assert!(output.status.success());
+    let stdout = String::from_utf8_lossy(&output.stdout);
+    assert!(stdout.contains("spans              1"), "{stdout}");
+    assert!(stdout.contains("errors             0"), "{stdout}");
+
+    assert_eq!(count(&pool, "spans", noisy.id), 1);
+    assert_eq!(count(&pool, "requests", noisy.id), 1);
+    assert_eq!(count(&pool, "error_occurrences", noisy.id), 1);
+    // The error group survives with its remaining occurrence
+    assert_eq!(count(&pool, "errors", noisy.id), 1);
+}
+
+#[test]
+fn prune_only_touches_the_named_project() {
+    let dir = tempfile::tempdir().unwrap();
+    let db = dir.path().join("miniapm.db");
+    let pool = open_db(&db);
+    let (noisy, _) = project::create(&pool, "Noisy").unwrap();
+    let (quiet, _) = project::create(&pool, "Quiet").unwrap();
+    seed(&pool, noisy.id, "2026-06-01", "1");
+    seed(&pool, quiet.id, "2026-06-01", "2");
+
+    assert!(
+        !miniapm(&db, &["prune", "--project", "missing", "--yes"])
+            .status
+            .success()
+    );
+
+    let output = miniapm(&db, &["prune", "--project", "noisy", "--yes"]);
+    assert!(output.status.success());
+    for table in TABLES {
+        assert_eq!(count(&pool, table, noisy.id), 0, "{table}");
+        assert_eq!(count(&pool, table, quiet.id), 1, "{table}");
+    }
+    assert!(project::find_by_slug(&pool, "noisy").unwrap().is_some());
+}